@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use hash::calc_hashes;
+use hash::HashKinds;
+use hash::Hashes;
+
+/// Collects files by size while the roots are searched, so only the files of the same size are read afterwards.
+/// Files of a unique size can't have duplicates and are never hashed.
+pub struct DupeFinder {
+    files_by_size: HashMap<u64, Vec<DupeCandidate>>,
+}
+
+/// File found under the root with the given index at the given depth.
+pub struct DupeCandidate {
+    pub path: PathBuf,
+    pub root_idx: usize,
+    pub depth: u32,
+}
+
+/// Files with the same content along with their hashes.
+pub struct DupeGroup {
+    pub size: u64,
+    pub files: Vec<(DupeCandidate, Hashes)>,
+}
+
+impl DupeFinder {
+    pub fn new() -> DupeFinder {
+        DupeFinder { files_by_size: HashMap::new() }
+    }
+
+    pub fn add(&mut self, path: PathBuf, size: u64, root_idx: usize, depth: u32) {
+        self.files_by_size.entry(size).or_insert_with(Vec::new).push(DupeCandidate { path, root_idx, depth });
+    }
+
+    /// Hashes the files sharing their size and groups them by `sha256`, other requested hashes are calculated along the way.
+    /// The groups of the largest files go first, files of a group are ordered by path.
+    pub fn find_groups<F: FnMut(&Path, io::Error)>(self, kinds: HashKinds, mut on_error: F) -> Vec<DupeGroup> {
+        let kinds = HashKinds { sha256: true, ..kinds };
+        let mut groups = vec![];
+
+        for (size, candidates) in self.files_by_size {
+            if candidates.len() < 2 {
+                continue;
+            }
+
+            let mut files_by_hash: HashMap<String, Vec<(DupeCandidate, Hashes)>> = HashMap::new();
+
+            for candidate in candidates {
+                match File::open(&candidate.path).and_then(|mut file| calc_hashes(&mut file, kinds)) {
+                    Ok(hashes) => {
+                        let sha256 = hashes.sha256.clone().unwrap_or_default();
+                        files_by_hash.entry(sha256).or_insert_with(Vec::new).push((candidate, hashes));
+                    },
+                    Err(err) => on_error(&candidate.path, err)
+                }
+            }
+
+            for (_, mut files) in files_by_hash {
+                if files.len() > 1 {
+                    files.sort_by(|a, b| (a.0).path.cmp(&(b.0).path));
+                    groups.push(DupeGroup { size, files });
+                }
+            }
+        }
+
+        groups.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| (a.files[0].0).path.cmp(&(b.files[0].0).path)));
+
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use util::TempDir;
+
+    #[test]
+    fn test_find_groups() {
+        let fixture = TempDir::new("dupe_finder");
+
+        let files = [("a1", "aaaa"), ("a2", "aaaa"), ("b1", "bbbb"), ("c1", "cc"), ("c2", "cc"), ("c3", "cc"), ("d1", "d")];
+        let mut finder = DupeFinder::new();
+        for &(name, content) in &files {
+            fs::write(fixture.join(name), content).unwrap();
+            finder.add(fixture.join(name), content.len() as u64, 0, 1);
+        }
+        finder.add(fixture.join("missing"), 1, 0, 1);
+
+        let mut errors = vec![];
+        let groups = finder.find_groups(HashKinds::default(), |path, _| errors.push(path.to_path_buf()));
+
+        let names: Vec<Vec<String>> = groups.iter()
+            .map(|group| group.files.iter().map(|file| (file.0).path.file_name().unwrap().to_string_lossy().to_string()).collect())
+            .collect();
+        assert_eq!(names, vec![vec!["a1", "a2"], vec!["c1", "c2", "c3"]]);
+        assert_eq!(groups[1].size, 2);
+        assert!((groups[0].files[0].1).sha256.is_some());
+        assert!((groups[0].files[0].1).md5.is_none());
+        assert_eq!(errors, vec![fixture.join("missing")]);
+    }
+}
@@ -7,19 +7,43 @@ use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Error;
 
+use util::edit_distance;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Hash)]
 pub enum Field {
     Name,
+    Extension,
     Path,
+    RelativePath,
+    Archive,
+    Depth,
     Size,
     FormattedSize,
+    DirSize,
+    FormattedDirSize,
+    Entries,
+    CompressedSize,
+    CompressionRatio,
     Uid,
     Gid,
+    Inode,
+    Device,
+    Nlinks,
     User,
     Group,
     Created,
     Accessed,
+    Changed,
     Modified,
+    CreatedUnix,
+    AccessedUnix,
+    ModifiedUnix,
+    CreatedUtc,
+    AccessedUtc,
+    ModifiedUtc,
+    AgeDays,
+    AccessAgeDays,
+    CreateAgeDays,
     IsDir,
     IsFile,
     IsSymlink,
@@ -27,7 +51,13 @@ pub enum Field {
     IsCharacterDevice,
     IsBlockDevice,
     IsSocket,
+    IsEmpty,
+    IsReadonly,
+    IsSystem,
+    IsArchiveBit,
     Mode,
+    ModeOctal,
+    Flags,
     UserRead,
     UserWrite,
     UserExec,
@@ -37,18 +67,45 @@ pub enum Field {
     OtherRead,
     OtherWrite,
     OtherExec,
+    IsSuid,
+    IsSgid,
+    IsSticky,
+    IsMine,
+    IsReadable,
+    IsWritable,
+    IsExecutable,
     IsHidden,
     HasXattrs,
+    Xattrs,
     IsShebang,
+    Shebang,
+    IsBinary,
+    IsText,
+    LineEndings,
+    HasBom,
+    GitStatus,
+    MimeType,
+    Md5,
+    Sha1,
+    Sha256,
     Width,
     Height,
+    AspectRatio,
     Bitrate,
+    MinBitrate,
+    MaxBitrate,
     Freq,
+    Duration,
+    FormattedDuration,
     Title,
     Artist,
     Album,
     Year,
     Genre,
+    VideoWidth,
+    VideoHeight,
+    VideoDuration,
+    VideoCodec,
     IsArchive,
     IsAudio,
     IsBook,
@@ -58,6 +115,23 @@ pub enum Field {
     IsVideo,
 }
 
+/// Names of all the fields, the aliases are left out.
+const FIELD_NAMES: &'static [&'static str] = &[
+    "name", "ext", "path", "relpath", "archive", "depth", "size", "fsize", "dirsize", "fdirsize", "entries", "compressed_size",
+    "compression_ratio", "uid", "gid", "inode", "device", "nlinks", "user", "group", "created", "accessed",
+    "changed", "modified", "created_unix", "accessed_unix", "modified_unix", "created_utc",
+    "accessed_utc", "modified_utc", "age_days", "access_age_days", "create_age_days", "is_dir", "is_file", "is_symlink",
+    "is_pipe", "is_char", "is_block", "is_socket",
+    "is_empty", "is_readonly", "is_system", "is_archive_bit", "mode", "mode_oct", "flags", "user_read", "user_write",
+    "user_exec", "group_read", "group_write", "group_exec", "other_read", "other_write", "other_exec",
+    "is_suid", "is_sgid", "is_sticky", "is_mine", "is_readable", "is_writable", "is_executable", "is_hidden",
+    "has_xattrs", "xattrs", "is_shebang", "shebang", "is_binary", "is_text", "line_endings", "has_bom", "git_status", "mime", "md5",
+    "sha1", "sha256", "width", "height", "aspect_ratio", "mp3_bitrate", "mp3_min_bitrate",
+    "mp3_max_bitrate", "mp3_freq", "duration", "fduration", "mp3_title",
+    "mp3_artist", "mp3_album", "mp3_year", "mp3_genre", "video_width", "video_height", "video_duration",
+    "video_codec", "is_archive", "is_audio", "is_book", "is_doc", "is_image", "is_source", "is_video"
+];
+
 impl FromStr for Field {
     type Err = String;
 
@@ -66,16 +140,38 @@ impl FromStr for Field {
 
         match field.as_str() {
             "name" => Ok(Field::Name),
+            "ext" | "extension" => Ok(Field::Extension),
             "path" => Ok(Field::Path),
+            "relpath" => Ok(Field::RelativePath),
+            "archive" => Ok(Field::Archive),
+            "depth" => Ok(Field::Depth),
             "size" => Ok(Field::Size),
             "fsize" | "hsize" => Ok(Field::FormattedSize),
+            "dirsize" => Ok(Field::DirSize),
+            "fdirsize" | "hdirsize" => Ok(Field::FormattedDirSize),
+            "entries" => Ok(Field::Entries),
+            "compressed_size" => Ok(Field::CompressedSize),
+            "compression_ratio" => Ok(Field::CompressionRatio),
             "uid" => Ok(Field::Uid),
             "gid" => Ok(Field::Gid),
+            "inode" => Ok(Field::Inode),
+            "device" => Ok(Field::Device),
+            "nlinks" | "hardlinks" => Ok(Field::Nlinks),
             "user" => Ok(Field::User),
             "group" => Ok(Field::Group),
             "created" => Ok(Field::Created),
             "accessed" => Ok(Field::Accessed),
+            "changed" | "ctime" => Ok(Field::Changed),
             "modified" => Ok(Field::Modified),
+            "created_unix" => Ok(Field::CreatedUnix),
+            "accessed_unix" => Ok(Field::AccessedUnix),
+            "modified_unix" => Ok(Field::ModifiedUnix),
+            "created_utc" => Ok(Field::CreatedUtc),
+            "accessed_utc" => Ok(Field::AccessedUtc),
+            "modified_utc" => Ok(Field::ModifiedUtc),
+            "age_days" => Ok(Field::AgeDays),
+            "access_age_days" => Ok(Field::AccessAgeDays),
+            "create_age_days" => Ok(Field::CreateAgeDays),
             "is_dir" => Ok(Field::IsDir),
             "is_file" => Ok(Field::IsFile),
             "is_symlink" => Ok(Field::IsSymlink),
@@ -83,7 +179,13 @@ impl FromStr for Field {
             "is_char" | "is_character" => Ok(Field::IsCharacterDevice),
             "is_block" => Ok(Field::IsBlockDevice),
             "is_socket" => Ok(Field::IsSocket),
+            "is_empty" => Ok(Field::IsEmpty),
+            "is_readonly" => Ok(Field::IsReadonly),
+            "is_system" => Ok(Field::IsSystem),
+            "is_archive_bit" => Ok(Field::IsArchiveBit),
             "mode" => Ok(Field::Mode),
+            "mode_oct" | "mode_octal" => Ok(Field::ModeOctal),
+            "flags" => Ok(Field::Flags),
             "user_read" => Ok(Field::UserRead),
             "user_write" => Ok(Field::UserWrite),
             "user_exec" => Ok(Field::UserExec),
@@ -93,18 +195,45 @@ impl FromStr for Field {
             "other_read" => Ok(Field::OtherRead),
             "other_write" => Ok(Field::OtherWrite),
             "other_exec" => Ok(Field::OtherExec),
+            "is_suid" | "suid" => Ok(Field::IsSuid),
+            "is_sgid" | "sgid" => Ok(Field::IsSgid),
+            "is_sticky" | "sticky" => Ok(Field::IsSticky),
+            "is_mine" | "mine" => Ok(Field::IsMine),
+            "is_readable" | "readable" => Ok(Field::IsReadable),
+            "is_writable" | "writable" => Ok(Field::IsWritable),
+            "is_executable" | "executable" => Ok(Field::IsExecutable),
             "is_hidden" => Ok(Field::IsHidden),
             "has_xattrs" => Ok(Field::HasXattrs),
+            "xattrs" => Ok(Field::Xattrs),
             "is_shebang" => Ok(Field::IsShebang),
+            "shebang" => Ok(Field::Shebang),
+            "is_binary" => Ok(Field::IsBinary),
+            "is_text" => Ok(Field::IsText),
+            "line_endings" => Ok(Field::LineEndings),
+            "has_bom" => Ok(Field::HasBom),
+            "git_status" => Ok(Field::GitStatus),
+            "mime" | "mimetype" => Ok(Field::MimeType),
+            "md5" => Ok(Field::Md5),
+            "sha1" => Ok(Field::Sha1),
+            "sha256" => Ok(Field::Sha256),
             "width" => Ok(Field::Width),
             "height" => Ok(Field::Height),
+            "aspect_ratio" => Ok(Field::AspectRatio),
             "mp3_bitrate" | "bitrate" => Ok(Field::Bitrate),
+            "mp3_min_bitrate" | "min_bitrate" => Ok(Field::MinBitrate),
+            "mp3_max_bitrate" | "max_bitrate" => Ok(Field::MaxBitrate),
             "mp3_freq" | "freq" => Ok(Field::Freq),
+            "duration" => Ok(Field::Duration),
+            "fduration" | "hduration" => Ok(Field::FormattedDuration),
             "mp3_title" | "title" => Ok(Field::Title),
             "mp3_artist" | "artist" => Ok(Field::Artist),
             "mp3_album" | "album" => Ok(Field::Album),
             "mp3_year" => Ok(Field::Year),
             "mp3_genre" | "genre" => Ok(Field::Genre),
+            "video_width" => Ok(Field::VideoWidth),
+            "video_height" => Ok(Field::VideoHeight),
+            "video_duration" => Ok(Field::VideoDuration),
+            "video_codec" => Ok(Field::VideoCodec),
             "is_archive" => Ok(Field::IsArchive),
             "is_audio" => Ok(Field::IsAudio),
             "is_book" => Ok(Field::IsBook),
@@ -135,28 +264,135 @@ impl Serialize for Field {
 }
 
 impl Field {
+    /// Field name closest to the misspelled one, if it's only a typo or two away.
+    pub fn suggest_name(s: &str) -> Option<&'static str> {
+        let s = s.to_ascii_lowercase();
+        let max_distance = (s.chars().count() / 4).max(1);
+
+        FIELD_NAMES.iter()
+            .map(|name| (edit_distance(&s, name), *name))
+            .filter(|&(distance, _)| distance <= max_distance)
+            .min_by_key(|&(distance, _)| distance)
+            .map(|(_, name)| name)
+    }
+
     pub fn is_numeric_field(&self) -> bool {
         match self {
-            Field::Size | Field::FormattedSize
+            Field::Depth | Field::Size | Field::FormattedSize
+            | Field::DirSize | Field::FormattedDirSize | Field::Entries
+            | Field::CompressedSize | Field::CompressionRatio
             | Field::Uid | Field::Gid
-            | Field::Width | Field::Height
-            | Field::Bitrate | Field::Freq | Field::Year => true,
+            | Field::Inode | Field::Device | Field::Nlinks
+            | Field::CreatedUnix | Field::AccessedUnix | Field::ModifiedUnix
+            | Field::AgeDays | Field::AccessAgeDays | Field::CreateAgeDays
+            | Field::Width | Field::Height | Field::AspectRatio
+            | Field::Bitrate | Field::MinBitrate | Field::MaxBitrate | Field::Freq | Field::Duration | Field::Year
+            | Field::VideoWidth | Field::VideoHeight | Field::VideoDuration => true,
+            _ => false
+        }
+    }
+
+    /// Tells whether the value is a number printed along with its unit, e.g. `fsize`.
+    pub fn is_formatted_field(&self) -> bool {
+        match self {
+            Field::FormattedSize | Field::FormattedDirSize | Field::FormattedDuration => true,
+            _ => false
+        }
+    }
+
+    pub fn is_boolean_field(&self) -> bool {
+        match self {
+            Field::IsDir | Field::IsFile | Field::IsSymlink | Field::IsPipe
+            | Field::IsCharacterDevice | Field::IsBlockDevice | Field::IsSocket | Field::IsEmpty
+            | Field::IsReadonly | Field::IsSystem | Field::IsArchiveBit
+            | Field::UserRead | Field::UserWrite | Field::UserExec
+            | Field::GroupRead | Field::GroupWrite | Field::GroupExec
+            | Field::OtherRead | Field::OtherWrite | Field::OtherExec
+            | Field::IsSuid | Field::IsSgid | Field::IsSticky
+            | Field::IsMine | Field::IsReadable | Field::IsWritable | Field::IsExecutable
+            | Field::IsHidden | Field::HasXattrs | Field::IsShebang | Field::IsBinary | Field::IsText | Field::HasBom
+            | Field::IsArchive | Field::IsAudio | Field::IsBook | Field::IsDoc
+            | Field::IsImage | Field::IsSource | Field::IsVideo => true,
             _ => false
         }
     }
 
     pub fn is_datetime_field(&self) -> bool {
         match self {
-            Field::Created | Field::Accessed | Field::Modified => true,
+            Field::Created | Field::Accessed | Field::Changed | Field::Modified => true,
             _ => false
         }
     }
 
+    /// Tells whether the value could only be obtained from the file system metadata,
+    /// i.e. requires a `stat` call. Name derived fields and fields reading the file content don't.
+    pub fn needs_metadata(&self) -> bool {
+        match self {
+            Field::Name | Field::Extension | Field::Path | Field::RelativePath | Field::Archive | Field::Depth
+            | Field::CompressedSize | Field::CompressionRatio
+            | Field::HasXattrs | Field::Xattrs | Field::IsShebang | Field::Shebang | Field::IsBinary | Field::IsText | Field::MimeType
+            | Field::LineEndings | Field::HasBom | Field::GitStatus
+            | Field::Md5 | Field::Sha1 | Field::Sha256
+            | Field::Width | Field::Height | Field::AspectRatio
+            | Field::IsArchive | Field::IsAudio | Field::IsBook | Field::IsDoc
+            | Field::IsImage | Field::IsSource | Field::IsVideo => false,
+            Field::IsHidden => cfg!(windows) || cfg!(target_os = "macos"),
+            _ => !self.is_mp3_field() && !self.is_video_field()
+        }
+    }
+
     pub fn is_mp3_field(&self) -> bool {
         match self {
-            Field::Bitrate | Field::Freq | Field::Title
+            Field::Bitrate | Field::MinBitrate | Field::MaxBitrate | Field::Freq
+            | Field::Duration | Field::FormattedDuration | Field::Title
             | Field::Artist | Field::Album | Field::Year | Field::Genre => true,
             _ => false
         }
     }
-}
\ No newline at end of file
+
+    /// Tells whether the value is kept in the index, so the file system is not asked for it.
+    pub fn is_indexed_field(&self) -> bool {
+        match self {
+            Field::Name | Field::Extension | Field::Path | Field::RelativePath | Field::Depth
+            | Field::Size | Field::FormattedSize | Field::Modified | Field::ModifiedUnix | Field::ModifiedUtc | Field::Mode | Field::ModeOctal
+            | Field::IsDir | Field::IsFile | Field::IsSymlink => true,
+            _ => false
+        }
+    }
+
+    /// Fields computed from the image dimensions, which are read once per file.
+    pub fn is_img_dimension_field(&self) -> bool {
+        match self {
+            Field::Width | Field::Height | Field::AspectRatio => true,
+            _ => false
+        }
+    }
+
+    pub fn is_video_field(&self) -> bool {
+        match self {
+            Field::VideoWidth | Field::VideoHeight | Field::VideoDuration | Field::VideoCodec => true,
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_names() {
+        for name in FIELD_NAMES {
+            assert!(Field::from_str(name).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_suggest_name() {
+        assert_eq!(Field::suggest_name("modfied"), Some("modified"));
+        assert_eq!(Field::suggest_name("SISE"), Some("size"));
+        assert_eq!(Field::suggest_name("isdir"), Some("is_dir"));
+        assert_eq!(Field::suggest_name("compresion_ratio"), Some("compression_ratio"));
+        assert_eq!(Field::suggest_name("colour"), None);
+    }
+}
@@ -1,18 +1,314 @@
+use std::cmp;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use hash::Hashes;
+use tar;
+use time;
+use time::Timespec;
 use time::Tm;
 use zip;
 
 pub struct FileInfo {
     pub name: String,
     pub size: u64,
+    /// Size of the stored data, known for zip entries only
+    pub compressed_size: Option<u64>,
     pub mode: Option<u32>,
+    /// Owner ids, known for zip entries with a unix extra field only
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
     pub modified: Tm,
+    pub hashes: Option<Hashes>,
+    /// Index of the entry in its zip archive, so its content can be read again
+    pub zip_index: Option<usize>,
+}
+
+impl FileInfo {
+    /// Uncompressed size divided by compressed size, rounded to two decimal places.
+    /// There is no ratio for empty entries.
+    pub fn get_compression_ratio(&self) -> Option<f64> {
+        match self.compressed_size {
+            Some(compressed_size) if compressed_size > 0 => {
+                Some((self.size as f64 / compressed_size as f64 * 100.0).round() / 100.0)
+            },
+            _ => None
+        }
+    }
+}
+
+/// Zip archive over any reader. The archives being searched are kept as these,
+/// so the content of a member is decompressed only when a condition asks for it.
+pub trait ZipMembers {
+    fn by_index<'a>(&'a mut self, idx: usize) -> zip::result::ZipResult<zip::read::ZipFile<'a>>;
+}
+
+impl<R: Read + Seek> ZipMembers for zip::ZipArchive<R> {
+    fn by_index<'a>(&'a mut self, idx: usize) -> zip::result::ZipResult<zip::read::ZipFile<'a>> {
+        zip::ZipArchive::by_index(self, idx)
+    }
 }
 
-pub fn to_file_info(zipped_file: &zip::read::ZipFile) -> FileInfo {
+/// The extra field of the entry from the central directory carries the owner ids, if any.
+pub fn to_file_info(zipped_file: &zip::read::ZipFile, extra_field: &[u8]) -> FileInfo {
+    let owner = parse_unix_owner(extra_field);
+
     FileInfo {
         name: zipped_file.name().to_string(),
         size: zipped_file.size(),
+        compressed_size: Some(zipped_file.compressed_size()),
         mode: zipped_file.unix_mode(),
-        modified: zipped_file.last_modified()
+        uid: owner.map(|owner| owner.0),
+        gid: owner.map(|owner| owner.1),
+        modified: zipped_file.last_modified(),
+        hashes: None,
+        zip_index: None,
+    }
+}
+
+pub fn tar_entry_to_file_info<R: Read>(tar_entry: &tar::Entry<R>) -> FileInfo {
+    let header = tar_entry.header();
+    let entry_type = header.entry_type();
+
+    let mut name = String::from_utf8_lossy(&tar_entry.path_bytes()).to_string();
+    // directories are told apart by the trailing slash, the same way zip archives store them
+    if entry_type.is_dir() && !name.ends_with('/') {
+        name.push('/');
+    }
+
+    let file_type = match entry_type {
+        tar::EntryType::Directory => 0o040000,
+        tar::EntryType::Symlink => 0o120000,
+        tar::EntryType::Char => 0o020000,
+        tar::EntryType::Block => 0o060000,
+        tar::EntryType::Fifo => 0o010000,
+        _ => 0o100000
+    };
+
+    FileInfo {
+        name,
+        size: header.size().unwrap_or(0),
+        compressed_size: None,
+        mode: header.mode().ok().map(|mode| mode & 0o7777 | file_type),
+        uid: None,
+        gid: None,
+        modified: time::at(Timespec::new(header.mtime().unwrap_or(0) as i64, 0)),
+        hashes: None,
+        zip_index: None,
     }
-}
\ No newline at end of file
+}
+
+const ZIP_END_OF_CENTRAL_DIR_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+const ZIP_CENTRAL_DIR_HEADER_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x01, 0x02];
+const ZIP_END_OF_CENTRAL_DIR_SIZE: u64 = 22;
+const ZIP_CENTRAL_DIR_HEADER_SIZE: usize = 46;
+/// Central directories larger than this are not read again, their entries get no extra fields.
+const MAX_ZIP_CENTRAL_DIR_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Extra fields of the zip entries in the order of the central directory, which is the order of the entry indexes.
+/// The zip crate doesn't expose them, so the central directory is read once more.
+/// ZIP64 archives and the ones with huge directories give no extra fields.
+/// The archive may be anything, so every size and offset is checked against the bytes actually read.
+pub fn read_zip_extra_fields<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<Vec<u8>>> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    // the end record is followed by a comment of at most 64 KB
+    let tail_len = cmp::min(file_len, ZIP_END_OF_CENTRAL_DIR_SIZE + 0xffff);
+    reader.seek(SeekFrom::Start(file_len - tail_len))?;
+    let mut tail = vec![0; tail_len as usize];
+    reader.read_exact(&mut tail)?;
+
+    let end_pos = match tail.windows(4).rposition(|window| window == ZIP_END_OF_CENTRAL_DIR_SIGNATURE) {
+        Some(pos) if pos + ZIP_END_OF_CENTRAL_DIR_SIZE as usize <= tail.len() => pos,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "no end of central directory"))
+    };
+
+    let entry_count = read_le(&tail[end_pos + 10..end_pos + 12]);
+    let dir_size = read_le(&tail[end_pos + 12..end_pos + 16]);
+    if entry_count == 0xffff || dir_size == 0xffffffff || dir_size > MAX_ZIP_CENTRAL_DIR_SIZE {
+        return Ok(vec![]);
+    }
+
+    // data prepended to the archive shifts the offsets, so the directory is looked for right before the end record
+    let dir_end = file_len - tail_len + end_pos as u64;
+    if dir_size > dir_end {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid central directory size"));
+    }
+    reader.seek(SeekFrom::Start(dir_end - dir_size))?;
+    let mut dir = vec![];
+    reader.take(dir_size).read_to_end(&mut dir)?;
+    if dir.len() as u64 != dir_size {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated central directory"));
+    }
+
+    let mut result = vec![];
+    let mut pos = 0;
+    while result.len() < entry_count as usize && pos + ZIP_CENTRAL_DIR_HEADER_SIZE <= dir.len() {
+        if dir[pos..pos + 4] != ZIP_CENTRAL_DIR_HEADER_SIGNATURE {
+            break;
+        }
+
+        let name_len = read_le(&dir[pos + 28..pos + 30]) as usize;
+        let extra_len = read_le(&dir[pos + 30..pos + 32]) as usize;
+        let comment_len = read_le(&dir[pos + 32..pos + 34]) as usize;
+        let extra_start = pos + ZIP_CENTRAL_DIR_HEADER_SIZE + name_len;
+        let extra_end = cmp::min(extra_start + extra_len, dir.len());
+
+        result.push(dir.get(extra_start..extra_end).unwrap_or(&[]).to_vec());
+        pos = extra_end + comment_len;
+    }
+
+    Ok(result)
+}
+
+/// Uid and gid from the Info-ZIP "ux" field or, failing that, from the PKWARE or the old Info-ZIP unix field.
+fn parse_unix_owner(extra_field: &[u8]) -> Option<(u32, u32)> {
+    let mut result = None;
+    let mut pos = 0;
+
+    while pos + 4 <= extra_field.len() {
+        let id = read_le(&extra_field[pos..pos + 2]);
+        let len = read_le(&extra_field[pos + 2..pos + 4]) as usize;
+        let data = match extra_field.get(pos + 4..pos + 4 + len) {
+            Some(data) => data,
+            None => break
+        };
+
+        match id {
+            0x7875 => {
+                if let Some(owner) = parse_info_zip_owner(data) {
+                    return Some(owner);
+                }
+            },
+            0x000d | 0x5855 if data.len() >= 12 => {
+                result = Some((read_le(&data[8..10]) as u32, read_le(&data[10..12]) as u32));
+            },
+            _ => { }
+        }
+
+        pos += 4 + len;
+    }
+
+    result
+}
+
+/// Version 1 of the "ux" field: the sizes of the ids go before the ids themselves.
+fn parse_info_zip_owner(data: &[u8]) -> Option<(u32, u32)> {
+    if data.first() != Some(&1) {
+        return None;
+    }
+
+    let uid_len = *data.get(1)? as usize;
+    let uid = read_id(data.get(2..2 + uid_len)?)?;
+    let gid_len = *data.get(2 + uid_len)? as usize;
+    let gid = read_id(data.get(3 + uid_len..3 + uid_len + gid_len)?)?;
+
+    Some((uid, gid))
+}
+
+fn read_id(bytes: &[u8]) -> Option<u32> {
+    match bytes.len() {
+        0 => None,
+        len if len > 4 => None,
+        _ => Some(read_le(bytes) as u32)
+    }
+}
+
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |result, &byte| result << 8 | byte as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_parse_unix_owner() {
+        assert_eq!(parse_unix_owner(&[0x75, 0x78, 0x0b, 0x00, 1, 4, 0x92, 0x10, 0, 0, 4, 0xf7, 0x10, 0, 0]), Some((4242, 4343)));
+        assert_eq!(parse_unix_owner(&[0x55, 0x54, 0x01, 0x00, 3, 0x75, 0x78, 0x05, 0x00, 1, 1, 7, 1, 9]), Some((7, 9)));
+        assert_eq!(parse_unix_owner(&[0x0d, 0x00, 0x0c, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0xe8, 0x03, 0x64, 0x00]), Some((1000, 100)));
+        assert_eq!(parse_unix_owner(&[0x75, 0x78, 0x03, 0x00, 2, 1, 7]), None);
+        assert_eq!(parse_unix_owner(&[0x75, 0x78, 0x20, 0x00, 1]), None);
+        assert_eq!(parse_unix_owner(&[]), None);
+    }
+
+    #[test]
+    fn test_read_zip_extra_fields() {
+        let mut file = File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/owners.zip")).unwrap();
+        let owners: Vec<Option<(u32, u32)>> = read_zip_extra_fields(&mut file).unwrap().iter()
+            .map(|extra_field| parse_unix_owner(extra_field))
+            .collect();
+
+        assert_eq!(owners, vec![Some((0, 0)), Some((4242, 4343)), None]);
+    }
+
+    /// Extra fields of the bytes, which must be read without a panic, and the owners parsed from them.
+    fn read_owners(bytes: &[u8]) -> Option<Vec<Option<(u32, u32)>>> {
+        let extra_fields = read_zip_extra_fields(&mut io::Cursor::new(bytes)).ok()?;
+        assert!(extra_fields.iter().all(|extra_field| extra_field.len() <= 0xffff));
+
+        Some(extra_fields.iter().map(|extra_field| parse_unix_owner(extra_field)).collect())
+    }
+
+    #[test]
+    fn test_read_broken_zip_extra_fields() {
+        let mut archive = vec![];
+        File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/owners.zip")).unwrap().read_to_end(&mut archive).unwrap();
+        assert_eq!(read_owners(&archive), Some(vec![Some((0, 0)), Some((4242, 4343)), None]));
+
+        // truncated at either end
+        for len in 0..archive.len() {
+            if let Some(owners) = read_owners(&archive[..len]) {
+                assert!(owners.len() <= 3);
+            }
+            read_owners(&archive[archive.len() - len..]);
+        }
+
+        // the end record claims more than there is
+        let end_pos = archive.windows(4).rposition(|window| window == ZIP_END_OF_CENTRAL_DIR_SIGNATURE).unwrap();
+        let mut huge_dir = archive.clone();
+        huge_dir[end_pos + 12..end_pos + 16].copy_from_slice(&[0xf0, 0xff, 0xff, 0x7f]);
+        assert_eq!(read_owners(&huge_dir), Some(vec![]));
+        huge_dir[end_pos + 12..end_pos + 16].copy_from_slice(&[0xf0, 0xff, 0xff, 0x00]);
+        assert_eq!(read_owners(&huge_dir), None);
+
+        let mut many_entries = archive.clone();
+        many_entries[end_pos + 10..end_pos + 12].copy_from_slice(&[0xfe, 0xff]);
+        assert_eq!(read_owners(&many_entries).map(|owners| owners.len()), Some(3));
+
+        // random bytes, random ones around the signatures, and the archive with random bytes changed
+        let mut seed: u64 = 0x2545f4914f6cdd1d;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        for _ in 0..2000 {
+            let len = (next() % 512) as usize;
+            let mut bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            if len >= 4 && next() % 2 == 0 {
+                let pos = (next() as usize) % (len - 3);
+                bytes[pos..pos + 4].copy_from_slice(&ZIP_END_OF_CENTRAL_DIR_SIGNATURE);
+            }
+            if len >= 4 && next() % 2 == 0 {
+                let pos = (next() as usize) % (len - 3);
+                bytes[pos..pos + 4].copy_from_slice(&ZIP_CENTRAL_DIR_HEADER_SIGNATURE);
+            }
+            read_owners(&bytes);
+
+            let mut mutated = archive.clone();
+            for _ in 0..1 + next() % 8 {
+                let pos = (next() as usize) % mutated.len();
+                mutated[pos] = next() as u8;
+            }
+            read_owners(&mutated);
+
+            let extra_field: Vec<u8> = (0..next() % 64).map(|_| next() as u8).collect();
+            parse_unix_owner(&extra_field);
+        }
+    }
+}
@@ -12,6 +12,10 @@ pub enum Function {
     Lower,
     Upper,
     Length,
+    Trim,
+    Substr,
+    Replace,
+    Concat,
 
     Min,
     Max,
@@ -22,6 +26,16 @@ pub enum Function {
     Day,
     Month,
     Year,
+
+    Contains,
+    Matches,
+
+    IsNewerThan,
+    IsOlderThan,
+    SameFileAs,
+
+    Xattr,
+    Capture,
 }
 
 impl FromStr for Function {
@@ -34,6 +48,10 @@ impl FromStr for Function {
             "lower" => Ok(Function::Lower),
             "upper" => Ok(Function::Upper),
             "length" => Ok(Function::Length),
+            "trim" => Ok(Function::Trim),
+            "substr" | "substring" => Ok(Function::Substr),
+            "replace" => Ok(Function::Replace),
+            "concat" => Ok(Function::Concat),
 
             "day" => Ok(Function::Day),
             "month" => Ok(Function::Month),
@@ -45,6 +63,16 @@ impl FromStr for Function {
             "sum" => Ok(Function::Sum),
             "count" => Ok(Function::Count),
 
+            "contains" => Ok(Function::Contains),
+            "matches" => Ok(Function::Matches),
+
+            "is_newer_than" => Ok(Function::IsNewerThan),
+            "is_older_than" => Ok(Function::IsOlderThan),
+            "same_file_as" => Ok(Function::SameFileAs),
+
+            "xattr" => Ok(Function::Xattr),
+            "capture" => Ok(Function::Capture),
+
             _ => {
                 let err = String::from("Unknown function ") + &function;
                 Err(err)
@@ -76,4 +104,30 @@ impl Function {
             _ => false
         }
     }
-}
\ No newline at end of file
+
+    /// Minimum and maximum number of arguments, `None` means any number of them.
+    pub fn get_arg_count_range(&self) -> (usize, Option<usize>) {
+        match self {
+            Function::Trim => (1, Some(1)),
+            Function::Substr => (2, Some(3)),
+            Function::Replace => (3, Some(3)),
+            Function::Concat => (1, None),
+            _ => (0, Some(1))
+        }
+    }
+
+    pub fn is_content_function(&self) -> bool {
+        match self {
+            Function::Contains | Function::Matches => true,
+            _ => false
+        }
+    }
+
+    /// Conditions comparing the file with a marker file, either its modification time or whether it's the very same file.
+    pub fn is_marker_function(&self) -> bool {
+        match self {
+            Function::IsNewerThan | Function::IsOlderThan | Function::SameFileAs => true,
+            _ => false
+        }
+    }
+}
@@ -1,11 +1,15 @@
+use std::env;
 use std::fs::File;
 use std::ops::Add;
 use std::ops::Index;
 use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
 
 use regex::Captures;
 use regex::Error;
 use regex::Regex;
+use regex::escape;
 
 #[derive(Clone, Debug)]
 pub struct GitignoreFilter {
@@ -22,31 +26,37 @@ impl GitignoreFilter {
     }
 }
 
-pub fn matches_gitignore_filter(gitignore_filters: &Option<Vec<GitignoreFilter>>, file_name: &str, is_dir: bool) -> bool {
-    match gitignore_filters {
-        Some(gitignore_filters) => {
-            let mut matched = false;
+/// Name of the ignore file honored in every traversed directory, unless the root sets another one with `ignorefile`.
+pub const DEFAULT_IGNORE_FILE: &'static str = ".fselectignore";
 
-            for gitignore_filter in gitignore_filters {
-                if gitignore_filter.only_dir && !is_dir {
-                    continue;
-                }
+/// Kind of the file the filters of a directory were read from.
+/// Within the same directory the filters of `.gitignore` go first, so the ignore file can override them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum IgnoreSource {
+    Gitignore,
+    IgnoreFile(String),
+}
 
-                let is_match = gitignore_filter.regex.is_match(file_name);
+/// Filters of the ignore files applied in a directory, a list per file. The lists are shared
+/// by all the directories below instead of being copied for every one of them.
+pub type GitignoreFilters = Vec<Rc<Vec<GitignoreFilter>>>;
 
-                if is_match && gitignore_filter.negate {
-                    return false;
-                }
+/// The filters are expected to be ordered from the least specific to the most specific one,
+/// the last matching filter decides whether the file is ignored or re-included with `!`.
+pub fn matches_gitignore_filter(gitignore_filters: &GitignoreFilters, file_name: &str, is_dir: bool) -> bool {
+    let mut matched = false;
 
-                if is_match {
-                    matched = true;
-                }
-            }
+    for gitignore_filter in gitignore_filters.iter().flat_map(|filters| filters.iter()) {
+        if gitignore_filter.only_dir && !is_dir {
+            continue;
+        }
 
-            matched
-        },
-        _ => false
+        if gitignore_filter.regex.is_match(file_name) {
+            matched = !gitignore_filter.negate;
+        }
     }
+
+    matched
 }
 
 pub fn parse_gitignore(file_path: &Path, dir_path: &Path) -> Vec<GitignoreFilter> {
@@ -70,6 +80,57 @@ pub fn parse_gitignore(file_path: &Path, dir_path: &Path) -> Vec<GitignoreFilter
     result
 }
 
+/// Parses an ignore file other than `.gitignore`, no repository specific rules are added.
+pub fn parse_ignore_file(file_path: &Path, dir_path: &Path) -> Vec<GitignoreFilter> {
+    parse_file(file_path, dir_path)
+}
+
+/// Filters from the user-wide `~/.config/fselect/ignore`, its patterns match in any directory.
+pub fn parse_global_ignore() -> Vec<GitignoreFilter> {
+    match global_ignore_file() {
+        Some(ref file_path) if file_path.is_file() => parse_file(file_path, Path::new("")),
+        _ => vec![]
+    }
+}
+
+fn global_ignore_file() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(ref dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+            PathBuf::from(home).join(".config")
+        }
+    };
+
+    Some(config_dir.join("fselect").join("ignore"))
+}
+
+/// Collects filters from `.gitignore` files found in the directories above `dir`,
+/// stopping at the repository root (the directory containing `.git`) or the filesystem root.
+/// `dir` is expected to be an absolute path.
+pub fn parse_parent_gitignores(dir: &Path) -> Vec<(PathBuf, Vec<GitignoreFilter>)> {
+    let mut result = vec![];
+
+    if dir.join(".git").is_dir() {
+        return result;
+    }
+
+    let mut path = dir.to_path_buf();
+
+    while path.pop() {
+        let gitignore_file = path.join(".gitignore");
+        if gitignore_file.is_file() {
+            result.push((path.clone(), parse_gitignore(&gitignore_file, &path)));
+        }
+
+        if path.join(".git").is_dir() {
+            break;
+        }
+    }
+
+    result
+}
+
 fn parse_file(file_path: &Path, dir_path: &Path) -> Vec<GitignoreFilter> {
     let mut result = vec![];
 
@@ -137,7 +198,7 @@ fn convert_gitignore_glob(glob: &str, file_path: &Path) -> Result<Regex, Error>
         }.to_string()
     }).to_string();
 
-    pattern = file_path.to_string_lossy().to_string().add("/([^/]+/)*").add(&pattern);
+    pattern = escape(&file_path.to_string_lossy()).add("/([^/]+/)*").add(&pattern);
 
     Regex::new(&pattern)
 }
@@ -145,6 +206,9 @@ fn convert_gitignore_glob(glob: &str, file_path: &Path) -> Result<Regex, Error>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
+    use std::io::Write;
+    use util::TempDir;
 
     #[test]
     fn test_simple_pattern() {
@@ -199,4 +263,65 @@ mod tests {
         assert_eq!(filter.only_dir, false);
         assert_eq!(filter.negate, true);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_special_chars_in_dir() {
+        let file_path = Path::new("/home/user/projects/c++");
+        let glob = "foo";
+
+        let result = convert_gitignore_pattern(glob, file_path);
+
+        assert_eq!(result[0].regex.as_str(), "/home/user/projects/c\\+\\+/([^/]+/)*foo");
+    }
+
+    #[test]
+    fn test_last_match_wins() {
+        let dir = Path::new("/home/user/projects/testprj");
+        let sub = dir.join("sub");
+
+        let mut filters = convert_gitignore_pattern("*.log", dir);
+        filters.append(&mut convert_gitignore_pattern("!keep.log", dir));
+        let filters = vec![Rc::new(filters)];
+
+        assert!(matches_gitignore_filter(&filters, &dir.join("debug.log").to_string_lossy(), false));
+        assert!(!matches_gitignore_filter(&filters, &dir.join("keep.log").to_string_lossy(), false));
+        assert!(!matches_gitignore_filter(&filters, &sub.join("keep.log").to_string_lossy(), false));
+
+        let mut filters = filters;
+        filters.push(Rc::new(convert_gitignore_pattern("keep.log", &sub)));
+
+        assert!(!matches_gitignore_filter(&filters, &dir.join("keep.log").to_string_lossy(), false));
+        assert!(matches_gitignore_filter(&filters, &sub.join("keep.log").to_string_lossy(), false));
+    }
+
+    #[test]
+    fn test_parent_gitignores() {
+        let fixture = TempDir::new("gitignore");
+        let repo = fixture.join("repo");
+        let src = repo.join("src");
+        let nested = src.join("nested");
+
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(repo.join("target")).unwrap();
+        fs::File::create(repo.join(".gitignore")).unwrap().write_all(b"target/\n*.log\n").unwrap();
+        fs::File::create(src.join(".gitignore")).unwrap().write_all(b"generated.rs\n").unwrap();
+        fs::File::create(fixture.join(".gitignore")).unwrap().write_all(b"*\n").unwrap();
+
+        let parents = parse_parent_gitignores(&nested);
+
+        assert_eq!(parents.len(), 2);
+        assert_eq!(parents[0].0, src);
+        assert_eq!(parents[1].0, repo);
+
+        let filters: GitignoreFilters = parents.into_iter().rev().map(|(_, filters)| Rc::new(filters)).collect();
+
+        assert!(matches_gitignore_filter(&filters, &repo.join("target").to_string_lossy(), true));
+        assert!(matches_gitignore_filter(&filters, &nested.join("debug.log").to_string_lossy(), false));
+        assert!(matches_gitignore_filter(&filters, &nested.join("generated.rs").to_string_lossy(), false));
+        assert!(!matches_gitignore_filter(&filters, &nested.join("main.rs").to_string_lossy(), false));
+        assert!(!matches_gitignore_filter(&filters, &repo.join("generated.rs").to_string_lossy(), false));
+
+        assert!(parse_parent_gitignores(&repo).is_empty());
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+#[cfg(feature = "git")]
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[cfg(feature = "git")]
+use git2::Repository;
+#[cfg(feature = "git")]
+use git2::Status;
+#[cfg(feature = "git")]
+use git2::StatusOptions;
+
+pub const STATUS_CLEAN: &'static str = "clean";
+#[allow(unused)]
+pub const STATUS_MODIFIED: &'static str = "modified";
+#[allow(unused)]
+pub const STATUS_STAGED: &'static str = "staged";
+pub const STATUS_UNTRACKED: &'static str = "untracked";
+pub const STATUS_IGNORED: &'static str = "ignored";
+
+/// Status of the files of one repository by their paths relative to its working directory.
+/// Untracked and ignored directories are listed as a whole, their content has the same status.
+struct RepoStatus {
+    entries: HashMap<String, &'static str>,
+}
+
+/// Statuses of the repositories met during the search.
+/// Every repository is read once, and every directory is looked up once to find its repository.
+pub struct GitStatusCache {
+    /// Working directory of the repository and the path of the directory inside it.
+    repos_by_dir: HashMap<PathBuf, Option<(PathBuf, String)>>,
+    statuses_by_repo: HashMap<PathBuf, RepoStatus>,
+}
+
+impl GitStatusCache {
+    pub fn new() -> GitStatusCache {
+        GitStatusCache { repos_by_dir: HashMap::new(), statuses_by_repo: HashMap::new() }
+    }
+
+    /// One of `clean`, `modified`, `staged`, `untracked` or `ignored` for a file in a git working directory.
+    /// Directories have a status only when they are untracked or ignored as a whole.
+    pub fn get_status(&mut self, path: &Path, is_dir: bool) -> Option<&'static str> {
+        let dir = path.parent()?;
+        let name = path.file_name()?.to_string_lossy().to_string();
+
+        if !self.repos_by_dir.contains_key(dir) {
+            let repo = find_repo(dir);
+            if let Some((ref workdir, _)) = repo {
+                if !self.statuses_by_repo.contains_key(workdir) {
+                    let status = load_repo_status(workdir);
+                    self.statuses_by_repo.insert(workdir.clone(), status);
+                }
+            }
+            self.repos_by_dir.insert(dir.to_path_buf(), repo);
+        }
+
+        let (workdir, rel_dir) = match self.repos_by_dir.get(dir) {
+            Some(&Some(ref repo)) => repo,
+            _ => return None
+        };
+
+        let rel_path = match rel_dir.is_empty() {
+            true => name,
+            false => format!("{}/{}", rel_dir, name)
+        };
+
+        if rel_path == ".git" || rel_path.starts_with(".git/") {
+            return None;
+        }
+
+        self.statuses_by_repo.get(workdir).and_then(|repo_status| repo_status.get(&rel_path, is_dir))
+    }
+}
+
+impl RepoStatus {
+    fn get(&self, rel_path: &str, is_dir: bool) -> Option<&'static str> {
+        if let Some(status) = self.entries.get(rel_path) {
+            return Some(status);
+        }
+
+        let mut ancestor = rel_path;
+        while let Some(idx) = ancestor.rfind('/') {
+            ancestor = &ancestor[..idx];
+            if let Some(status) = self.entries.get(ancestor) {
+                if *status == STATUS_UNTRACKED || *status == STATUS_IGNORED {
+                    return Some(status);
+                }
+            }
+        }
+
+        match is_dir {
+            true => None,
+            false => Some(STATUS_CLEAN)
+        }
+    }
+}
+
+/// Working directory of the repository the directory belongs to, the canonical paths are compared.
+#[allow(unused)]
+fn find_repo(dir: &Path) -> Option<(PathBuf, String)> {
+    #[cfg(feature = "git")]
+    {
+        let repo = Repository::discover(dir).ok()?;
+        let workdir = fs::canonicalize(repo.workdir()?).ok()?;
+        let dir = fs::canonicalize(dir).ok()?;
+        let rel_dir = dir.strip_prefix(&workdir).ok()?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<String>>()
+            .join("/");
+
+        Some((workdir, rel_dir))
+    }
+
+    #[cfg(not(feature = "git"))]
+    {
+        None
+    }
+}
+
+/// Changes in the working tree take precedence over the staged ones.
+#[allow(unused)]
+fn load_repo_status(workdir: &Path) -> RepoStatus {
+    let mut entries = HashMap::new();
+
+    #[cfg(feature = "git")]
+    {
+        let mut options = StatusOptions::new();
+        options.include_untracked(true)
+            .recurse_untracked_dirs(false)
+            .include_ignored(true)
+            .recurse_ignored_dirs(false)
+            .exclude_submodules(true);
+
+        if let Ok(repo) = Repository::open(workdir) {
+            if let Ok(statuses) = repo.statuses(Some(&mut options)) {
+                for entry in statuses.iter() {
+                    if let (Some(path), Some(status)) = (entry.path(), to_status_name(entry.status())) {
+                        entries.insert(path.trim_end_matches('/').to_string(), status);
+                    }
+                }
+            }
+        }
+    }
+
+    RepoStatus { entries }
+}
+
+#[cfg(feature = "git")]
+fn to_status_name(status: Status) -> Option<&'static str> {
+    if status.contains(Status::IGNORED) {
+        Some(STATUS_IGNORED)
+    } else if status.contains(Status::WT_NEW) {
+        Some(STATUS_UNTRACKED)
+    } else if status.intersects(Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_TYPECHANGE | Status::WT_RENAMED | Status::CONFLICTED) {
+        Some(STATUS_MODIFIED)
+    } else if status.intersects(Status::INDEX_NEW | Status::INDEX_MODIFIED | Status::INDEX_DELETED | Status::INDEX_RENAMED | Status::INDEX_TYPECHANGE) {
+        Some(STATUS_STAGED)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_status() {
+        let mut entries = HashMap::new();
+        entries.insert(String::from("src/new.rs"), STATUS_UNTRACKED);
+        entries.insert(String::from("src/main.rs"), STATUS_MODIFIED);
+        entries.insert(String::from("target"), STATUS_IGNORED);
+        entries.insert(String::from("docs"), STATUS_UNTRACKED);
+        let repo_status = RepoStatus { entries };
+
+        assert_eq!(repo_status.get("src/main.rs", false), Some(STATUS_MODIFIED));
+        assert_eq!(repo_status.get("src/new.rs", false), Some(STATUS_UNTRACKED));
+        assert_eq!(repo_status.get("src/lib.rs", false), Some(STATUS_CLEAN));
+        assert_eq!(repo_status.get("src", true), None);
+        assert_eq!(repo_status.get("target", true), Some(STATUS_IGNORED));
+        assert_eq!(repo_status.get("target/debug/app", false), Some(STATUS_IGNORED));
+        assert_eq!(repo_status.get("docs/usage/index.md", false), Some(STATUS_UNTRACKED));
+    }
+}
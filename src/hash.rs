@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+use std::io;
+use std::io::Read;
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::Digest;
+use sha2::Sha256;
+
+use field::Field;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Tells which digests of the file content should be calculated.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HashKinds {
+    pub md5: bool,
+    pub sha1: bool,
+    pub sha256: bool,
+}
+
+impl HashKinds {
+    pub fn from_fields(fields: &HashSet<Field>) -> HashKinds {
+        HashKinds {
+            md5: fields.contains(&Field::Md5),
+            sha1: fields.contains(&Field::Sha1),
+            sha256: fields.contains(&Field::Sha256),
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        self.md5 || self.sha1 || self.sha256
+    }
+}
+
+/// Hex encoded digests of the file content, only the requested ones are present.
+#[derive(Clone, Debug, Default)]
+pub struct Hashes {
+    pub md5: Option<String>,
+    pub sha1: Option<String>,
+    pub sha256: Option<String>,
+}
+
+impl Hashes {
+    pub fn get(&self, field: &Field) -> Option<&String> {
+        match field {
+            Field::Md5 => self.md5.as_ref(),
+            Field::Sha1 => self.sha1.as_ref(),
+            Field::Sha256 => self.sha256.as_ref(),
+            _ => None
+        }
+    }
+}
+
+/// Streams the content through the requested hashers chunk by chunk, so the whole file is never held in memory.
+pub fn calc_hashes<R: Read>(reader: &mut R, kinds: HashKinds) -> io::Result<Hashes> {
+    let mut md5 = match kinds.md5 { true => Some(Md5::new()), false => None };
+    let mut sha1 = match kinds.sha1 { true => Some(Sha1::new()), false => None };
+    let mut sha256 = match kinds.sha256 { true => Some(Sha256::new()), false => None };
+
+    let mut buf = vec![0; BUFFER_SIZE];
+
+    loop {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err)
+        };
+
+        if let Some(ref mut hasher) = md5 {
+            hasher.input(&buf[..len]);
+        }
+
+        if let Some(ref mut hasher) = sha1 {
+            hasher.input(&buf[..len]);
+        }
+
+        if let Some(ref mut hasher) = sha256 {
+            hasher.input(&buf[..len]);
+        }
+    }
+
+    Ok(Hashes {
+        md5: md5.map(|hasher| format!("{:x}", hasher.result())),
+        sha1: sha1.map(|hasher| format!("{:x}", hasher.result())),
+        sha256: sha256.map(|hasher| format!("{:x}", hasher.result())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calc_hashes() {
+        let kinds = HashKinds { md5: true, sha1: true, sha256: true };
+        let hashes = calc_hashes(&mut "abc".as_bytes(), kinds).unwrap();
+
+        assert_eq!(hashes.get(&Field::Md5).unwrap(), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(hashes.get(&Field::Sha1).unwrap(), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(hashes.get(&Field::Sha256).unwrap(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn test_calc_requested_hashes_only() {
+        let kinds = HashKinds { md5: false, sha1: false, sha256: true };
+        let content = vec![b'a'; BUFFER_SIZE * 2 + 1];
+        let hashes = calc_hashes(&mut &content[..], kinds).unwrap();
+
+        assert!(hashes.md5.is_none());
+        assert!(hashes.sha1.is_none());
+        assert_eq!(hashes.sha256.unwrap().len(), 64);
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::File;
+use std::fs::Metadata;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+#[cfg(windows)]
+use std::os::windows::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+const INDEX_MAGIC: &'static [u8] = b"FSELIDX";
+/// Index files of another version are ignored and built anew.
+const INDEX_VERSION: u32 = 1;
+
+/// Directory listings with the metadata of their entries, kept on disk between the searches.
+/// A directory is listed again only when its modification time has changed,
+/// so changes of files which leave their directory untouched, like appending to a file, go unnoticed.
+pub struct Index {
+    dirs: HashMap<PathBuf, IndexedDir>,
+    /// Directories listed during this search
+    visited: HashSet<PathBuf>,
+    changed: bool,
+}
+
+#[derive(Debug, PartialEq)]
+struct IndexedDir {
+    modified: Timestamp,
+    entries: Vec<IndexedEntry>,
+}
+
+/// Metadata of a directory entry, symbolic links are not followed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexedEntry {
+    pub name: OsString,
+    pub kind: EntryKind,
+    pub size: u64,
+    modified: Timestamp,
+    /// Permission bits on Unix, file attributes on Windows
+    pub mode: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+/// Seconds and nanoseconds since the Unix epoch, negative for earlier times.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Timestamp(i64, u32);
+
+impl IndexedEntry {
+    fn new(name: OsString, metadata: &Metadata) -> IndexedEntry {
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_symlink() {
+            EntryKind::Symlink
+        } else if file_type.is_dir() {
+            EntryKind::Dir
+        } else if file_type.is_file() {
+            EntryKind::File
+        } else {
+            EntryKind::Other
+        };
+
+        IndexedEntry {
+            name,
+            kind,
+            size: metadata.len(),
+            modified: metadata.modified().map(Timestamp::from).unwrap_or(Timestamp(0, 0)),
+            mode: get_raw_mode(metadata),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Dir
+    }
+
+    pub fn modified(&self) -> SystemTime {
+        self.modified.to_system_time()
+    }
+}
+
+impl Timestamp {
+    fn from(time: SystemTime) -> Timestamp {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => Timestamp(duration.as_secs() as i64, duration.subsec_nanos()),
+            Err(err) => {
+                let duration = err.duration();
+                match duration.subsec_nanos() {
+                    0 => Timestamp(-(duration.as_secs() as i64), 0),
+                    nanos => Timestamp(-(duration.as_secs() as i64) - 1, 1_000_000_000 - nanos)
+                }
+            }
+        }
+    }
+
+    fn to_system_time(&self) -> SystemTime {
+        match self.0 >= 0 {
+            true => UNIX_EPOCH + Duration::new(self.0 as u64, self.1),
+            false => UNIX_EPOCH - Duration::from_secs((-self.0) as u64) + Duration::new(0, self.1)
+        }
+    }
+}
+
+impl Index {
+    /// Index file that doesn't exist, is damaged or has another version gives an empty index.
+    pub fn load(path: &Path) -> Index {
+        let dirs = File::open(path)
+            .and_then(|file| read_dirs(&mut BufReader::new(file)))
+            .unwrap_or_default();
+
+        Index {
+            dirs,
+            visited: HashSet::new(),
+            changed: false,
+        }
+    }
+
+    /// Directories which were searched, but weren't met this time, are gone and dropped from the index.
+    /// The file is replaced only when it's written completely.
+    pub fn save(&mut self, path: &Path, roots: &[PathBuf]) -> io::Result<()> {
+        let visited = &self.visited;
+        let dirs_count = self.dirs.len();
+        self.dirs.retain(|dir, _| visited.contains(dir) || !roots.iter().any(|root| dir.starts_with(root)));
+
+        if !self.changed && self.dirs.len() == dirs_count {
+            return Ok(());
+        }
+
+        let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, process::id()));
+
+        let result = File::create(&temp_path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write_dirs(&mut writer, &self.dirs)?;
+            writer.flush()
+        }).and_then(|_| fs::rename(&temp_path, path));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+
+        result
+    }
+
+    /// Entries of the directory ordered by name.
+    /// The directory is listed and its entries are examined only if it has changed since it was indexed.
+    pub fn list_dir(&mut self, dir: &Path) -> io::Result<Vec<IndexedEntry>> {
+        let modified = Timestamp::from(fs::metadata(dir)?.modified()?);
+        self.visited.insert(dir.to_path_buf());
+
+        if let Some(indexed_dir) = self.dirs.get(dir) {
+            if indexed_dir.modified == modified {
+                return Ok(indexed_dir.entries.clone());
+            }
+        }
+
+        let mut entries = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            // the entry may be gone already
+            if let Ok(metadata) = entry.metadata() {
+                entries.push(IndexedEntry::new(entry.file_name(), &metadata));
+            }
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        self.dirs.insert(dir.to_path_buf(), IndexedDir { modified, entries: entries.clone() });
+        self.changed = true;
+
+        Ok(entries)
+    }
+}
+
+#[cfg(unix)]
+fn get_raw_mode(metadata: &Metadata) -> u32 {
+    metadata.mode()
+}
+
+#[cfg(windows)]
+fn get_raw_mode(metadata: &Metadata) -> u32 {
+    metadata.file_attributes()
+}
+
+fn write_dirs<W: Write>(writer: &mut W, dirs: &HashMap<PathBuf, IndexedDir>) -> io::Result<()> {
+    writer.write_all(INDEX_MAGIC)?;
+    write_u32(writer, INDEX_VERSION)?;
+    write_u64(writer, dirs.len() as u64)?;
+
+    for (path, dir) in dirs {
+        write_os_str(writer, path.as_os_str())?;
+        write_timestamp(writer, dir.modified)?;
+        write_u64(writer, dir.entries.len() as u64)?;
+
+        for entry in &dir.entries {
+            write_os_str(writer, &entry.name)?;
+            writer.write_all(&[match entry.kind {
+                EntryKind::File => 0,
+                EntryKind::Dir => 1,
+                EntryKind::Symlink => 2,
+                EntryKind::Other => 3
+            }])?;
+            write_u64(writer, entry.size)?;
+            write_timestamp(writer, entry.modified)?;
+            write_u32(writer, entry.mode)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_dirs<R: Read>(reader: &mut R) -> io::Result<HashMap<PathBuf, IndexedDir>> {
+    let mut magic = vec![0; INDEX_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != INDEX_MAGIC || read_u32(reader)? != INDEX_VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not an index of this version"));
+    }
+
+    let mut dirs = HashMap::new();
+
+    for _ in 0..read_u64(reader)? {
+        let path = PathBuf::from(read_os_str(reader)?);
+        let modified = read_timestamp(reader)?;
+
+        let mut entries = vec![];
+        for _ in 0..read_u64(reader)? {
+            let name = read_os_str(reader)?;
+            let mut kind = [0];
+            reader.read_exact(&mut kind)?;
+            let kind = match kind[0] {
+                0 => EntryKind::File,
+                1 => EntryKind::Dir,
+                2 => EntryKind::Symlink,
+                _ => EntryKind::Other
+            };
+
+            entries.push(IndexedEntry {
+                name,
+                kind,
+                size: read_u64(reader)?,
+                modified: read_timestamp(reader)?,
+                mode: read_u32(reader)?,
+            });
+        }
+
+        dirs.insert(path, IndexedDir { modified, entries });
+    }
+
+    Ok(dirs)
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+/// Names are kept as they are on Unix, even if they aren't valid UTF-8.
+fn write_os_str<W: Write>(writer: &mut W, value: &OsStr) -> io::Result<()> {
+    #[cfg(unix)]
+    let bytes = value.as_bytes().to_vec();
+    #[cfg(not(unix))]
+    let bytes = value.to_string_lossy().to_string().into_bytes();
+
+    write_u64(writer, bytes.len() as u64)?;
+    writer.write_all(&bytes)
+}
+
+fn write_timestamp<W: Write>(writer: &mut W, value: Timestamp) -> io::Result<()> {
+    write_u64(writer, value.0 as u64)?;
+    write_u32(writer, value.1)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_os_str<R: Read>(reader: &mut R) -> io::Result<OsString> {
+    let len = read_u64(reader)?;
+    let mut buf = vec![];
+    reader.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated index"));
+    }
+
+    #[cfg(unix)]
+    let value = OsStr::from_bytes(&buf).to_os_string();
+    #[cfg(not(unix))]
+    let value = OsString::from(String::from_utf8(buf).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?);
+
+    Ok(value)
+}
+
+fn read_timestamp<R: Read>(reader: &mut R) -> io::Result<Timestamp> {
+    let secs = read_u64(reader)? as i64;
+    let nanos = read_u32(reader)?;
+    Ok(Timestamp(secs, nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::TempDir;
+
+    #[test]
+    fn test_timestamp_round_trip() {
+        for time in &[UNIX_EPOCH, UNIX_EPOCH + Duration::new(1_600_000_000, 123), UNIX_EPOCH - Duration::new(100, 250)] {
+            assert_eq!(Timestamp::from(*time).to_system_time(), *time);
+        }
+        assert_eq!(Timestamp::from(UNIX_EPOCH - Duration::new(100, 250)), Timestamp(-101, 999_999_750));
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let mut dirs = HashMap::new();
+        dirs.insert(PathBuf::from("/home/user"), IndexedDir {
+            modified: Timestamp(1_600_000_000, 5),
+            entries: vec![
+                IndexedEntry { name: OsString::from("docs"), kind: EntryKind::Dir, size: 4096, modified: Timestamp(1_500_000_000, 0), mode: 0o40755 },
+                IndexedEntry { name: OsString::from("ünïcödé.txt"), kind: EntryKind::File, size: 12, modified: Timestamp(-5, 1), mode: 0o100644 },
+            ],
+        });
+
+        let mut buf = vec![];
+        write_dirs(&mut buf, &dirs).unwrap();
+        assert_eq!(read_dirs(&mut &buf[..]).unwrap(), dirs);
+
+        // a truncated index or an index of another version is not read at all
+        assert!(read_dirs(&mut &buf[..buf.len() - 1]).is_err());
+        buf[INDEX_MAGIC.len()] += 1;
+        assert!(read_dirs(&mut &buf[..]).is_err());
+    }
+
+    #[test]
+    fn test_changed_dirs_listed_again() {
+        let fixture = TempDir::new("index");
+        fs::create_dir_all(fixture.join("sub")).unwrap();
+        fs::write(fixture.join("b.txt"), "bb").unwrap();
+        fs::write(fixture.join("a.txt"), "a").unwrap();
+
+        let index_path = fixture.join("sub").join("test.idx");
+        let mut index = Index::load(&index_path);
+        let names: Vec<OsString> = index.list_dir(&fixture).unwrap().into_iter().map(|entry| entry.name).collect();
+        assert_eq!(names, vec!["a.txt", "b.txt", "sub"]);
+        index.save(&index_path, &[fixture.to_path_buf()]).unwrap();
+
+        // the unchanged directory is served from the index, even if its files have changed
+        let mut index = Index::load(&index_path);
+        fs::write(fixture.join("a.txt"), "aaa").unwrap();
+        let entries = index.list_dir(&fixture).unwrap();
+        assert_eq!(entries[0].size, 1);
+        assert!(entries[2].is_dir());
+        assert!(!index.changed);
+
+        fs::remove_file(fixture.join("b.txt")).unwrap();
+        fs::write(fixture.join("c.txt"), "c").unwrap();
+        // the modification time of the directory may have a coarse resolution
+        let mut indexed_dir = index.dirs.get_mut(&*fixture).unwrap();
+        indexed_dir.modified = Timestamp(0, 0);
+        let entries = index.list_dir(&fixture).unwrap();
+        let names: Vec<&OsStr> = entries.iter().map(|entry| entry.name.as_os_str()).collect();
+        assert_eq!(names, vec!["a.txt", "c.txt", "sub"]);
+        assert_eq!(entries[0].size, 3);
+        assert!(index.changed);
+    }
+}
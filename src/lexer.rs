@@ -1,6 +1,7 @@
 #[derive(Clone, PartialEq, Debug)]
 pub enum Lexem {
     RawString(String),
+    Distinct,
     Comma,
     From,
     Where,
@@ -11,11 +12,17 @@ pub enum Lexem {
     ArithmeticOperator(String),
     And,
     Or,
+    Not,
+    Between,
+    In,
     Order,
     By,
     DescendingOrder,
     Limit,
+    Offset,
     Into,
+    Union,
+    DateFormat,
 }
 
 #[derive(Debug)]
@@ -32,17 +39,32 @@ enum LexingMode {
 
 pub struct Lexer<'a> {
     input: &'a str,
-    index: usize
+    index: usize,
+    lexem_start: usize,
+    last_lexem: Option<Lexem>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &str) -> Lexer {
-        return Lexer { input, index: 0 }
+        return Lexer { input, index: 0, lexem_start: 0, last_lexem: None }
+    }
+
+    /// Character offsets of the start and the end of the last lexem read.
+    pub fn last_span(&self) -> (usize, usize) {
+        (self.lexem_start, self.index)
     }
 
     pub fn next_lexem(&mut self) -> Option<Lexem> {
+        let lexem = self.read_lexem();
+        self.last_lexem = lexem.clone();
+
+        lexem
+    }
+
+    fn read_lexem(&mut self) -> Option<Lexem> {
         let mut s = String::new();
         let mut mode = LexingMode::Undefined;
+        let mut quote = '\'';
         let mut escape_next = false;
 
         for c in self.input.chars().skip(self.index) {
@@ -52,10 +74,18 @@ impl<'a> Lexer<'a> {
                 },
                 LexingMode::String => {
                     self.index += 1;
-                    if c == '\'' {
-                        break
+                    if !escape_next {
+                        // only the quote is escaped, so Windows paths keep their backslashes
+                        if c == '\\' && self.is_escaped_quote(quote) {
+                            escape_next = true;
+                            continue;
+                        }
+                        if c == quote {
+                            break
+                        }
                     }
                     s.push(c);
+                    escape_next = false;
                 },
                 LexingMode::Operator => {
                     if !is_op_char(c) {
@@ -76,12 +106,17 @@ impl<'a> Lexer<'a> {
                 LexingMode::RawString => {
                     if !escape_next {
                         //Detect the escape character ... ignore it ... push pointer over and continue
-                        if c == '\\' {
+                        //Backslashes in front of ordinary characters and after a drive letter are kept, e.g. in C:\Users or C:\
+                        if c == '\\' && self.next_char_is_special() && !is_drive(&s) {
                             escape_next = true;
                             self.index += 1;
                             continue;
                         }
-                        if c == ' ' || c == ',' || c == '(' || c == ')' || is_op_char(c) || is_arithmetic_op_char(c) {
+                        // keep unquoted dates like 2017-05-01 in one piece
+                        let date_separator = c == '-' && is_date_part(&s) && self.next_char_is_digit();
+                        // commas of a brace pattern like /data/{web,api}/logs don't separate the roots
+                        let brace_comma = c == ',' && has_open_brace(&s);
+                        if c == ' ' || (c == ',' && !brace_comma) || c == '(' || c == ')' || is_op_char(c) || (is_arithmetic_op_char(c) && !date_separator) {
                             break
                         }
                     }
@@ -91,13 +126,23 @@ impl<'a> Lexer<'a> {
                     escape_next = false;
                 },
                 LexingMode::Undefined => {
+                    if c != ' ' {
+                        self.lexem_start = self.index;
+                    }
                     self.index += 1;
                     match c {
                         ' ' => {},
+                        '*' | '/' if self.is_standalone_arithmetic_op() => {
+                            mode = LexingMode::ArithmeticOperator;
+                            s.push(c);
+                        },
                         '/' => {
                             s.push(c);
                         },
-                        '\'' => mode = LexingMode::String,
+                        '\'' | '"' => {
+                            quote = c;
+                            mode = LexingMode::String;
+                        },
                         ',' => mode = LexingMode::Comma,
                         '(' => mode = LexingMode::Open,
                         ')' => mode = LexingMode::Close,
@@ -129,14 +174,21 @@ impl<'a> Lexer<'a> {
                     "where" => Some(Lexem::Where),
                     "or" => Some(Lexem::Or),
                     "and" => Some(Lexem::And),
+                    "distinct" => Some(Lexem::Distinct),
+                    "not" => Some(Lexem::Not),
+                    "between" => Some(Lexem::Between),
+                    "in" => Some(Lexem::In),
                     "order" => Some(Lexem::Order),
                     "by" => Some(Lexem::By),
                     "asc" => self.next_lexem(),
                     "desc" => Some(Lexem::DescendingOrder),
                     "limit" => Some(Lexem::Limit),
+                    "offset" => Some(Lexem::Offset),
                     "into" => Some(Lexem::Into),
+                    "union" => Some(Lexem::Union),
+                    "dateformat" => Some(Lexem::DateFormat),
                     "eq" | "ne" | "gt" | "lt" | "ge" | "le" | "gte" | "lte" |
-                    "regexp" | "rx" | "like" => Some(Lexem::Operator(s)),
+                    "regexp" | "rx" | "like" | "glob" | "eqi" | "nei" | "has" => Some(Lexem::Operator(s)),
                     "mul" | "div" | "plus" | "minus" => Some(Lexem::ArithmeticOperator(s)),
                     _ => Some(Lexem::RawString(s)),
                 }
@@ -144,6 +196,66 @@ impl<'a> Lexer<'a> {
             _ => None
         }
     }
+
+    /// `*` and `/` are operators only when they follow an operand and are followed by a space,
+    /// otherwise they are a part of a glob or a path, e.g. `select *` or `from /home`.
+    fn is_standalone_arithmetic_op(&self) -> bool {
+        let follows_operand = match self.last_lexem {
+            Some(Lexem::RawString(ref s)) => s.to_ascii_lowercase() != "select",
+            Some(Lexem::String(_)) | Some(Lexem::Close) => true,
+            _ => false
+        };
+
+        let followed_by_space = match self.input.chars().nth(self.index) {
+            Some(c) => c == ' ' || c == '(',
+            None => true
+        };
+
+        follows_operand && followed_by_space
+    }
+
+    /// A backslash followed by the quote escapes it, unless the quote ends the value,
+    /// as in `'C:\'` or `'C:\', D:\`.
+    fn is_escaped_quote(&self, quote: char) -> bool {
+        let mut chars = self.input.chars().skip(self.index);
+
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c == quote => false,
+            (Some(c), Some(next)) if c == quote => !(next == ' ' || next == ',' || next == ')'),
+            _ => false
+        }
+    }
+
+    fn next_char_is_special(&self) -> bool {
+        match self.input.chars().nth(self.index + 1) {
+            Some(c) => c == ' ' || c == ',' || c == '(' || c == ')' || c == '\'' || c == '"' || is_op_char(c) || is_arithmetic_op_char(c),
+            None => false
+        }
+    }
+
+    fn next_char_is_digit(&self) -> bool {
+        match self.input.chars().nth(self.index + 1) {
+            Some(c) => c.is_ascii_digit(),
+            None => false
+        }
+    }
+}
+
+fn is_drive(s: &str) -> bool {
+    let mut chars = s.chars();
+
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(letter), Some(':'), None) => letter.is_ascii_alphabetic(),
+        _ => false
+    }
+}
+
+fn has_open_brace(s: &str) -> bool {
+    s.matches('{').count() > s.matches('}').count()
+}
+
+fn is_date_part(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit() || c == '-')
 }
 
 fn is_op_char(c: char) -> bool {
@@ -222,6 +334,99 @@ mod tests {
         assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("50"))));
     }
 
+    #[test]
+    fn root_patterns() {
+        let mut lexer = Lexer::new("from /data/*/logs, /srv/{web,api}/log depth 2, /tmp where");
+
+        assert_eq!(lexer.next_lexem(), Some(Lexem::From));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("/data/*/logs"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Comma));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("/srv/{web,api}/log"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("depth"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("2"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Comma));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("/tmp"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Where));
+    }
+
+    #[test]
+    fn not() {
+        let mut lexer = Lexer::new("where not (name like '%.tmp' or size > 1m) and NOT is_dir = true");
+
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Where));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Not));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Open));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("name"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Operator(String::from("like"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::String(String::from("%.tmp"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Or));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("size"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Operator(String::from(">"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("1m"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Close));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::And));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Not));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("is_dir"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Operator(String::from("="))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("true"))));
+    }
+
+    #[test]
+    fn between_and_in() {
+        let mut lexer = Lexer::new("where modified between 2023-01-01 and 2023-06-30 and ext not in ('jpg', png) and width-10 > 0");
+
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Where));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("modified"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Between));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("2023-01-01"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::And));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("2023-06-30"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::And));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("ext"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Not));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::In));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Open));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::String(String::from("jpg"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Comma));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("png"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Close));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::And));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("width"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::ArithmeticOperator(String::from("-"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("10"))));
+    }
+
+    #[test]
+    fn quoted_strings() {
+        let mut lexer = Lexer::new(r#"from 'a b', "c,d" where name = 'don\'t' or name = 100\%"#);
+
+        assert_eq!(lexer.next_lexem(), Some(Lexem::From));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::String(String::from("a b"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Comma));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::String(String::from("c,d"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Where));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("name"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Operator(String::from("="))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::String(String::from("don't"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Or));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("name"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Operator(String::from("="))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from(r"100\%"))));
+        assert_eq!(lexer.next_lexem(), None);
+    }
+
+    #[test]
+    fn spans() {
+        let mut lexer = Lexer::new("name,  size from 'a b' where size>=10 order by name asc");
+
+        let mut spans = vec![];
+        while let Some(_) = lexer.next_lexem() {
+            spans.push(lexer.last_span());
+        }
+
+        assert_eq!(spans, vec![(0, 4), (4, 5), (7, 11), (12, 16), (17, 22), (23, 28), (29, 33), (33, 35), (35, 37), (38, 43), (44, 46), (47, 51)]);
+    }
+
     #[test]
     fn spaces() {
         let lexer = Lexer::new("path,size from . where size=0");
@@ -299,4 +504,57 @@ mod tests {
         assert_eq!(lexer.next_lexem(), Some(Lexem::From));
         assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("."))));
     }
+
+    #[test]
+    fn multiplication_and_division() {
+        let mut lexer = Lexer::new("select *, size / 1m, (size + 1) * 2 from /home/user where name = *.txt and size * 2 > 1g");
+
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("select"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("*"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Comma));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("size"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::ArithmeticOperator(String::from("/"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("1m"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Comma));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Open));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("size"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::ArithmeticOperator(String::from("+"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("1"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Close));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::ArithmeticOperator(String::from("*"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("2"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::From));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("/home/user"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Where));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("name"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Operator(String::from("="))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("*.txt"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::And));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("size"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::ArithmeticOperator(String::from("*"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("2"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Operator(String::from(">"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("1g"))));
+    }
+
+    #[test]
+    fn float_literals() {
+        let mut lexer = Lexer::new("where aspect_ratio != 1.778 and width * 1.5 > height and ratio <= 0.75");
+
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Where));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("aspect_ratio"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Operator(String::from("!="))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("1.778"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::And));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("width"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::ArithmeticOperator(String::from("*"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("1.5"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Operator(String::from(">"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("height"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::And));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("ratio"))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::Operator(String::from("<="))));
+        assert_eq!(lexer.next_lexem(), Some(Lexem::RawString(String::from("0.75"))));
+        assert_eq!(lexer.next_lexem(), None);
+    }
 }
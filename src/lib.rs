@@ -0,0 +1,80 @@
+//! Find files with SQL-like queries.
+//!
+//! The query is parsed into one or more `Query` values, the first one goes to `Searcher`,
+//! the rest are the parts of a `union`. `Searcher::search` hands the rows over as `Record`s as soon as they are found,
+//! `RecordWriter` prints them in the format the query asks for, like the `fselect` utility does.
+
+extern crate atty;
+extern crate bzip2;
+extern crate chrono;
+extern crate chrono_english;
+extern crate csv;
+extern crate flate2;
+#[cfg(feature = "git")]
+extern crate git2;
+extern crate humansize;
+extern crate imagesize;
+#[macro_use]
+extern crate lazy_static;
+#[cfg(unix)]
+extern crate libc;
+extern crate md5;
+extern crate mp3_metadata;
+extern crate regex;
+extern crate rusqlite;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate sha1;
+extern crate sha2;
+extern crate tar;
+extern crate term;
+extern crate term_size;
+extern crate time;
+#[cfg(unix)]
+extern crate users;
+#[cfg(windows)]
+extern crate winapi;
+#[cfg(unix)]
+extern crate xattr;
+extern crate zip;
+
+mod dupes;
+mod field;
+mod fileinfo;
+mod function;
+mod gitignore;
+mod gitstatus;
+mod hash;
+mod index;
+mod lexer;
+mod mimetype;
+mod mode;
+mod mp3;
+mod output;
+mod owners;
+mod parser;
+mod progress;
+mod record;
+mod report;
+mod searcher;
+mod sqlite;
+mod table;
+mod template;
+mod util;
+mod video;
+mod writer;
+
+pub use output::is_broken_pipe;
+pub use parser::ParseError;
+pub use parser::Parser;
+pub use parser::Query;
+pub use progress::install_interrupt_handler;
+pub use record::Record;
+pub use report::ErrorReporter;
+pub use report::StderrReporter;
+pub use searcher::Searcher;
+pub use util::error_message;
+pub use util::parse_filesize;
+pub use writer::RecordWriter;
@@ -1,41 +1,38 @@
-extern crate chrono;
-extern crate chrono_english;
-extern crate csv;
+extern crate fselect;
 extern crate humansize;
-extern crate imagesize;
-#[macro_use]
-extern crate lazy_static;
-extern crate mp3_metadata;
-extern crate regex;
-extern crate serde;
-#[macro_use]
-extern crate serde_derive;
-extern crate serde_json;
 extern crate term;
-extern crate time;
-#[cfg(unix)]
-extern crate users;
-#[cfg(unix)]
-extern crate xattr;
-extern crate zip;
 
 use std::env;
-
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use std::time::Instant;
+
+use humansize::{FileSize, file_size_opts};
 use term::StdoutTerminal;
 
-mod field;
-mod fileinfo;
-mod function;
-mod gitignore;
-mod lexer;
-mod mode;
-mod parser;
-mod searcher;
-mod util;
-
-use parser::Parser;
-use searcher::Searcher;
-use util::error_message;
+use fselect::error_message;
+use fselect::install_interrupt_handler;
+use fselect::is_broken_pipe;
+use fselect::parse_filesize;
+use fselect::Parser;
+use fselect::RecordWriter;
+use fselect::Searcher;
+
+/// Some files or directories could not be read, the results are incomplete.
+const EXIT_SEARCH_ERROR: i32 = 1;
+/// Nothing matched the query run with `--exists`.
+const EXIT_NOT_FOUND: i32 = 1;
+/// The query could not be parsed, nothing was searched.
+const EXIT_QUERY_ERROR: i32 = 2;
+/// The search was stopped with Ctrl-C, only the results found so far were output.
+const EXIT_INTERRUPTED: i32 = 130;
+
+const SORT_ROWS_ENV_VAR: &'static str = "FSELECT_SORT_ROWS";
+/// A size like `1g`.
+const SORT_MEMORY_ENV_VAR: &'static str = "FSELECT_SORT_MEMORY";
 
 fn main() {
     let mut t = term::stdout().unwrap();
@@ -55,20 +52,171 @@ fn main() {
         return;
     }
 
+    let mut quiet = false;
+    let mut index_path = None;
+    let mut exists = false;
+    let mut count_only = false;
+
+    // options go before the query in any order
+    loop {
+        match args.first().map(|arg| arg.to_ascii_lowercase()) {
+            Some(ref arg) if arg == "-q" || arg == "--quiet" => {
+                quiet = true;
+                args.remove(0);
+            },
+            Some(ref arg) if arg == "--exists" => {
+                exists = true;
+                args.remove(0);
+            },
+            Some(ref arg) if arg == "--count-only" => {
+                count_only = true;
+                args.remove(0);
+            },
+            Some(ref arg) if arg == "--index" => {
+                args.remove(0);
+                if args.is_empty() {
+                    error_message("index", "expected path to the index file after --index");
+                    process::exit(EXIT_QUERY_ERROR);
+                }
+                index_path = Some(PathBuf::from(args.remove(0)));
+            },
+            _ => break
+        }
+    }
+
+    if args.is_empty() {
+        short_usage_info(&mut t);
+        help_hint();
+        return;
+    }
+
     let query = args.join(" ");
 
     let mut p = Parser::new();
-    let query = p.parse(&query);
+    let parsed_query = p.parse(&query);
 
-    match query {
+    match parsed_query {
         Ok(query) => {
-            let mut searcher = Searcher::new(query);
-            searcher.list_search_results(&mut t).unwrap()
+            if query.explain {
+                print!("{}", query);
+                for query in &query.unions {
+                    println!("union");
+                    print!("{}", query);
+                }
+                return;
+            }
+
+            install_interrupt_handler();
+
+            let mut searcher = Searcher::new(query.clone());
+            searcher.set_quiet(quiet);
+            if let Some(ref index_path) = index_path {
+                searcher.set_index(index_path);
+            }
+            if let Some(max_rows) = env::var(SORT_ROWS_ENV_VAR).ok().and_then(|val| val.trim().parse().ok()).filter(|&max_rows| max_rows > 0) {
+                searcher.set_sort_max_rows(max_rows);
+            }
+            if let Some(max_bytes) = env::var(SORT_MEMORY_ENV_VAR).ok().and_then(|val| parse_filesize(&val)).filter(|&max_bytes| max_bytes > 0) {
+                searcher.set_sort_max_memory(max_bytes);
+            }
+
+            if exists {
+                let found = searcher.exists();
+
+                if searcher.is_interrupted() {
+                    process::exit(EXIT_INTERRUPTED);
+                }
+
+                // like `grep -q`, a match is a success even if some files could not be read
+                if !found {
+                    process::exit(EXIT_NOT_FOUND);
+                }
+
+                return;
+            }
+
+            if count_only {
+                let count = searcher.count();
+
+                if searcher.is_interrupted() {
+                    error_message("interrupted", "the search was stopped, the count is partial");
+                    process::exit(EXIT_INTERRUPTED);
+                }
+
+                if let Err(err) = writeln!(io::stdout(), "{}", count) {
+                    if !is_broken_pipe(&err) {
+                        error_message("output", &err.to_string());
+                        process::exit(EXIT_SEARCH_ERROR);
+                    }
+                }
+
+                if searcher.has_errors() {
+                    process::exit(EXIT_SEARCH_ERROR);
+                }
+
+                return;
+            }
+
+            let mut writer = match RecordWriter::new(&query) {
+                Ok(writer) => writer,
+                Err(err) => {
+                    error_message("output", &err.to_string());
+                    process::exit(EXIT_SEARCH_ERROR);
+                }
+            };
+
+            let started = Instant::now();
+            searcher.search(&mut |record| writer.write(&record));
+
+            let interrupted = searcher.is_interrupted();
+            let has_errors = searcher.has_errors();
+            let (matched, shown, matched_size) = (searcher.matched(), searcher.shown(), searcher.matched_size());
+            // `process::exit` skips the destructors, the temporary files of the ordered rows are removed here
+            drop(searcher);
+
+            if let Err(err) = writer.finish(&mut t) {
+                // the reader has got all it wanted, like `fselect ... | head`
+                if is_broken_pipe(&err) {
+                    return;
+                }
+
+                error_message("output", &err.to_string());
+                process::exit(EXIT_SEARCH_ERROR);
+            }
+
+            if query.summary {
+                eprintln!("{}", format_summary(matched, shown, matched_size, started.elapsed()));
+            }
+
+            if interrupted {
+                error_message("interrupted", "the search was stopped, results are partial");
+                process::exit(EXIT_INTERRUPTED);
+            }
+
+            if has_errors {
+                process::exit(EXIT_SEARCH_ERROR);
+            }
         },
-        Err(err) => error_message("query", &err, &mut t)
+        Err(err) => {
+            error_message("query", &err.to_string());
+            eprintln!("  {}", query);
+            eprintln!("  {}^", " ".repeat(err.position));
+            process::exit(EXIT_QUERY_ERROR);
+        }
     }
 }
 
+/// Footer of the results, e.g. `matched: 3, shown: 1, total size: 1.50 KiB (1536 bytes), elapsed: 0.012s`.
+/// The matched files are counted past the limit, the shown rows are those of files or groups actually output.
+fn format_summary(matched: u32, shown: u32, matched_size: u64, elapsed: Duration) -> String {
+    format!("matched: {}, shown: {}, total size: {} ({} bytes), elapsed: {:.3}s",
+            matched,
+            shown,
+            matched_size.file_size(file_size_opts::BINARY).unwrap(),
+            matched_size,
+            elapsed.as_secs() as f64 + elapsed.subsec_millis() as f64 / 1000.0)
+}
+
 fn short_usage_info(t: &mut Box<StdoutTerminal>) {
     const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
@@ -83,7 +231,7 @@ fn short_usage_info(t: &mut Box<StdoutTerminal>) {
     println!("https://github.com/jhspetersson/fselect");
     t.reset().unwrap();
 
-    println!("Usage: fselect COLUMN[, COLUMN...] [from PATH[, PATH...]] [where EXPR] [order by COLUMN (asc|desc), ...] [limit N] [into FORMAT]");
+    println!("Usage: fselect [-q] [--index FILE] [--exists | --count-only] [explain] [distinct] COLUMN [as ALIAS][, COLUMN...] [from PATH[, PATH...]] [where EXPR] [dupes] [group by COLUMN, ...] [union QUERY] [order by COLUMN (asc|desc), ...] [limit N] [offset N] [dateformat FORMAT] [into FORMAT [FILE]] [summary] [progress]");
 }
 
 fn help_hint() {
@@ -97,7 +245,7 @@ fn usage_info(t: &mut Box<StdoutTerminal>) {
     println!("
 
 Files Detected as Audio: .aac, .aiff, .amr, .flac, .gsm, .m4a, .m4b, .m4p, .mp3, .ogg, .wav, .wma
-Files Detected as Archives: .7z, .bz2, .bzip2, .gz, .gzip, .rar, .tar, .xz, .zip
+Files Detected as Archives: .7z, .bz2, .bzip2, .gz, .gzip, .rar, .tar, .tbz2, .tgz, .xz, .zip
 Files Detected as Book: .azw3, .chm, .epub, .fb2, .mobi, .pdf
 Files Detected as Document: .accdb, .doc, .docm, .docx, .dot, .dotm, .dotx, .mdb, .ods, .odt, .pdf, .potm, .potx, .ppt, .pptm, .pptx, .rtf, .xlm, .xls, .xlsm, .xlsx, .xlt, .xltm, .xltx, .xps
 Files Detected as Image: .bmp, .gif, .jpeg, .jpg, .png, .webp
@@ -105,16 +253,37 @@ Files Detected as Source Code: .asm, .c, .cpp, .cs, .go, .h, .hpp, .java, .js, .
 Files Detected as Video: .3gp, .avi, .flv, .m4p, .m4v, .mkv, .mov, .mp4, .mpeg, .mpg, .webm, .wmv
 
 Column Options:
+        *                               Returns name, size, modified, is_dir, mode, user, group and path
         name                            Returns the name of the file
+        ext | extension                 Returns the extension of the file in lowercase, without the dot
         path                            Returns the path of the file
+        relpath                         Returns the path of the file relative to the search root
+        archive                         Returns the path of the archive holding the file, name and path leave it out then
+        depth                           Returns the depth of the file, entries right inside the search root are at depth 1
         size                            Returns the size of the file in bytes
         fsize                           Returns the size of the file accompanied with the unit
+        dirsize                         Returns the total size of the files inside the directory, recursively
+        fdirsize                        Returns the directory size accompanied with the unit
+        entries                         Returns the number of the entries directly inside the directory
+        compressed_size                 Returns the compressed size of the file inside a zip archive
+        compression_ratio               Returns the size divided by the compressed size of the file inside a zip archive
         uid                             Returns the UID of the owner
         gid                             Returns the GID of the owner's group
+        inode                           Returns the inode number of the file (Unix only)
+        device                          Returns the id of the device the file resides on (Unix only)
+        nlinks | hardlinks              Returns the number of hard links to the file (Unix only)
 
         accessed                        Returns the time the file was last accessed (YYYY-MM-DD HH:MM:SS)
-        created                         Returns the file creation date (YYYY-MM-DD HH:MM:SS)
+        created                         Returns the file creation date, the earliest of modified and changed if it's not available (YYYY-MM-DD HH:MM:SS)
+        changed | ctime                 Returns the time the file metadata was last changed, Unix only (YYYY-MM-DD HH:MM:SS)
         modified                        Returns the time the file was last modified (YYYY-MM-DD HH:MM:SS)
+        created_unix | accessed_unix | modified_unix
+                                        Returns the time as seconds since 1970-01-01 UTC
+        created_utc | accessed_utc | modified_utc
+                                        Returns the time in RFC 3339 format in UTC, e.g. 2024-03-01T09:30:00Z
+        age_days                        Returns the number of whole days since the file was last modified
+        access_age_days                 Returns the number of whole days since the file was last accessed
+        create_age_days                 Returns the number of whole days since the file was created
 
         is_dir                          Returns a boolean signifying whether the file path is a directory
         is_file                         Returns a boolean signifying whether the file path is a file
@@ -123,17 +292,24 @@ Column Options:
         is_char | is_character          Returns a boolean signifying whether the file path is a character device or character special file
         is_block                        Returns a boolean signifying whether the file path is a block or block special file
         is_socket                       Returns a boolean signifying whether the file path is a socket file
-        is_hidden                       Returns a boolean signifying whether the file is a hidden file (files that start with a dot)
+        is_empty                        Returns a boolean signifying whether the file has zero size or the directory has no entries
+        is_readonly                     Returns a boolean signifying whether the file is read-only
+        is_system                       Returns a boolean signifying whether the file has the system attribute (Windows only)
+        is_archive_bit                  Returns a boolean signifying whether the file has the archive attribute (Windows only)
+        is_hidden                       Returns a boolean signifying whether the file is a hidden file (files that start with a dot, or having the hidden flag on macOS)
         has_xattrs                      Returns a boolean signifying whether the file has extended attributes
+        xattrs                          Returns the names of the extended attributes of the file, separated with commas
 
         mode                            Returns the permissions of the owner, group, and everybody (similar to the first field in `ls -la`)
+        mode_oct                        Returns the permission bits in octal, e.g. 0644 or 4755
+        flags                           Returns the file flags separated with commas, e.g. uchg,hidden,nodump (macOS and BSD only)
 
-        user                            Returns the name of the owner for this file
+        user                            Returns the name of the owner for this file, or its UID when unknown
         user_read                       Returns a boolean signifying whether the file can be read by the owner
         user_write                      Returns a boolean signifying whether the file can be written by the owner
         user_exec                       Returns a boolean signifying whether the file can be executed by the owner
 
-        group                           Returns the name of the owner's group for this file
+        group                           Returns the name of the owner's group for this file, or its GID when unknown
         group_read                      Returns a boolean signifying whether the file can be read by the owner's group
         group_write                     Returns a boolean signifying whether the file can be written by the owner's group
         group_exec                      Returns a boolean signifying whether the file can be executed by the owner's group
@@ -142,18 +318,46 @@ Column Options:
         other_write                     Returns a boolean signifying whether the file can be written by others
         other_exec                      Returns a boolean signifying whether the file can be executed by others
 
+        is_suid | suid                  Returns a boolean signifying whether the file has the set-user-ID bit
+        is_sgid | sgid                  Returns a boolean signifying whether the file has the set-group-ID bit
+        is_sticky | sticky              Returns a boolean signifying whether the file has the sticky bit
+        is_mine | mine                  Returns a boolean signifying whether the file is owned by the current user
+        is_readable | readable          Returns a boolean signifying whether the file can be read by the current user
+        is_writable | writable          Returns a boolean signifying whether the file can be written by the current user
+        is_executable | executable      Returns a boolean signifying whether the file can be executed by the current user
+
         mp3_title | title               Returns the title of the audio file taken from the file's metadata
         mp3_album | album               Returns the album name of the audio file taken from the file's metadata
         mp3_artist | artist             Returns the artist of the audio file taken from the file's metadata
         mp3_genre | genre               Returns the genre of the audio file taken from the file's metadata
         mp3_year                        Returns the year of the audio file taken from the file's metadata
         mp3_freq | freq                 Returns the sampling rate of audio or video file
-        mp3_bitrate | bitrate           Returns the bitrate of the audio file in kbps
+        mp3_bitrate | bitrate           Returns the average bitrate of the audio file in kbps
+        mp3_min_bitrate | min_bitrate   Returns the lowest bitrate of a frame of the audio file in kbps
+        mp3_max_bitrate | max_bitrate   Returns the highest bitrate of a frame of the audio file in kbps
+        duration                        Returns the duration of the audio file in seconds
+        fduration                       Returns the duration of the audio file as HH:MM:SS
+
+        video_width                     Returns the width of the first video track of MP4 or Matroska file
+        video_height                    Returns the height of the first video track of MP4 or Matroska file
+        video_duration                  Returns the duration of MP4 or Matroska video in seconds
+        video_codec                     Returns the codec of the first video track, e.g. h264, hevc, vp9 or av1
 
         width                           Returns the number of pixels along the width of the photo
         height                          Returns the number of pixels along the height of the photo
+        aspect_ratio                    Returns the width divided by the height of the photo, rounded to three decimal places
 
         is_shebang                      Returns a boolean signifying whether the file starts with a shebang (#!)
+        shebang                         Returns the interpreter command of the script, e.g. python3 for #!/usr/bin/env python3
+        is_binary                       Returns a boolean signifying whether the file content looks binary
+        is_text                         Returns a boolean signifying whether the file content looks like text
+        line_endings                    Returns the kind of line breaks in the file: lf, crlf or mixed
+        has_bom                         Returns a boolean signifying whether the file starts with a UTF-8 or UTF-16 byte order mark
+        git_status                      Returns the git status of the file: clean, modified, staged, untracked or ignored
+        mime | mimetype                 Returns MIME type of the file detected by its content
+        md5                             Returns MD5 digest of the file content
+        sha1                            Returns SHA-1 digest of the file content
+        sha256                          Returns SHA-256 digest of the file content
         is_archive                      Returns a boolean signifying whether the file is an archival file
         is_audio                        Returns a boolean signifying whether the file is an audio file
         is_book                         Returns a boolean signifying whether the file is a book
@@ -165,7 +369,7 @@ Column Options:
 Functions:
     Aggregate:
         AVG                             Returns average of all values
-        COUNT                           Returns number of all values
+        COUNT                           Returns number of all values, count(distinct COLUMN) counts only different non-empty ones
         MAX                             Returns maximum value
         MIN                             Returns minimum value
         SUM                             Returns sum of all values
@@ -177,6 +381,19 @@ Functions:
         LENGTH                          Returns length of string value
         LOWER                           Returns lowercase value
         UPPER                           Returns uppercase value
+        TRIM                            Returns value without leading and trailing whitespace
+        SUBSTR | SUBSTRING              Returns part of the value, e.g. substr(name, 1, 10) or substr(name, 5)
+        REPLACE                         Returns value with all occurrences replaced, e.g. replace(name, ' ', '_')
+        CONCAT                          Returns all the arguments joined together, e.g. concat(name, '.bak')
+        XATTR                           Returns the value of the named extended attribute, e.g. xattr('user.comment')
+        CAPTURE                         Returns the capture group of the name or path regex matched in where, e.g. capture(1)
+    Content search (in where clause only, zip archive members are searched too):
+        CONTAINS                        Checks if the file contains the string, optional second argument limits file size (10 MB by default)
+        MATCHES                         Checks if the file content matches the regular expression, optional second argument limits file size
+    Marker files (in where clause only):
+        IS_NEWER_THAN                   Checks if the file was modified after the marker file, e.g. is_newer_than('/var/run/lastbackup.stamp')
+        IS_OLDER_THAN                   Checks if the file was modified before the marker file
+        SAME_FILE_AS                    Checks if the file is the marker file or a hard link to it
 
 Expressions:
     Operators:
@@ -190,15 +407,67 @@ Expressions:
         >= | gte                        Used to check whether the column value is greater than or equal to the value
         ~= | =~ | regexp | rx           Used to check if the column value matches the regex pattern
         like                            Used to check if the column value matches the pattern which follows SQL conventions
+        glob                            Used to check if the column value matches the glob, even if it has no * ? or [...] in it
+        eqi | nei                       Used to check for equality or inequality of text values ignoring the case of the letters
+        has                             Used to check whether the mode has all the permission bits, e.g. mode has 'o+w'
+                                        Modes are matched with like and glob case-sensitively, with or without the file type, e.g. mode like '-rwxr-?r-?'
+        between X and Y                 Used to check whether the column value lies within the range, bounds included
+        in (X, Y, ...)                  Used to check whether the column value equals any of the listed values
+        not between | not in            Negated forms of the two operators above
+
+        The value could be another field or expression, e.g. width > height, quote it to compare with the word itself
+    Arithmetic Operators:
+        + | plus                        Adds values of the column expressions
+        - | minus                       Subtracts values of the column expressions
+        * | mul                         Multiplies values of the column expressions
+        / | div                         Divides values of the column expressions, integer division
     Logical Operators:
         and                             Used as an AND operator for two conditions made with the above operators
         or                              Used as an OR operator for two conditions made with the above operators
+        not                             Used to negate the condition or the parenthesized group of conditions that follows
 
 Format:
-        tabs (default)                  Outputs each file with its column value(s) on a line with each column value delimited by a tab
-        lines                           Outputs each column value on a new line
+        tabs (default) | tabs raw       Outputs each file with its column value(s) on a line with each column value delimited by a tab, newlines, tabs and backslashes are escaped unless raw is given
+        lines | lines esc               Outputs each column value on a new line, escaping newlines, tabs and backslashes if esc is given
+                                        Columns of both could be given a width, e.g. name:40 or fsize:>10 to align to the right
         list                            Outputs entire output onto a single line for xargs
         csv                             Outputs each file with its column value(s) on a line with each column value delimited by a comma
         json                            Outputs a JSON array with JSON objects holding the column value(s) of each file
+        jsonl | ndjson                  Outputs a JSON object holding the column value(s) of each file on a separate line
+        html                            Outputs HTML document with a table holding the column value(s) of each file
+        md | markdown                   Outputs Markdown table holding the column value(s) of each file
+        table | table:full              Outputs an aligned table, colored and fitted to the terminal width unless full is given
+        sqlite:PATH[:TABLE][:append]    Writes the column value(s) of each file into a SQLite table, replacing its content unless append is given
+        cmd 'TEMPLATE'                  Outputs a shell command for each file, e.g. 'rm -v {{path}}', placeholders are replaced with quoted field values, {{}} with the first column
+
+Date format:
+        iso                             Outputs dates in RFC 3339 format with the time zone offset
+        FORMAT                          Outputs dates using strftime specifiers, e.g. '%d.%m.%Y %H:%M'
+
+Duplicates:
+        dupes                           Leaves only the files having a copy with the same content, only the files of the same size are read
+
+Explain:
+        explain                         Goes before the query, prints how the query is parsed instead of running it
+
+Summary:
+        summary                         Goes after the into clause, prints the number of matched and shown files, their total size and the time spent to stderr
+
+Progress:
+        progress                        Shows the search status on stderr, Ctrl-C stops the search and outputs the results found so far anyway
+        FSELECT_PROGRESS=1              Environment variable that turns the progress on for every query
+
+Ordering:
+        FSELECT_SORT_ROWS=N             Ordered rows above this number are sorted in temporary files, 1000000 by default
+        FSELECT_SORT_MEMORY=SIZE        The same for the estimated size of the rows, 512m by default
+
+Exit code only:
+        --exists                        Goes before the query, prints nothing and exits with 0 if any file matches, 1 otherwise,
+                                        the search stops at the first match
+        --count-only                    Goes before the query, prints the number of matching files only, columns, ordering and limit are ignored
+
+Index:
+        --index FILE                    Goes before the query, keeps names, sizes, modification dates and modes of the files in FILE
+                                        and examines only the directories changed since the previous search
     ");
 }
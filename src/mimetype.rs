@@ -0,0 +1,173 @@
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// How many bytes from the beginning of a file are examined.
+const SNIFF_LEN: usize = 512;
+
+struct Magic {
+    offset: usize,
+    signature: &'static [u8],
+    mime_type: &'static str,
+}
+
+macro_rules! def_magic {
+    ($($offset:expr, $signature:expr => $mime_type:expr);*) => {
+        const MAGIC: &'static [Magic] = &[
+            $(
+                Magic { offset: $offset, signature: $signature, mime_type: $mime_type },
+            )*
+        ];
+    }
+}
+
+def_magic! {
+    0,   b"\x89PNG\r\n\x1a\n"         => "image/png"
+;   0,   b"\xff\xd8\xff"              => "image/jpeg"
+;   0,   b"GIF87a"                    => "image/gif"
+;   0,   b"GIF89a"                    => "image/gif"
+;   0,   b"BM"                        => "image/bmp"
+;   0,   b"II*\x00"                   => "image/tiff"
+;   0,   b"MM\x00*"                   => "image/tiff"
+;   0,   b"\x00\x00\x01\x00"          => "image/vnd.microsoft.icon"
+;   8,   b"WEBP"                      => "image/webp"
+;   8,   b"WAVE"                      => "audio/x-wav"
+;   8,   b"AVI "                      => "video/x-msvideo"
+;   0,   b"ID3"                       => "audio/mpeg"
+;   0,   b"\xff\xfb"                  => "audio/mpeg"
+;   0,   b"\xff\xf3"                  => "audio/mpeg"
+;   0,   b"\xff\xf2"                  => "audio/mpeg"
+;   0,   b"fLaC"                      => "audio/flac"
+;   0,   b"OggS"                      => "audio/ogg"
+;   0,   b"MThd"                      => "audio/midi"
+;   4,   b"ftypqt"                    => "video/quicktime"
+;   4,   b"ftypM4A"                   => "audio/mp4"
+;   4,   b"ftyp"                      => "video/mp4"
+;   0,   b"\x1a\x45\xdf\xa3"          => "video/x-matroska"
+;   0,   b"FLV"                       => "video/x-flv"
+;   0,   b"%PDF-"                     => "application/pdf"
+;   0,   b"%!PS"                      => "application/postscript"
+;   0,   b"{\\rtf"                    => "application/rtf"
+;   0,   b"PK\x03\x04"                => "application/zip"
+;   0,   b"PK\x05\x06"                => "application/zip"
+;   0,   b"\x1f\x8b"                  => "application/gzip"
+;   0,   b"BZh"                       => "application/x-bzip2"
+;   0,   b"\xfd7zXZ\x00"              => "application/x-xz"
+;   0,   b"7z\xbc\xaf\x27\x1c"        => "application/x-7z-compressed"
+;   0,   b"Rar!\x1a\x07"              => "application/vnd.rar"
+;   257, b"ustar"                     => "application/x-tar"
+;   0,   b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1" => "application/x-ole-storage"
+;   0,   b"SQLite format 3\x00"       => "application/x-sqlite3"
+;   0,   b"\x7fELF"                   => "application/x-executable"
+;   0,   b"MZ"                        => "application/x-msdownload"
+;   0,   b"\xca\xfe\xba\xbe"          => "application/java-vm"
+;   0,   b"\x00asm"                   => "application/wasm"
+;   0,   b"#!"                        => "text/x-shellscript"
+;   0,   b"<?xml"                     => "application/xml"
+}
+
+/// Determines MIME type of a file by looking at its content rather than its extension.
+/// Other entries than regular files and directories, like FIFOs or devices, are not opened and have none.
+pub fn get_mime_type(path: &Path) -> Option<String> {
+    match fs::metadata(path) {
+        Ok(ref metadata) if metadata.is_dir() => return Some(String::from("inode/directory")),
+        Ok(ref metadata) if metadata.is_file() => { },
+        _ => return None
+    }
+
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        _ => return None
+    };
+
+    let mut buf = [0u8; SNIFF_LEN];
+    let mut len = 0;
+    while len < SNIFF_LEN {
+        match file.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            _ => return None
+        }
+    }
+
+    Some(String::from(sniff(&buf[..len])))
+}
+
+fn sniff(buf: &[u8]) -> &'static str {
+    if buf.is_empty() {
+        return "application/x-empty";
+    }
+
+    for magic in MAGIC {
+        let end = magic.offset + magic.signature.len();
+        if buf.len() >= end && &buf[magic.offset..end] == magic.signature {
+            return magic.mime_type;
+        }
+    }
+
+    if is_html(buf) {
+        return "text/html";
+    }
+
+    if is_text(buf) {
+        return "text/plain";
+    }
+
+    "application/octet-stream"
+}
+
+fn is_html(buf: &[u8]) -> bool {
+    let start = String::from_utf8_lossy(&buf[..buf.len().min(64)]).trim_start().to_ascii_lowercase();
+
+    start.starts_with("<!doctype html") || start.starts_with("<html")
+}
+
+fn is_text(buf: &[u8]) -> bool {
+    if buf.contains(&0) {
+        return false;
+    }
+
+    match ::std::str::from_utf8(buf) {
+        Ok(_) => true,
+        // the buffer could cut a multibyte character in half
+        Err(e) => e.error_len().is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_images() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"), "image/png");
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0\x00\x10JFIF"), "image/jpeg");
+        assert_eq!(sniff(b"RIFF\x24\x00\x00\x00WEBPVP8 "), "image/webp");
+    }
+
+    #[test]
+    fn test_offset_signature() {
+        let mut tar = vec![0u8; 512];
+        tar[..8].copy_from_slice(b"file.txt");
+        tar[257..262].copy_from_slice(b"ustar");
+
+        assert_eq!(sniff(&tar), "application/x-tar");
+        assert_eq!(sniff(b"\x00\x00\x00\x20ftypisom"), "video/mp4");
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(sniff(b""), "application/x-empty");
+        assert_eq!(sniff(b"hello, world\n"), "text/plain");
+        assert_eq!(sniff("привет".as_bytes()), "text/plain");
+        assert_eq!(sniff(&"привет".as_bytes()[..3]), "text/plain");
+        assert_eq!(sniff(b"  <!DOCTYPE html><html>"), "text/html");
+        assert_eq!(sniff(b"#!/bin/sh\necho"), "text/x-shellscript");
+    }
+
+    #[test]
+    fn test_binary() {
+        assert_eq!(sniff(b"\x01\x02\x00\x03"), "application/octet-stream");
+    }
+}
@@ -3,6 +3,13 @@ use std::fs::Metadata;
 use std::os::unix::fs::MetadataExt;
 #[cfg(windows)]
 use std::os::windows::fs::MetadataExt;
+#[cfg(unix)]
+use std::time::Duration;
+use std::time::SystemTime;
+#[cfg(unix)]
+use std::time::UNIX_EPOCH;
+#[cfg(unix)]
+use users;
 
 pub fn get_mode(meta: &Box<Metadata>) -> String {
     #[cfg(unix)]
@@ -46,10 +53,11 @@ fn get_mode_unix(mode: u32) -> String {
         s.push('-');
     }
 
-    if mode_user_exec(mode) {
-        s.push('x')
-    } else {
-        s.push('-');
+    match (mode_user_exec(mode), mode_is_suid(mode)) {
+        (true, true) => s.push('s'),
+        (false, true) => s.push('S'),
+        (true, false) => s.push('x'),
+        (false, false) => s.push('-')
     }
 
     // group
@@ -66,10 +74,11 @@ fn get_mode_unix(mode: u32) -> String {
         s.push('-');
     }
 
-    if mode_group_exec(mode) {
-        s.push('x')
-    } else {
-        s.push('-');
+    match (mode_group_exec(mode), mode_is_sgid(mode)) {
+        (true, true) => s.push('s'),
+        (false, true) => s.push('S'),
+        (true, false) => s.push('x'),
+        (false, false) => s.push('-')
     }
 
     // other
@@ -86,15 +95,108 @@ fn get_mode_unix(mode: u32) -> String {
         s.push('-');
     }
 
-    if mode_other_exec(mode) {
-        s.push('x')
-    } else {
-        s.push('-');
+    match (mode_other_exec(mode), mode_is_sticky(mode)) {
+        (true, true) => s.push('t'),
+        (false, true) => s.push('T'),
+        (true, false) => s.push('x'),
+        (false, false) => s.push('-')
     }
 
     s
 }
 
+/// Permission bits along with the setuid, setgid and sticky ones, the way `chmod` takes them, e.g. `0644`.
+pub fn format_mode_octal(mode: u32) -> String {
+    format!("{:04o}", mode & 0o7777)
+}
+
+/// Octal permission bits, e.g. `0755`, `755` or `0o755`.
+pub fn parse_octal_mode(s: &str) -> Option<u32> {
+    let digits = match s.starts_with("0o") || s.starts_with("0O") {
+        true => &s[2..],
+        false => s
+    };
+
+    match digits.len() >= 3 && digits.len() <= 4 {
+        true => u32::from_str_radix(digits, 8).ok(),
+        false => None
+    }
+}
+
+/// Bits given either in octal or the symbolic way of `chmod`, e.g. `o+w`, `ug+rx`, `+t` or `u+s,g+s`.
+/// No bits at all is not an answer.
+pub fn parse_mode_bits(s: &str) -> Option<u32> {
+    if let Some(bits) = parse_octal_mode(s) {
+        return match bits {
+            0 => None,
+            bits => Some(bits)
+        };
+    }
+
+    let mut result = 0;
+    for clause in s.split(',') {
+        let plus = clause.find('+')?;
+        let (who, perms) = (&clause[..plus], &clause[plus + 1..]);
+
+        let mut classes = 0;
+        for c in who.chars() {
+            classes |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                'a' => 0o7777,
+                _ => return None
+            };
+        }
+        if classes == 0 {
+            classes = 0o7777;
+        }
+
+        if perms.is_empty() {
+            return None;
+        }
+
+        for c in perms.chars() {
+            let bits = match c {
+                'r' => S_IRUSR | S_IRGRP | S_IROTH,
+                'w' => S_IWUSR | S_IWGRP | S_IWOTH,
+                'x' => S_IXUSR | S_IXGRP | S_IXOTH,
+                's' => S_ISUID | S_ISGID,
+                't' => S_ISVTX,
+                _ => return None
+            };
+            result |= bits & classes;
+        }
+    }
+
+    match result {
+        0 => None,
+        result => Some(result)
+    }
+}
+
+/// Tells whether all the bits are set, see `parse_mode_bits` for how they are given.
+pub fn mode_has_bits(mode: u32, bits: &str) -> bool {
+    match parse_mode_bits(bits) {
+        Some(bits) => mode & bits == bits,
+        None => false
+    }
+}
+
+/// The file type the way `ls -l` shows it in front of the permissions, if the mode has it at all.
+pub fn get_file_type_char(mode: u32) -> Option<char> {
+    match mode & S_IFMT {
+        S_IFREG => Some('-'),
+        S_IFDIR => Some('d'),
+        S_IFLNK => Some('l'),
+        S_IFIFO => Some('p'),
+        S_IFCHR => Some('c'),
+        S_IFBLK => Some('b'),
+        S_IFSOCK => Some('s'),
+        _ => None
+    }
+}
+
 #[allow(unused)]
 pub fn get_mode_from_boxed_unix_int(meta: &Box<Metadata>) -> Option<u32> {
     #[cfg(unix)]
@@ -207,6 +309,81 @@ pub fn mode_other_exec(mode: u32) -> bool {
     mode & S_IXOTH == S_IXOTH
 }
 
+pub fn is_suid(meta: &Box<Metadata>) -> bool {
+    match get_mode_from_boxed_unix_int(meta) {
+        Some(mode) => mode_is_suid(mode),
+        None => false
+    }
+}
+
+pub fn mode_is_suid(mode: u32) -> bool {
+    mode & S_ISUID == S_ISUID
+}
+
+pub fn is_sgid(meta: &Box<Metadata>) -> bool {
+    match get_mode_from_boxed_unix_int(meta) {
+        Some(mode) => mode_is_sgid(mode),
+        None => false
+    }
+}
+
+pub fn mode_is_sgid(mode: u32) -> bool {
+    mode & S_ISGID == S_ISGID
+}
+
+pub fn is_sticky(meta: &Box<Metadata>) -> bool {
+    match get_mode_from_boxed_unix_int(meta) {
+        Some(mode) => mode_is_sticky(mode),
+        None => false
+    }
+}
+
+pub fn mode_is_sticky(mode: u32) -> bool {
+    mode & S_ISVTX == S_ISVTX
+}
+
+pub fn is_readonly(meta: &Box<Metadata>) -> bool {
+    #[cfg(windows)]
+    {
+        meta.file_attributes() & FILE_ATTRIBUTE_READONLY == FILE_ATTRIBUTE_READONLY
+    }
+
+    #[cfg(not(windows))]
+    {
+        meta.permissions().readonly()
+    }
+}
+
+pub fn mode_is_readonly(mode: u32) -> bool {
+    mode & (S_IWUSR | S_IWGRP | S_IWOTH) == 0
+}
+
+#[allow(unused)]
+pub fn is_system(meta: &Box<Metadata>) -> bool {
+    #[cfg(windows)]
+    {
+        meta.file_attributes() & FILE_ATTRIBUTE_SYSTEM == FILE_ATTRIBUTE_SYSTEM
+    }
+
+    #[cfg(not(windows))]
+    {
+        false
+    }
+}
+
+#[allow(unused)]
+pub fn is_archive_bit(meta: &Box<Metadata>) -> bool {
+    #[cfg(windows)]
+    {
+        meta.file_attributes() & FILE_ATTRIBUTE_ARCHIVE == FILE_ATTRIBUTE_ARCHIVE
+    }
+
+    #[cfg(not(windows))]
+    {
+        false
+    }
+}
+
 pub fn is_pipe(meta: &Box<Metadata>) -> bool {
     match get_mode_from_boxed_unix_int(meta) {
         Some(mode) => mode_is_pipe(mode),
@@ -215,7 +392,7 @@ pub fn is_pipe(meta: &Box<Metadata>) -> bool {
 }
 
 pub fn mode_is_pipe(mode: u32) -> bool {
-    mode & S_IFMT & S_IFIFO == S_IFMT & S_IFIFO
+    mode & S_IFMT == S_IFIFO
 }
 
 pub fn is_char_device(meta: &Box<Metadata>) -> bool {
@@ -226,7 +403,7 @@ pub fn is_char_device(meta: &Box<Metadata>) -> bool {
 }
 
 pub fn mode_is_char_device(mode: u32) -> bool {
-    mode & S_IFMT & S_IFCHR == S_IFMT & S_IFCHR
+    mode & S_IFMT == S_IFCHR
 }
 
 pub fn is_block_device(meta: &Box<Metadata>) -> bool {
@@ -237,7 +414,7 @@ pub fn is_block_device(meta: &Box<Metadata>) -> bool {
 }
 
 pub fn mode_is_block_device(mode: u32) -> bool {
-    mode & S_IFMT & S_IFBLK == S_IFMT & S_IFBLK
+    mode & S_IFMT == S_IFBLK
 }
 
 pub fn is_socket(meta: &Box<Metadata>) -> bool {
@@ -248,56 +425,75 @@ pub fn is_socket(meta: &Box<Metadata>) -> bool {
 }
 
 pub fn mode_is_socket(mode: u32) -> bool {
-    mode & S_IFMT & S_IFSOCK == S_IFMT & S_IFSOCK
+    mode & S_IFMT == S_IFSOCK
 }
 
-const S_IRUSR: u32 = 400;
-const S_IWUSR: u32 = 200;
-const S_IXUSR: u32 = 100;
+const S_IRUSR: u32 = 0o400;
+const S_IWUSR: u32 = 0o200;
+const S_IXUSR: u32 = 0o100;
 
-const S_IRGRP: u32 = 40;
-const S_IWGRP: u32 = 20;
-const S_IXGRP: u32 = 10;
+const S_IRGRP: u32 = 0o40;
+const S_IWGRP: u32 = 0o20;
+const S_IXGRP: u32 = 0o10;
 
-const S_IROTH: u32 = 4;
-const S_IWOTH: u32 = 2;
-const S_IXOTH: u32 = 1;
+const S_IROTH: u32 = 0o4;
+const S_IWOTH: u32 = 0o2;
+const S_IXOTH: u32 = 0o1;
 
-#[allow(unused)]
-const S_ISUID: u32 = 4000;
-#[allow(unused)]
-const S_ISGID: u32 = 2000;
-#[allow(unused)]
-const S_ISVTX: u32 = 1000;
+const S_ISUID: u32 = 0o4000;
+const S_ISGID: u32 = 0o2000;
+const S_ISVTX: u32 = 0o1000;
 
-const S_IFMT: u32 = 170000;
-const S_IFIFO: u32 = 10000;
-const S_IFCHR: u32 = 20000;
-const S_IFBLK: u32 = 60000;
-const S_IFSOCK: u32 = 140000;
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o40000;
+const S_IFLNK: u32 = 0o120000;
+const S_IFIFO: u32 = 0o10000;
+const S_IFCHR: u32 = 0o20000;
+const S_IFBLK: u32 = 0o60000;
+const S_IFSOCK: u32 = 0o140000;
 
 #[cfg(windows)]
-fn get_mode_windows(mode: u32) -> String {
-    const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x20;
-    const FILE_ATTRIBUTE_COMPRESSED: u32 = 0x800;
-    const FILE_ATTRIBUTE_DEVICE: u32 = 0x40;
-    const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
-    const FILE_ATTRIBUTE_ENCRYPTED: u32 = 0x4000;
-    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
-    const FILE_ATTRIBUTE_INTEGRITY_STREAM: u32 = 0x8000;
-    const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
-    const FILE_ATTRIBUTE_NOT_CONTENT_INDEXED: u32 = 0x2000;
-    const FILE_ATTRIBUTE_NO_SCRUB_DATA: u32 = 0x20000;
-    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
-    const FILE_ATTRIBUTE_READONLY: u32 = 0x1;
-    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
-    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x40000;
-    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
-    const FILE_ATTRIBUTE_SPARSE_FILE: u32 = 0x200;
-    const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
-    const FILE_ATTRIBUTE_TEMPORARY: u32 = 0x100;
-    const FILE_ATTRIBUTE_VIRTUAL: u32 = 0x10000;
+const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x20;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_COMPRESSED: u32 = 0x800;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_DEVICE: u32 = 0x40;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_ENCRYPTED: u32 = 0x4000;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_INTEGRITY_STREAM: u32 = 0x8000;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_NOT_CONTENT_INDEXED: u32 = 0x2000;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_NO_SCRUB_DATA: u32 = 0x20000;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_READONLY: u32 = 0x1;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x40000;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_SPARSE_FILE: u32 = 0x200;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_TEMPORARY: u32 = 0x100;
+#[cfg(windows)]
+const FILE_ATTRIBUTE_VIRTUAL: u32 = 0x10000;
 
+#[cfg(windows)]
+fn get_mode_windows(mode: u32) -> String {
     let mut v = vec![];
 
     if mode & FILE_ATTRIBUTE_ARCHIVE == FILE_ATTRIBUTE_ARCHIVE {
@@ -379,6 +575,23 @@ fn get_mode_windows(mode: u32) -> String {
     v.join(", ")
 }
 
+/// Time of the last inode change, e.g. of permissions or owner, not only of the content.
+#[allow(unused)]
+pub fn get_ctime(meta: &Box<Metadata>) -> Option<SystemTime> {
+    #[cfg(unix)]
+    {
+        match meta.ctime() {
+            secs if secs >= 0 => Some(UNIX_EPOCH + Duration::new(secs as u64, meta.ctime_nsec() as u32)),
+            _ => None
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
 #[allow(unused)]
 pub fn get_uid(meta: &Box<Metadata>) -> Option<u32> {
     #[cfg(unix)]
@@ -403,4 +616,330 @@ pub fn get_gid(meta: &Box<Metadata>) -> Option<u32> {
     {
         None
     }
-}
\ No newline at end of file
+}
+
+#[allow(unused)]
+pub fn get_inode(meta: &Box<Metadata>) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        Some(meta.ino())
+    }
+
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Device and inode of the file, the same for all the hard links to it.
+pub fn get_file_id(meta: &Metadata) -> Option<(u64, u64)> {
+    #[cfg(unix)]
+    {
+        Some((meta.dev(), meta.ino()))
+    }
+
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Id of the device the file resides on, directories with another id than their parent are mount points.
+#[allow(unused)]
+pub fn get_device(meta: &Metadata) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        Some(meta.dev())
+    }
+
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+#[allow(unused)]
+pub fn get_nlinks(meta: &Box<Metadata>) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        Some(meta.nlink())
+    }
+
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// File flags set with `chflags` on macOS and BSD systems, there are no such flags elsewhere.
+#[allow(unused)]
+pub fn get_flags(meta: &Box<Metadata>) -> Option<u32> {
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::macos::fs::MetadataExt as MacosMetadataExt;
+        return Some(meta.st_flags());
+    }
+
+    #[cfg(target_os = "freebsd")]
+    {
+        use std::os::freebsd::fs::MetadataExt as FreebsdMetadataExt;
+        return Some(meta.st_flags());
+    }
+
+    #[cfg(target_os = "dragonfly")]
+    {
+        use std::os::dragonfly::fs::MetadataExt as DragonflyMetadataExt;
+        return Some(meta.st_flags());
+    }
+
+    #[cfg(target_os = "netbsd")]
+    {
+        use std::os::netbsd::fs::MetadataExt as NetbsdMetadataExt;
+        return Some(meta.st_flags());
+    }
+
+    #[cfg(target_os = "openbsd")]
+    {
+        use std::os::openbsd::fs::MetadataExt as OpenbsdMetadataExt;
+        return Some(meta.st_flags());
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd", target_os = "openbsd")))]
+    {
+        None
+    }
+}
+
+/// Lists the names of the flags as `ls -lo` does, e.g. `uchg,hidden,nodump`.
+/// Bits without a name are skipped.
+pub fn format_flags(flags: u32) -> String {
+    FLAG_NAMES.iter()
+        .filter(|&&(flag, _)| flags & flag == flag)
+        .map(|&(_, name)| name)
+        .collect::<Vec<&str>>()
+        .join(",")
+}
+
+/// Finder and other macOS applications hide files with the `hidden` flag.
+#[allow(unused)]
+pub fn has_hidden_flag(meta: &Box<Metadata>) -> bool {
+    match get_flags(meta) {
+        Some(flags) => flags & UF_HIDDEN == UF_HIDDEN,
+        None => false
+    }
+}
+
+const UF_NODUMP: u32 = 0x1;
+const UF_IMMUTABLE: u32 = 0x2;
+const UF_APPEND: u32 = 0x4;
+const UF_OPAQUE: u32 = 0x8;
+const UF_HIDDEN: u32 = 0x8000;
+
+const SF_ARCHIVED: u32 = 0x10000;
+const SF_IMMUTABLE: u32 = 0x20000;
+const SF_APPEND: u32 = 0x40000;
+
+const FLAG_NAMES: [(u32, &'static str); 8] = [
+    (UF_IMMUTABLE, "uchg"),
+    (UF_APPEND, "uappnd"),
+    (UF_OPAQUE, "opaque"),
+    (UF_HIDDEN, "hidden"),
+    (UF_NODUMP, "nodump"),
+    (SF_ARCHIVED, "arch"),
+    (SF_IMMUTABLE, "schg"),
+    (SF_APPEND, "sappnd"),
+];
+
+/// Identity of the running process which is checked against the file owner and permissions.
+pub struct CurrentUser {
+    uid: u32,
+    gids: Vec<u32>,
+}
+
+impl CurrentUser {
+    pub fn get() -> CurrentUser {
+        #[cfg(unix)]
+        {
+            let mut gids = vec![users::get_effective_gid()];
+            if let Ok(groups) = users::group_access_list() {
+                for group in groups {
+                    if !gids.contains(&group.gid()) {
+                        gids.push(group.gid());
+                    }
+                }
+            }
+
+            CurrentUser { uid: users::get_effective_uid(), gids }
+        }
+
+        #[cfg(not(unix))]
+        {
+            CurrentUser { uid: 0, gids: vec![] }
+        }
+    }
+}
+
+const ACCESS_READ: u32 = 0o4;
+const ACCESS_WRITE: u32 = 0o2;
+const ACCESS_EXEC: u32 = 0o1;
+
+#[allow(unused)]
+pub fn is_mine(meta: &Box<Metadata>, user: &CurrentUser) -> bool {
+    match get_uid(meta) {
+        Some(uid) => uid == user.uid,
+        None => false
+    }
+}
+
+pub fn is_readable(meta: &Box<Metadata>, user: &CurrentUser) -> bool {
+    has_access(meta, user, ACCESS_READ)
+}
+
+pub fn is_writable(meta: &Box<Metadata>, user: &CurrentUser) -> bool {
+    has_access(meta, user, ACCESS_WRITE)
+}
+
+pub fn is_executable(meta: &Box<Metadata>, user: &CurrentUser) -> bool {
+    has_access(meta, user, ACCESS_EXEC)
+}
+
+#[allow(unused)]
+fn has_access(meta: &Box<Metadata>, user: &CurrentUser, access: u32) -> bool {
+    #[cfg(unix)]
+    {
+        mode_has_access(meta.mode(), meta.uid(), meta.gid(), user, access)
+    }
+
+    #[cfg(windows)]
+    {
+        match access {
+            ACCESS_READ => true,
+            ACCESS_WRITE => !is_readonly(meta),
+            _ => true
+        }
+    }
+}
+
+/// Mimics access(2): the owner is checked against the user bits, group members against the group bits
+/// and everybody else against the other bits. Root may read and write anything
+/// and execute whatever has at least one exec bit set.
+#[allow(unused)]
+fn mode_has_access(mode: u32, uid: u32, gid: u32, user: &CurrentUser, access: u32) -> bool {
+    if user.uid == 0 {
+        return access != ACCESS_EXEC || mode & 0o111 != 0;
+    }
+
+    let bits = if uid == user.uid {
+        mode >> 6
+    } else if user.gids.contains(&gid) {
+        mode >> 3
+    } else {
+        mode
+    };
+
+    bits & access == access
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_special_bits() {
+        assert!(mode_is_suid(0o104755));
+        assert!(!mode_is_suid(0o100755));
+        assert!(mode_is_sgid(0o42755));
+        assert!(mode_is_sticky(0o41777));
+        assert!(!mode_is_sticky(0o40777));
+    }
+
+    #[test]
+    fn test_access() {
+        let user = CurrentUser { uid: 1000, gids: vec![100, 200] };
+
+        assert!(mode_has_access(0o100640, 1000, 0, &user, ACCESS_READ));
+        assert!(mode_has_access(0o100640, 1000, 0, &user, ACCESS_WRITE));
+        assert!(!mode_has_access(0o100640, 1000, 0, &user, ACCESS_EXEC));
+
+        assert!(mode_has_access(0o100640, 0, 200, &user, ACCESS_READ));
+        assert!(!mode_has_access(0o100640, 0, 200, &user, ACCESS_WRITE));
+        assert!(!mode_has_access(0o100640, 0, 300, &user, ACCESS_READ));
+
+        // the user bits win even if the group or others have more permissions
+        assert!(!mode_has_access(0o100077, 1000, 100, &user, ACCESS_READ));
+        assert!(mode_has_access(0o100705, 0, 300, &user, ACCESS_EXEC));
+
+        let root = CurrentUser { uid: 0, gids: vec![0] };
+
+        assert!(mode_has_access(0o100000, 1000, 1000, &root, ACCESS_READ));
+        assert!(mode_has_access(0o100000, 1000, 1000, &root, ACCESS_WRITE));
+        assert!(!mode_has_access(0o100644, 1000, 1000, &root, ACCESS_EXEC));
+        assert!(mode_has_access(0o100001, 1000, 1000, &root, ACCESS_EXEC));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_special_bits_format() {
+        assert_eq!(format_mode(0o104755), "rwsr-xr-x");
+        assert_eq!(format_mode(0o104644), "rwSr--r--");
+        assert_eq!(format_mode(0o102755), "rwxr-sr-x");
+        assert_eq!(format_mode(0o102745), "rwxr-Sr-x");
+        assert_eq!(format_mode(0o41777), "rwxrwxrwt");
+        assert_eq!(format_mode(0o41776), "rwxrwxrwT");
+        assert_eq!(format_mode(0o100644), "rw-r--r--");
+    }
+
+    #[test]
+    fn test_octal_mode() {
+        assert_eq!(format_mode_octal(0o100644), "0644");
+        assert_eq!(format_mode_octal(0o104755), "4755");
+        assert_eq!(format_mode_octal(0o41777), "1777");
+
+        assert_eq!(parse_octal_mode("0777"), Some(0o777));
+        assert_eq!(parse_octal_mode("644"), Some(0o644));
+        assert_eq!(parse_octal_mode("0o2755"), Some(0o2755));
+        assert_eq!(parse_octal_mode("0778"), None);
+        assert_eq!(parse_octal_mode("12345"), None);
+        assert_eq!(parse_octal_mode("rwxr-xr-x"), None);
+    }
+
+    #[test]
+    fn test_mode_bits() {
+        assert_eq!(parse_mode_bits("o+w"), Some(0o2));
+        assert_eq!(parse_mode_bits("ug+rx"), Some(0o550));
+        assert_eq!(parse_mode_bits("+x"), Some(0o111));
+        assert_eq!(parse_mode_bits("a+r"), Some(0o444));
+        assert_eq!(parse_mode_bits("u+s,g+s"), Some(0o6000));
+        assert_eq!(parse_mode_bits("+t"), Some(0o1000));
+        assert_eq!(parse_mode_bits("0002"), Some(0o2));
+        assert_eq!(parse_mode_bits("0000"), None);
+        assert_eq!(parse_mode_bits("o-w"), None);
+        assert_eq!(parse_mode_bits("z+w"), None);
+        assert_eq!(parse_mode_bits("u+"), None);
+
+        assert!(mode_has_bits(0o100646, "o+w"));
+        assert!(!mode_has_bits(0o100644, "o+w"));
+        assert!(mode_has_bits(0o100755, "ug+rx"));
+        assert!(!mode_has_bits(0o100745, "ug+rx"));
+        assert!(mode_has_bits(0o41777, "+t"));
+    }
+
+    #[test]
+    fn test_file_type_char() {
+        assert_eq!(get_file_type_char(0o100644), Some('-'));
+        assert_eq!(get_file_type_char(0o40755), Some('d'));
+        assert_eq!(get_file_type_char(0o120777), Some('l'));
+        assert_eq!(get_file_type_char(0o10644), Some('p'));
+        assert_eq!(get_file_type_char(0o140755), Some('s'));
+        assert_eq!(get_file_type_char(0o644), None);
+    }
+
+    #[test]
+    fn test_format_flags() {
+        assert_eq!(format_flags(0), "");
+        assert_eq!(format_flags(UF_NODUMP | UF_IMMUTABLE | UF_HIDDEN), "uchg,hidden,nodump");
+        assert_eq!(format_flags(SF_IMMUTABLE | SF_APPEND | UF_APPEND), "uappnd,schg,sappnd");
+        assert_eq!(format_flags(SF_ARCHIVED | UF_OPAQUE), "opaque,arch");
+        assert_eq!(format_flags(0x100 | UF_HIDDEN), "hidden");
+    }
+}
@@ -0,0 +1,124 @@
+use mp3_metadata::MP3Metadata;
+
+/// Bitrates in kbps and the sampling frequency in Hz of an MP3 file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioRates {
+    /// Average of the frames, rounded. It's the same as the others for a CBR file
+    pub bitrate: u16,
+    pub min_bitrate: u16,
+    pub max_bitrate: u16,
+    pub freq: u16,
+}
+
+/// Files without a single valid frame give nothing.
+pub fn get_audio_rates(mp3_info: &MP3Metadata) -> Option<AudioRates> {
+    get_frame_rates(mp3_info.frames.iter().map(|frame| (frame.bitrate, frame.sampling_freq)))
+}
+
+/// Frames come as bitrate and frequency pairs. Frames without either of them, like a broken stub
+/// in front of the first real frame or a free format frame, are skipped.
+fn get_frame_rates<I: Iterator<Item=(u16, u16)>>(frames: I) -> Option<AudioRates> {
+    let mut count = 0u64;
+    let mut total = 0u64;
+    let mut min_bitrate = u16::max_value();
+    let mut max_bitrate = 0;
+    let mut freq = 0;
+
+    for (bitrate, sampling_freq) in frames.filter(|&(bitrate, sampling_freq)| bitrate > 0 && sampling_freq > 0) {
+        if count == 0 {
+            freq = sampling_freq;
+        }
+
+        count += 1;
+        total += bitrate as u64;
+        min_bitrate = min_bitrate.min(bitrate);
+        max_bitrate = max_bitrate.max(bitrate);
+    }
+
+    if count == 0 {
+        return None;
+    }
+
+    Some(AudioRates {
+        bitrate: ((total + count / 2) / count) as u16,
+        min_bitrate,
+        max_bitrate,
+        freq,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VBR: &'static [u8] = include_bytes!("../tests/fixtures/vbr.mp3");
+
+    const BITRATES: [u16; 16] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 0];
+    const FREQS: [u16; 4] = [44100, 48000, 32000, 0];
+
+    /// Headers of the MPEG-1 Layer III frames of the fixture, a broken header is followed by the next one right away.
+    fn read_frame_headers(bytes: &[u8]) -> Vec<(u16, u16)> {
+        let mut frames = vec![];
+        let mut pos = 0;
+
+        while pos + 4 <= bytes.len() {
+            if bytes[pos] != 0xff || bytes[pos + 1] & 0xe0 != 0xe0 {
+                pos += 1;
+                continue;
+            }
+
+            let bitrate = BITRATES[(bytes[pos + 2] >> 4) as usize];
+            let freq = FREQS[((bytes[pos + 2] >> 2) & 0x03) as usize];
+            let padding = ((bytes[pos + 2] >> 1) & 0x01) as usize;
+            frames.push((bitrate, freq));
+
+            pos += match bitrate > 0 && freq > 0 {
+                true => 144 * bitrate as usize * 1000 / freq as usize + padding,
+                false => 4
+            };
+        }
+
+        frames
+    }
+
+    #[test]
+    fn test_vbr_rates() {
+        let frames = read_frame_headers(VBR);
+        assert_eq!(frames.len(), 9);
+        assert_eq!(frames[0], (0, 0));
+
+        assert_eq!(get_frame_rates(frames.into_iter()), Some(AudioRates {
+            bitrate: 216,
+            min_bitrate: 128,
+            max_bitrate: 320,
+            freq: 44100,
+        }));
+    }
+
+    #[test]
+    fn test_cbr_rates() {
+        let frames = vec![(320, 48000); 100];
+
+        assert_eq!(get_frame_rates(frames.into_iter()), Some(AudioRates {
+            bitrate: 320,
+            min_bitrate: 320,
+            max_bitrate: 320,
+            freq: 48000,
+        }));
+    }
+
+    #[test]
+    fn test_average_is_rounded() {
+        let frames = vec![(128, 44100), (160, 44100), (160, 44100)];
+        assert_eq!(get_frame_rates(frames.into_iter()).map(|rates| rates.bitrate), Some(149));
+
+        let frames = vec![(128, 44100), (129, 44100)];
+        assert_eq!(get_frame_rates(frames.into_iter()).map(|rates| rates.bitrate), Some(129));
+    }
+
+    #[test]
+    fn test_no_valid_frames() {
+        assert_eq!(get_frame_rates(vec![(0, 44100), (128, 0)].into_iter()), None);
+        assert_eq!(get_frame_rates(vec![].into_iter()), None);
+    }
+}
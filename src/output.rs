@@ -0,0 +1,179 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+
+/// Destination of the search results.
+/// After the first failed write the rest of the output is skipped, the error is reported by `finish`.
+pub struct Output {
+    target: OutputTarget,
+    error: Option<io::Error>,
+}
+
+enum OutputTarget {
+    /// Stdout is locked once the first row is written and stays locked till the output is finished
+    Stdout(Option<BufWriter<io::StdoutLock<'static>>>),
+    File(FileOutput),
+}
+
+impl Output {
+    pub fn stdout() -> Output {
+        Output { target: OutputTarget::Stdout(None), error: None }
+    }
+
+    pub fn file(path: &str) -> io::Result<Output> {
+        let file_output = FileOutput::create(Path::new(path))?;
+        Ok(Output { target: OutputTarget::File(file_output), error: None })
+    }
+
+    pub fn is_stdout(&self) -> bool {
+        match self.target {
+            OutputTarget::Stdout(_) => true,
+            _ => false
+        }
+    }
+
+    pub fn print(&mut self, s: &str) {
+        if self.error.is_some() {
+            return;
+        }
+
+        if let Err(err) = self.write_all(s.as_bytes()) {
+            self.error = Some(err);
+        }
+    }
+
+    /// Rows written to stdout one by one are flushed right away, so a reader of the pipe gets every row
+    /// as soon as it's found. Files are written in large chunks anyway.
+    pub fn end_row(&mut self) {
+        if self.error.is_some() || !self.is_stdout() {
+            return;
+        }
+
+        if let Err(err) = self.flush() {
+            self.error = Some(err);
+        }
+    }
+
+    /// A write has failed, e.g. the reader of the pipe has gone, so there's no point to go on.
+    pub fn has_failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Flushes the output, the file appears at its place only if everything was written successfully.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        match self.target {
+            OutputTarget::Stdout(Some(mut stdout)) => stdout.flush(),
+            OutputTarget::Stdout(None) => Ok(()),
+            OutputTarget::File(file_output) => file_output.finish()
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.target {
+            OutputTarget::Stdout(ref mut stdout) => stdout.get_or_insert_with(|| BufWriter::new(io::stdout().lock())).write(buf),
+            OutputTarget::File(ref mut file_output) => file_output.writer.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.target {
+            OutputTarget::Stdout(Some(ref mut stdout)) => stdout.flush(),
+            OutputTarget::Stdout(None) => Ok(()),
+            OutputTarget::File(ref mut file_output) => file_output.writer.flush()
+        }
+    }
+}
+
+/// The reader of the output has gone, like `head` does once it has read enough lines.
+/// Windows reports a closed pipe with another error code, which is mapped to the same kind.
+pub fn is_broken_pipe(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::BrokenPipe
+}
+
+/// Writes into a temporary file next to the target one and renames it once finished,
+/// so an interrupted search doesn't leave a half-written file behind.
+struct FileOutput {
+    path: PathBuf,
+    temp_path: PathBuf,
+    writer: BufWriter<File>,
+    finished: bool,
+}
+
+impl FileOutput {
+    fn create(path: &Path) -> io::Result<FileOutput> {
+        let file_name = match path.file_name() {
+            Some(file_name) => file_name.to_string_lossy().to_string(),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))
+        };
+
+        let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, process::id()));
+        let file = File::create(&temp_path)?;
+
+        Ok(FileOutput {
+            path: path.to_path_buf(),
+            temp_path,
+            writer: BufWriter::new(file),
+            finished: false,
+        })
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        fs::rename(&self.temp_path, &self.path)?;
+        self.finished = true;
+
+        Ok(())
+    }
+}
+
+impl Drop for FileOutput {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_file_output() {
+        let path = env::temp_dir().join(format!("fselect_output_{}.txt", process::id()));
+        let path_str = path.to_string_lossy().to_string();
+
+        let mut output = Output::file(&path_str).unwrap();
+        output.print("first\n");
+        assert!(!path.exists());
+        output.print("second\n");
+        output.finish().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+
+        let mut output = Output::file(&path_str).unwrap();
+        output.print("partial\n");
+        drop(output);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+        assert_eq!(fs::read_dir(env::temp_dir()).unwrap()
+                       .filter_map(|entry| entry.ok())
+                       .filter(|entry| entry.file_name().to_string_lossy().starts_with(&format!(".fselect_output_{}", process::id())))
+                       .count(), 0);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
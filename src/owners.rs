@@ -0,0 +1,55 @@
+#[cfg(unix)]
+use users::{Groups, Users, UsersCache};
+
+/// Looks up the names of file owners by their ids.
+pub trait OwnerNames {
+    fn get_user_name(&self, uid: u32) -> Option<String>;
+
+    fn get_group_name(&self, gid: u32) -> Option<String>;
+}
+
+/// Names of the users and the groups known to this system, each of them is looked up once.
+#[cfg(unix)]
+pub struct SystemOwnerNames {
+    cache: UsersCache,
+}
+
+#[cfg(unix)]
+impl OwnerNames for SystemOwnerNames {
+    fn get_user_name(&self, uid: u32) -> Option<String> {
+        self.cache.get_user_by_uid(uid).map(|user| user.name().to_string_lossy().to_string())
+    }
+
+    fn get_group_name(&self, gid: u32) -> Option<String> {
+        self.cache.get_group_by_gid(gid).map(|group| group.name().to_string_lossy().to_string())
+    }
+}
+
+/// Files have no owner ids on Windows, only the archived ones are met.
+#[cfg(windows)]
+pub struct SystemOwnerNames;
+
+#[cfg(windows)]
+impl OwnerNames for SystemOwnerNames {
+    fn get_user_name(&self, _: u32) -> Option<String> {
+        None
+    }
+
+    fn get_group_name(&self, _: u32) -> Option<String> {
+        None
+    }
+}
+
+impl SystemOwnerNames {
+    pub fn new() -> SystemOwnerNames {
+        #[cfg(unix)]
+        {
+            SystemOwnerNames { cache: UsersCache::new() }
+        }
+
+        #[cfg(windows)]
+        {
+            SystemOwnerNames
+        }
+    }
+}
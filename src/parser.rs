@@ -5,23 +5,60 @@ use std::collections::HashSet;
 use std::fmt;
 use std::fmt::Display;
 use std::fmt::Formatter;
-use std::ops::Index;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::Seek;
+use std::io::SeekFrom;
 use std::rc::Rc;
 use std::str::FromStr;
 
 use chrono::DateTime;
 use chrono::Local;
-use regex::Captures;
+use chrono::format::Item;
+use chrono::format::StrftimeItems;
 use regex::Regex;
 
 use lexer::Lexer;
 use lexer::Lexem;
 use field::Field;
 use function::Function;
+use mode;
+use template::CmdTemplate;
 use util::parse_datetime;
+use util::parse_duration;
+use util::parse_filesize;
+
+/// Dates are kept in this format until they are output.
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Windows file names can't hold newlines or tabs, while escaping would double every path separator.
+const DEFAULT_TABS_ESCAPE: bool = cfg!(not(windows));
+
+/// Query syntax error pointing to the lexem which couldn't be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// What was expected instead of the lexem found.
+    pub message: String,
+    /// Text of the offending lexem as written in the query, `None` at the end of the query.
+    pub found: Option<String>,
+    /// Character offset of the offending lexem in the query.
+    pub position: usize,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        match self.found {
+            Some(ref found) => write!(f, "{}, found '{}' at position {}", self.message, found, self.position),
+            None => write!(f, "{}, found end of query at position {}", self.message, self.position)
+        }
+    }
+}
 
 pub struct Parser {
     lexems: Vec<Lexem>,
+    spans: Vec<(usize, usize)>,
+    query: Vec<char>,
     index: usize,
 }
 
@@ -29,35 +66,133 @@ impl Parser {
     pub fn new() -> Parser {
         Parser {
             lexems: vec![],
+            spans: vec![],
+            query: vec![],
             index: 0
         }
     }
 
-    pub fn parse(&mut self, query: &str) -> Result<Query, String> {
+    /// Parses the query, the queries joined to it with `union` are attached to it.
+    /// Ordering, limit, offset and output format written after the last one apply to the whole union.
+    pub fn parse(&mut self, query: &str) -> Result<Query, ParseError> {
+        self.lexems.clear();
+        self.spans.clear();
+        self.query = query.chars().collect();
+        self.index = 0;
+
         let mut lexer = Lexer::new(query);
         while let Some(lexem) = lexer.next_lexem() {
             self.lexems.push(lexem);
+            self.spans.push(lexer.last_span());
         }
 
-        let fields = self.parse_fields()?;
-        let roots = self.parse_roots();
-        let expr = self.parse_where()?;
-        let (ordering_fields, ordering_asc) = self.parse_order_by(&fields)?;
+        let explain = self.parse_explain();
+        let mut queries: Vec<Query> = vec![];
+
+        loop {
+            let distinct = self.parse_distinct();
+            let fields_index = self.index;
+            let fields = self.parse_fields()?;
+
+            if let Some(first_query) = queries.first() {
+                if first_query.fields.len() != fields.len() {
+                    let message = format!("expected {} columns like in the first query of the union", first_query.fields.len());
+                    return Err(self.error_at(fields_index, &message));
+                }
+            }
+
+            let roots = self.parse_roots()?;
+            let expr = self.parse_where()?;
+            let dupes = self.parse_dupes();
+            let grouping_fields = self.parse_group_by(&fields)?;
+
+            queries.push(Query {
+                fields,
+                distinct,
+                roots,
+                expr,
+                dupes,
+                grouping_fields,
+                ordering_fields: vec![],
+                ordering_asc: Rc::new(vec![]),
+                limit: 0,
+                offset: 0,
+                output_format: OutputFormat::tabs(),
+                output_file: None,
+                date_format: DateFormat::Default,
+                summary: false,
+                progress: false,
+                explain,
+                unions: vec![],
+            });
+
+            match self.get_lexem() {
+                Some(Lexem::Union) => {},
+                _ => {
+                    self.drop_lexem();
+                    break;
+                }
+            }
+        }
+
+        let first_fields = queries[0].fields.clone();
+        let (ordering_fields, ordering_asc) = self.parse_order_by(&first_fields)?;
         let limit = self.parse_limit()?;
-        let output_format = self.parse_output_format()?;
+        let offset = self.parse_offset()?;
+        let mut date_format = self.parse_date_format()?;
+        let (output_format, output_file) = self.parse_output_format()?;
+        if date_format.is_none() {
+            date_format = self.parse_date_format()?;
+        }
+        let date_format = date_format.unwrap_or(DateFormat::Default);
+        let summary = self.parse_summary();
+        let progress = self.parse_progress();
 
-        Ok(Query {
-            fields,
-            roots,
-            expr,
-            ordering_fields,
-            ordering_asc: Rc::new(ordering_asc),
-            limit,
-            output_format,
-        })
+        if self.get_lexem().is_some() {
+            return Err(self.error("expected end of query"));
+        }
+
+        let ordering_asc = Rc::new(ordering_asc);
+        for (idx, query) in queries.iter_mut().enumerate() {
+            query.ordering_fields = match idx {
+                0 => ordering_fields.clone(),
+                _ => get_union_ordering_fields(&first_fields, &query.fields, &ordering_fields)
+            };
+            query.ordering_asc = ordering_asc.clone();
+            query.limit = limit;
+            query.offset = offset;
+            query.output_format = output_format.clone();
+            query.output_file = output_file.clone();
+            query.date_format = date_format.clone();
+            query.summary = summary;
+            query.progress = progress;
+        }
+
+        let mut query = queries.remove(0);
+        query.unions = queries;
+
+        Ok(query)
+    }
+
+    /// Takes an optional `select` keyword and reports whether `distinct` follows it.
+    fn parse_distinct(&mut self) -> bool {
+        let mut lexem = self.get_lexem();
+        if let Some(Lexem::RawString(ref s)) = lexem {
+            if s.eq_ignore_ascii_case("select") {
+                lexem = self.get_lexem();
+            }
+        }
+
+        match lexem {
+            Some(Lexem::Distinct) => true,
+            _ => {
+                self.drop_lexem();
+                false
+            }
+        }
     }
 
-    fn parse_fields(&mut self) -> Result<Vec<ColumnExpr>, String> {
+    fn parse_fields(&mut self) -> Result<Vec<ColumnExpr>, ParseError> {
         let mut fields = vec![];
 
         loop {
@@ -66,9 +201,22 @@ impl Parser {
                 Some(Lexem::Comma) => {
                     // skip
                 },
+                Some(Lexem::Open) => {
+                    self.drop_lexem();
+                    if let Some(mut field) = self.parse_column_expr()? {
+                        field.format = self.parse_column_format()?;
+                        field.alias = self.parse_alias(&fields)?;
+                        fields.push(field);
+                    }
+                },
                 Some(Lexem::String(ref s)) | Some(Lexem::RawString(ref s)) => {
                     if s.to_ascii_lowercase() != "select" {
                         if s == "*" {
+                            fields.push(ColumnExpr::field(Field::Name));
+                            fields.push(ColumnExpr::field(Field::Size));
+                            fields.push(ColumnExpr::field(Field::Modified));
+                            fields.push(ColumnExpr::field(Field::IsDir));
+
                             #[cfg(unix)]
                                 {
                                     fields.push(ColumnExpr::field(Field::Mode));
@@ -76,11 +224,13 @@ impl Parser {
                                     fields.push(ColumnExpr::field(Field::Group));
                                 }
 
-                            fields.push(ColumnExpr::field(Field::Size));
                             fields.push(ColumnExpr::field(Field::Path));
                         } else {
                             self.drop_lexem();
-                            if let Some(field) = self.parse_column_expr() {
+                            self.split_column_format();
+                            if let Some(mut field) = self.parse_column_expr()? {
+                                field.format = self.parse_column_format()?;
+                                field.alias = self.parse_alias(&fields)?;
                                 fields.push(field);
                             }
                         }
@@ -94,80 +244,297 @@ impl Parser {
         }
 
         if fields.is_empty() {
-            return Err(String::from("Error parsing fields, no selector found"))
+            self.get_lexem();
+            return Err(self.error("expected column list"))
         }
 
         Ok(fields)
     }
 
-    fn parse_column_expr(&mut self) -> Option<ColumnExpr> {
-        let mut column_expr = ColumnExpr::new();
+    /// Parses optional `as alias` after the column, every column has to be named differently.
+    fn parse_alias(&mut self, fields: &Vec<ColumnExpr>) -> Result<Option<String>, ParseError> {
+        match self.get_lexem() {
+            Some(Lexem::RawString(ref s)) if s.eq_ignore_ascii_case("as") => {},
+            _ => {
+                self.drop_lexem();
+                return Ok(None);
+            }
+        }
+
+        let alias = match self.get_lexem() {
+            Some(Lexem::String(ref s)) | Some(Lexem::RawString(ref s)) if !s.is_empty() => s.to_string(),
+            _ => return Err(self.error("expected column alias after AS"))
+        };
 
-        if let Some(left) = self.parse_expr() {
-            column_expr.left = Some(Box::new(left));
+        if fields.iter().any(|field| field.get_column_name().eq_ignore_ascii_case(&alias)) {
+            return Err(self.error(&format!("duplicate column name '{}'", alias)));
         }
 
-        if let Some(Lexem::ArithmeticOperator(op)) = self.get_lexem() {
-            column_expr.arithmetic_op = ArithmeticOp::from(op);
+        Ok(Some(alias))
+    }
+
+    /// A field name with the width glued to it, e.g. `name:40`, is split in two, so the field is parsed as usual.
+    fn split_column_format(&mut self) {
+        let (name, format) = match self.lexems.get(self.index) {
+            Some(&Lexem::RawString(ref s)) => match s.find(':') {
+                Some(pos) if pos > 0 && Field::from_str(&s[..pos]).is_ok() => (s[..pos].to_string(), s[pos..].to_string()),
+                _ => return
+            },
+            _ => return
+        };
+
+        let (start, end) = self.spans[self.index];
+        let split = start + name.chars().count();
 
-            if let Some(right) = self.parse_expr() {
-                column_expr.right = Some(Box::new(right));
+        self.lexems[self.index] = Lexem::RawString(name);
+        self.spans[self.index] = (start, split);
+        self.lexems.insert(self.index + 1, Lexem::RawString(format));
+        self.spans.insert(self.index + 1, (split, end));
+    }
+
+    /// Optional width of the column in the text output: `:40` pads or cuts the value to 40 characters,
+    /// `:>10` aligns it to the right, `:<10` to the left, which is the default.
+    fn parse_column_format(&mut self) -> Result<Option<ColumnFormat>, ParseError> {
+        let width = match self.get_lexem() {
+            Some(Lexem::RawString(ref s)) if s.starts_with(':') => s[1..].to_string(),
+            _ => {
+                self.drop_lexem();
+                return Ok(None);
+            }
+        };
+
+        let (right_align, width) = match width.is_empty() {
+            true => {
+                let right_align = match self.get_lexem() {
+                    Some(Lexem::Operator(ref op)) if op == ">" => true,
+                    Some(Lexem::Operator(ref op)) if op == "<" => false,
+                    _ => return Err(self.error("expected positive column width after ':', e.g. name:40 or size:>10"))
+                };
+
+                match self.get_lexem() {
+                    Some(Lexem::RawString(s)) => (right_align, s),
+                    _ => return Err(self.error("expected positive column width after alignment, e.g. name:<40 or size:>10"))
+                }
+            },
+            false => (false, width)
+        };
+
+        match width.parse::<usize>() {
+            Ok(width) if width > 0 => Ok(Some(ColumnFormat { width, right_align })),
+            _ => Err(self.error("expected positive column width, e.g. name:40 or size:>10"))
+        }
+    }
+
+    /// Parses column expression with optional arithmetic, `*` and `/` take precedence over `+` and `-`.
+    fn parse_column_expr(&mut self) -> Result<Option<ColumnExpr>, ParseError> {
+        let mut column_expr = match self.parse_term()? {
+            Some(term) => term,
+            None => return Ok(None)
+        };
+
+        loop {
+            match self.get_arithmetic_op(&[ArithmeticOp::Add, ArithmeticOp::Subtract]) {
+                Some(op) => {
+                    match self.parse_term()? {
+                        Some(right) => column_expr = ColumnExpr::arithmetic(column_expr, op, right),
+                        None => return Err(self.error_at_next("expected operand after arithmetic operator"))
+                    }
+                },
+                None => break
+            }
+        }
+
+        match column_expr.arithmetic_op {
+            Some(_) => Ok(Some(column_expr)),
+            None => Ok(Some(ColumnExpr::left(column_expr)))
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<Option<ColumnExpr>, ParseError> {
+        let mut term = match self.parse_factor()? {
+            Some(factor) => factor,
+            None => return Ok(None)
+        };
+
+        loop {
+            match self.get_arithmetic_op(&[ArithmeticOp::Multiply, ArithmeticOp::Divide]) {
+                Some(op) => {
+                    match self.parse_factor()? {
+                        Some(right) => term = ColumnExpr::arithmetic(term, op, right),
+                        None => return Err(self.error_at_next("expected operand after arithmetic operator"))
+                    }
+                },
+                None => break
+            }
+        }
+
+        Ok(Some(term))
+    }
+
+    fn parse_factor(&mut self) -> Result<Option<ColumnExpr>, ParseError> {
+        match self.get_lexem() {
+            Some(Lexem::Open) => {
+                let column_expr = self.parse_column_expr()?;
+
+                match self.get_lexem() {
+                    Some(Lexem::Close) => Ok(column_expr),
+                    _ => Err(self.error("expected closing parenthesis in arithmetic expression"))
+                }
+            },
+            _ => {
+                self.drop_lexem();
+                self.parse_expr()
+            }
+        }
+    }
+
+    /// Takes the next lexem if it's one of the expected arithmetic operators.
+    fn get_arithmetic_op(&mut self, expected: &[ArithmeticOp]) -> Option<ArithmeticOp> {
+        if let Some(Lexem::ArithmeticOperator(op)) = self.get_lexem() {
+            if let Some(op) = ArithmeticOp::from(op) {
+                if expected.contains(&op) {
+                    return Some(op);
+                }
             }
-        } else {
-            self.drop_lexem();
         }
 
-        Some(column_expr)
+        self.drop_lexem();
+
+        None
     }
 
-    fn parse_expr(&mut self) -> Option<ColumnExpr> {
+    fn parse_expr(&mut self) -> Result<Option<ColumnExpr>, ParseError> {
         let lexem = self.get_lexem();
         match lexem {
-            Some(Lexem::String(ref s)) | Some(Lexem::RawString(ref s)) => {
+            // other quoted text is taken literally, e.g. in concat(name, '.bak')
+            Some(Lexem::String(ref s)) => {
+                if let Ok(field) = Field::from_str(s) {
+                    return Ok(Some(ColumnExpr::field(field)));
+                }
+
+                Ok(Some(ColumnExpr::value(s.to_string())))
+            },
+            Some(Lexem::RawString(ref s)) => {
                 if let Ok(field) = Field::from_str(s) {
-                    return Some(ColumnExpr::field(field));
+                    return Ok(Some(ColumnExpr::field(field)));
                 }
 
                 if let Ok(function) = Function::from_str(s) {
-                    return Some(self.parse_function(function));
+                    return Ok(Some(self.parse_function(function)?));
                 }
 
-                Some(ColumnExpr::value(s.to_string()))
+                // other unquoted words are values, unless they look like a misspelled field name
+                if s.len() > 3 && s.chars().all(|c| c.is_ascii_alphabetic() || c == '_') && Field::suggest_name(s).is_some() {
+                    return Err(self.unknown_field_error(self.index - 1, s));
+                }
+
+                Ok(Some(ColumnExpr::value(s.to_string())))
             },
 
             _ => {
                 self.drop_lexem();
 
-                None
+                Ok(None)
             }
         }
     }
 
-    fn parse_function(&mut self, function: Function) -> ColumnExpr {
+    fn parse_function(&mut self, function: Function) -> Result<ColumnExpr, ParseError> {
+        let function_name = function.to_string().to_lowercase();
+        let (min_args, max_args) = function.get_arg_count_range();
         let mut function_expr = ColumnExpr::function(function);
 
-        if let Some(lexem) = self.get_lexem() {
-            if lexem != Lexem::Open {
-                panic!("Error in function expression");
-            }
+        match self.get_lexem() {
+            Some(Lexem::Open) => {},
+            _ => return Err(self.error(&format!("expected opening parenthesis after {}", function_name)))
         }
 
-        if let Some(function_arg) = self.parse_column_expr() {
-            function_expr.left = Some(Box::from(function_arg));
+        match self.get_lexem() {
+            Some(Lexem::Distinct) => function_expr.distinct = true,
+            _ => self.drop_lexem()
         }
 
-        if let Some(lexem) = self.get_lexem() {
-            if lexem != Lexem::Close {
-                panic!("Error in function expression");
+        if let Some(function_arg) = self.parse_column_expr()? {
+            function_expr.left = Some(Box::from(function_arg));
+
+            loop {
+                match self.get_lexem() {
+                    Some(Lexem::Comma) => {},
+                    _ => {
+                        self.drop_lexem();
+                        break;
+                    }
+                }
+
+                match self.parse_column_expr()? {
+                    Some(function_arg) => function_expr.args.push(function_arg),
+                    None => return Err(self.error_at_next(&format!("expected {} argument after comma", function_name)))
+                }
             }
         }
 
-        function_expr
+        match self.get_lexem() {
+            Some(Lexem::Close) => {},
+            _ => return Err(self.error(&format!("expected closing parenthesis after {} argument", function_name)))
+        }
+
+        let arg_count = function_expr.left.iter().count() + function_expr.args.len();
+        if arg_count < min_args || max_args.map_or(false, |max_args| arg_count > max_args) {
+            return Err(self.error(&format!("wrong number of arguments for {}", function_name)));
+        }
+
+        Ok(function_expr)
+    }
+
+    /// Query in parentheses in place of a search root, the paths it finds become the roots.
+    /// It could have its own ordering and limit, but not union and output options.
+    fn parse_subquery(&mut self) -> Result<Query, ParseError> {
+        let distinct = self.parse_distinct();
+        let fields_index = self.index;
+        let fields = self.parse_fields()?;
+
+        if !fields.iter().any(|column_expr| column_expr.get_field() == Some(Field::Path)) {
+            return Err(self.error_at(fields_index, "expected path column in subquery"));
+        }
+
+        let roots = self.parse_roots()?;
+        let expr = self.parse_where()?;
+        let dupes = self.parse_dupes();
+        let grouping_fields = self.parse_group_by(&fields)?;
+        let (ordering_fields, ordering_asc) = self.parse_order_by(&fields)?;
+        let limit = self.parse_limit()?;
+        let offset = self.parse_offset()?;
+
+        match self.get_lexem() {
+            Some(Lexem::Close) => {},
+            _ => return Err(self.error("expected closing parenthesis after subquery"))
+        }
+
+        Ok(Query {
+            fields,
+            distinct,
+            roots,
+            expr,
+            dupes,
+            grouping_fields,
+            ordering_fields,
+            ordering_asc: Rc::new(ordering_asc),
+            limit,
+            offset,
+            output_format: OutputFormat::List,
+            output_file: None,
+            date_format: DateFormat::Default,
+            summary: false,
+            progress: false,
+            explain: false,
+            unions: vec![],
+        })
     }
 
-    fn parse_roots(&mut self) -> Vec<Root> {
+    fn parse_roots(&mut self) -> Result<Vec<Root>, ParseError> {
+        #[derive(PartialEq)]
         enum RootParsingMode {
-            Unknown, From, Root, MinDepth, Depth, Options, Comma
+            Unknown, From, Root, MinDepth, Depth, IgnoreFile, MaxArchiveSize, ArchiveDepth, MaxNestedSize, MaxFiles, Archives, Prune, PruneList, Options, Comma
         }
 
         let mut roots: Vec<Root> = Vec::new();
@@ -192,49 +559,123 @@ impl Parser {
         }
 
         if let RootParsingMode::From = mode {
-            let mut path: String = String::from("");
-            let mut min_depth: u32 = 0;
-            let mut depth: u32 = 0;
-            let mut archives = false;
-            let mut symlinks = false;
-            let mut gitignore = false;
+            // options before the first root are the defaults of all the roots, each of them could override those
+            let mut defaults = Root::default();
+            let mut in_defaults = false;
+            let mut root = Root { path: String::new(), ..defaults.clone() };
 
             loop {
                 let lexem = self.get_lexem();
                 match lexem {
                     Some(ref lexem) => {
                         match lexem {
+                            &Lexem::RawString(ref s) if s.eq_ignore_ascii_case("options") && !in_defaults && roots.is_empty()
+                                && root.path.is_empty() && root.subquery.is_none() => {
+                                in_defaults = true;
+                                mode = RootParsingMode::Options;
+                            },
                             &Lexem::String(ref s) | &Lexem::RawString(ref s) => {
                                 match mode {
                                     RootParsingMode::From | RootParsingMode::Comma => {
-                                        path = s.to_string();
+                                        let root_index = self.index - 1;
+                                        // quoted paths are taken literally
+                                        match lexem {
+                                            &Lexem::RawString(_) => {
+                                                root.path = self.glue_hyphenated_word(s);
+                                                root.glob = is_root_pattern(&root.path);
+                                            },
+                                            _ => {
+                                                root.path = s.to_string();
+                                                root.glob = false;
+                                            }
+                                        }
+
+                                        if !is_quoted(lexem) && root.path.len() > 1 && root.path.starts_with('@') {
+                                            root.path = root.path[1..].to_string();
+                                            root.glob = false;
+                                            root.list = true;
+
+                                            match RootList::open(&root.path) {
+                                                Ok(list_file) => root.list_file = Some(list_file),
+                                                Err(err) => {
+                                                    return Err(self.error_at(root_index, &format!("cannot read the list of roots '{}': {}", root.path, err)));
+                                                }
+                                            }
+                                        }
                                         mode = RootParsingMode::Root;
                                     },
-                                    RootParsingMode::Root | RootParsingMode::Options => {
-                                        let s = s.to_ascii_lowercase();
-                                        if s == "mindepth" {
+                                    RootParsingMode::Archives if s.parse::<u32>().is_ok() => {
+                                        root.max_archive_entries = s.parse().unwrap();
+                                        mode = RootParsingMode::Options;
+                                    },
+                                    RootParsingMode::Options if in_defaults && is_quoted(lexem) => {
+                                        // a quoted word is never an option, so it's the first root
+                                        in_defaults = false;
+                                        defaults = Root { path: String::from("."), ..root.clone() };
+                                        self.drop_lexem();
+                                        mode = RootParsingMode::From;
+                                    },
+                                    RootParsingMode::Prune => {
+                                        let pattern = match lexem {
+                                            &Lexem::RawString(_) => self.glue_hyphenated_word(s),
+                                            _ => s.to_string()
+                                        };
+
+                                        match PrunePattern::new(&pattern) {
+                                            Ok(prune_pattern) => root.prune.push(prune_pattern),
+                                            _ => return Err(self.error("expected directory name pattern"))
+                                        }
+                                        mode = RootParsingMode::PruneList;
+                                    },
+                                    RootParsingMode::Root | RootParsingMode::Options | RootParsingMode::Archives | RootParsingMode::PruneList => {
+                                        let option_index = self.index - 1;
+                                        let option = match lexem {
+                                            &Lexem::RawString(_) => self.glue_hyphenated_word(s).to_ascii_lowercase(),
+                                            _ => s.to_ascii_lowercase()
+                                        };
+
+                                        if option == "mindepth" {
                                             mode = RootParsingMode::MinDepth;
-                                        } else if s == "maxdepth" || s == "depth" {
+                                        } else if option == "maxdepth" || option == "depth" {
                                             mode = RootParsingMode::Depth;
-                                        } else if s.starts_with("arc") {
-                                            archives = true;
-                                            mode = RootParsingMode::Options;
-                                        } else if s.starts_with("sym") {
-                                            symlinks = true;
-                                            mode = RootParsingMode::Options;
-                                        } else if s.starts_with("git") {
-                                            gitignore = true;
-                                            mode = RootParsingMode::Options;
-                                        } else {
+                                        } else if option == "maxarchivesize" {
+                                            mode = RootParsingMode::MaxArchiveSize;
+                                        } else if option == "archivedepth" {
+                                            mode = RootParsingMode::ArchiveDepth;
+                                        } else if option == "maxnestedsize" {
+                                            mode = RootParsingMode::MaxNestedSize;
+                                        } else if option == "maxfiles" {
+                                            mode = RootParsingMode::MaxFiles;
+                                        } else if option == "ignorefile" {
+                                            mode = RootParsingMode::IgnoreFile;
+                                        } else if option == "prune" {
+                                            mode = RootParsingMode::Prune;
+                                        } else if set_root_switch(&mut root, &option, !in_defaults).is_some() {
+                                            // after the options with values, as the switches are known by their first letters too
+                                            mode = match is_archives_switch(&option) {
+                                                true => RootParsingMode::Archives,
+                                                false => RootParsingMode::Options
+                                            };
+                                        } else if in_defaults {
+                                            // the first word which isn't an option is the first root
+                                            in_defaults = false;
+                                            defaults = Root { path: String::from("."), ..root.clone() };
+                                            self.index = option_index;
+                                            mode = RootParsingMode::From;
+                                        } else if is_root_list_end(&option) {
+                                            roots.push(root);
+
                                             self.drop_lexem();
                                             break;
+                                        } else {
+                                            return Err(self.error_at(option_index, &format!("unknown root option '{}'", option)));
                                         }
                                     },
                                     RootParsingMode::MinDepth => {
                                         let d: Result<u32, _> = s.parse();
                                         match d {
                                             Ok(d) => {
-                                                min_depth = d;
+                                                root.min_depth = d;
                                                 mode = RootParsingMode::Options;
                                             },
                                             _ => {
@@ -247,7 +688,7 @@ impl Parser {
                                         let d: Result<u32, _> = s.parse();
                                         match d {
                                             Ok(d) => {
-                                                depth = d;
+                                                root.max_depth = d;
                                                 mode = RootParsingMode::Options;
                                             },
                                             _ => {
@@ -256,18 +697,76 @@ impl Parser {
                                             }
                                         }
                                     },
+                                    RootParsingMode::IgnoreFile => {
+                                        root.ignore_file = Some(s.to_string());
+                                        mode = RootParsingMode::Options;
+                                    },
+                                    RootParsingMode::MaxArchiveSize => {
+                                        match parse_filesize(s) {
+                                            Some(size) => {
+                                                root.max_archive_size = size;
+                                                mode = RootParsingMode::Options;
+                                            },
+                                            None => return Err(self.error("expected archive size"))
+                                        }
+                                    },
+                                    RootParsingMode::ArchiveDepth => {
+                                        match s.parse::<u32>() {
+                                            Ok(n) if n > 0 => {
+                                                root.archive_depth = n;
+                                                mode = RootParsingMode::Options;
+                                            },
+                                            _ => return Err(self.error("expected archive depth"))
+                                        }
+                                    },
+                                    RootParsingMode::MaxNestedSize => {
+                                        match parse_filesize(s) {
+                                            Some(size) => {
+                                                root.max_nested_size = size;
+                                                mode = RootParsingMode::Options;
+                                            },
+                                            None => return Err(self.error("expected archive size"))
+                                        }
+                                    },
+                                    RootParsingMode::MaxFiles => {
+                                        match s.parse::<u32>() {
+                                            Ok(n) if n > 0 => {
+                                                root.max_files = n;
+                                                mode = RootParsingMode::Options;
+                                            },
+                                            _ => return Err(self.error("expected number of files"))
+                                        }
+                                    },
                                     _ => { }
                                 }
                             },
-                            &Lexem::Comma => {
-                                if path.len() > 0 {
-                                    roots.push(Root::new(path, min_depth, depth, archives, symlinks, gitignore));
+                            &Lexem::ArithmeticOperator(ref op) if op == "-" && root.path.is_empty() && root.subquery.is_none() => {
+                                if in_defaults {
+                                    in_defaults = false;
+                                    defaults = Root { path: String::from("."), ..root.clone() };
+                                    mode = RootParsingMode::From;
+                                }
 
-                                    path = String::from("");
-                                    depth = 0;
-                                    archives = false;
-                                    symlinks = false;
-                                    gitignore = false;
+                                match mode {
+                                    RootParsingMode::From | RootParsingMode::Comma => {
+                                        // the roots come from stdin
+                                        root.path = String::from("-");
+                                        root.list = true;
+                                        mode = RootParsingMode::Root;
+                                    },
+                                    _ => {
+                                        self.drop_lexem();
+                                        break
+                                    }
+                                }
+                            },
+                            &Lexem::Comma if mode == RootParsingMode::PruneList && self.is_prune_pattern_next() => {
+                                mode = RootParsingMode::Prune;
+                            },
+                            &Lexem::Comma if !in_defaults => {
+                                if root.path.len() > 0 || root.subquery.is_some() {
+                                    roots.push(root);
+                                    root = Root { path: String::new(), ..defaults.clone() };
 
                                     mode = RootParsingMode::Comma;
                                 } else {
@@ -275,9 +774,33 @@ impl Parser {
                                     break;
                                 }
                             },
+                            &Lexem::Open if root.path.is_empty() && root.subquery.is_none() => {
+                                if in_defaults {
+                                    in_defaults = false;
+                                    defaults = Root { path: String::from("."), ..root.clone() };
+                                    mode = RootParsingMode::From;
+                                }
+
+                                match mode {
+                                    RootParsingMode::From | RootParsingMode::Comma => {
+                                        root.subquery = Some(Box::new(self.parse_subquery()?));
+                                        mode = RootParsingMode::Root;
+                                    },
+                                    _ => {
+                                        self.drop_lexem();
+                                        break
+                                    }
+                                }
+                            },
+                            _ if mode == RootParsingMode::Prune => {
+                                return Err(self.error("expected directory name pattern"));
+                            },
+                            _ if in_defaults => {
+                                return Err(self.error("expected root after options"));
+                            },
                             _ => {
-                                if path.len() > 0 {
-                                    roots.push(Root::new(path, min_depth, depth, archives, symlinks, gitignore));
+                                if root.path.len() > 0 || root.subquery.is_some() {
+                                    roots.push(root);
                                 }
 
                                 self.drop_lexem();
@@ -285,9 +808,15 @@ impl Parser {
                             }
                         }
                     },
+                    None if mode == RootParsingMode::Prune => {
+                        return Err(self.error_at_next("expected directory name pattern"));
+                    },
+                    None if in_defaults => {
+                        return Err(self.error("expected root after options"));
+                    },
                     None => {
-                        if path.len() > 0 {
-                            roots.push(Root::new(path, min_depth, depth, archives, symlinks, gitignore));
+                        if root.path.len() > 0 || root.subquery.is_some() {
+                            roots.push(root);
                         }
                         break;
                     }
@@ -295,10 +824,49 @@ impl Parser {
             }
         }
 
-        roots
+        Ok(roots)
+    }
+
+    /// After a comma, a quoted directory name continues the list of `prune` patterns,
+    /// while an unquoted word or a path with a separator, `.` or `..` is the next root.
+    fn is_prune_pattern_next(&mut self) -> bool {
+        let index = self.index;
+        let result = match self.get_lexem() {
+            Some(Lexem::String(ref s)) => is_name_pattern(s),
+            _ => false
+        };
+
+        self.index = index;
+        result
+    }
+
+    /// Words joined with hyphens, like `follow-symlinks`, are split by the lexer, so they are glued back.
+    /// Only the parts written without spaces around the hyphens are taken.
+    fn glue_hyphenated_word(&mut self, first: &str) -> String {
+        let mut word = first.to_string();
+
+        loop {
+            let idx = self.index;
+            let is_glued = match (self.lexems.get(idx), self.lexems.get(idx + 1)) {
+                (Some(&Lexem::ArithmeticOperator(ref op)), Some(&Lexem::RawString(_))) if op == "-" => {
+                    self.spans[idx - 1].1 == self.spans[idx].0 && self.spans[idx].1 == self.spans[idx + 1].0
+                },
+                _ => false
+            };
+
+            if !is_glued {
+                return word;
+            }
+
+            if let Some(&Lexem::RawString(ref next)) = self.lexems.get(idx + 1) {
+                word.push('-');
+                word.push_str(next);
+            }
+            self.index += 2;
+        }
     }
 
-    fn parse_where(&mut self) -> Result<Option<Box<Expr>>, String> {
+    fn parse_where(&mut self) -> Result<Option<Box<Expr>>, ParseError> {
         let lexem = self.get_lexem();
 
         match lexem {
@@ -312,7 +880,7 @@ impl Parser {
         }
     }
 
-    fn parse_or(&mut self) -> Result<Option<Box<Expr>>, String> {
+    fn parse_or(&mut self) -> Result<Option<Box<Expr>>, ParseError> {
         let node = self.parse_and();
         match node {
             Ok(mut node) => {
@@ -339,7 +907,7 @@ impl Parser {
         }
     }
 
-    fn parse_and(&mut self) -> Result<Option<Box<Expr>>, String> {
+    fn parse_and(&mut self) -> Result<Option<Box<Expr>>, ParseError> {
         let node = self.parse_cond();
         match node {
             Ok(mut node) => {
@@ -366,52 +934,148 @@ impl Parser {
         }
     }
 
-    fn parse_cond(&mut self) -> Result<Option<Box<Expr>>, String> {
+    fn parse_cond(&mut self) -> Result<Option<Box<Expr>>, ParseError> {
         let lexem = self.get_lexem();
 
         match lexem {
+            Some(Lexem::Not) => {
+                match self.parse_cond() {
+                    Ok(Some(mut expr)) => {
+                        expr.negate = !expr.negate;
+                        Ok(Some(expr))
+                    },
+                    Ok(None) => Err(self.error_at_next("expected condition after NOT")),
+                    Err(err) => Err(err)
+                }
+            },
             Some(Lexem::RawString(ref s)) => {
+                let field_index = self.index - 1;
+
+                if let Ok(function) = Function::from_str(s) {
+                    if function.is_content_function() {
+                        return self.parse_content_function(function);
+                    }
+
+                    if function == Function::Xattr {
+                        return self.parse_xattr_cond();
+                    }
+
+                    if function.is_marker_function() {
+                        return self.parse_marker_cond(function, &s.to_ascii_lowercase());
+                    }
+                }
+
+                if let Some(Lexem::ArithmeticOperator(_)) = self.get_lexem() {
+                    self.drop_lexem();
+                    self.drop_lexem();
+                    return self.parse_arithmetic_cond();
+                }
+                self.drop_lexem();
+
+                let mut lexem2 = self.get_lexem();
+
+                let negate = match lexem2 {
+                    Some(Lexem::Not) => {
+                        lexem2 = self.get_lexem();
+                        true
+                    },
+                    _ => false
+                };
 
-                let lexem2 = self.get_lexem();
+                match lexem2 {
+                    Some(Lexem::Between) => return self.parse_between(s, field_index, negate),
+                    Some(Lexem::In) => return self.parse_in(s, field_index, negate),
+                    _ if negate => return Err(self.error("expected BETWEEN or IN after NOT")),
+                    _ => {}
+                }
 
                 if let Some(Lexem::Operator(ref s2)) = lexem2 {
+                    let op = Op::from(s2.to_string());
 
-                    let lexem3 = self.get_lexem();
+                    let val_index = self.index;
+                    if let Some(val_expr) = self.parse_compared_column_expr(&op)? {
+                        let field = Field::from_str(s).map_err(|_| self.unknown_field_error(field_index, s))?;
 
-                    match lexem3 {
-                        Some(Lexem::String(ref s3)) | Some(Lexem::RawString(ref s3)) => {
-                            let op = Op::from(s2.to_string());
-                            let mut expr: Expr;
-                            let field;
+                        if field.is_datetime_field() != val_expr.is_datetime() {
+                            let message = match field.is_datetime_field() {
+                                true => format!("expected date or date field to compare with '{}'", s),
+                                false => format!("expected value or field of the same kind as '{}'", s)
+                            };
+                            return Err(self.error_at(val_index, &message));
+                        }
+
+                        return Ok(Some(Box::new(Expr::compare_column_exprs(ColumnExpr::field(field), op, val_expr))));
+                    }
+
+                    let lexem3 = self.get_lexem();
+                    let is_raw = match lexem3 {
+                        Some(Lexem::RawString(_)) => true,
+                        _ => false
+                    };
+
+                    match lexem3 {
+                        Some(Lexem::String(ref s3)) | Some(Lexem::RawString(ref s3)) => {
+                            let mut expr: Expr;
+                            let field;
                             match Field::from_str(s) {
                                 Ok(field_) => field = field_,
-                                Err(err) => return Err(err)
+                                Err(_) => return Err(self.unknown_field_error(field_index, s))
                             }
                             if let Some(Op::Rx) = op {
                                 let regex;
                                 match Regex::new(&s3) {
                                     Ok(regex_) => regex = regex_,
-                                    _ => return Err("Error parsing regular expression".to_string())
+                                    _ => return Err(self.error("expected valid regular expression"))
                                 }
                                 expr = Expr::leaf_regex(field, op, s3.to_string(), regex);
                             } else if let Some(Op::Like) = op {
-                                let pattern = convert_like_to_pattern(s3);
+                                let pattern = match field {
+                                    Field::Mode => convert_mode_glob_to_pattern(s3),
+                                    _ => convert_like_to_pattern(s3)
+                                };
                                 let regex;
                                 match Regex::new(&pattern) {
                                     Ok(regex_) => regex = regex_,
-                                    _ => return Err("Error parsing LIKE expression".to_string())
+                                    _ => return Err(self.error("expected valid LIKE pattern"))
                                 }
 
                                 expr = Expr::leaf_regex(field, op, s3.to_string(), regex);
+                            } else if let Some(Op::Eqi) | Some(Op::Nei) = op {
+                                if field.is_numeric_field() || field.is_datetime_field() || field.is_boolean_field() {
+                                    return Err(self.error_at(field_index, &format!("expected text field to compare ignoring case, found '{}'", s)));
+                                }
+
+                                let regex = self.compile_glob(&convert_to_case_insensitive_pattern(s3))?;
+                                expr = Expr::leaf_regex(field, op, s3.to_string(), regex);
+                            } else if let Some(Op::Has) = op {
+                                if field != Field::Mode && field != Field::ModeOctal {
+                                    return Err(self.error_at(field_index, &format!("expected mode to check the bits of, found '{}'", s)));
+                                }
+
+                                if mode::parse_mode_bits(s3).is_none() {
+                                    return Err(self.error("expected permission bits like 'o+w' or 0002"));
+                                }
+
+                                expr = Expr::leaf(field, op, s3.to_string());
                             } else {
-                                expr = match is_glob(s3) {
+                                // an unquoted word is most likely a misspelled field name
+                                if is_raw && field.is_numeric_field() && !is_glob(s3)
+                                    && parse_filesize(s3).is_none() && parse_duration(s3).is_none() {
+                                    return Err(self.error("expected number or field name"));
+                                }
+
+                                let force_glob = match op {
+                                    Some(Op::Glob) => true,
+                                    _ => false
+                                };
+
+                                expr = match force_glob || is_glob(s3) {
                                     true => {
-                                        let pattern = convert_glob_to_pattern(s3);
-                                        let regex;
-                                        match Regex::new(&pattern) {
-                                            Ok(regex_) => regex = regex_,
-                                            _ => return Err("Error parsing glob pattern".to_string())
-                                        }
+                                        let pattern = match field {
+                                            Field::Mode => convert_mode_glob_to_pattern(s3),
+                                            _ => convert_glob_to_pattern(s3)
+                                        };
+                                        let regex = self.compile_glob(&pattern)?;
 
                                         Expr::leaf_regex(field, op, s3.to_string(), regex)
                                     },
@@ -419,25 +1083,29 @@ impl Parser {
                                 };
                             };
 
-                            let field = &Field::from_str(s)?;
-                            if field.is_datetime_field() {
-                                match parse_datetime(s3) {
-                                    Ok((dt_from, dt_to)) => {
-                                        expr.dt_from = Some(dt_from);
-                                        expr.dt_to = Some(dt_to);
-                                    },
-                                    Err(err) => {
-                                        return Err(err)
+                            if let Some(ref field) = expr.field.as_ref().and_then(|column_expr| column_expr.field.clone()) {
+                                if field.is_datetime_field() {
+                                    match parse_datetime(s3) {
+                                        Ok((dt_from, dt_to)) => {
+                                            expr.dt_from = Some(dt_from);
+                                            expr.dt_to = Some(dt_to);
+                                        },
+                                        Err(_) if is_raw => {
+                                            return Err(self.error("expected date or field name"))
+                                        },
+                                        Err(_) => {
+                                            return Err(self.error("expected date"))
+                                        }
                                     }
                                 }
                             }
 
                             Ok(Some(Box::new(expr)))
                         },
-                        _ => Err("Error parsing condition, no operand found".to_string())
+                        _ => Err(self.error(&format!("expected value after operator '{}'", s2)))
                     }
                 } else {
-                    Err("Error parsing condition, no operator found".to_string())
+                    Err(self.error(&format!("expected comparison operator after field '{}'", s)))
                 }
             },
             Some(Lexem::Open) => {
@@ -446,31 +1114,346 @@ impl Parser {
 
                 match lexem4 {
                     Some(Lexem::Close) => expr_result,
-                    _ => Ok(None)
+                    _ => Err(self.error("expected closing parenthesis"))
                 }
             },
             _ => Ok(None)
         }
     }
 
-    fn parse_order_by(&mut self, fields: &Vec<ColumnExpr>) -> Result<(Vec<ColumnExpr>, Vec<bool>), String> {
+    fn parse_arithmetic_cond(&mut self) -> Result<Option<Box<Expr>>, ParseError> {
+        let column_expr = match self.parse_column_expr()? {
+            Some(column_expr) => column_expr,
+            None => return Err(self.error_at_next("expected arithmetic expression"))
+        };
+
+        let op = match self.get_lexem() {
+            Some(Lexem::Operator(s)) => Op::from(s),
+            _ => return Err(self.error("expected comparison operator after arithmetic expression"))
+        };
+
+        let val_index = self.index;
+        if let Some(val_expr) = self.parse_compared_column_expr(&op)? {
+            if val_expr.is_datetime() {
+                return Err(self.error_at(val_index, "expected number or numeric field"));
+            }
+
+            return Ok(Some(Box::new(Expr::compare_column_exprs(column_expr, op, val_expr))));
+        }
+
+        match self.get_lexem() {
+            Some(Lexem::String(s)) | Some(Lexem::RawString(s)) => {
+                if s.parse::<i64>().is_err() && parse_filesize(&s).is_none() {
+                    return Err(self.error("expected number"));
+                }
+
+                Ok(Some(Box::new(Expr::leaf_column_expr(column_expr, op, s))))
+            },
+            _ => Err(self.error("expected value after comparison operator"))
+        }
+    }
+
+    /// The right side of a comparison is another column when it's an unquoted field name or a function call,
+    /// quoted values are always taken literally.
+    fn parse_compared_column_expr(&mut self, op: &Option<Op>) -> Result<Option<ColumnExpr>, ParseError> {
+        match op {
+            Some(Op::Rx) | Some(Op::Like) | Some(Op::Glob) | Some(Op::Has) | None => return Ok(None),
+            _ => {}
+        }
+
+        let is_column = match self.get_lexem() {
+            Some(Lexem::RawString(ref s)) if Field::from_str(s).is_ok() => true,
+            Some(Lexem::RawString(ref s)) if Function::from_str(s).is_ok() => {
+                let is_call = match self.get_lexem() {
+                    Some(Lexem::Open) => true,
+                    _ => false
+                };
+                self.drop_lexem();
+
+                is_call
+            },
+            _ => false
+        };
+        self.drop_lexem();
+
+        match is_column {
+            true => self.parse_column_expr(),
+            false => Ok(None)
+        }
+    }
+
+    fn parse_content_function(&mut self, function: Function) -> Result<Option<Box<Expr>>, ParseError> {
+        let function_name = function.to_string().to_lowercase();
+
+        match self.get_lexem() {
+            Some(Lexem::Open) => {},
+            _ => return Err(self.error(&format!("expected opening parenthesis after {}", function_name)))
+        }
+
+        let pattern = match self.get_lexem() {
+            Some(Lexem::String(s)) | Some(Lexem::RawString(s)) => s,
+            _ => return Err(self.error(&format!("expected search pattern in {}", function_name)))
+        };
+
+        let mut max_size = None;
+        let mut lexem = self.get_lexem();
+        if let Some(Lexem::Comma) = lexem {
+            match self.get_lexem() {
+                Some(Lexem::String(s)) | Some(Lexem::RawString(s)) => {
+                    if parse_filesize(&s).is_none() {
+                        return Err(self.error("expected file size limit"));
+                    }
+
+                    max_size = Some(s);
+                },
+                _ => return Err(self.error(&format!("expected file size limit in {}", function_name)))
+            }
+
+            lexem = self.get_lexem();
+        }
+
+        match lexem {
+            Some(Lexem::Close) => {},
+            _ => return Err(self.error(&format!("expected closing parenthesis after {} arguments", function_name)))
+        }
+
+        let regex = match function {
+            Function::Matches => {
+                match Regex::new(&pattern) {
+                    Ok(regex) => Some(regex),
+                    _ => return Err(self.error("expected valid regular expression"))
+                }
+            },
+            _ => None
+        };
+
+        Ok(Some(Box::new(Expr::content_search(function, pattern, max_size, regex))))
+    }
+
+    /// Parses `is_newer_than('path')` or `is_older_than('path')`, the marker file is read when the search starts.
+    fn parse_marker_cond(&mut self, function: Function, function_name: &str) -> Result<Option<Box<Expr>>, ParseError> {
+        match self.get_lexem() {
+            Some(Lexem::Open) => {},
+            _ => return Err(self.error(&format!("expected opening parenthesis after {}", function_name)))
+        }
+
+        let path = match self.get_lexem() {
+            Some(Lexem::String(ref s)) | Some(Lexem::RawString(ref s)) if !s.is_empty() => s.to_string(),
+            _ => return Err(self.error(&format!("expected path of the marker file in {}", function_name)))
+        };
+
+        match self.get_lexem() {
+            Some(Lexem::Close) => {},
+            _ => return Err(self.error(&format!("expected closing parenthesis after {} argument", function_name)))
+        }
+
+        Ok(Some(Box::new(Expr::marker(function, path))))
+    }
+
+    /// Parses `xattr('name') op value`, the attribute value is compared as a string.
+    fn parse_xattr_cond(&mut self) -> Result<Option<Box<Expr>>, ParseError> {
+        match self.get_lexem() {
+            Some(Lexem::Open) => {},
+            _ => return Err(self.error("expected opening parenthesis after xattr"))
+        }
+
+        let name = match self.get_lexem() {
+            Some(Lexem::String(s)) | Some(Lexem::RawString(s)) => s,
+            _ => return Err(self.error("expected attribute name in xattr"))
+        };
+
+        match self.get_lexem() {
+            Some(Lexem::Close) => {},
+            _ => return Err(self.error("expected closing parenthesis after xattr argument"))
+        }
+
+        let op = match self.get_lexem() {
+            Some(Lexem::Operator(s)) => Op::from(s),
+            _ => return Err(self.error("expected comparison operator after xattr"))
+        };
+
+        let val = match self.get_lexem() {
+            Some(Lexem::String(s)) | Some(Lexem::RawString(s)) => s,
+            _ => return Err(self.error("expected value after comparison operator"))
+        };
+
+        let pattern = match op {
+            Some(Op::Rx) => Some(val.clone()),
+            Some(Op::Like) => Some(convert_like_to_pattern(&val)),
+            Some(Op::Glob) => Some(convert_glob_to_pattern(&val)),
+            Some(Op::Eq) | Some(Op::Ne) if is_glob(&val) => Some(convert_glob_to_pattern(&val)),
+            Some(Op::Eqi) | Some(Op::Nei) => Some(convert_to_case_insensitive_pattern(&val)),
+            _ => None
+        };
+
+        let mut function_expr = ColumnExpr::function(Function::Xattr);
+        function_expr.left = Some(Box::new(ColumnExpr::value(name)));
+
+        let mut expr = Expr::leaf_column_expr(function_expr, op, val);
+        if let Some(pattern) = pattern {
+            match Regex::new(&pattern) {
+                Ok(regex) => expr.regex = Some(regex),
+                _ => return Err(self.error("expected valid regular expression"))
+            }
+        }
+
+        Ok(Some(Box::new(expr)))
+    }
+
+    fn parse_between(&mut self, field_name: &str, field_index: usize, negate: bool) -> Result<Option<Box<Expr>>, ParseError> {
+        let field = Field::from_str(field_name).map_err(|_| self.unknown_field_error(field_index, field_name))?;
+
+        let lower = match self.get_lexem() {
+            Some(Lexem::String(s)) | Some(Lexem::RawString(s)) => s,
+            _ => return Err(self.error("expected lower bound after BETWEEN"))
+        };
+        let lower_index = self.index - 1;
+
+        match self.get_lexem() {
+            Some(Lexem::And) => {},
+            _ => return Err(self.error("expected AND after lower bound of BETWEEN"))
+        }
+
+        let upper = match self.get_lexem() {
+            Some(Lexem::String(s)) | Some(Lexem::RawString(s)) => s,
+            _ => return Err(self.error("expected upper bound after AND"))
+        };
+
+        let lower_expr = Expr::leaf_value(field.clone(), Op::Gte, lower)
+            .map_err(|_| self.error_at(lower_index, "expected date"))?;
+        let upper_expr = Expr::leaf_value(field, Op::Lte, upper)
+            .map_err(|_| self.error("expected date"))?;
+
+        let mut expr = Expr::node(Some(Box::new(lower_expr)), Some(LogicalOp::And), Some(Box::new(upper_expr)));
+        expr.negate = negate;
+
+        Ok(Some(Box::new(expr)))
+    }
+
+    fn parse_in(&mut self, field_name: &str, field_index: usize, negate: bool) -> Result<Option<Box<Expr>>, ParseError> {
+        let field = Field::from_str(field_name).map_err(|_| self.unknown_field_error(field_index, field_name))?;
+
+        match self.get_lexem() {
+            Some(Lexem::Open) => {},
+            _ => return Err(self.error("expected opening parenthesis after IN"))
+        }
+
+        let mut expr: Option<Box<Expr>> = None;
+
+        loop {
+            let value = match self.get_lexem() {
+                Some(Lexem::String(s)) | Some(Lexem::RawString(s)) => s,
+                _ => return Err(self.error("expected value in IN list"))
+            };
+
+            let value_expr = Expr::leaf_value(field.clone(), Op::Eq, value)
+                .map_err(|_| self.error("expected date"))?;
+            let value_expr = Box::new(value_expr);
+            expr = match expr {
+                Some(expr) => Some(Box::new(Expr::node(Some(expr), Some(LogicalOp::Or), Some(value_expr)))),
+                None => Some(value_expr)
+            };
+
+            match self.get_lexem() {
+                Some(Lexem::Comma) => {},
+                Some(Lexem::Close) => break,
+                _ => return Err(self.error("expected comma or closing parenthesis in IN list"))
+            }
+        }
+
+        if let Some(ref mut expr) = expr {
+            expr.negate = negate;
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_group_by(&mut self, fields: &Vec<ColumnExpr>) -> Result<Vec<ColumnExpr>, ParseError> {
+        let mut group_by_fields: Vec<ColumnExpr> = vec![];
+
+        match self.get_lexem() {
+            Some(Lexem::RawString(ref s)) if s.to_ascii_lowercase() == "group" => {
+                if let Some(Lexem::By) = self.get_lexem() {
+                    loop {
+                        match self.get_lexem() {
+                            Some(Lexem::Comma) => {},
+                            Some(Lexem::RawString(ref grouping_field)) => {
+                                let actual_field = match grouping_field.parse::<usize>() {
+                                    Ok(idx) if idx > 0 && idx <= fields.len() => fields[idx - 1].clone(),
+                                    Ok(_) => return Err(self.error("expected column index in range")),
+                                    _ => match self.find_aliased_column(fields, grouping_field)? {
+                                        Some(column_expr) => column_expr,
+                                        None => ColumnExpr::field(self.parse_field_name(grouping_field)?)
+                                    },
+                                };
+                                group_by_fields.push(actual_field);
+                            },
+                            Some(Lexem::String(ref alias)) => match self.find_aliased_column(fields, alias)? {
+                                Some(column_expr) => group_by_fields.push(column_expr),
+                                None => return Err(self.error(&format!("unknown column alias '{}'", alias)))
+                            },
+                            _ => {
+                                self.drop_lexem();
+                                break;
+                            },
+                        }
+                    }
+                } else {
+                    self.drop_lexem();
+                    self.drop_lexem();
+                }
+            },
+            _ => {
+                self.drop_lexem();
+            }
+        }
+
+        Ok(group_by_fields)
+    }
+
+    fn parse_order_by(&mut self, fields: &Vec<ColumnExpr>) -> Result<(Vec<ColumnExpr>, Vec<bool>), ParseError> {
         let mut order_by_fields: Vec<ColumnExpr> = vec![];
         let mut order_by_directions: Vec<bool> = vec![];
 
         if let Some(Lexem::Order) = self.get_lexem() {
             if let Some(Lexem::By) = self.get_lexem() {
                 loop {
-                    use std::str::FromStr;
                     match self.get_lexem() {
                         Some(Lexem::Comma) => {},
                         Some(Lexem::RawString(ref ordering_field)) => {
                             let actual_field = match ordering_field.parse::<usize>() {
-                                Ok(idx) => fields[idx - 1].clone(),
-                                _ => ColumnExpr::field(Field::from_str(ordering_field)?),
+                                Ok(idx) if idx > 0 && idx <= fields.len() => fields[idx - 1].clone(),
+                                Ok(_) => return Err(self.error("expected column index in range")),
+                                _ => match self.find_aliased_column(fields, ordering_field)? {
+                                    Some(column_expr) => column_expr,
+                                    None if self.is_column_expr_start(ordering_field) => {
+                                        self.drop_lexem();
+                                        match self.parse_column_expr()? {
+                                            Some(column_expr) => column_expr,
+                                            None => return Err(self.error("expected column expression"))
+                                        }
+                                    },
+                                    None => ColumnExpr::field(self.parse_field_name(ordering_field)?),
+                                },
                             };
                             order_by_fields.push(actual_field.clone());
                             order_by_directions.push(true);
                         },
+                        Some(Lexem::String(ref alias)) => match self.find_aliased_column(fields, alias)? {
+                            Some(column_expr) => {
+                                order_by_fields.push(column_expr);
+                                order_by_directions.push(true);
+                            },
+                            None => return Err(self.error(&format!("unknown column alias '{}'", alias)))
+                        },
+                        Some(Lexem::Open) => {
+                            self.drop_lexem();
+                            match self.parse_column_expr()? {
+                                Some(column_expr) => order_by_fields.push(column_expr),
+                                None => return Err(self.error("expected column expression"))
+                            }
+                            order_by_directions.push(true);
+                        },
                         Some(Lexem::DescendingOrder) => {
                             let cnt = order_by_directions.len();
                             order_by_directions[cnt - 1] = false;
@@ -492,7 +1475,19 @@ impl Parser {
     }
 
 
-    fn parse_limit<'a>(&mut self) -> Result<u32, &'a str> {
+    /// Tells whether the ordering word just taken starts a function call or arithmetic rather than being a single field.
+    fn is_column_expr_start(&mut self, s: &str) -> bool {
+        let result = match self.get_lexem() {
+            Some(Lexem::Open) => Function::from_str(s).is_ok(),
+            Some(Lexem::ArithmeticOperator(_)) => Field::from_str(s).is_ok(),
+            _ => false
+        };
+        self.drop_lexem();
+
+        result
+    }
+
+    fn parse_limit(&mut self) -> Result<u32, ParseError> {
         let lexem = self.get_lexem();
         match lexem {
             Some(Lexem::Limit) => {
@@ -502,12 +1497,11 @@ impl Parser {
                         if let Ok(limit) = s.parse() {
                             return Ok(limit);
                         } else {
-                            return Err("Error parsing limit");
+                            return Err(self.error("expected limit value"));
                         }
                     },
                     _ => {
-                        self.drop_lexem();
-                        return Err("Error parsing limit, limit value not found");
+                        return Err(self.error("expected limit value"));
                     }
                 }
             },
@@ -519,40 +1513,202 @@ impl Parser {
         Ok(0)
     }
 
-    fn parse_output_format<'a>(&mut self) -> Result<OutputFormat, &'a str>{
+    fn parse_offset(&mut self) -> Result<u32, ParseError> {
         let lexem = self.get_lexem();
         match lexem {
-            Some(Lexem::Into) => {
+            Some(Lexem::Offset) => {
                 let lexem = self.get_lexem();
                 match lexem {
                     Some(Lexem::RawString(s)) | Some(Lexem::String(s)) => {
+                        if let Ok(offset) = s.parse() {
+                            return Ok(offset);
+                        } else {
+                            return Err(self.error("expected offset value"));
+                        }
+                    },
+                    _ => {
+                        return Err(self.error("expected offset value"));
+                    }
+                }
+            },
+            _ => {
+                self.drop_lexem();
+            }
+        }
+
+        Ok(0)
+    }
+
+    /// Takes an optional `dupes` keyword which leaves only the files having duplicates.
+    fn parse_dupes(&mut self) -> bool {
+        if let Some(Lexem::RawString(ref s)) = self.get_lexem() {
+            if s.eq_ignore_ascii_case("dupes") {
+                return true;
+            }
+        }
+
+        self.drop_lexem();
+        false
+    }
+
+    /// Takes an optional `explain` keyword which starts the query.
+    fn parse_explain(&mut self) -> bool {
+        if let Some(Lexem::RawString(ref s)) = self.get_lexem() {
+            if s.eq_ignore_ascii_case("explain") {
+                return true;
+            }
+        }
+
+        self.drop_lexem();
+        false
+    }
+
+    /// Takes an optional `summary` keyword which follows the `into` clause.
+    fn parse_summary(&mut self) -> bool {
+        if let Some(Lexem::RawString(ref s)) = self.get_lexem() {
+            if s.eq_ignore_ascii_case("summary") {
+                return true;
+            }
+        }
+
+        self.drop_lexem();
+        false
+    }
+
+    /// Takes an optional `progress` keyword which ends the query.
+    fn parse_progress(&mut self) -> bool {
+        if let Some(Lexem::RawString(ref s)) = self.get_lexem() {
+            if s.eq_ignore_ascii_case("progress") {
+                return true;
+            }
+        }
+
+        self.drop_lexem();
+        false
+    }
+
+    /// Parses `dateformat iso` or `dateformat FORMAT` with `strftime`-like specifiers.
+    fn parse_date_format(&mut self) -> Result<Option<DateFormat>, ParseError> {
+        match self.get_lexem() {
+            Some(Lexem::DateFormat) => {
+                match self.get_lexem() {
+                    Some(Lexem::RawString(s)) | Some(Lexem::String(s)) => {
+                        if s.eq_ignore_ascii_case("iso") {
+                            return Ok(Some(DateFormat::Iso));
+                        }
+
+                        if s.is_empty() || StrftimeItems::new(&s).any(|item| item == Item::Error) {
+                            return Err(self.error("expected valid date format"));
+                        }
+
+                        Ok(Some(DateFormat::Custom(s)))
+                    },
+                    _ => Err(self.error("expected date format"))
+                }
+            },
+            _ => {
+                self.drop_lexem();
+                Ok(None)
+            }
+        }
+    }
+
+    /// Parses `into FORMAT [FILE]`, results go to stdout unless the file is given.
+    fn parse_output_format(&mut self) -> Result<(OutputFormat, Option<String>), ParseError> {
+        let lexem = self.get_lexem();
+        match lexem {
+            Some(Lexem::Into) => {
+                let lexem = self.get_lexem();
+                let output_format = match lexem {
+                    Some(Lexem::RawString(s)) | Some(Lexem::String(s)) => {
+                        if s.to_lowercase().starts_with("sqlite:") {
+                            return match parse_sqlite_target(&s["sqlite:".len()..]) {
+                                Some(output_format) => Ok((output_format, None)),
+                                None => Err(self.error("expected SQLite database path"))
+                            };
+                        }
+
                         let s = s.to_lowercase();
-                        if s == "lines" {
-                            return Ok(OutputFormat::Lines);
+                        if s == "cmd" {
+                            OutputFormat::Cmd { template: self.parse_cmd_template()? }
+                        } else if s == "lines" {
+                            OutputFormat::Lines { escape: self.parse_escape_option(false) }
                         } else if s == "list" {
-                            return Ok(OutputFormat::List);
+                            OutputFormat::List
                         } else if s == "csv" {
-                            return Ok(OutputFormat::Csv);
+                            OutputFormat::Csv
                         } else if s == "json" {
-                            return Ok(OutputFormat::Json);
+                            OutputFormat::Json
+                        } else if s == "jsonl" || s == "ndjson" {
+                            OutputFormat::JsonLines
                         } else if s == "tabs" {
-                            return Ok(OutputFormat::Tabs);
+                            OutputFormat::Tabs { escape: self.parse_escape_option(DEFAULT_TABS_ESCAPE) }
+                        } else if s == "html" {
+                            OutputFormat::Html
+                        } else if s == "md" || s == "markdown" {
+                            OutputFormat::Markdown
+                        } else if s == "table" {
+                            OutputFormat::Table { truncate: true }
+                        } else if s == "table:full" {
+                            OutputFormat::Table { truncate: false }
                         } else {
-                            return Err("Unknown output format");
+                            return Err(self.error("expected output format"));
                         }
                     },
+                    _ => {
+                        return Err(self.error("expected output format"));
+                    }
+                };
+
+                let output_file = match self.get_lexem() {
+                    // a file named like a keyword which could end the query has to be quoted
+                    Some(Lexem::RawString(ref s)) if s.eq_ignore_ascii_case("summary") || s.eq_ignore_ascii_case("progress") => {
+                        self.drop_lexem();
+                        None
+                    },
+                    Some(Lexem::RawString(s)) | Some(Lexem::String(s)) => Some(s),
                     _ => {
                         self.drop_lexem();
-                        return Err("Error parsing output format");
+                        None
                     }
-                }
+                };
+
+                return Ok((output_format, output_file));
             },
             _ => {
                 self.drop_lexem();
             }
         }
 
-        Ok(OutputFormat::Tabs)
+        Ok((OutputFormat::tabs(), None))
+    }
+
+    /// Parses the command template following `into cmd`, its placeholders should be the names of fields.
+    fn parse_cmd_template(&mut self) -> Result<CmdTemplate, ParseError> {
+        match self.get_lexem() {
+            Some(Lexem::String(s)) | Some(Lexem::RawString(s)) => {
+                CmdTemplate::parse(&s).map_err(|placeholder| {
+                    let name = placeholder.trim_start_matches('{').trim_end_matches('}');
+                    match Field::suggest_name(name) {
+                        Some(suggestion) => self.error(&format!("unknown placeholder '{}' in the command template (did you mean '{{{}}}'?)", placeholder, suggestion)),
+                        None => self.error(&format!("unknown placeholder '{}' in the command template", placeholder))
+                    }
+                })
+            },
+            _ => Err(self.error("expected command template"))
+        }
+    }
+
+    /// Parses optional `esc` or `raw` after the format, telling whether the values should be escaped.
+    fn parse_escape_option(&mut self, default: bool) -> bool {
+        match self.get_lexem() {
+            Some(Lexem::RawString(ref s)) if s.eq_ignore_ascii_case("esc") => true,
+            Some(Lexem::RawString(ref s)) if s.eq_ignore_ascii_case("raw") => false,
+            _ => {
+                self.drop_lexem();
+                default
+            }
+        }
     }
 
     fn get_lexem(&mut self) -> Option<Lexem> {
@@ -568,79 +1724,432 @@ impl Parser {
     fn drop_lexem(&mut self) {
         self.index -= 1;
     }
-}
-
-fn is_glob(s: &str) -> bool {
-    s.contains("*") || s.contains('?')
-}
 
-fn convert_glob_to_pattern(s: &str) -> String {
-    let string = s.to_string();
-    let regex = Regex::new("(\\?|\\.|\\*|\\[|\\]|\\(|\\)|\\^|\\$)").unwrap();
-    let string = regex.replace_all(&string, |c: &Captures| {
-        match c.index(0) {
-            "." => "\\.",
-            "*" => ".*",
-            "?" => ".",
-            "[" => "\\[",
-            "]" => "\\]",
-            "(" => "\\(",
-            ")" => "\\)",
-            "^" => "\\^",
-            "$" => "\\$",
-            _ => panic!("Error parsing glob")
-        }.to_string()
-    });
+    fn parse_field_name(&self, s: &str) -> Result<Field, ParseError> {
+        Field::from_str(s).map_err(|_| self.unknown_field_error(self.index.saturating_sub(1), s))
+    }
 
-    format!("^(?i){}$", string)
-}
+    /// Error for a word that isn't a field name, the closest field name is suggested if there's any.
+    fn unknown_field_error(&self, idx: usize, s: &str) -> ParseError {
+        match Field::suggest_name(s) {
+            Some(name) => self.error_at(idx, &format!("expected field name (did you mean '{}'?)", name)),
+            None => self.error_at(idx, "expected field name")
+        }
+    }
 
-fn convert_like_to_pattern(s: &str) -> String {
-    let string = s.to_string();
-    let regex = Regex::new("(%|_|\\?|\\.|\\*|\\[|\\]|\\(|\\)|\\^|\\$)").unwrap();
-    let string = regex.replace_all(&string, |c: &Captures| {
-        match c.index(0) {
-            "%" => ".*",
-            "_" => ".",
-            "?" => ".?",
-            "." => "\\.",
-            "*" => "\\*",
-            "[" => "\\[",
-            "]" => "\\]",
-            "(" => "\\(",
-            ")" => "\\)",
-            "^" => "\\^",
-            "$" => "\\$",
-            _ => panic!("Error parsing like expression")
-        }.to_string()
-    });
-
-    format!("^(?i){}$", string)
-}
+    /// Column named so in the select list, either by its alias or by itself, it's looked up before the field names.
+    /// A name given to more than one column is ambiguous, unless they are the same expression,
+    /// e.g. the alias of one column is the name of a field selected after it.
+    fn find_aliased_column(&self, fields: &Vec<ColumnExpr>, name: &str) -> Result<Option<ColumnExpr>, ParseError> {
+        let mut candidates: Vec<&ColumnExpr> = vec![];
 
-#[derive(Debug, Clone)]
-pub struct Query {
-    pub fields: Vec<ColumnExpr>,
-    pub roots: Vec<Root>,
-    pub expr: Option<Box<Expr>>,
-    pub ordering_fields: Vec<ColumnExpr>,
-    pub ordering_asc: Rc<Vec<bool>>,
-    pub limit: u32,
-    pub output_format: OutputFormat,
-}
+        for column_expr in fields.iter().filter(|column_expr| column_expr.get_column_name().eq_ignore_ascii_case(name)) {
+            if !candidates.iter().any(|candidate| candidate.to_string() == column_expr.to_string()) {
+                candidates.push(column_expr);
+            }
+        }
 
-impl Query {
-    pub fn get_all_fields(&self) -> HashSet<Field> {
+        match candidates.len() {
+            0 => Ok(None),
+            // a column named after its field is the same as the field itself
+            1 if candidates[0].alias.is_none() => Ok(None),
+            1 => Ok(Some(candidates[0].clone())),
+            _ => {
+                let names: Vec<String> = candidates.iter().map(|column_expr| column_expr.to_string().to_lowercase()).collect();
+                Err(self.error(&format!("ambiguous column '{}', it could be {}", name, names.join(" or "))))
+            }
+        }
+    }
+
+    /// Regex of a glob converted to a pattern, the error points to the glob just taken.
+    fn compile_glob(&self, pattern: &str) -> Result<Regex, ParseError> {
+        Regex::new(pattern).map_err(|_| self.error("expected valid glob pattern"))
+    }
+
+    /// Error pointing to the lexem taken last.
+    fn error(&self, message: &str) -> ParseError {
+        self.error_at(self.index.saturating_sub(1), message)
+    }
+
+    /// Error pointing to the lexem which is going to be taken next.
+    fn error_at_next(&self, message: &str) -> ParseError {
+        self.error_at(self.index, message)
+    }
+
+    fn error_at(&self, idx: usize, message: &str) -> ParseError {
+        match self.spans.get(idx) {
+            Some(&(start, end)) => ParseError {
+                message: message.to_string(),
+                found: Some(self.query[start..end].iter().collect()),
+                position: start,
+            },
+            None => ParseError {
+                message: message.to_string(),
+                found: None,
+                position: self.query.len(),
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref TABLE_NAME_REGEX: Regex = Regex::new("^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+}
+
+/// Parses `PATH[:TABLE][:append]`. Path itself could contain colons, e.g. on Windows,
+/// so the optional parts are taken from the right.
+fn parse_sqlite_target(s: &str) -> Option<OutputFormat> {
+    let mut parts: Vec<&str> = s.split(':').collect();
+
+    let mut append = false;
+    if parts.len() > 1 && parts[parts.len() - 1].eq_ignore_ascii_case("append") {
+        append = true;
+        parts.pop();
+    }
+
+    let mut table = String::from("files");
+    if parts.len() > 1 && TABLE_NAME_REGEX.is_match(parts[parts.len() - 1]) {
+        table = parts.pop().unwrap().to_string();
+    }
+
+    let path = parts.join(":");
+    if path.is_empty() {
+        return None;
+    }
+
+    Some(OutputFormat::Sqlite { path, table, append })
+}
+
+/// Columns of the union are named after the first query, so ordering by one of them
+/// means ordering by the column at the same position in every query.
+fn get_union_ordering_fields(first_fields: &Vec<ColumnExpr>, fields: &Vec<ColumnExpr>, ordering_fields: &Vec<ColumnExpr>) -> Vec<ColumnExpr> {
+    ordering_fields.iter().map(|ordering_field| {
+        let key = ordering_field.to_string().to_lowercase();
+        match first_fields.iter().position(|field| field.to_string().to_lowercase() == key) {
+            Some(idx) => fields[idx].clone(),
+            None => ordering_field.clone()
+        }
+    }).collect()
+}
+
+pub fn is_glob(s: &str) -> bool {
+    s.contains('*') || s.contains('?') || find_glob_class_end(s, 0).is_some()
+}
+
+/// Turns on or off the root option which has no value, e.g. `symlinks` or `follow-symlinks`,
+/// `no` or `no-` in front turns it off, e.g. `nosymlinks` or `no-follow-symlinks`.
+/// With `by_prefix` a word starting like a switch is taken too, it's how the options after a root were always read.
+/// Returns the value set, or nothing if there's no such option.
+fn set_root_switch(root: &mut Root, name: &str, by_prefix: bool) -> Option<bool> {
+    let (name, value) = match name {
+        _ if name.starts_with("no-") => (&name[3..], false),
+        _ if name.starts_with("no") => (&name[2..], false),
+        _ => (name, true)
+    };
+
+    match name {
+        "symlinks" | "sym" | "follow-symlinks" => root.symlinks = value,
+        "archives" | "arc" | "search-archives" => root.archives = value,
+        "gitignore" | "git" | "apply-gitignore" => root.gitignore = value,
+        "gitignored" => root.gitignored = value,
+        "globalignore" | "global-ignore" => root.global_ignore = value,
+        "dedup" => root.dedup = value,
+        "lenient" => root.lenient = value,
+        "onefilesystem" | "one-filesystem" => root.one_filesystem = value,
+        "ordered" => root.ordered = value,
+        // e.g. `symlink` or `archive`
+        _ if by_prefix && name.starts_with("sym") => root.symlinks = value,
+        _ if by_prefix && name.starts_with("arc") => root.archives = value,
+        _ if by_prefix && name.starts_with("git") => root.gitignore = value,
+        _ => return None
+    }
+
+    Some(value)
+}
+
+/// `archives` could be followed by the number of entries to examine.
+fn is_archives_switch(name: &str) -> bool {
+    name == "search-archives" || name.starts_with("arc")
+}
+
+/// Words of the clauses which could follow the roots without a keyword of the lexer.
+fn is_root_list_end(s: &str) -> bool {
+    match s {
+        "dupes" | "group" | "summary" | "progress" => true,
+        _ => false
+    }
+}
+
+/// Names of directories have no path separators.
+fn is_name_pattern(s: &str) -> bool {
+    !s.is_empty() && s != "." && s != ".." && !s.contains('/') && !s.contains('\\')
+}
+
+fn is_quoted(lexem: &Lexem) -> bool {
+    match lexem {
+        &Lexem::String(_) => true,
+        _ => false
+    }
+}
+
+/// Unquoted root paths with wildcards or braces are expanded to the paths they match.
+fn is_root_pattern(s: &str) -> bool {
+    is_glob(s) || s.contains('{')
+}
+
+/// Translates glob into an anchored case-insensitive regex.
+/// `*` and `?` don't match path separators, `**` matches any number of directories,
+/// `[abc]`, `[a-z]` and `[!abc]` match a single character of the class.
+/// Everything else is matched as is.
+pub fn convert_glob_to_pattern(s: &str) -> String {
+    glob_to_pattern(s, "(?is)^")
+}
+
+/// Translates a pattern of a symbolic mode like `-rwxr-?r-?` into an anchored regex.
+/// Globs work as usual, `_` and `%` of LIKE do too, but the case matters as `s` and `S` mean different bits.
+pub fn convert_mode_glob_to_pattern(s: &str) -> String {
+    let glob: String = s.chars()
+        .map(|c| match c {
+            '_' => '?',
+            '%' => '*',
+            c => c
+        })
+        .collect();
+
+    glob_to_pattern(&glob, "(?s)^")
+}
+
+fn glob_to_pattern(s: &str, prefix: &str) -> String {
+    let mut pattern = String::from(prefix);
+    let chars: Vec<char> = s.chars().collect();
+    let mut idx = 0;
+
+    while idx < chars.len() {
+        match chars[idx] {
+            '*' if chars.get(idx + 1) == Some(&'*') => {
+                idx += 1;
+                if is_glob_separator(chars.get(idx + 1)) {
+                    idx += 1;
+                    pattern.push_str("(?:.*[/\\\\])?");
+                } else {
+                    pattern.push_str(".*");
+                }
+            },
+            '*' => pattern.push_str("[^/\\\\]*"),
+            '?' => pattern.push_str("[^/\\\\]"),
+            '[' if find_glob_class_end(s, idx).is_some() => {
+                let end = find_glob_class_end(s, idx).unwrap();
+                pattern.push('[');
+                idx += 1;
+                if chars[idx] == '!' || chars[idx] == '^' {
+                    pattern.push('^');
+                    idx += 1;
+                }
+                while idx < end {
+                    match chars[idx] {
+                        '-' => pattern.push('-'),
+                        c => pattern.push_str(&regex::escape(&c.to_string())),
+                    }
+                    idx += 1;
+                }
+                pattern.push(']');
+            },
+            c => pattern.push_str(&regex::escape(&c.to_string()))
+        }
+
+        idx += 1;
+    }
+
+    pattern.push('$');
+
+    pattern
+}
+
+/// Anchored case-insensitive regex matching the value as a whole, globs are translated as usual.
+fn convert_to_case_insensitive_pattern(s: &str) -> String {
+    match is_glob(s) {
+        true => convert_glob_to_pattern(s),
+        false => format!("(?is)^{}$", regex::escape(s))
+    }
+}
+
+fn is_glob_separator(c: Option<&char>) -> bool {
+    match c {
+        Some('/') | Some('\\') => true,
+        _ => false
+    }
+}
+
+/// Index of the `]` closing the character class that starts at `start`, if there's a class at all.
+/// A `]` right after the opening bracket or the negation is a part of the class.
+fn find_glob_class_end(s: &str, start: usize) -> Option<usize> {
+    let chars: Vec<char> = s.chars().collect();
+    let open = chars.iter().skip(start).position(|&c| c == '[')? + start;
+
+    let mut idx = open + 1;
+    if chars.get(idx) == Some(&'!') || chars.get(idx) == Some(&'^') {
+        idx += 1;
+    }
+    if chars.get(idx) == Some(&']') {
+        idx += 1;
+    }
+
+    chars.iter().skip(idx).position(|&c| c == ']').map(|pos| pos + idx)
+}
+
+/// Translates SQL LIKE pattern into an anchored case-insensitive regex.
+/// `%` matches any sequence of characters, `_` matches exactly one character,
+/// backslash makes them literal. Everything else is matched as is.
+fn convert_like_to_pattern(s: &str) -> String {
+    let mut pattern = String::from("(?is)^");
+    let mut escape_next = false;
+
+    for c in s.chars() {
+        match c {
+            '\\' if !escape_next => escape_next = true,
+            '%' if !escape_next => pattern.push_str(".*"),
+            '_' if !escape_next => pattern.push('.'),
+            _ => {
+                pattern.push_str(&regex::escape(&c.to_string()));
+                escape_next = false;
+            }
+        }
+    }
+
+    if escape_next {
+        pattern.push_str(&regex::escape("\\"));
+    }
+
+    pattern.push('$');
+
+    pattern
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub fields: Vec<ColumnExpr>,
+    pub distinct: bool,
+    pub roots: Vec<Root>,
+    pub expr: Option<Box<Expr>>,
+    pub dupes: bool,
+    pub grouping_fields: Vec<ColumnExpr>,
+    pub ordering_fields: Vec<ColumnExpr>,
+    pub ordering_asc: Rc<Vec<bool>>,
+    pub limit: u32,
+    pub offset: u32,
+    pub output_format: OutputFormat,
+    pub output_file: Option<String>,
+    pub date_format: DateFormat,
+    /// Numbers of matched and shown files, their total size and the time spent are printed to stderr after the results
+    pub summary: bool,
+    pub progress: bool,
+    /// The query plan is printed instead of searching
+    pub explain: bool,
+    /// Queries joined to this one with `union`, they share its ordering, limit and output format
+    pub unions: Vec<Query>,
+}
+
+impl Query {
+    pub fn get_all_fields(&self) -> HashSet<Field> {
         let mut result = HashSet::new();
 
         for column_expr in &self.fields {
             result.extend(column_expr.get_required_fields());
         }
 
+        for column_expr in &self.grouping_fields {
+            result.extend(column_expr.get_required_fields());
+        }
+
+        if let OutputFormat::Cmd { ref template } = self.output_format {
+            result.extend(template.get_fields());
+        }
+
         result
     }
 }
 
+/// Plan of the query printed by `explain`: every clause on its own line, fields and functions by their internal names.
+impl Display for Query {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let fields: Vec<String> = self.fields.iter()
+            .map(|column_expr| {
+                let column = match column_expr.format {
+                    Some(ref format) => format!("{}{}", column_expr, format),
+                    None => column_expr.to_string()
+                };
+                match column_expr.alias {
+                    Some(ref alias) => format!("{} as {}", column, alias),
+                    None => column
+                }
+            })
+            .collect();
+        match self.distinct {
+            true => writeln!(fmt, "select distinct: {}", fields.join(", "))?,
+            false => writeln!(fmt, "select: {}", fields.join(", "))?
+        }
+
+        writeln!(fmt, "from:")?;
+        for root in &self.roots {
+            writeln!(fmt, "  {}", root)?;
+            if let Some(ref subquery) = root.subquery {
+                for line in subquery.to_string().lines() {
+                    writeln!(fmt, "    {}", line)?;
+                }
+            }
+        }
+
+        if let Some(ref expr) = self.expr {
+            writeln!(fmt, "where:")?;
+            expr.fmt_tree(fmt, 1)?;
+        }
+
+        if self.dupes {
+            writeln!(fmt, "dupes")?;
+        }
+
+        if !self.grouping_fields.is_empty() {
+            let fields: Vec<String> = self.grouping_fields.iter().map(|column_expr| column_expr.to_string()).collect();
+            writeln!(fmt, "group by: {}", fields.join(", "))?;
+        }
+
+        if !self.ordering_fields.is_empty() {
+            let fields: Vec<String> = self.ordering_fields.iter()
+                .zip(self.ordering_asc.iter())
+                .map(|(column_expr, &asc)| format!("{} {}", column_expr, if asc { "asc" } else { "desc" }))
+                .collect();
+            writeln!(fmt, "order by: {}", fields.join(", "))?;
+        }
+
+        if self.limit > 0 {
+            writeln!(fmt, "limit: {}", self.limit)?;
+        }
+
+        if self.offset > 0 {
+            writeln!(fmt, "offset: {}", self.offset)?;
+        }
+
+        if self.date_format != DateFormat::Default {
+            writeln!(fmt, "dateformat: {}", self.date_format)?;
+        }
+
+        match self.output_file {
+            Some(ref output_file) => writeln!(fmt, "into: {} '{}'", self.output_format, output_file)?,
+            None => writeln!(fmt, "into: {}", self.output_format)?
+        }
+
+        if self.summary {
+            writeln!(fmt, "summary")?;
+        }
+
+        if self.progress {
+            writeln!(fmt, "progress")?;
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Root {
     pub path: String,
@@ -649,15 +2158,210 @@ pub struct Root {
     pub archives: bool,
     pub symlinks: bool,
     pub gitignore: bool,
+    /// Only the files `.gitignore` files exclude are found, the opposite of `gitignore`
+    pub gitignored: bool,
+    pub dedup: bool,
+    pub lenient: bool,
+    /// Don't descend into directories on other file systems than the root's one
+    pub one_filesystem: bool,
+    /// Number of entries examined in every archive, 0 for all of them
+    pub max_archive_entries: u32,
+    /// Archives larger than this are not opened, 0 for any size
+    pub max_archive_size: u64,
+    /// Length of the chain of archives inside each other that is searched, 0 for the default one
+    pub archive_depth: u32,
+    /// Archives inside archives larger than this are not read into memory, 0 for the default size
+    pub max_nested_size: u64,
+    /// Entries of every directory are visited sorted by name rather than in the order the file system gives
+    pub ordered: bool,
+    /// Number of files examined in every directory, 0 for all of them. Subdirectories are always visited.
+    pub max_files: u32,
+    /// Name of the ignore file to honor instead of `.fselectignore`
+    pub ignore_file: Option<String>,
+    /// Patterns of the user-wide ignore file are applied
+    pub global_ignore: bool,
+    /// Query finding the paths to search at, they all share the options of this root
+    pub subquery: Option<Box<Query>>,
+    /// The path is a glob or brace pattern, every path it matches is searched as a root with these options
+    pub glob: bool,
+    /// The path is a file listing the roots, `-` for stdin, every root listed is searched with these options
+    pub list: bool,
+    /// The file listing the roots, opened when the query is parsed
+    pub list_file: Option<RootList>,
+    /// Directories with the names matching any of these are found, but not descended into
+    pub prune: Vec<PrunePattern>,
 }
 
 impl Root {
+    #[cfg(test)]
     fn new(path: String, min_depth: u32, max_depth: u32, archives: bool, symlinks: bool, gitignore: bool) -> Root {
-        Root { path, min_depth, max_depth, archives, symlinks, gitignore }
+        Root { path, min_depth, max_depth, archives, symlinks, gitignore, ..Root::default() }
     }
 
     fn default() -> Root {
-        Root { path: String::from("."), min_depth: 0, max_depth: 0, archives: false, symlinks: false, gitignore: false }
+        Root { path: String::from("."), min_depth: 0, max_depth: 0, archives: false, symlinks: false, gitignore: false, dedup: true, lenient: false, one_filesystem: false, max_archive_entries: 0, max_archive_size: 0, archive_depth: 0, max_nested_size: 0, gitignored: false, ordered: false, max_files: 0, ignore_file: None, global_ignore: true, subquery: None, glob: false, list: false, list_file: None, prune: vec![] }
+    }
+}
+
+/// Path followed by the options that differ from the defaults, the same way they are written in the query.
+impl Display for Root {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self.subquery {
+            Some(_) => fmt.write_str("(subquery)")?,
+            None if self.list && self.path == "-" => fmt.write_str("-")?,
+            None if self.list => write!(fmt, "@{}", self.path)?,
+            None if self.glob => fmt.write_str(&self.path)?,
+            None => write!(fmt, "'{}'", self.path)?
+        }
+
+        if self.min_depth > 0 {
+            write!(fmt, " mindepth {}", self.min_depth)?;
+        }
+        if self.max_depth > 0 {
+            write!(fmt, " depth {}", self.max_depth)?;
+        }
+        if self.archives {
+            fmt.write_str(" archives")?;
+            if self.max_archive_entries > 0 {
+                write!(fmt, " {}", self.max_archive_entries)?;
+            }
+        }
+        if self.max_archive_size > 0 {
+            write!(fmt, " maxarchivesize {}", self.max_archive_size)?;
+        }
+        if self.archive_depth > 0 {
+            write!(fmt, " archivedepth {}", self.archive_depth)?;
+        }
+        if self.max_nested_size > 0 {
+            write!(fmt, " maxnestedsize {}", self.max_nested_size)?;
+        }
+        if self.symlinks {
+            fmt.write_str(" symlinks")?;
+        }
+        if self.gitignore {
+            fmt.write_str(" gitignore")?;
+        }
+        if self.gitignored {
+            fmt.write_str(" gitignored")?;
+        }
+        if !self.dedup {
+            fmt.write_str(" nodedup")?;
+        }
+        if self.lenient {
+            fmt.write_str(" lenient")?;
+        }
+        if self.one_filesystem {
+            fmt.write_str(" onefilesystem")?;
+        }
+        if self.ordered {
+            fmt.write_str(" ordered")?;
+        }
+        if self.max_files > 0 {
+            write!(fmt, " maxfiles {}", self.max_files)?;
+        }
+        if let Some(ref ignore_file) = self.ignore_file {
+            write!(fmt, " ignorefile '{}'", ignore_file)?;
+        }
+        if !self.global_ignore {
+            fmt.write_str(" noglobalignore")?;
+        }
+        if !self.prune.is_empty() {
+            let patterns: Vec<String> = self.prune.iter().map(|pattern| format!("'{}'", pattern.glob)).collect();
+            write!(fmt, " prune {}", patterns.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// File listing the roots, the file checked when the query is parsed is the file read when the search gets to it.
+#[derive(Debug, Clone)]
+pub struct RootList {
+    file: Rc<File>,
+}
+
+impl RootList {
+    /// A pipe like `@<(find ...)` is fine, a directory isn't.
+    pub fn open(path: &str) -> io::Result<RootList> {
+        let file = File::open(path)?;
+        if file.metadata()?.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::Other, "it's a directory"));
+        }
+
+        Ok(RootList { file: Rc::new(file) })
+    }
+
+    /// Reads the list from its start every time, but a pipe could be read only once.
+    pub fn reader(&self) -> BufReader<&File> {
+        let mut file = &*self.file;
+        let _ = file.seek(SeekFrom::Start(0));
+
+        BufReader::new(file)
+    }
+}
+
+/// The lists are told apart by their paths.
+impl PartialEq for RootList {
+    fn eq(&self, _: &RootList) -> bool {
+        true
+    }
+}
+
+/// Glob of the names of the directories which are not descended into, e.g. `node_modules` or `*.egg-info`.
+#[derive(Debug, Clone)]
+pub struct PrunePattern {
+    pub glob: String,
+    regex: Regex,
+}
+
+impl PrunePattern {
+    fn new(glob: &str) -> Result<PrunePattern, regex::Error> {
+        let regex = Regex::new(&convert_glob_to_pattern(glob))?;
+        Ok(PrunePattern { glob: glob.to_string(), regex })
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
+impl PartialEq for PrunePattern {
+    fn eq(&self, other: &PrunePattern) -> bool {
+        self.glob == other.glob
+    }
+}
+
+/// Width and alignment of the column in the `lines` and `tabs` output.
+#[derive(Debug, Clone, PartialOrd, PartialEq, Eq, Hash, Serialize)]
+pub struct ColumnFormat {
+    pub width: usize,
+    pub right_align: bool,
+}
+
+impl ColumnFormat {
+    /// Pads the value with spaces or cuts it with an ellipsis, the characters are counted rather than bytes.
+    pub fn apply(&self, value: &str) -> String {
+        let len = value.chars().count();
+        if len > self.width {
+            let mut result: String = value.chars().take(self.width - 1).collect();
+            result.push('…');
+            return result;
+        }
+
+        let padding = " ".repeat(self.width - len);
+        match self.right_align {
+            true => padding + value,
+            false => value.to_string() + &padding
+        }
+    }
+}
+
+impl Display for ColumnFormat {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self.right_align {
+            true => write!(fmt, ":>{}", self.width),
+            false => write!(fmt, ":{}", self.width)
+        }
     }
 }
 
@@ -668,40 +2372,59 @@ pub struct ColumnExpr {
     pub right: Option<Box<ColumnExpr>>,
     pub field: Option<Field>,
     pub function: Option<Function>,
+    /// Function arguments following the first one, which is kept in `left`.
+    pub args: Vec<ColumnExpr>,
+    pub distinct: bool,
     pub val: Option<String>,
+    /// Width of the column in the text output, it's not a part of the column name
+    pub format: Option<ColumnFormat>,
+    /// Name given with `as`, it replaces the expression in the headers and the JSON keys
+    pub alias: Option<String>,
 }
 
 impl ColumnExpr {
-    pub fn new() -> ColumnExpr {
+    pub fn left(left: ColumnExpr) -> ColumnExpr {
         ColumnExpr {
-            left: None,
+            left: Some(Box::new(left)),
             arithmetic_op: None,
             right: None,
             field: None,
             function: None,
+            args: vec![],
+            distinct: false,
             val: None,
+            format: None,
+            alias: None,
         }
     }
 
-    pub fn left(left: ColumnExpr) -> ColumnExpr {
+    pub fn field(field: Field) -> ColumnExpr {
         ColumnExpr {
-            left: Some(Box::new(left)),
+            left: None,
             arithmetic_op: None,
             right: None,
-            field: None,
+            field: Some(field),
             function: None,
+            args: vec![],
+            distinct: false,
             val: None,
+            format: None,
+            alias: None,
         }
     }
 
-    pub fn field(field: Field) -> ColumnExpr {
+    fn arithmetic(left: ColumnExpr, op: ArithmeticOp, right: ColumnExpr) -> ColumnExpr {
         ColumnExpr {
-            left: None,
-            arithmetic_op: None,
-            right: None,
-            field: Some(field),
+            left: Some(Box::new(left)),
+            arithmetic_op: Some(op),
+            right: Some(Box::new(right)),
+            field: None,
             function: None,
+            args: vec![],
+            distinct: false,
             val: None,
+            format: None,
+            alias: None,
         }
     }
 
@@ -712,7 +2435,11 @@ impl ColumnExpr {
             right: None,
             field: None,
             function: Some(function),
+            args: vec![],
+            distinct: false,
             val: None,
+            format: None,
+            alias: None,
         }
     }
 
@@ -723,7 +2450,11 @@ impl ColumnExpr {
             right: None,
             field: None,
             function: None,
+            args: vec![],
+            distinct: false,
             val: Some(value),
+            format: None,
+            alias: None,
         }
     }
 
@@ -740,6 +2471,10 @@ impl ColumnExpr {
             }
         }
 
+        if self.args.iter().any(|arg| arg.has_aggregate_function()) {
+            return true;
+        }
+
         if let Some(ref function) = self.function {
             if function.is_aggregate_function() {
                 return true;
@@ -749,6 +2484,56 @@ impl ColumnExpr {
         false
     }
 
+    /// Tells whether the function of this expression counts only distinct values, e.g. `count(distinct ext)`.
+    pub fn is_distinct(&self) -> bool {
+        if self.distinct {
+            return true;
+        }
+
+        match (&self.field, &self.arithmetic_op, &self.left) {
+            (&None, &None, &Some(ref left)) => left.is_distinct(),
+            _ => false
+        }
+    }
+
+    /// Returns the field this expression consists of, looking through the wrapping expressions.
+    pub fn get_field(&self) -> Option<Field> {
+        if let Some(ref field) = self.field {
+            return Some(field.clone());
+        }
+
+        match (&self.function, &self.arithmetic_op, &self.left) {
+            (&None, &None, &Some(ref left)) => left.get_field(),
+            _ => None
+        }
+    }
+
+    /// Returns the function this expression consists of, looking through the wrapping expressions.
+    pub fn get_function(&self) -> Option<Function> {
+        if let Some(ref function) = self.function {
+            return Some(function.clone());
+        }
+
+        match (&self.field, &self.arithmetic_op, &self.left) {
+            (&None, &None, &Some(ref left)) => left.get_function(),
+            _ => None
+        }
+    }
+
+    /// Tells whether the expression results in a date, i.e. it's a date field or an aggregate of dates.
+    pub fn is_datetime(&self) -> bool {
+        if let Some(field) = self.get_field() {
+            return field.is_datetime_field();
+        }
+
+        match (&self.function, &self.arithmetic_op, &self.left) {
+            (&Some(Function::Min), &None, &Some(ref left)) | (&Some(Function::Max), &None, &Some(ref left))
+            | (&Some(Function::Avg), &None, &Some(ref left)) | (&Some(Function::Sum), &None, &Some(ref left))
+            | (&None, &None, &Some(ref left)) => left.is_datetime(),
+            _ => false
+        }
+    }
+
     pub fn get_required_fields(&self) -> HashSet<Field> {
         let mut result = HashSet::new();
 
@@ -760,30 +2545,71 @@ impl ColumnExpr {
             result.extend(right.get_required_fields());
         }
 
+        for arg in &self.args {
+            result.extend(arg.get_required_fields());
+        }
+
         if let Some(ref field) = self.field {
             result.insert(field.clone());
         }
 
         result
     }
-}
 
-impl Display for ColumnExpr {
+    /// Name of the column in the headers and the JSON keys.
+    pub fn get_column_name(&self) -> String {
+        match self.alias {
+            Some(ref alias) => alias.clone(),
+            None => self.to_string().to_lowercase()
+        }
+    }
+
+    /// Nested arithmetic is put in parentheses to keep the order of evaluation visible.
+    fn to_operand_string(&self) -> String {
+        match self.arithmetic_op {
+            Some(_) => format!("({})", self),
+            None => self.to_string()
+        }
+    }
+}
+
+impl Display for ColumnExpr {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
         use std::fmt::Write;
         if let Some(ref function) = self.function {
             fmt.write_str(&function.to_string())?;
             fmt.write_char('(')?;
+            if self.distinct {
+                fmt.write_str("distinct ")?;
+            }
             if let Some(ref left) = self.left {
                 fmt.write_str(&left.to_string())?;
             }
+            for arg in &self.args {
+                fmt.write_str(", ")?;
+                fmt.write_str(&arg.to_string())?;
+            }
             fmt.write_char(')')?;
+        } else if let Some(ref arithmetic_op) = self.arithmetic_op {
+            if let Some(ref left) = self.left {
+                fmt.write_str(&left.to_operand_string())?;
+            }
+            write!(fmt, " {} ", arithmetic_op)?;
+            if let Some(ref right) = self.right {
+                fmt.write_str(&right.to_operand_string())?;
+            }
+        } else if let Some(ref left) = self.left {
+            fmt.write_str(&left.to_string())?;
         }
 
         if let Some(ref field) = self.field {
             fmt.write_str(&field.to_string())?;
         }
 
+        if let Some(ref val) = self.val {
+            fmt.write_str(val)?;
+        }
+
         Ok(())
     }
 }
@@ -793,10 +2619,13 @@ pub struct Expr {
     pub left: Option<Box<Expr>>,
     pub logical_op: Option<LogicalOp>,
     pub right: Option<Box<Expr>>,
+    pub negate: bool,
 
     pub field: Option<ColumnExpr>,
     pub op: Option<Op>,
     pub val: Option<String>,
+    /// Field or expression compared with instead of the literal `val`, e.g. `created` in `modified > created`.
+    pub val_expr: Option<ColumnExpr>,
     pub regex: Option<Regex>,
 
     pub dt_from: Option<DateTime<Local>>,
@@ -809,10 +2638,12 @@ impl Expr {
             left,
             logical_op,
             right,
+            negate: false,
 
             field: None,
             op: None,
             val: None,
+            val_expr: None,
             regex: None,
 
             dt_from: None,
@@ -825,10 +2656,73 @@ impl Expr {
             left: None,
             logical_op: None,
             right: None,
+            negate: false,
 
             field: Some(ColumnExpr::field(field)),
             op,
             val: Some(val),
+            val_expr: None,
+            regex: None,
+
+            dt_from: None,
+            dt_to: None,
+        }
+    }
+
+    fn leaf_column_expr(column_expr: ColumnExpr, op: Option<Op>, val: String) -> Expr {
+        Expr {
+            left: None,
+            logical_op: None,
+            right: None,
+            negate: false,
+
+            field: Some(column_expr),
+            op,
+            val: Some(val),
+            val_expr: None,
+            regex: None,
+
+            dt_from: None,
+            dt_to: None,
+        }
+    }
+
+    fn content_search(function: Function, pattern: String, max_size: Option<String>, regex: Option<Regex>) -> Expr {
+        let mut function_expr = ColumnExpr::function(function);
+        function_expr.left = Some(Box::new(ColumnExpr::value(pattern.clone())));
+        function_expr.right = max_size.map(|max_size| Box::new(ColumnExpr::value(max_size)));
+
+        Expr {
+            left: None,
+            logical_op: None,
+            right: None,
+            negate: false,
+
+            field: Some(function_expr),
+            op: None,
+            val: Some(pattern),
+            val_expr: None,
+            regex,
+
+            dt_from: None,
+            dt_to: None,
+        }
+    }
+
+    fn marker(function: Function, path: String) -> Expr {
+        let mut function_expr = ColumnExpr::function(function);
+        function_expr.left = Some(Box::new(ColumnExpr::value(path.clone())));
+
+        Expr {
+            left: None,
+            logical_op: None,
+            right: None,
+            negate: false,
+
+            field: Some(function_expr),
+            op: None,
+            val: Some(path),
+            val_expr: None,
             regex: None,
 
             dt_from: None,
@@ -836,15 +2730,150 @@ impl Expr {
         }
     }
 
+    /// Paths of the marker files `is_newer_than` and `is_older_than` compare the files with.
+    pub fn get_marker_paths(&self) -> Vec<String> {
+        let mut result = vec![];
+
+        if let Some(ref left) = self.left {
+            result.extend(left.get_marker_paths());
+        }
+
+        if let Some(ref right) = self.right {
+            result.extend(right.get_marker_paths());
+        }
+
+        if let (&Some(ColumnExpr { function: Some(ref function), .. }), &Some(ref val)) = (&self.field, &self.val) {
+            if function.is_marker_function() {
+                result.push(val.clone());
+            }
+        }
+
+        result
+    }
+
+    pub fn has_content_search(&self) -> bool {
+        if let Some(ref left) = self.left {
+            if left.has_content_search() {
+                return true;
+            }
+        }
+
+        if let Some(ref right) = self.right {
+            if right.has_content_search() {
+                return true;
+            }
+        }
+
+        match self.field {
+            Some(ColumnExpr { function: Some(ref function), .. }) => function.is_content_function(),
+            _ => false
+        }
+    }
+
+    pub fn get_required_fields(&self) -> HashSet<Field> {
+        let mut result = HashSet::new();
+
+        if let Some(ref left) = self.left {
+            result.extend(left.get_required_fields());
+        }
+
+        if let Some(ref right) = self.right {
+            result.extend(right.get_required_fields());
+        }
+
+        if let Some(ref field) = self.field {
+            result.extend(field.get_required_fields());
+        }
+
+        if let Some(ref val_expr) = self.val_expr {
+            result.extend(val_expr.get_required_fields());
+        }
+
+        result
+    }
+
+    fn compare_column_exprs(column_expr: ColumnExpr, op: Option<Op>, val_expr: ColumnExpr) -> Expr {
+        let mut expr = Expr::leaf_column_expr(column_expr, op, val_expr.to_string());
+        expr.val_expr = Some(val_expr);
+
+        expr
+    }
+
+    /// Plain comparison with a value, no patterns involved.
+    /// Dates are expanded into intervals just like in ordinary conditions.
+    fn leaf_value(field: Field, op: Op, val: String) -> Result<Expr, String> {
+        let mut expr = Expr::leaf(field.clone(), Some(op), val);
+
+        if field.is_datetime_field() {
+            let (dt_from, dt_to) = parse_datetime(expr.val.as_ref().unwrap())?;
+            expr.dt_from = Some(dt_from);
+            expr.dt_to = Some(dt_to);
+        }
+
+        Ok(expr)
+    }
+
+    /// Writes the condition tree, one condition or logical operator per line indented by its depth.
+    /// Conditions show the compiled pattern and the date interval they are checked against.
+    fn fmt_tree(&self, fmt: &mut Formatter, depth: usize) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        let not = if self.negate { "not " } else { "" };
+
+        if let Some(ref logical_op) = self.logical_op {
+            writeln!(fmt, "{}{}{}", indent, not, logical_op)?;
+            if let Some(ref left) = self.left {
+                left.fmt_tree(fmt, depth + 1)?;
+            }
+            if let Some(ref right) = self.right {
+                right.fmt_tree(fmt, depth + 1)?;
+            }
+
+            return Ok(());
+        }
+
+        if let Some(ref field) = self.field {
+            write!(fmt, "{}{}{}", indent, not, field)?;
+
+            if let Some(ref op) = self.op {
+                match (&self.val_expr, &self.val) {
+                    (&Some(ref val_expr), _) => write!(fmt, " {} {}", op, val_expr)?,
+                    (_, &Some(ref val)) => write!(fmt, " {} '{}'", op, val)?,
+                    _ => write!(fmt, " {}", op)?
+                }
+            }
+
+            if let Some(ref regex) = self.regex {
+                write!(fmt, " [regex {}]", regex.as_str())?;
+            }
+
+            if let (Some(ref dt_from), Some(ref dt_to)) = (self.dt_from, self.dt_to) {
+                write!(fmt, " [from {} to {}]", dt_from.format(DEFAULT_DATE_FORMAT), dt_to.format(DEFAULT_DATE_FORMAT))?;
+            }
+
+            return writeln!(fmt);
+        }
+
+        match self.left {
+            Some(ref left) if !self.negate => left.fmt_tree(fmt, depth),
+            Some(ref left) => {
+                writeln!(fmt, "{}not", indent)?;
+                left.fmt_tree(fmt, depth + 1)
+            },
+            None => Ok(())
+        }
+    }
+
     fn leaf_regex(field: Field, op: Option<Op>, val: String, regex: Regex) -> Expr {
         Expr {
             left: None,
             logical_op: None,
             right: None,
+            negate: false,
 
             field: Some(ColumnExpr::field(field)),
             op,
             val: Some(val),
+            val_expr: None,
             regex: Some(regex),
 
             dt_from: None,
@@ -865,6 +2894,12 @@ pub enum Op {
     Lte,
     Rx,
     Like,
+    Glob,
+    /// Equality ignoring the case of the letters
+    Eqi,
+    Nei,
+    /// All the permission bits are set
+    Has,
 }
 
 impl Op {
@@ -880,17 +2915,53 @@ impl Op {
             "<=" | "lte" | "le" => Some(Op::Lte),
             "~=" | "=~" | "regexp" | "rx" => Some(Op::Rx),
             "like" => Some(Op::Like),
+            "glob" => Some(Op::Glob),
+            "eqi" => Some(Op::Eqi),
+            "nei" => Some(Op::Nei),
+            "has" => Some(Op::Has),
             _ => None
         }
     }
 }
 
+impl Display for Op {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let op = match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Eeq => "===",
+            Op::Ene => "!==",
+            Op::Gt => ">",
+            Op::Gte => ">=",
+            Op::Lt => "<",
+            Op::Lte => "<=",
+            Op::Rx => "=~",
+            Op::Like => "like",
+            Op::Glob => "glob",
+            Op::Eqi => "eqi",
+            Op::Nei => "nei",
+            Op::Has => "has",
+        };
+
+        fmt.write_str(op)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum LogicalOp {
     And,
     Or,
 }
 
+impl Display for LogicalOp {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            LogicalOp::And => fmt.write_str("and"),
+            LogicalOp::Or => fmt.write_str("or"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialOrd, PartialEq, Eq, Hash, Serialize)]
 pub enum ArithmeticOp {
     Add,
@@ -904,28 +2975,123 @@ impl ArithmeticOp {
         match text.to_lowercase().as_str() {
             "+" | "plus" => Some(ArithmeticOp::Add),
             "-" | "minus"  => Some(ArithmeticOp::Subtract),
-            "mul" => Some(ArithmeticOp::Divide),
-            "div" => Some(ArithmeticOp::Multiply),
+            "*" | "mul" => Some(ArithmeticOp::Multiply),
+            "/" | "div" => Some(ArithmeticOp::Divide),
             _ => None
         }
     }
+
+    /// Integer arithmetic, `None` on overflow or division by zero.
+    pub fn calc(&self, left: i64, right: i64) -> Option<i64> {
+        match self {
+            ArithmeticOp::Add => left.checked_add(right),
+            ArithmeticOp::Subtract => left.checked_sub(right),
+            ArithmeticOp::Multiply => left.checked_mul(right),
+            ArithmeticOp::Divide => left.checked_div(right),
+        }
+    }
+}
+
+impl Display for ArithmeticOp {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let op = match self {
+            ArithmeticOp::Add => "+",
+            ArithmeticOp::Subtract => "-",
+            ArithmeticOp::Multiply => "*",
+            ArithmeticOp::Divide => "/",
+        };
+
+        fmt.write_str(op)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum OutputFormat {
-    Tabs, Lines, List, Csv, Json
+    /// Newlines, tabs and backslashes in the values are backslash-escaped when `escape` is set
+    Tabs { escape: bool },
+    Lines { escape: bool },
+    List, Csv, Json, JsonLines, Html, Markdown,
+    Sqlite { path: String, table: String, append: bool },
+    Table { truncate: bool },
+    /// A shell command for every row, printed but never run
+    Cmd { template: CmdTemplate },
+}
+
+impl OutputFormat {
+    /// The default format
+    pub fn tabs() -> OutputFormat {
+        OutputFormat::Tabs { escape: DEFAULT_TABS_ESCAPE }
+    }
+}
+
+/// The format as it's written after `into`.
+impl Display for OutputFormat {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            OutputFormat::Tabs { escape: true } => fmt.write_str("tabs"),
+            OutputFormat::Tabs { escape: false } => fmt.write_str("tabs raw"),
+            OutputFormat::Lines { escape: true } => fmt.write_str("lines esc"),
+            OutputFormat::Lines { escape: false } => fmt.write_str("lines"),
+            OutputFormat::List => fmt.write_str("list"),
+            OutputFormat::Csv => fmt.write_str("csv"),
+            OutputFormat::Json => fmt.write_str("json"),
+            OutputFormat::JsonLines => fmt.write_str("jsonl"),
+            OutputFormat::Html => fmt.write_str("html"),
+            OutputFormat::Markdown => fmt.write_str("md"),
+            OutputFormat::Sqlite { ref path, ref table, append } => {
+                write!(fmt, "sqlite:{}:{}", path, table)?;
+                match append {
+                    true => fmt.write_str(":append"),
+                    false => Ok(())
+                }
+            },
+            OutputFormat::Table { truncate: true } => fmt.write_str("table"),
+            OutputFormat::Table { truncate: false } => fmt.write_str("table:full"),
+            OutputFormat::Cmd { ref template } => write!(fmt, "cmd {}", template),
+        }
+    }
+}
+
+/// How dates are output. The rows keep the time itself until then,
+/// so the dates are compared, ordered and aggregated regardless of the output.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DateFormat {
+    Default,
+    Iso,
+    Custom(String),
+}
+
+impl DateFormat {
+    pub fn format(&self, dt: &DateTime<Local>) -> String {
+        match self {
+            DateFormat::Default => dt.format(DEFAULT_DATE_FORMAT).to_string(),
+            DateFormat::Iso => dt.to_rfc3339(),
+            DateFormat::Custom(ref format) => dt.format(format).to_string(),
+        }
+    }
+}
+
+impl Display for DateFormat {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            DateFormat::Default => fmt.write_str("default"),
+            DateFormat::Iso => fmt.write_str("iso"),
+            DateFormat::Custom(ref format) => write!(fmt, "'{}'", format),
+        }
+    }
 }
 
-#[cfg(test)]
 impl PartialEq for Expr {
     fn eq(&self, other: &Expr) -> bool {
         self.left == other.left
             && self.logical_op == other.logical_op
             && self.right == other.right
+            && self.negate == other.negate
 
             && self.field == other.field
             && self.op == other.op
             && self.val == other.val
+            && self.val_expr == other.val_expr
 
             && match self.regex {
             Some(ref left_rx) => {
@@ -956,6 +3122,10 @@ impl PartialEq for Expr {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::env;
+    use std::fs;
+
+    use chrono::TimeZone;
 
     #[test]
     fn simple_query() {
@@ -1014,4 +3184,1159 @@ mod tests {
         assert_eq!(query.ordering_asc, Rc::new(vec![true, false]));
         assert_eq!(query.limit, 50);
     }
+
+    #[test]
+    fn not() {
+        let query = "select name from /test where not (name like '%.tmp' or size > 1m) and not is_dir = true";
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        let mut negated_or = Expr::node(
+            Some(Box::new(Expr::leaf_regex(Field::Name, Some(Op::Like), String::from("%.tmp"), Regex::new(&convert_like_to_pattern("%.tmp")).unwrap()))),
+            Some(LogicalOp::Or),
+            Some(Box::new(Expr::leaf(Field::Size, Some(Op::Gt), String::from("1m")))),
+        );
+        negated_or.negate = true;
+
+        let mut negated_is_dir = Expr::leaf(Field::IsDir, Some(Op::Eq), String::from("true"));
+        negated_is_dir.negate = true;
+
+        let expr = Expr::node(
+            Some(Box::new(negated_or)),
+            Some(LogicalOp::And),
+            Some(Box::new(negated_is_dir)),
+        );
+
+        assert_eq!(query.expr, Some(Box::new(expr)));
+
+        let query = "select name from /test where not not size > 0";
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        assert_eq!(query.expr, Some(Box::new(Expr::leaf(Field::Size, Some(Op::Gt), String::from("0")))));
+    }
+
+    #[test]
+    fn content_search() {
+        let query = "select path from src where ext = rs and (contains('TODO') or matches('fix(me)?', 1m))";
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        let expr = Expr::node(
+            Some(Box::new(Expr::leaf(Field::Extension, Some(Op::Eq), String::from("rs")))),
+            Some(LogicalOp::And),
+            Some(Box::new(Expr::node(
+                Some(Box::new(Expr::content_search(Function::Contains, String::from("TODO"), None, None))),
+                Some(LogicalOp::Or),
+                Some(Box::new(Expr::content_search(Function::Matches, String::from("fix(me)?"), Some(String::from("1m")), Some(Regex::new("fix(me)?").unwrap())))),
+            ))),
+        );
+
+        assert_eq!(query.expr, Some(Box::new(expr)));
+        assert!(query.expr.unwrap().has_content_search());
+
+        let mut p = Parser::new();
+        assert!(p.parse("select path from src where contains('TODO', lots)").is_err());
+    }
+
+    #[test]
+    fn marker_files() {
+        let query = "select path from /data where is_newer_than('/var/run/last backup.stamp') or not IS_OLDER_THAN(/tmp/stamp) and size > 0";
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        let mut older = Expr::marker(Function::IsOlderThan, String::from("/tmp/stamp"));
+        older.negate = true;
+
+        let expr = Expr::node(
+            Some(Box::new(Expr::marker(Function::IsNewerThan, String::from("/var/run/last backup.stamp")))),
+            Some(LogicalOp::Or),
+            Some(Box::new(Expr::node(
+                Some(Box::new(older)),
+                Some(LogicalOp::And),
+                Some(Box::new(Expr::leaf(Field::Size, Some(Op::Gt), String::from("0")))),
+            ))),
+        );
+
+        assert_eq!(query.expr, Some(Box::new(expr)));
+        assert_eq!(query.expr.unwrap().get_marker_paths(), vec!["/var/run/last backup.stamp", "/tmp/stamp"]);
+
+        let mut p = Parser::new();
+        let err = p.parse("select path from /data where is_newer_than").unwrap_err();
+        assert_eq!(err.message, "expected opening parenthesis after is_newer_than");
+
+        let err = p.parse("select path from /data where is_older_than()").unwrap_err();
+        assert_eq!(err.message, "expected path of the marker file in is_older_than");
+
+        let query = p.parse("select path from /data where same_file_as('/data/master.bin')").unwrap();
+        assert_eq!(query.expr, Some(Box::new(Expr::marker(Function::SameFileAs, String::from("/data/master.bin")))));
+    }
+
+    #[test]
+    fn count_all() {
+        let query = "select count(*) from /test";
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        let mut count = ColumnExpr::function(Function::Count);
+        count.left = Some(Box::new(ColumnExpr::left(ColumnExpr::value(String::from("*")))));
+
+        assert_eq!(query.fields, vec![ColumnExpr::left(count)]);
+        assert_eq!(query.fields[0].get_function(), Some(Function::Count));
+        assert_eq!(query.fields[0].to_string(), "Count(*)");
+        assert!(query.get_all_fields().is_empty());
+    }
+
+    #[test]
+    fn offset() {
+        let query = Parser::new().parse("select name from /test order by name limit 50 offset 100 into json").unwrap();
+        assert_eq!(query.limit, 50);
+        assert_eq!(query.offset, 100);
+        assert_eq!(query.output_format, OutputFormat::Json);
+
+        let query = Parser::new().parse("select name from /test offset 5").unwrap();
+        assert_eq!(query.limit, 0);
+        assert_eq!(query.offset, 5);
+
+        assert!(Parser::new().parse("select name from /test limit 10 offset -1").is_err());
+    }
+
+    #[test]
+    fn group_by() {
+        let query = "select user, count(*), sum(size) from /test where size gt 0 group by user, 1 order by 2 desc limit 10";
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        assert_eq!(query.grouping_fields, vec![ColumnExpr::field(Field::User), ColumnExpr::left(ColumnExpr::field(Field::User))]);
+        assert_eq!(query.ordering_fields, vec![query.fields[1].clone()]);
+        assert_eq!(query.ordering_asc, Rc::new(vec![false]));
+        assert_eq!(query.limit, 10);
+
+        let query = "select extension, count(*) from /test group by 1";
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        assert_eq!(query.roots, vec![Root::new(String::from("/test"), 0, 0, false, false, false)]);
+        assert_eq!(query.grouping_fields, vec![query.fields[0].clone()]);
+    }
+
+    #[test]
+    fn order_by_expressions() {
+        let query = "select name, size from /test order by lower(name) desc, 2, (size + 1) * 2 desc";
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        let mut lower = ColumnExpr::function(Function::Lower);
+        lower.left = Some(Box::new(ColumnExpr::left(ColumnExpr::field(Field::Name))));
+        let doubled = ColumnExpr::arithmetic(
+            ColumnExpr::arithmetic(ColumnExpr::field(Field::Size), ArithmeticOp::Add, ColumnExpr::value(String::from("1"))),
+            ArithmeticOp::Multiply,
+            ColumnExpr::value(String::from("2"))
+        );
+
+        assert_eq!(query.ordering_fields, vec![ColumnExpr::left(lower), query.fields[1].clone(), doubled]);
+        assert_eq!(query.ordering_asc, Rc::new(vec![false, true, false]));
+
+        assert!(Parser::new().parse("select name from /test order by lowr(name)").is_err());
+        assert!(Parser::new().parse("select name from /test order by ()").is_err());
+    }
+
+    #[test]
+    fn group_field_without_group_by() {
+        let query = "select name, group from /test where group = wheel";
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        assert_eq!(query.fields, vec![ColumnExpr::left(ColumnExpr::field(Field::Name)),
+                                      ColumnExpr::left(ColumnExpr::field(Field::Group))
+        ]);
+        assert!(query.grouping_fields.is_empty());
+        assert_eq!(query.expr, Some(Box::new(Expr::leaf(Field::Group, Some(Op::Eq), String::from("wheel")))));
+    }
+
+    #[test]
+    fn between() {
+        let query = "select name from /test where modified between 2023-01-01 and 2023-06-30 and size not between 1k and 1m";
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        let (dt_from, _) = parse_datetime("2023-01-01").unwrap();
+        let (_, dt_to) = parse_datetime("2023-06-30").unwrap();
+
+        let expr = query.expr.unwrap();
+        let modified = expr.left.unwrap();
+        let lower = modified.left.clone().unwrap();
+        let upper = modified.right.clone().unwrap();
+
+        assert_eq!(modified.logical_op, Some(LogicalOp::And));
+        assert_eq!(lower.op, Some(Op::Gte));
+        assert_eq!(lower.dt_from, Some(dt_from));
+        assert_eq!(upper.op, Some(Op::Lte));
+        assert_eq!(upper.dt_to, Some(dt_to));
+
+        let mut size = Expr::node(Some(Box::new(Expr::leaf(Field::Size, Some(Op::Gte), String::from("1k")))),
+                                  Some(LogicalOp::And),
+                                  Some(Box::new(Expr::leaf(Field::Size, Some(Op::Lte), String::from("1m")))));
+        size.negate = true;
+
+        assert_eq!(expr.right, Some(Box::new(size)));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let query = "select size / 1m, (size + 1) * 2, width + height * 2 - 1 from /test where size * 2 > 1g";
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        let size = || ColumnExpr::field(Field::Size);
+        let value = |val: &str| ColumnExpr::value(String::from(val));
+
+        assert_eq!(query.fields, vec![
+            ColumnExpr::arithmetic(size(), ArithmeticOp::Divide, value("1m")),
+            ColumnExpr::arithmetic(ColumnExpr::arithmetic(size(), ArithmeticOp::Add, value("1")), ArithmeticOp::Multiply, value("2")),
+            ColumnExpr::arithmetic(
+                ColumnExpr::arithmetic(
+                    ColumnExpr::field(Field::Width),
+                    ArithmeticOp::Add,
+                    ColumnExpr::arithmetic(ColumnExpr::field(Field::Height), ArithmeticOp::Multiply, value("2"))
+                ),
+                ArithmeticOp::Subtract,
+                value("1")
+            ),
+        ]);
+
+        assert_eq!(query.fields[1].to_string(), "(Size + 1) * 2");
+        assert_eq!(query.fields[2].to_string(), "(Width + (Height * 2)) - 1");
+
+        let expr = Expr::leaf_column_expr(ColumnExpr::arithmetic(size(), ArithmeticOp::Multiply, value("2")), Some(Op::Gt), String::from("1g"));
+        assert_eq!(query.expr, Some(Box::new(expr)));
+    }
+
+    #[test]
+    fn arithmetic_errors() {
+        assert!(Parser::new().parse("select size + from /test").is_err());
+        assert!(Parser::new().parse("select (size + 1 from /test").is_err());
+        assert!(Parser::new().parse("select name from /test where size * 2 > big").is_err());
+    }
+
+    #[test]
+    fn string_functions() {
+        let query = r#"select concat('mv "', path, '" "', lower(path), '"'), substr(trim(name), 2), replace(name, 'file', size + 1), 'size', concat(name, 'path') from /test where name != lower(name)"#;
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        let concat = query.fields[0].left.clone().unwrap();
+        assert_eq!(concat.function, Some(Function::Concat));
+        assert_eq!(concat.left, Some(Box::new(ColumnExpr::left(ColumnExpr::value(String::from("mv \""))))));
+        assert_eq!(concat.args.len(), 4);
+        assert_eq!(query.fields[0].to_string(), r#"Concat(mv ", Path, " ", Lower(Path), ")"#);
+        assert_eq!(query.fields[1].to_string(), "Substr(Trim(Name), 2)");
+        assert_eq!(query.fields[2].to_string(), "Replace(Name, file, Size + 1)");
+        assert!(query.fields[2].get_required_fields().contains(&Field::Size));
+
+        // quoted field names are still fields
+        assert_eq!(query.fields[3], ColumnExpr::left(ColumnExpr::field(Field::Size)));
+        assert_eq!(query.fields[4].to_string(), "Concat(Name, Path)");
+
+        assert!(Parser::new().parse("select substr(name) from /test").is_err());
+        assert!(Parser::new().parse("select replace(name, a) from /test").is_err());
+        assert!(Parser::new().parse("select trim(name, a) from /test").is_err());
+        assert!(Parser::new().parse("select lower(name, path) from /test").is_err());
+        assert!(Parser::new().parse("select concat(name, ) from /test").is_err());
+        assert!(Parser::new().parse("select concat(name, path from /test").is_err());
+    }
+
+    #[test]
+    fn in_list() {
+        let query = "select name from /test where ext in ('jpg', png, 'gif') or name not in (a.txt)";
+        let mut p = Parser::new();
+        let query = p.parse(&query).unwrap();
+
+        let ext = Expr::node(
+            Some(Box::new(Expr::node(
+                Some(Box::new(Expr::leaf(Field::Extension, Some(Op::Eq), String::from("jpg")))),
+                Some(LogicalOp::Or),
+                Some(Box::new(Expr::leaf(Field::Extension, Some(Op::Eq), String::from("png"))))
+            ))),
+            Some(LogicalOp::Or),
+            Some(Box::new(Expr::leaf(Field::Extension, Some(Op::Eq), String::from("gif"))))
+        );
+
+        let mut name = Expr::leaf(Field::Name, Some(Op::Eq), String::from("a.txt"));
+        name.negate = true;
+
+        assert_eq!(query.expr, Some(Box::new(Expr::node(Some(Box::new(ext)), Some(LogicalOp::Or), Some(Box::new(name))))));
+
+        let mut p = Parser::new();
+        assert!(p.parse("select name from /test where ext in ()").is_err());
+        assert!(p.parse("select name from /test where ext in ('jpg'").is_err());
+        assert!(p.parse("select name from /test where ext not = 'jpg'").is_err());
+    }
+
+    #[test]
+    fn like_pattern() {
+        let like = |pattern: &str, s: &str| Regex::new(&convert_like_to_pattern(pattern)).unwrap().is_match(s);
+
+        assert!(like("report_2023-%.xlsx", "report_2023-05.xlsx"));
+        assert!(like("report_2023-%.xlsx", "REPORT-2023-Q1.XLSX"));
+        assert!(!like("report_2023-%.xlsx", "report_2023-05xlsx"));
+        assert!(!like("report_2023-%.xlsx", "old_report_2023-05.xlsx"));
+        assert!(like("%.txt", "notes.txt"));
+        assert!(!like("%.txt", "notes.txt.bak"));
+        assert!(like("a+b (1)?.c", "a+b (1)?.c"));
+        assert!(!like("a+b (1)?.c", "aab (1).c"));
+        assert!(like("100\\%", "100%"));
+        assert!(!like("100\\%", "1000"));
+        assert!(like("file\\_name", "file_name"));
+        assert!(!like("file\\_name", "file-name"));
+    }
+
+    #[test]
+    fn mixed_case_keywords() {
+        let mut p = Parser::new();
+        let lower = p.parse("select name, size from /test depth 2 where size > 1m and name like '%.rs' order by size desc limit 5 into json").unwrap();
+
+        for query in &["SELECT NAME, SIZE FROM /test DEPTH 2 WHERE SIZE > 1m AND NAME LIKE '%.rs' ORDER BY SIZE DESC LIMIT 5 INTO JSON",
+                       "Select Name, Size From /test Depth 2 Where Size > 1m And Name Like '%.rs' Order By Size Desc Limit 5 Into Json",
+                       "sElEcT nAmE, sIzE fRoM /test dEpTh 2 wHeRe SiZe > 1m aNd NaMe LiKe '%.rs' oRdEr By SiZe DeSc LiMiT 5 iNtO jSoN"] {
+            let query = p.parse(query).unwrap();
+            assert_eq!(query.to_string(), lower.to_string());
+            assert_eq!(query.roots, lower.roots);
+            assert_eq!(query.expr, lower.expr);
+            assert_eq!(query.ordering_asc, lower.ordering_asc);
+            assert_eq!(query.limit, 5);
+            assert_eq!(query.output_format, OutputFormat::Json);
+        }
+    }
+
+    #[test]
+    fn case_insensitive_comparison() {
+        let mut p = Parser::new();
+        let query = p.parse("select name from /test where name EQI 'ReadMe.md' or ext nei MD").unwrap();
+        let expr = query.expr.unwrap();
+
+        let left = expr.left.unwrap();
+        assert_eq!(left.op, Some(Op::Eqi));
+        assert!(left.regex.as_ref().unwrap().is_match("README.MD"));
+        assert!(!left.regex.as_ref().unwrap().is_match("README.md.bak"));
+
+        let right = expr.right.unwrap();
+        assert_eq!(right.op, Some(Op::Nei));
+        assert!(right.regex.as_ref().unwrap().is_match("md"));
+
+        // the value is not a regex
+        let query = p.parse("select name from /test where name eqi 'a.b+c'").unwrap();
+        let regex = query.expr.unwrap().regex.unwrap();
+        assert!(regex.is_match("A.B+C"));
+        assert!(!regex.is_match("axbbc"));
+
+        let err = p.parse("select name from /test where size eqi 10").unwrap_err();
+        assert_eq!(err.message, "expected text field to compare ignoring case, found 'size'");
+    }
+
+    #[test]
+    fn glob_pattern() {
+        let glob = |pattern: &str, s: &str| Regex::new(&convert_glob_to_pattern(pattern)).unwrap().is_match(s);
+
+        assert!(glob("*.rs", "main.RS"));
+        assert!(!glob("*.rs", "src/main.rs"));
+        assert!(glob("**/target/**", "/home/user/project/target/debug/fselect"));
+        assert!(glob("**/target/**", "target/debug"));
+        assert!(!glob("**/target/**", "/home/user/project/targets/debug"));
+        assert!(glob("/home/*/notes.txt", "/home/user/notes.txt"));
+        assert!(!glob("/home/*/notes.txt", "/home/user/old/notes.txt"));
+        assert!(glob("file?.txt", "file1.txt"));
+        assert!(!glob("file?.txt", "file10.txt"));
+        assert!(glob("file[abc].txt", "fileb.txt"));
+        assert!(!glob("file[abc].txt", "filed.txt"));
+        assert!(glob("file[0-9].txt", "file7.txt"));
+        assert!(glob("file[!0-9].txt", "filex.txt"));
+        assert!(!glob("file[!0-9].txt", "file7.txt"));
+        assert!(glob("a+b (1)[.c", "a+b (1)[.c"));
+
+        assert!(is_glob("file[abc].txt"));
+        assert!(!is_glob("[files.zip"));
+        assert!(!is_glob("name.txt"));
+
+        let mut p = Parser::new();
+        let query = p.parse("select name from /test where name glob 'readme.md'").unwrap();
+        let expr = query.expr.unwrap();
+        assert_eq!(expr.op, Some(Op::Glob));
+        assert!(expr.regex.unwrap().is_match("README.md"));
+    }
+
+    #[test]
+    fn sqlite_output() {
+        let mut p = Parser::new();
+        let query = p.parse("select name, size from /test into sqlite:/tmp/Files.db:found:append").unwrap();
+        assert_eq!(query.output_format, OutputFormat::Sqlite { path: String::from("/tmp/Files.db"), table: String::from("found"), append: true });
+
+        let query = p.parse("select name from /test into 'sqlite:C:\\My Files\\files.db'").unwrap();
+        assert_eq!(query.output_format, OutputFormat::Sqlite { path: String::from("C:\\My Files\\files.db"), table: String::from("files"), append: false });
+
+        let query = p.parse("select name from /test into sqlite:files.db:append").unwrap();
+        assert_eq!(query.output_format, OutputFormat::Sqlite { path: String::from("files.db"), table: String::from("files"), append: true });
+
+        assert!(p.parse("select name from /test into sqlite:").is_err());
+    }
+
+    #[test]
+    fn xattr_condition() {
+        let mut p = Parser::new();
+        let query = p.parse("select name, xattr('user.comment') from /test where xattr('user.backup') = 'yes'").unwrap();
+
+        let mut function_expr = ColumnExpr::function(Function::Xattr);
+        function_expr.left = Some(Box::new(ColumnExpr::left(ColumnExpr::value(String::from("user.comment")))));
+        assert_eq!(query.fields[1], ColumnExpr::left(function_expr));
+
+        let mut function_expr = ColumnExpr::function(Function::Xattr);
+        function_expr.left = Some(Box::new(ColumnExpr::value(String::from("user.backup"))));
+        assert_eq!(query.expr, Some(Box::new(Expr::leaf_column_expr(function_expr, Some(Op::Eq), String::from("yes")))));
+
+        assert!(p.parse("select name from /test where xattr('user.backup')").is_err());
+        assert!(p.parse("select name from /test where xattr() = 'yes'").is_err());
+    }
+
+    #[test]
+    fn distinct() {
+        let mut p = Parser::new();
+        let query = p.parse("select distinct ext from /test").unwrap();
+        assert!(query.distinct);
+        assert_eq!(query.fields, vec![ColumnExpr::left(ColumnExpr::field(Field::Extension))]);
+
+        let query = p.parse("select ext from /test").unwrap();
+        assert!(!query.distinct);
+
+        let query = p.parse("select count(distinct ext) from /test").unwrap();
+        assert!(!query.distinct);
+        assert!(query.fields[0].is_distinct());
+        assert_eq!(query.fields[0].to_string().to_lowercase(), "count(distinct extension)");
+    }
+
+    #[test]
+    fn table_output() {
+        let mut p = Parser::new();
+        let query = p.parse("select name, size from /test into table").unwrap();
+        assert_eq!(query.output_format, OutputFormat::Table { truncate: true });
+
+        let query = p.parse("select name, size from /test into TABLE:FULL").unwrap();
+        assert_eq!(query.output_format, OutputFormat::Table { truncate: false });
+    }
+
+    #[test]
+    fn cmd_output() {
+        let mut p = Parser::new();
+        let query = p.parse("select name from /test into cmd 'rm -v {path} # {}' /tmp/cleanup.sh").unwrap();
+        assert_eq!(query.output_format, OutputFormat::Cmd { template: CmdTemplate::parse("rm -v {path} # {}").unwrap() });
+        assert_eq!(query.output_file, Some(String::from("/tmp/cleanup.sh")));
+        assert!(query.get_all_fields().contains(&Field::Path));
+
+        let err = Parser::new().parse("select name from /test into cmd 'rm {pth}'").unwrap_err();
+        assert_eq!(err.message, "unknown placeholder '{pth}' in the command template (did you mean '{path}'?)");
+        assert!(Parser::new().parse("select name from /test into cmd").is_err());
+    }
+
+    #[test]
+    fn escaped_output() {
+        let mut p = Parser::new();
+        let query = p.parse("select name from /test").unwrap();
+        assert_eq!(query.output_format, OutputFormat::Tabs { escape: cfg!(not(windows)) });
+
+        let query = p.parse("select name from /test into tabs raw").unwrap();
+        assert_eq!(query.output_format, OutputFormat::Tabs { escape: false });
+
+        let query = p.parse("select name from /test into lines").unwrap();
+        assert_eq!(query.output_format, OutputFormat::Lines { escape: false });
+
+        let query = p.parse("select name from /test into lines ESC '/tmp/names.txt'").unwrap();
+        assert_eq!(query.output_format, OutputFormat::Lines { escape: true });
+        assert_eq!(query.output_file, Some(String::from("/tmp/names.txt")));
+
+        // a quoted word is a file name
+        let query = p.parse("select name from /test into lines 'esc'").unwrap();
+        assert_eq!(query.output_format, OutputFormat::Lines { escape: false });
+        assert_eq!(query.output_file, Some(String::from("esc")));
+    }
+
+    #[test]
+    fn quoted_roots() {
+        let mut p = Parser::new();
+        let query = p.parse(r#"select name from 'a b', "c,d" where name = x"#).unwrap();
+        assert_eq!(query.roots, vec![
+            Root::new(String::from("a b"), 0, 0, false, false, false),
+            Root::new(String::from("c,d"), 0, 0, false, false, false),
+        ]);
+        assert_eq!(query.expr, Some(Box::new(Expr::leaf(Field::Name, Some(Op::Eq), String::from("x")))));
+
+        let query = p.parse(r#"select name from 'it\'s', "say \"hi\"" depth 2 where name = x"#).unwrap();
+        assert_eq!(query.roots, vec![
+            Root::new(String::from("it's"), 0, 0, false, false, false),
+            Root::new(String::from("say \"hi\""), 0, 2, false, false, false),
+        ]);
+
+        let query = p.parse(r#"select name from C:\Users\me, 'D:\My Files\', E:\ where name = x"#).unwrap();
+        assert_eq!(query.roots, vec![
+            Root::new(String::from(r"C:\Users\me"), 0, 0, false, false, false),
+            Root::new(String::from(r"D:\My Files\"), 0, 0, false, false, false),
+            Root::new(String::from(r"E:\"), 0, 0, false, false, false),
+        ]);
+    }
+
+    #[test]
+    fn ignore_file_root_option() {
+        let mut p = Parser::new();
+        let query = p.parse("select name from /test ignorefile '.MyIgnore' depth 2, /test2 gitignore where name = x").unwrap();
+        assert_eq!(query.roots, vec![
+            Root { ignore_file: Some(String::from(".MyIgnore")), ..Root::new(String::from("/test"), 0, 2, false, false, false) },
+            Root::new(String::from("/test2"), 0, 0, false, false, true),
+        ]);
+    }
+
+    #[test]
+    fn one_filesystem_root_option() {
+        let mut p = Parser::new();
+        let query = p.parse("select name from /mnt onefilesystem depth 3, /home where name = x").unwrap();
+        assert_eq!(query.roots, vec![
+            Root { one_filesystem: true, ..Root::new(String::from("/mnt"), 0, 3, false, false, false) },
+            Root::new(String::from("/home"), 0, 0, false, false, false),
+        ]);
+    }
+
+    #[test]
+    fn mode_bits_operator() {
+        let mut p = Parser::new();
+        let query = p.parse("select name where mode has 'o+w'").unwrap();
+        let expr = query.expr.unwrap();
+        assert_eq!(expr.op, Some(Op::Has));
+        assert_eq!(expr.val, Some(String::from("o+w")));
+
+        let err = p.parse("select name where size has 'o+w'").unwrap_err();
+        assert_eq!(err.message, "expected mode to check the bits of, found 'size'");
+
+        let err = p.parse("select name where mode has 'o-w'").unwrap_err();
+        assert_eq!(err.message, "expected permission bits like 'o+w' or 0002");
+    }
+
+    #[test]
+    fn archive_root_options() {
+        let mut p = Parser::new();
+        let query = p.parse("select name from /a archives 1000 maxarchivesize 50m depth 2, /b archives lenient, /c maxarchivesize 2k arc where name = x").unwrap();
+        assert_eq!(query.roots, vec![
+            Root { max_archive_entries: 1000, max_archive_size: 50 * 1024 * 1024, ..Root::new(String::from("/a"), 0, 2, true, false, false) },
+            Root { lenient: true, ..Root::new(String::from("/b"), 0, 0, true, false, false) },
+            Root { max_archive_size: 2048, ..Root::new(String::from("/c"), 0, 0, true, false, false) },
+        ]);
+
+        let err = p.parse("select name from /a archives maxarchivesize lots where name = x").unwrap_err();
+        assert_eq!(err.message, "expected archive size");
+        assert_eq!(err.found, Some(String::from("lots")));
+
+        let query = p.parse("select name from /a archives archivedepth 3 maxnestedsize 10m, /b archives where name = x").unwrap();
+        assert_eq!(query.roots, vec![
+            Root { archive_depth: 3, max_nested_size: 10 * 1024 * 1024, ..Root::new(String::from("/a"), 0, 0, true, false, false) },
+            Root::new(String::from("/b"), 0, 0, true, false, false),
+        ]);
+
+        let err = p.parse("select name from /a archives archivedepth 0 where name = x").unwrap_err();
+        assert_eq!(err.message, "expected archive depth");
+    }
+
+    #[test]
+    fn traversal_root_options() {
+        let mut p = Parser::new();
+        let query = p.parse("select name from /a ordered maxfiles 100 depth 2, /b maxfiles 5, /c where name = x").unwrap();
+        assert_eq!(query.roots, vec![
+            Root { ordered: true, max_files: 100, ..Root::new(String::from("/a"), 0, 2, false, false, false) },
+            Root { max_files: 5, ..Root::new(String::from("/b"), 0, 0, false, false, false) },
+            Root::new(String::from("/c"), 0, 0, false, false, false),
+        ]);
+        assert_eq!(query.roots[0].to_string(), "'/a' depth 2 ordered maxfiles 100");
+
+        let err = p.parse("select name from /a maxfiles many where name = x").unwrap_err();
+        assert_eq!(err.message, "expected number of files");
+        assert_eq!(err.found, Some(String::from("many")));
+
+        let query = p.parse("select name from /a gitignored, /b git where name = x").unwrap();
+        assert_eq!(query.roots, vec![
+            Root { gitignored: true, ..Root::new(String::from("/a"), 0, 0, false, false, false) },
+            Root::new(String::from("/b"), 0, 0, false, false, true),
+        ]);
+        assert_eq!(query.roots[0].to_string(), "'/a' gitignored");
+    }
+
+    #[test]
+    fn prune_root_option() {
+        let mut p = Parser::new();
+        let prune = |globs: &[&str]| globs.iter().map(|glob| PrunePattern::new(glob).unwrap()).collect::<Vec<_>>();
+
+        let query = p.parse("select name from /a prune node_modules, '.git', '*.egg-info' depth 5, /b prune target, c, '/d' where name = x").unwrap();
+        assert_eq!(query.roots, vec![
+            Root { prune: prune(&["node_modules", ".git", "*.egg-info"]), ..Root::new(String::from("/a"), 0, 5, false, false, false) },
+            Root { prune: prune(&["target"]), ..Root::new(String::from("/b"), 0, 0, false, false, false) },
+            Root::new(String::from("c"), 0, 0, false, false, false),
+            Root::new(String::from("/d"), 0, 0, false, false, false),
+        ]);
+        assert_eq!(query.roots[0].to_string(), "'/a' depth 5 prune 'node_modules', '.git', '*.egg-info'");
+
+        // the patterns match whole names
+        assert!(query.roots[0].prune[2].is_match("fselect.EGG-INFO"));
+        assert!(!query.roots[0].prune[0].is_match("node_modules_old"));
+
+        // the option could be repeated, and a word with no separator after another option is a root again
+        let query = p.parse("select name from options prune build prune dist gitignore /a, . where name = x").unwrap();
+        assert_eq!(query.roots.len(), 2);
+        assert_eq!(query.roots[1], Root { prune: prune(&["build", "dist"]), gitignore: true, ..Root::default() });
+
+        let query = p.parse("select name from /a prune target gitignore, src").unwrap();
+        assert_eq!(query.roots.len(), 2);
+        assert_eq!(query.roots[1].path, "src");
+
+        let err = p.parse("select name from /a prune where name = x").unwrap_err();
+        assert_eq!(err.message, "expected directory name pattern");
+        assert_eq!(err.found, Some(String::from("where")));
+
+        let err = p.parse("select name from /a prune").unwrap_err();
+        assert_eq!(err.message, "expected directory name pattern");
+    }
+
+    #[test]
+    fn root_switches() {
+        let mut p = Parser::new();
+        let root = Root { path: String::from("/a"), ..Root::default() };
+
+        for &(options, ref expected) in &[
+            ("symlinks", Root { symlinks: true, ..root.clone() }),
+            ("sym", Root { symlinks: true, ..root.clone() }),
+            ("symlink", Root { symlinks: true, ..root.clone() }),
+            ("follow-symlinks", Root { symlinks: true, ..root.clone() }),
+            ("nosymlinks", root.clone()),
+            ("no-symlinks", root.clone()),
+            ("no-follow-symlinks", root.clone()),
+            ("archives", Root { archives: true, ..root.clone() }),
+            ("arc 10", Root { archives: true, max_archive_entries: 10, ..root.clone() }),
+            ("archive 10", Root { archives: true, max_archive_entries: 10, ..root.clone() }),
+            ("noarchive", root.clone()),
+            ("search-archives 10", Root { archives: true, max_archive_entries: 10, ..root.clone() }),
+            ("noarchives", root.clone()),
+            ("no-search-archives", root.clone()),
+            ("gitignore", Root { gitignore: true, ..root.clone() }),
+            ("git", Root { gitignore: true, ..root.clone() }),
+            ("apply-gitignore", Root { gitignore: true, ..root.clone() }),
+            ("no-apply-gitignore", root.clone()),
+            ("gitignored", Root { gitignored: true, ..root.clone() }),
+            ("no-gitignored", root.clone()),
+            ("gitignored git", Root { gitignored: true, gitignore: true, ..root.clone() }),
+            ("noglobalignore", Root { global_ignore: false, ..root.clone() }),
+            ("no-global-ignore", Root { global_ignore: false, ..root.clone() }),
+            ("dedup", root.clone()),
+            ("nodedup", Root { dedup: false, ..root.clone() }),
+            ("no-dedup", Root { dedup: false, ..root.clone() }),
+            ("lenient", Root { lenient: true, ..root.clone() }),
+            ("no-lenient", root.clone()),
+            ("onefilesystem", Root { one_filesystem: true, ..root.clone() }),
+            ("one-filesystem", Root { one_filesystem: true, ..root.clone() }),
+            ("no-one-filesystem", root.clone()),
+            ("ordered", Root { ordered: true, ..root.clone() }),
+            ("no-ordered", root.clone()),
+            ("Follow-Symlinks Search-Archives", Root { symlinks: true, archives: true, ..root.clone() }),
+            ("depth 3 follow-symlinks search-archives apply-gitignore", Root { max_depth: 3, symlinks: true, archives: true, gitignore: true, ..root.clone() }),
+            // the last one wins
+            ("symlinks no-symlinks", root.clone()),
+            ("no-archives archives 5", Root { archives: true, max_archive_entries: 5, ..root.clone() }),
+        ] {
+            let query = p.parse(&format!("select name from /a {} where name = x", options)).unwrap();
+            assert_eq!(query.roots, vec![expected.clone()], "{}", options);
+        }
+    }
+
+    #[test]
+    fn root_option_defaults() {
+        let mut p = Parser::new();
+        let query = p.parse("select name from options depth 3 follow-symlinks archives /data, /backup nosymlinks, /tmp depth 1 no-archives where name = x").unwrap();
+        assert_eq!(query.roots, vec![
+            Root { symlinks: true, archives: true, ..Root::new(String::from("/data"), 0, 3, false, false, false) },
+            Root { archives: true, ..Root::new(String::from("/backup"), 0, 3, false, false, false) },
+            Root { symlinks: true, ..Root::new(String::from("/tmp"), 0, 1, false, false, false) },
+        ]);
+
+        // the options clause takes the switches by their exact names only, so a root could start like one of them
+        let query = p.parse("select name from options symlinks gitlab, archive_2020 where name = x").unwrap();
+        assert_eq!(query.roots, vec![
+            Root { symlinks: true, ..Root::new(String::from("gitlab"), 0, 0, false, false, false) },
+            Root { symlinks: true, ..Root::new(String::from("archive_2020"), 0, 0, false, false, false) },
+        ]);
+        let query = p.parse("select name from options nosym symbols symlink where name = x").unwrap();
+        assert_eq!(query.roots, vec![Root { symlinks: true, ..Root::new(String::from("symbols"), 0, 0, false, false, false) }]);
+
+        // quoted words and subqueries are roots, the options of the subquery roots are their own
+        let query = p.parse("select name from options gitignore 'archives', (select path from /projects) depth 1 where name = x").unwrap();
+        assert_eq!(query.roots.len(), 2);
+        assert_eq!(query.roots[0], Root { gitignore: true, ..Root::new(String::from("archives"), 0, 0, false, false, false) });
+        assert!(query.roots[1].gitignore);
+        assert_eq!(query.roots[1].max_depth, 1);
+        assert!(!query.roots[1].subquery.as_ref().unwrap().roots[0].gitignore);
+
+        // clauses which follow the roots are still recognized
+        assert!(p.parse("select name from options archives /a, /b dupes").unwrap().dupes);
+        assert_eq!(p.parse("select name, count(*) from /a nodedup group by name").unwrap().grouping_fields.len(), 1);
+        assert!(p.parse("select name from /a archives 10 summary").unwrap().summary);
+
+        // unquoted paths with hyphens are kept whole
+        let query = p.parse("select name from /srv/my-app follow-symlinks, /srv/old-app where name = x").unwrap();
+        assert_eq!(query.roots, vec![
+            Root::new(String::from("/srv/my-app"), 0, 0, false, true, false),
+            Root::new(String::from("/srv/old-app"), 0, 0, false, false, false),
+        ]);
+
+        for (query, message, found) in vec![
+            ("select name from /a folow-symlinks where name = x", "unknown root option 'folow-symlinks'", Some("folow")),
+            ("select name from /a, /b symlinks nosuch where name = x", "unknown root option 'nosuch'", Some("nosuch")),
+            ("select name from /a options archives where name = x", "unknown root option 'options'", Some("options")),
+            ("select name from options archives", "expected root after options", None),
+            ("select name from options archives, /a", "expected root after options", Some(",")),
+        ] {
+            let err = p.parse(query).unwrap_err();
+            assert_eq!(err.message, message, "{}", query);
+            assert_eq!(err.found, found.map(String::from), "{}", query);
+        }
+    }
+
+    #[test]
+    fn subquery_root() {
+        let mut p = Parser::new();
+        let query = p.parse("select path from (select path, size from /projects where name = node_modules order by size desc limit 5) depth 2 sym, /tmp where size > 10m").unwrap();
+        assert_eq!(query.roots.len(), 2);
+
+        let root = &query.roots[0];
+        assert_eq!(root.path, "");
+        assert_eq!(root.max_depth, 2);
+        assert!(root.symlinks);
+
+        let subquery = root.subquery.as_ref().unwrap();
+        assert_eq!(subquery.fields.iter().map(|column_expr| column_expr.to_string()).collect::<Vec<String>>(), vec!["Path", "Size"]);
+        assert_eq!(subquery.roots, vec![Root::new(String::from("/projects"), 0, 0, false, false, false)]);
+        assert_eq!(subquery.expr, Some(Box::new(Expr::leaf(Field::Name, Some(Op::Eq), String::from("node_modules")))));
+        assert_eq!(subquery.ordering_fields[0].get_field(), Some(Field::Size));
+        assert_eq!(*subquery.ordering_asc, vec![false]);
+        assert_eq!(subquery.limit, 5);
+
+        assert_eq!(query.roots[1], Root::new(String::from("/tmp"), 0, 0, false, false, false));
+        assert_eq!(query.expr, Some(Box::new(Expr::leaf(Field::Size, Some(Op::Gt), String::from("10m")))));
+
+        let err = p.parse("select path from (select name from /projects) where size > 10m").unwrap_err();
+        assert_eq!(err.message, "expected path column in subquery");
+
+        let err = p.parse("select path from (select path from /projects where size > 10m").unwrap_err();
+        assert_eq!(err.message, "expected closing parenthesis after subquery");
+    }
+
+    #[test]
+    fn root_lists() {
+        let list = env::temp_dir().join(format!("fselect_parser_root_list_{}.txt", ::std::process::id()));
+        fs::write(&list, "/a\n/b\n").unwrap();
+
+        let mut p = Parser::new();
+        let query = p.parse(&format!("select path from - depth 2, @{} archives, '@literal' where size > 100m", list.to_string_lossy())).unwrap();
+        // the file is opened right away, stdin is read only when the search gets to it
+        let list_file = query.roots[1].list_file.clone();
+        assert!(list_file.is_some());
+        assert_eq!(query.roots, vec![
+            Root { list: true, ..Root::new(String::from("-"), 0, 2, false, false, false) },
+            Root { list: true, list_file, ..Root::new(list.to_string_lossy().to_string(), 0, 0, true, false, false) },
+            Root::new(String::from("@literal"), 0, 0, false, false, false),
+        ]);
+        assert_eq!(query.roots[0].to_string(), "- depth 2");
+        assert_eq!(query.roots[1].to_string(), format!("@{} archives", list.to_string_lossy()));
+
+        let query = p.parse("select path from options symlinks - where size > 100m").unwrap();
+        assert_eq!(query.roots, vec![Root { list: true, ..Root::new(String::from("-"), 0, 0, false, true, false) }]);
+
+        let err = p.parse("select path from @/no/such/roots.txt where size > 100m").unwrap_err();
+        assert!(err.message.starts_with("cannot read the list of roots '/no/such/roots.txt'"));
+        assert_eq!(err.found, Some(String::from("@/no/such/roots.txt")));
+
+        let err = p.parse(&format!("select path from @{} where size > 100m", env::temp_dir().to_string_lossy())).unwrap_err();
+        assert!(err.message.ends_with("it's a directory"));
+
+        fs::remove_file(&list).unwrap();
+    }
+
+    #[test]
+    fn root_patterns() {
+        let mut p = Parser::new();
+        let query = p.parse("select path from /srv/*/logs depth 1, /srv/{web,api}/conf, '/srv/weird*dir', /srv/plain where size > 0").unwrap();
+
+        assert_eq!(query.roots.iter().map(|root| root.path.as_str()).collect::<Vec<&str>>(),
+                   vec!["/srv/*/logs", "/srv/{web,api}/conf", "/srv/weird*dir", "/srv/plain"]);
+        assert_eq!(query.roots.iter().map(|root| root.glob).collect::<Vec<bool>>(), vec![true, true, false, false]);
+        assert_eq!(query.roots[0].max_depth, 1);
+        assert_eq!(query.roots[1].max_depth, 0);
+    }
+
+    #[test]
+    fn field_comparison() {
+        let mut p = Parser::new();
+
+        let query = p.parse("select name from /test where modified > created").unwrap();
+        let expr = query.expr.unwrap();
+        assert_eq!(expr.field, Some(ColumnExpr::field(Field::Modified)));
+        assert_eq!(expr.op, Some(Op::Gt));
+        assert_eq!(expr.val_expr.unwrap().get_field(), Some(Field::Created));
+        assert_eq!(expr.dt_from, None);
+
+        let query = p.parse("select name from /test where width >= height * 2 and name = 'size'").unwrap();
+        let expr = query.expr.unwrap();
+        let left = expr.left.unwrap();
+        assert_eq!(left.field, Some(ColumnExpr::field(Field::Width)));
+        assert_eq!(left.val_expr.unwrap().to_string(), "Height * 2");
+        let right = expr.right.unwrap();
+        assert_eq!(right.val, Some(String::from("size")));
+        assert_eq!(right.val_expr, None);
+
+        let query = p.parse("select name from /test where width * 9 > height * 16").unwrap();
+        assert_eq!(query.expr.unwrap().val_expr.unwrap().to_string(), "Height * 16");
+
+        let query = p.parse("select name from /test where name like ext").unwrap();
+        assert_eq!(query.expr.unwrap().val_expr, None);
+
+        let err = p.parse("select name from /test where modified > size").unwrap_err();
+        assert_eq!(err.message, "expected date or date field to compare with 'modified'");
+        assert_eq!(err.position, 40);
+
+        let err = p.parse("select name from /test where size > modified").unwrap_err();
+        assert_eq!(err.message, "expected value or field of the same kind as 'size'");
+
+        let err = p.parse("select name from /test where width > heigth").unwrap_err();
+        assert_eq!(err.message, "expected number or field name");
+        assert_eq!(err.found, Some(String::from("heigth")));
+    }
+
+    #[test]
+    fn star_columns() {
+        let mut p = Parser::new();
+        let query = p.parse("select * from /test where size > 1g").unwrap();
+        let columns: Vec<String> = query.fields.iter().map(|column_expr| column_expr.to_string()).collect();
+
+        assert_eq!(&columns[..4], &["Name", "Size", "Modified", "IsDir"]);
+        assert_eq!(columns.last().unwrap(), "Path");
+
+        let query = p.parse("select *, sha1 from /test").unwrap();
+        assert_eq!(query.fields.last().unwrap().get_field(), Some(Field::Sha1));
+    }
+
+    #[test]
+    fn error_positions() {
+        let mut p = Parser::new();
+
+        let err = p.parse("select name from /test where size frm 10").unwrap_err();
+        assert_eq!(err.found, Some(String::from("frm")));
+        assert_eq!(err.position, 34);
+        assert_eq!(err.to_string(), "expected comparison operator after field 'size', found 'frm' at position 34");
+
+        let err = p.parse("select name from /test where sise > 10").unwrap_err();
+        assert_eq!(err.message, "expected field name (did you mean 'size'?)");
+        assert_eq!(err.position, 29);
+
+        let err = p.parse("select name from /test where colour = red").unwrap_err();
+        assert_eq!(err.message, "expected field name");
+
+        let err = p.parse("select name, modfied from /test").unwrap_err();
+        assert_eq!(err.to_string(), "expected field name (did you mean 'modified'?), found 'modfied' at position 13");
+
+        let err = p.parse("select name from /test order by sise").unwrap_err();
+        assert_eq!(err.message, "expected field name (did you mean 'size'?)");
+        assert_eq!(err.position, 32);
+
+        let err = p.parse("select name from /test where size >").unwrap_err();
+        assert_eq!(err.found, None);
+        assert_eq!(err.position, 35);
+        assert_eq!(err.to_string(), "expected value after operator '>', found end of query at position 35");
+
+        let err = p.parse("select name from /test where size between 1 or 2").unwrap_err();
+        assert_eq!(err.message, "expected AND after lower bound of BETWEEN");
+        assert_eq!(err.position, 44);
+
+        let err = p.parse("select count( from /test").unwrap_err();
+        assert_eq!(err.found, Some(String::from("from")));
+        assert_eq!(err.position, 14);
+
+        let err = p.parse("select name from /test where (size > 10 order by name").unwrap_err();
+        assert_eq!(err.message, "expected closing parenthesis");
+        assert_eq!(err.position, 40);
+
+        let err = p.parse("select name from /test order by 3").unwrap_err();
+        assert_eq!(err.message, "expected column index in range");
+        assert_eq!(err.position, 32);
+
+        let err = p.parse("select name from /test limit 10 into nowhere").unwrap_err();
+        assert_eq!(err.message, "expected output format");
+        assert_eq!(err.position, 37);
+    }
+
+    #[test]
+    fn column_aliases() {
+        let mut p = Parser::new();
+        let query = p.parse("select size as bytes, lower(name) AS 'file name', name:20 as n from . group by n order by 'file name' desc, 1").unwrap();
+
+        assert_eq!(query.fields.iter().map(|column_expr| column_expr.get_column_name()).collect::<Vec<String>>(),
+                   vec!["bytes", "file name", "n"]);
+        assert_eq!(query.fields[2].format, Some(ColumnFormat { width: 20, right_align: false }));
+
+        assert_eq!(query.grouping_fields, vec![query.fields[2].clone()]);
+        assert_eq!(query.ordering_fields, vec![query.fields[1].clone(), query.fields[0].clone()]);
+        assert_eq!(*query.ordering_asc, vec![false, true]);
+
+        let err = p.parse("select size as bytes, name as BYTES from .").unwrap_err();
+        assert_eq!(err.message, "duplicate column name 'BYTES'");
+
+        let err = p.parse("select name, size as name from .").unwrap_err();
+        assert_eq!(err.message, "duplicate column name 'name'");
+
+        let err = p.parse("select size as bytes from . order by 'size'").unwrap_err();
+        assert_eq!(err.message, "unknown column alias 'size'");
+
+        let err = p.parse("select size as from .").unwrap_err();
+        assert_eq!(err.message, "expected column alias after AS");
+    }
+
+    #[test]
+    fn ordering_names() {
+        let mut p = Parser::new();
+
+        // an alias comes before the field of the same name
+        let query = p.parse("select name as size, modified from . order by size").unwrap();
+        assert_eq!(query.ordering_fields, vec![query.fields[0].clone()]);
+
+        // a field selected twice, or along with a formatted one, is still the field
+        let query = p.parse("select size, fsize, size from . order by size, fsize desc").unwrap();
+        assert_eq!(query.ordering_fields, vec![ColumnExpr::field(Field::Size), ColumnExpr::field(Field::FormattedSize)]);
+
+        let query = p.parse("select size as bytes, size as b from . order by bytes").unwrap();
+        assert_eq!(query.ordering_fields, vec![query.fields[0].clone()]);
+
+        let err = p.parse("select name as modified, modified from . order by modified").unwrap_err();
+        assert_eq!(err.message, "ambiguous column 'modified', it could be name or modified");
+        assert_eq!(err.found, Some(String::from("modified")));
+
+        let err = p.parse("select lower(name) as name, name from . group by 'NAME'").unwrap_err();
+        assert_eq!(err.message, "ambiguous column 'NAME', it could be lower(name) or name");
+    }
+
+    #[test]
+    fn union() {
+        let mut p = Parser::new();
+
+        let query = p.parse("select name, size from /a where size > 1 union select path, fsize from /b group by path \
+                             order by size desc, modified limit 5 into json").unwrap();
+        assert_eq!(query.unions.len(), 1);
+
+        assert_eq!(query.roots[0].path, "/a");
+        assert!(query.expr.is_some());
+        assert_eq!(query.unions[0].roots[0].path, "/b");
+        assert_eq!(query.unions[0].grouping_fields, vec![ColumnExpr::field(Field::Path)]);
+
+        assert_eq!(query.ordering_fields, vec![ColumnExpr::field(Field::Size), ColumnExpr::field(Field::Modified)]);
+        assert_eq!(query.unions[0].ordering_fields, vec![ColumnExpr::left(ColumnExpr::field(Field::FormattedSize)), ColumnExpr::field(Field::Modified)]);
+
+        for query in vec![&query, &query.unions[0]] {
+            assert_eq!(query.ordering_asc, Rc::new(vec![false, true]));
+            assert_eq!(query.limit, 5);
+            assert_eq!(query.output_format, OutputFormat::Json);
+        }
+
+        let err = p.parse("select name, size from /a union select name from /b").unwrap_err();
+        assert_eq!(err.message, "expected 2 columns like in the first query of the union");
+        assert_eq!(err.position, 39);
+
+        assert!(p.parse("select name from /a union").is_err());
+    }
+
+    #[test]
+    fn date_format() {
+        let mut p = Parser::new();
+
+        let query = p.parse("select name, modified from /test dateformat iso").unwrap();
+        assert_eq!(query.date_format, DateFormat::Iso);
+
+        let query = p.parse("select name, modified from /test limit 5 into json dateformat '%d.%m.%Y'").unwrap();
+        assert_eq!(query.date_format, DateFormat::Custom(String::from("%d.%m.%Y")));
+        assert_eq!(query.output_format, OutputFormat::Json);
+
+        let query = p.parse("select name from /test").unwrap();
+        assert_eq!(query.date_format, DateFormat::Default);
+
+        assert!(p.parse("select name from /test dateformat '%Q'").is_err());
+        assert!(p.parse("select name from /test dateformat").is_err());
+        assert!(p.parse("select name from /test dateformat iso into csv dateformat iso").is_err());
+
+        let dt = Local.ymd(2018, 5, 1).and_hms(12, 30, 0);
+        assert_eq!(DateFormat::Custom(String::from("%d.%m.%Y %H:%M")).format(&dt), "01.05.2018 12:30");
+        assert_eq!(DateFormat::Default.format(&dt), "2018-05-01 12:30:00");
+        assert!(DateFormat::Iso.format(&dt).starts_with("2018-05-01T12:30:00"));
+    }
+
+    #[test]
+    fn output_file() {
+        let mut p = Parser::new();
+
+        let query = p.parse("select name from /test into csv 'C:\\My Files\\report.csv' dateformat iso").unwrap();
+        assert_eq!(query.output_format, OutputFormat::Csv);
+        assert_eq!(query.output_file, Some(String::from("C:\\My Files\\report.csv")));
+        assert_eq!(query.date_format, DateFormat::Iso);
+
+        let query = p.parse("select name from /test into json").unwrap();
+        assert_eq!(query.output_file, None);
+
+        assert!(p.parse("select name from /test into sqlite:files.db report.txt").is_err());
+    }
+
+    #[test]
+    fn dupes() {
+        let mut p = Parser::new();
+
+        let query = p.parse("select path, size from /data where size gt 1m dupes order by size desc").unwrap();
+        assert!(query.dupes);
+        assert!(query.expr.is_some());
+        assert_eq!(query.ordering_fields.len(), 1);
+
+        let query = p.parse("select sha256, count(*) from /data archives dupes group by sha256").unwrap();
+        assert!(query.dupes);
+        assert!(query.roots[0].archives);
+        assert_eq!(query.grouping_fields.len(), 1);
+
+        let query = p.parse("select path from /data where name = dupes").unwrap();
+        assert!(!query.dupes);
+    }
+
+    #[test]
+    fn progress() {
+        let mut p = Parser::new();
+
+        let query = p.parse("select name from /test where size gt 1g into csv files.csv progress").unwrap();
+        assert!(query.progress);
+        assert_eq!(query.output_file, Some(String::from("files.csv")));
+
+        let query = p.parse("select name from /a union select name from /b limit 10 progress").unwrap();
+        assert!(query.progress && query.unions[0].progress);
+
+        let query = p.parse("select name from /test").unwrap();
+        assert!(!query.progress);
+
+        assert!(p.parse("select name from /test progress limit 10").is_err());
+    }
+
+    #[test]
+    fn summary() {
+        let mut p = Parser::new();
+
+        let query = p.parse("select name from /test limit 5 into csv summary progress").unwrap();
+        assert!(query.summary);
+        assert!(query.progress);
+        assert_eq!(query.limit, 5);
+
+        let query = p.parse("select name from /a union select name from /b summary").unwrap();
+        assert!(query.summary && query.unions[0].summary);
+
+        let query = p.parse("select name from /test").unwrap();
+        assert!(!query.summary);
+
+        let query = p.parse("select name from /test into json summary").unwrap();
+        assert!(query.summary);
+        assert_eq!(query.output_file, None);
+
+        let query = p.parse("select name from /test into json 'summary'").unwrap();
+        assert!(!query.summary);
+        assert_eq!(query.output_file, Some(String::from("summary")));
+
+        assert!(p.parse("select name from /test summary limit 10").is_err());
+        assert!(p.parse("select name from /test progress summary").is_err());
+    }
+
+    #[test]
+    fn column_format() {
+        let mut p = Parser::new();
+        let query = p.parse("select name:40, fsize:>10, upper(ext):<5, modified from /test").unwrap();
+        let formats: Vec<Option<ColumnFormat>> = query.fields.iter().map(|column_expr| column_expr.format.clone()).collect();
+        assert_eq!(formats, vec![
+            Some(ColumnFormat { width: 40, right_align: false }),
+            Some(ColumnFormat { width: 10, right_align: true }),
+            Some(ColumnFormat { width: 5, right_align: false }),
+            None,
+        ]);
+        // the width is not a part of the column name
+        assert_eq!(query.fields[0].to_string(), "Name");
+        assert_eq!(query.fields[1].get_field(), Some(Field::FormattedSize));
+
+        for (query, message) in vec![("select name:0 from /test", "expected positive column width, e.g. name:40 or size:>10"),
+                                     ("select name:wide from /test", "expected positive column width, e.g. name:40 or size:>10"),
+                                     ("select name:-5 from /test", "expected positive column width after ':', e.g. name:40 or size:>10"),
+                                     ("select size:> from /test", "expected positive column width after alignment, e.g. name:<40 or size:>10")] {
+            assert_eq!(p.parse(query).unwrap_err().message, message, "{}", query);
+        }
+
+        let format = ColumnFormat { width: 6, right_align: false };
+        assert_eq!(format.apply("ab"), "ab    ");
+        assert_eq!(format.apply("abcdef"), "abcdef");
+        assert_eq!(format.apply("abcdefg"), "abcde…");
+        assert_eq!(format.apply("привет, мир"), "приве…");
+        assert_eq!(ColumnFormat { width: 4, right_align: true }.apply("1"), "   1");
+        assert_eq!(ColumnFormat { width: 1, right_align: true }.apply("12"), "…");
+    }
+
+    #[test]
+    fn explain() {
+        let mut p = Parser::new();
+
+        let query = p.parse("explain select distinct name, fsize, upper(ext) from /test depth 2 archives 10 nodedup \
+                             where (name like '%.txt' or name =~ '^a') and not modified > 2020-01-01 and size gt 1k \
+                             order by size desc, name limit 5 offset 1 into json '/tmp/found.json'").unwrap();
+        assert!(query.explain);
+        assert_eq!(query.to_string(), "\
+select distinct: Name, FormattedSize, Upper(Extension)
+from:
+  '/test' depth 2 archives 10 nodedup
+where:
+  and
+    and
+      or
+        Name like '%.txt' [regex (?is)^.*\\.txt$]
+        Name =~ '^a' [regex ^a]
+      not Modified > '2020-01-01' [from 2020-01-01 00:00:00 to 2020-01-01 23:59:59]
+    Size > '1k'
+order by: Size desc, Name asc
+limit: 5
+offset: 1
+into: json '/tmp/found.json'
+");
+
+        let query = p.parse("EXPLAIN path from (select path from /home where is_dir = true limit 3) mindepth 1, /tmp \
+                             where name = '*.rs' and modified > created group by ext into lines esc").unwrap();
+        assert_eq!(query.to_string(), "\
+select: Path
+from:
+  (subquery) mindepth 1
+    select: Path
+    from:
+      '/home'
+    where:
+      IsDir = 'true'
+    limit: 3
+    into: list
+  '/tmp'
+where:
+  and
+    Name = '*.rs' [regex (?is)^[^/\\\\]*\\.rs$]
+    Modified > Created
+group by: Extension
+into: lines esc
+");
+
+        let query = p.parse("explain name from /a union name from /b").unwrap();
+        assert!(query.explain && query.unions[0].explain);
+
+        let query = p.parse("select name from /test").unwrap();
+        assert!(!query.explain);
+
+        assert!(p.parse("select name from /test explain").is_err());
+    }
 }
@@ -0,0 +1,195 @@
+use std::env;
+use std::io::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use atty;
+#[cfg(unix)]
+use libc;
+use term;
+#[cfg(windows)]
+use winapi::shared::minwindef::BOOL;
+#[cfg(windows)]
+use winapi::shared::minwindef::DWORD;
+#[cfg(windows)]
+use winapi::shared::minwindef::FALSE;
+#[cfg(windows)]
+use winapi::shared::minwindef::TRUE;
+#[cfg(windows)]
+use winapi::um::consoleapi::SetConsoleCtrlHandler;
+#[cfg(windows)]
+use winapi::um::wincon::CTRL_BREAK_EVENT;
+#[cfg(windows)]
+use winapi::um::wincon::CTRL_C_EVENT;
+use term::StderrTerminal;
+
+/// The status line is redrawn after this many files have been examined.
+const UPDATE_INTERVAL: u64 = 1000;
+/// Any value except an empty one or `0` turns the progress on for every query.
+const PROGRESS_ENV_VAR: &'static str = "FSELECT_PROGRESS";
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Single-line search status on stderr, so the results on stdout stay intact.
+pub struct Progress {
+    terminal: Option<Box<StderrTerminal>>,
+    started: Instant,
+    dirs: u64,
+    files: u64,
+    matches: u64,
+    drawn_len: usize,
+}
+
+impl Progress {
+    /// Nothing is shown when stderr is redirected.
+    pub fn start() -> Option<Progress> {
+        if !atty::is(atty::Stream::Stderr) {
+            return None;
+        }
+
+        Some(Progress {
+            terminal: term::stderr(),
+            started: Instant::now(),
+            dirs: 0,
+            files: 0,
+            matches: 0,
+            drawn_len: 0,
+        })
+    }
+
+    pub fn is_requested_by_env() -> bool {
+        match env::var(PROGRESS_ENV_VAR) {
+            Ok(val) => !val.is_empty() && val != "0",
+            Err(_) => false
+        }
+    }
+
+    pub fn add_dir(&mut self) {
+        self.dirs += 1;
+    }
+
+    pub fn add_file(&mut self) {
+        self.files += 1;
+
+        if self.files % UPDATE_INTERVAL == 0 {
+            let status = self.status();
+            self.draw(&status);
+        }
+    }
+
+    pub fn add_match(&mut self) {
+        self.matches += 1;
+    }
+
+    /// Removes the status line before anything else is printed to the terminal.
+    pub fn clear(&mut self) {
+        if self.drawn_len == 0 {
+            return;
+        }
+
+        let cleared = match self.terminal {
+            Some(ref mut t) => t.carriage_return().and_then(|_| t.delete_line()).is_ok(),
+            None => false
+        };
+
+        if !cleared {
+            eprint!("\r{}\r", " ".repeat(self.drawn_len));
+        }
+
+        self.drawn_len = 0;
+    }
+
+    /// Replaces the status line with the final summary.
+    pub fn finish(mut self) {
+        let mut status = self.status();
+        if is_interrupted() {
+            status.push_str(", interrupted");
+        }
+
+        self.draw(&status);
+        eprintln!();
+    }
+
+    fn status(&self) -> String {
+        format_status(self.dirs, self.files, self.matches, self.started.elapsed())
+    }
+
+    fn draw(&mut self, status: &str) {
+        self.clear();
+
+        match self.terminal {
+            Some(ref mut t) => {
+                let _ = write!(t, "{}", status);
+                let _ = t.flush();
+            },
+            None => eprint!("{}", status)
+        }
+
+        self.drawn_len = status.chars().count();
+    }
+}
+
+fn format_status(dirs: u64, files: u64, matches: u64, elapsed: Duration) -> String {
+    format!("{} directories visited, {} files examined, {} found in {}.{}s",
+            dirs, files, matches, elapsed.as_secs(), elapsed.subsec_millis() / 100)
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// A new search isn't stopped by the Ctrl-C which stopped the previous one.
+pub fn reset_interrupted() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+extern "C" fn handle_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+
+    // the second Ctrl-C terminates the process right away
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn handle_console_ctrl(ctrl_type: DWORD) -> BOOL {
+    match ctrl_type {
+        // the second Ctrl-C is passed to the default handler, which terminates the process
+        CTRL_C_EVENT | CTRL_BREAK_EVENT => match INTERRUPTED.swap(true, Ordering::SeqCst) {
+            true => FALSE,
+            false => TRUE
+        },
+        _ => FALSE
+    }
+}
+
+/// Ctrl-C stops the search instead of killing the process, so the results found so far are still output.
+/// It's up to the application to install the handler, the library never touches the signals.
+pub fn install_interrupt_handler() {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGINT, handle_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+
+    #[cfg(windows)]
+    unsafe {
+        SetConsoleCtrlHandler(Some(handle_console_ctrl), TRUE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_status() {
+        assert_eq!(format_status(12, 3400, 5, Duration::from_millis(2345)),
+                   "12 directories visited, 3400 files examined, 5 found in 2.3s");
+        assert_eq!(format_status(0, 0, 0, Duration::from_millis(80)),
+                   "0 directories visited, 0 files examined, 0 found in 0.0s");
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+/// Row of the search results: values of the selected columns in the order they are written in the query.
+/// Values are formatted the same way they are output, e.g. dates follow the `dateformat` of the query.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// Lowercased column names, rows of a union are named after the columns of its first query
+    pub columns: Vec<String>,
+    /// Columns without a value in this row, like the aggregates in the rows of the files, are empty
+    pub values: Vec<String>,
+    /// Tells the empty values from the missing ones, which JSON leaves out
+    pub(crate) has_value: Vec<bool>,
+    /// Query of the union the row was found by, 0 for the first one
+    pub(crate) part: usize,
+    /// Values of the fields by their lowercased names, kept only for the output formats referring to them
+    pub(crate) fields: HashMap<String, String>,
+}
+
+impl Record {
+    pub fn new(columns: Vec<String>, values: Vec<String>) -> Record {
+        let has_value = vec![true; values.len()];
+        Record { columns, values, has_value, part: 0, fields: HashMap::new() }
+    }
+
+    /// Value of the column by its name, e.g. `size` or `count(*)`, ignoring the case.
+    pub fn get(&self, column: &str) -> Option<&str> {
+        let column = column.to_lowercase();
+
+        self.columns.iter()
+            .position(|name| name == &column)
+            .and_then(|idx| self.values.get(idx))
+            .map(|value| value.as_str())
+    }
+}
+
+/// Records are the same when they have the same columns and values.
+impl PartialEq for Record {
+    fn eq(&self, other: &Record) -> bool {
+        self.columns == other.columns && self.values == other.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get() {
+        let record = Record::new(vec![String::from("name"), String::from("count(*)")], vec![String::from("a.txt"), String::from("3")]);
+
+        assert_eq!(record.get("name"), Some("a.txt"));
+        assert_eq!(record.get("COUNT(*)"), Some("3"));
+        assert_eq!(record.get("size"), None);
+    }
+}
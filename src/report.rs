@@ -0,0 +1,28 @@
+use std::io;
+use std::path::Path;
+
+use util::error_message;
+use util::path_error_message;
+
+/// Receives the errors met during the search. They don't stop it, the results are just incomplete.
+/// The default one prints them to stderr, a custom one could collect them instead.
+pub trait ErrorReporter {
+    /// A file, a directory or an archive could not be read.
+    fn path_error(&mut self, path: &Path, err: &io::Error);
+
+    /// Any other error or warning, `source` tells what it is about, e.g. `subquery` or a column name.
+    fn error(&mut self, source: &str, description: &str);
+}
+
+/// Prints the errors to stderr, so they don't get mixed with the results.
+pub struct StderrReporter;
+
+impl ErrorReporter for StderrReporter {
+    fn path_error(&mut self, path: &Path, err: &io::Error) {
+        path_error_message(path, err);
+    }
+
+    fn error(&mut self, source: &str, description: &str) {
+        error_message(source, description);
+    }
+}
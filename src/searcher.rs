@@ -1,4 +1,9 @@
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::cmp;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::env;
 use std::fs;
 use std::fs::DirEntry;
 use std::fs::File;
@@ -7,64 +12,363 @@ use std::fs::symlink_metadata;
 use std::path::Path;
 use std::path::PathBuf;
 use std::io;
+use std::io::BufRead;
 use std::io::BufReader;
+use std::io::Cursor;
 use std::io::Read;
+use std::io::Seek;
+use std::mem;
 use std::rc::Rc;
+use std::vec;
 
-use chrono::{Datelike, DateTime, Local};
-use csv;
+use bzip2::read::BzDecoder;
+use chrono::{Datelike, DateTime, Local, NaiveDateTime, TimeZone};
+use flate2::read::GzDecoder;
 use humansize::{FileSize, file_size_opts};
 use imagesize;
 use mp3_metadata;
 use mp3_metadata::MP3Metadata;
-use serde_json;
-use term::StdoutTerminal;
+use regex::Regex;
+use tar;
 #[cfg(unix)]
-use users::{Groups, Users, UsersCache};
-#[cfg(unix)]
-use xattr::FileExt;
+use xattr;
 use zip;
 
+use dupes::DupeFinder;
 use field::Field;
 use fileinfo::FileInfo;
+use fileinfo::tar_entry_to_file_info;
+use fileinfo::read_zip_extra_fields;
 use fileinfo::to_file_info;
+use fileinfo::ZipMembers;
 use function::Function;
+use gitignore::DEFAULT_IGNORE_FILE;
 use gitignore::GitignoreFilter;
+use gitignore::GitignoreFilters;
+use gitignore::IgnoreSource;
 use gitignore::matches_gitignore_filter;
 use gitignore::parse_gitignore;
+use gitignore::parse_global_ignore;
+use gitignore::parse_ignore_file;
+use gitignore::parse_parent_gitignores;
+use gitstatus::GitStatusCache;
+use hash::calc_hashes;
+use hash::HashKinds;
+use hash::Hashes;
+use index::EntryKind;
+use index::Index;
+use index::IndexedEntry;
+use mimetype::get_mime_type;
 use mode;
+use mp3::get_audio_rates;
+use mp3::AudioRates;
+use owners::OwnerNames;
+use owners::SystemOwnerNames;
+use parser::ArithmeticOp;
 use parser::ColumnExpr;
+use parser::DEFAULT_DATE_FORMAT;
 use parser::Query;
+use parser::Root;
+use parser::RootList;
+use parser::PrunePattern;
 use parser::Expr;
 use parser::LogicalOp;
 use parser::Op;
 use parser::OutputFormat;
+use progress;
+use progress::Progress;
+use record::Record;
+use report::ErrorReporter;
+use report::StderrReporter;
 use util::*;
+use video::read_video_metadata;
+use video::VideoMetadata;
+
+/// Files larger than this are skipped by `contains()` and `matches()` unless another limit is given.
+const DEFAULT_CONTENT_SEARCH_LIMIT: u64 = 10 * 1024 * 1024;
+/// Only the beginning of the file is read to tell binary files from text ones.
+const BINARY_CHECK_SIZE: u64 = 8 * 1024;
+const LINE_ENDINGS_CHECK_SIZE: u64 = 64 * 1024;
+/// Longest shebang line the kernel reads as well
+const SHEBANG_CHECK_SIZE: u64 = 256;
+/// Zip archives inside searched archives are entered, archives inside those aren't.
+const DEFAULT_ARCHIVE_DEPTH: u32 = 2;
+/// Archives inside archives are read into memory, so the larger ones are skipped.
+const DEFAULT_MAX_NESTED_SIZE: u64 = 100 * 1024 * 1024;
+/// Files whose content was probed are remembered up to this number.
+const PROBE_CACHE_SIZE: usize = 10_000;
+
+/// Receives the records as soon as they are final, returning `false` stops the search.
+type RecordSink<'a> = FnMut(Record) -> bool + 'a;
+
+/// What has been read from the file content so far, kept by the canonical path of the file,
+/// so the same file reached from overlapping roots or by another union query isn't read again.
+#[derive(Default)]
+struct FileProbes {
+    dimensions: Option<Option<(usize, usize)>>,
+    mp3: Option<Option<Rc<MP3Metadata>>>,
+    video: Option<Option<VideoMetadata>>,
+    /// Only the digests of the kinds they were calculated for are there
+    hashes: Option<(HashKinds, Hashes)>,
+}
+
+/// Marker file of `is_newer_than`, `is_older_than` or `same_file_as`, read once when the search starts.
+struct Marker {
+    modified: DateTime<Local>,
+    size: u64,
+    /// Device and inode, known on Unix-like systems only
+    file_id: Option<(u64, u64)>,
+}
 
 pub struct Searcher {
     query: Query,
-    user_cache: UsersCache,
+    union_queries: Vec<Query>,
+    union: bool,
+    union_columns: Vec<String>,
+    /// Query of the union being searched, 0 for the first one
+    part: usize,
+    user_cache: Box<OwnerNames>,
     found: u32,
-    raw_output_buffer: Vec<HashMap<String, String>>,
-    output_buffer: TopN<Criteria<String>, String>,
-    gitignore_map: HashMap<PathBuf, Vec<GitignoreFilter>>,
+    raw_output_buffer: Vec<HashMap<String, Value>>,
+    output_buffer: SortBuffer,
+    /// Aggregates over all the files of a query, output after its rows
+    summary_rows: Vec<Record>,
+    gitignore_map: HashMap<(PathBuf, IgnoreSource), Rc<Vec<GitignoreFilter>>>,
+    global_ignore_filters: Rc<Vec<GitignoreFilter>>,
+    /// The user-wide ignore file is applied under the current root
+    apply_global_ignore: bool,
+    dedup: bool,
+    visited_paths: HashSet<PathBuf>,
+    dupe_finder: Option<DupeFinder>,
+    /// Files are only counted, their rows are neither built nor output
+    count_only: bool,
+    /// The search stops once this many files matched, 0 means it doesn't
+    max_matches: u32,
+    /// Matching files of all the queries of the union
+    matched: u32,
+    /// Total size of the matching regular files, it's summed up for the summary only
+    matched_size: u64,
+    /// Rows of files or groups output, the summary rows of aggregates aside
+    shown: u32,
+    dupe_hashes: HashMap<PathBuf, Hashes>,
+    distinct_rows: HashSet<Vec<Option<Value>>>,
+    root_dir: PathBuf,
+    /// Index of the root being searched in `searched_roots`
+    root_idx: usize,
+    /// Every root searched by the query with the index of the query root it comes from, listed roots share the index of their list.
+    /// The duplicates found are checked again with the options of their roots.
+    searched_roots: Vec<(usize, PathBuf)>,
+    /// Depth of the file being checked, the root's own entries are at depth 1.
+    /// Archive members are at the depth of their archive.
+    depth: u32,
+    follow_symlinks: bool,
+    apply_gitignore: bool,
+    /// Only the files excluded by `.gitignore` files are checked, the other ignore files are applied as usual
+    invert_gitignore: bool,
+    ignore_file: String,
+    /// Device of the current root when the search should stay on its file system
+    root_device: Option<u64>,
+    max_archive_entries: u32,
+    max_archive_size: u64,
+    archive_depth: u32,
+    max_nested_size: u64,
+    /// Names and paths of archive members go without the archive, which is a column of its own then
+    split_archive_names: bool,
+    /// Zip archives being searched, the innermost last
+    zip_archives: Vec<Box<ZipMembers>>,
+    ordered: bool,
+    max_files: u32,
+    /// Names of the directories of the current root that are not descended into
+    prune: Vec<PrunePattern>,
+    lenient: bool,
+    dir_size_cache: RefCell<HashMap<PathBuf, u64>>,
+    hash_kinds: HashKinds,
+    hash_cache: RefCell<Option<Hashes>>,
+    binary_cache: RefCell<Option<Option<bool>>>,
+    line_endings_cache: RefCell<Option<Option<&'static str>>>,
+    bom_cache: RefCell<Option<Option<bool>>>,
+    shebang_cache: RefCell<Option<Option<String>>>,
+    index: Option<Index>,
+    index_path: Option<PathBuf>,
+    /// Roots searched with the index, what is under them and wasn't visited is gone
+    indexed_roots: Vec<PathBuf>,
+    /// The index has every field of the query, otherwise the files it doesn't rule out are checked on the disk
+    answered_from_index: bool,
+    video_cache: RefCell<Option<Option<VideoMetadata>>>,
+    /// Path of the current entry with its parent directory resolved, the key of the probes and of the visited paths
+    canonical_path_cache: RefCell<Option<PathBuf>>,
+    probe_cache: RefCell<LruCache<PathBuf, FileProbes>>,
+    /// Images actually read for their dimensions
+    #[cfg(test)]
+    dimension_probes: Cell<u32>,
+    /// Parent directories resolved, once per entry at most
+    #[cfg(test)]
+    canonicalized_paths: Cell<u32>,
+    /// Directory entries looked at, pruned directories save them all
+    #[cfg(test)]
+    visited_entries: u32,
+    git_status_cache: RefCell<GitStatusCache>,
+    empty_dir_cache: RefCell<Option<Option<bool>>>,
+    /// Number of directory entries, only when counted till the end
+    entries_cache: RefCell<Option<Option<u64>>>,
+    created_fallback_reported: Cell<bool>,
+    /// Start of the search, the ages in days are counted till then
+    now: DateTime<Local>,
+    /// Marker files by the paths written in the query
+    markers: HashMap<String, Marker>,
+    current_user: mode::CurrentUser,
+    captures: Vec<String>,
+    quiet: bool,
+    errors: u32,
+    error_reporter: RefCell<Box<ErrorReporter>>,
+    progress: RefCell<Option<Progress>>,
+    /// The receiver of the records wants no more of them
+    stopped: Cell<bool>,
 }
 
 impl Searcher {
-    pub fn new(query: Query) -> Self {
-        let limit = query.limit;
+    /// The queries of the union attached to the query are searched after it.
+    pub fn new(mut query: Query) -> Self {
+        // the skipped rows have to be sorted as well
+        let limit = match query.limit {
+            0 => 0,
+            limit => limit.saturating_add(query.offset)
+        };
+        let union_queries = mem::replace(&mut query.unions, vec![]);
+        let union = !union_queries.is_empty();
         Searcher {
             query,
-            user_cache: UsersCache::new(),
+            union_queries,
+            union,
+            union_columns: vec![],
+            part: 0,
+            user_cache: Box::new(SystemOwnerNames::new()),
             found: 0,
             raw_output_buffer: vec![],
-            output_buffer: if limit == 0 { TopN::limitless() } else { TopN::new(limit) },
+            output_buffer: SortBuffer::new(limit),
+            summary_rows: vec![],
             gitignore_map: HashMap::new(),
+            global_ignore_filters: Rc::new(vec![]),
+            apply_global_ignore: true,
+            dedup: false,
+            visited_paths: HashSet::new(),
+            dupe_finder: None,
+            count_only: false,
+            max_matches: 0,
+            matched: 0,
+            matched_size: 0,
+            shown: 0,
+            dupe_hashes: HashMap::new(),
+            distinct_rows: HashSet::new(),
+            root_dir: PathBuf::new(),
+            root_idx: 0,
+            searched_roots: vec![],
+            depth: 0,
+            follow_symlinks: false,
+            apply_gitignore: false,
+            invert_gitignore: false,
+            ignore_file: String::from(DEFAULT_IGNORE_FILE),
+            root_device: None,
+            max_archive_entries: 0,
+            max_archive_size: 0,
+            archive_depth: DEFAULT_ARCHIVE_DEPTH,
+            max_nested_size: DEFAULT_MAX_NESTED_SIZE,
+            split_archive_names: false,
+            zip_archives: vec![],
+            ordered: false,
+            max_files: 0,
+            prune: vec![],
+            lenient: false,
+            dir_size_cache: RefCell::new(HashMap::new()),
+            hash_kinds: HashKinds::default(),
+            hash_cache: RefCell::new(None),
+            binary_cache: RefCell::new(None),
+            line_endings_cache: RefCell::new(None),
+            bom_cache: RefCell::new(None),
+            shebang_cache: RefCell::new(None),
+            index: None,
+            index_path: None,
+            indexed_roots: vec![],
+            answered_from_index: false,
+            video_cache: RefCell::new(None),
+            canonical_path_cache: RefCell::new(None),
+            probe_cache: RefCell::new(LruCache::new(PROBE_CACHE_SIZE)),
+            #[cfg(test)]
+            dimension_probes: Cell::new(0),
+            #[cfg(test)]
+            canonicalized_paths: Cell::new(0),
+            #[cfg(test)]
+            visited_entries: 0,
+            git_status_cache: RefCell::new(GitStatusCache::new()),
+            empty_dir_cache: RefCell::new(None),
+            entries_cache: RefCell::new(None),
+            created_fallback_reported: Cell::new(false),
+            now: Local::now(),
+            markers: HashMap::new(),
+            current_user: mode::CurrentUser::get(),
+            captures: vec![],
+            quiet: false,
+            errors: 0,
+            error_reporter: RefCell::new(Box::new(StderrReporter)),
+            progress: RefCell::new(None),
+            stopped: Cell::new(false),
+        }
+    }
+
+    /// Suppresses error messages, they are still counted though.
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    /// Errors are printed to stderr unless another reporter is given.
+    pub fn set_error_reporter(&mut self, error_reporter: Box<ErrorReporter>) {
+        self.error_reporter = RefCell::new(error_reporter);
+    }
+
+    /// Without a limit, ordered rows above this number are written to temporary files and merged back for the output.
+    /// 1M rows by default.
+    pub fn set_sort_max_rows(&mut self, max_rows: usize) {
+        self.output_buffer.set_max_rows(max_rows);
+    }
+
+    /// The same for the estimated size of the rows in bytes, 512 MiB by default.
+    pub fn set_sort_max_memory(&mut self, max_bytes: u64) {
+        self.output_buffer.set_max_bytes(max_bytes);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors > 0
+    }
+
+    /// Metadata of the files is kept in the index file between the searches,
+    /// the directories which haven't changed since then are not examined again.
+    pub fn set_index(&mut self, path: &Path) {
+        self.index = Some(Index::load(path));
+        self.index_path = Some(path.to_path_buf());
+    }
+
+    /// The search was stopped with Ctrl-C, so the results are partial.
+    /// Ctrl-C stops the search only when the application has called `install_interrupt_handler`.
+    pub fn is_interrupted(&self) -> bool {
+        progress::is_interrupted()
+    }
+
+    fn report_error(&mut self, path: &Path, err: io::Error) {
+        self.errors += 1;
+
+        if !self.quiet {
+            if let Some(ref mut progress) = *self.progress.borrow_mut() {
+                progress.clear();
+            }
+
+            self.error_reporter.borrow_mut().path_error(path, &err);
         }
     }
 
+    /// Queries of a union are searched one by one, so their rows are buffered
+    /// to apply the ordering and the limit to all of them.
     pub fn is_buffered(&self) -> bool {
-        self.has_ordering() || self.has_aggregate_column()
+        self.union || self.has_ordering() || self.has_aggregate_column()
     }
 
     fn has_ordering(&self) -> bool {
@@ -75,2119 +379,6966 @@ impl Searcher {
         self.query.fields.iter().any(|ref f| f.has_aggregate_function())
     }
 
-    fn print_results_start(&self) {
-        if let OutputFormat::Json = self.query.output_format {
-            print!("[");
-        }
+    /// Without `group by` the aggregates can be mixed with columns of files:
+    /// every file gets a row of its own, and the aggregates follow in a summary row.
+    fn has_summary_row(&self) -> bool {
+        self.query.grouping_fields.is_empty()
+            && self.has_aggregate_column()
+            && self.query.fields.iter().any(|ref f| !f.has_aggregate_function() && !f.get_required_fields().is_empty())
     }
 
-    fn format_results_row(&self, record: String,
-                          mut output_value: String,
-                          records: &mut Vec<String>) -> String {
-        match self.query.output_format {
-            OutputFormat::Lines => {
-                output_value.push_str(&record);
-                output_value.push('\n');
-            },
-            OutputFormat::List => {
-                output_value.push_str(&record);
-                output_value.push('\0');
-            },
-            OutputFormat::Json => {
-                // use file_map later
-            },
-            OutputFormat::Tabs => {
-                output_value.push_str(&record);
-                output_value.push('\t');
-            },
-            OutputFormat::Csv => {
-                records.push(record);
-            },
+    /// Streamed results stop once `limit` rows following the `offset` ones are printed.
+    /// Unordered rows of a union are all equal for the buffer, so the search stops once it's full.
+    /// The summary needs all the matching files though, so the rest of them are only counted then.
+    fn is_limit_reached(&self) -> bool {
+        if self.count_only || self.query.summary {
+            return false;
         }
 
-        output_value
+        match self.is_buffered() {
+            true => !self.has_ordering() && !self.has_aggregate_column() && self.output_buffer.is_full(),
+            false => self.query.limit > 0 && self.query.limit.saturating_add(self.query.offset) <= self.found
+        }
     }
 
-    fn format_results_row_end(&self,
-                              mut output_value: String,
-                              records: &Vec<String>,
-                              file_map: &HashMap<String, String>) -> String {
-        match self.query.output_format {
-            OutputFormat::Lines | OutputFormat::List => {},
-            OutputFormat::Tabs => {
-                output_value.push('\n');
-            },
-            OutputFormat::Csv => {
-                let mut csv_output = WritableBuffer::new();
-                {
-                    let mut csv_writer = csv::Writer::from_writer(&mut csv_output);
-                    let _ = csv_writer.write_record(records);
-                }
-                let result: String = csv_output.into();
-                output_value.push_str(result.as_ref());
-            },
-            OutputFormat::Json => {
-                if !self.is_buffered() && self.found > 1 {
-                    output_value.push(',');
-                }
-                output_value.push_str(&serde_json::to_string(&file_map).unwrap());
-            },
+    /// Ctrl-C stops the search, the rows found so far are output as usual.
+    /// The receiver of the records stops it too, e.g. once the reader of the pipe has gone.
+    fn is_search_stopped(&self) -> bool {
+        self.is_limit_reached()
+            || (self.max_matches > 0 && self.matched >= self.max_matches)
+            || progress::is_interrupted()
+            || self.stopped.get()
+    }
+
+    /// Only the number of found files is needed, so there is no reason to keep the rows.
+    fn is_count_only(&self) -> bool {
+        if self.count_only {
+            return true;
         }
 
-        output_value
+        self.query.grouping_fields.is_empty()
+            && !self.has_ordering()
+            && !self.query.distinct
+            && self.query.fields.iter().all(|ref f| f.get_function() == Some(Function::Count) && !f.is_distinct())
     }
 
-    fn print_results_end(&self) {
-        if let OutputFormat::Json = self.query.output_format {
-            print!("]");
+    /// Rows of files are deduplicated right away, while aggregated rows are deduplicated once the groups are done.
+    fn is_distinct_rows(&self) -> bool {
+        !self.count_only && self.query.distinct && (!self.has_aggregate_column() || self.has_summary_row())
+    }
+
+    /// Counts the file as found and tells whether its row should be output.
+    /// The size is only added up for the summary.
+    fn count_found(&mut self, size: u64) -> bool {
+        self.found += 1;
+        self.matched += 1;
+        self.matched_size += size;
+
+        if let Some(ref mut progress) = *self.progress.borrow_mut() {
+            progress.add_match();
+        }
+
+        if self.is_count_only() {
+            return false;
+        }
+
+        if self.is_buffered() {
+            return true;
         }
+
+        self.found > self.query.offset
+            && (self.query.limit == 0 || self.found <= self.query.limit.saturating_add(self.query.offset))
     }
 
-    pub fn list_search_results(&mut self, t: &mut Box<StdoutTerminal>) -> io::Result<()> {
-        let need_metadata = self.query.get_all_fields().iter().any(|f| f != &Field::Name);
-        let need_dim = self.query.get_all_fields().iter().any(|f| f == &Field::Width || f == &Field::Height);
-        let need_mp3 = self.query.get_all_fields().iter().any(|f| f.is_mp3_field());
+    /// Values of the columns as they are output, `None` for a column which has no value in this row,
+    /// like an aggregate in a row of a file. The columns are named once the record is handed over.
+    fn to_record(&self, values: &Vec<Option<Value>>, file_map: &HashMap<String, Value>) -> Record {
+        let fields = match self.query.output_format {
+            OutputFormat::Cmd { .. } | OutputFormat::Table { .. } => {
+                file_map.iter().map(|(key, value)| (key.clone(), self.format_column_value(value))).collect()
+            },
+            _ => HashMap::new()
+        };
 
-        self.print_results_start();
+        Record {
+            columns: vec![],
+            values: values.iter()
+                .map(|value| match value {
+                    Some(ref value) => self.format_column_value(value),
+                    None => String::new()
+                })
+                .collect(),
+            has_value: values.iter().map(|value| value.is_some()).collect(),
+            part: self.part,
+            fields,
+        }
+    }
 
-        for root in &self.query.clone().roots {
-            let root_dir = Path::new(&root.path);
-            let min_depth = root.min_depth;
-            let max_depth = root.max_depth;
-            let search_archives = root.archives;
-            let follow_symlinks = root.symlinks;
-            let apply_gitignore = root.gitignore;
-            let _result = self.visit_dirs(
-                root_dir,
-                need_metadata,
-                need_dim,
-                need_mp3,
-                min_depth,
-                max_depth,
-                1,
-                search_archives,
-                follow_symlinks,
-                apply_gitignore,
-                t
-            );
+    /// Dates keep their time until they are output in the date format of the query.
+    fn format_column_value(&self, value: &Value) -> String {
+        match value.to_datetime() {
+            Some(dt) => self.query.date_format.format(&dt),
+            None => value.to_string()
         }
+    }
 
-        if self.has_aggregate_column() {
-            let mut records = vec![];
-            let mut file_map = HashMap::new();
-            let mut output_value = String::new();
+    /// Hands the record over, the search stops once the receiver wants no more of them.
+    fn emit(&self, mut record: Record, on_record: &mut RecordSink) {
+        record.columns = self.union_columns.clone();
 
-            for column_expr in &self.query.fields {
-                let record = format!("{}", self.get_aggregate_function_value(column_expr));
-                file_map.insert(column_expr.to_string().to_lowercase(), record.clone());
+        if let Some(ref mut progress) = *self.progress.borrow_mut() {
+            progress.clear();
+        }
 
-                output_value = self.format_results_row(record, output_value, &mut records);
-            }
+        if !on_record(record) {
+            self.stopped.set(true);
+        }
+    }
 
-            output_value = self.format_results_row_end(output_value, &records, &file_map);
+    /// Finds the rows and hands them over as soon as they are final: the rows of files right away,
+    /// ordered and aggregated rows once all the files are found. The search stops when `on_record` returns `false`.
+    /// The output format of the query only decides which fields the records keep, `RecordWriter` prints them.
+    /// Errors go to the error reporter as usual.
+    pub fn search(&mut self, on_record: &mut FnMut(Record) -> bool) {
+        if self.query.progress || Progress::is_requested_by_env() {
+            *self.progress.borrow_mut() = Progress::start();
+        }
 
-            print!("{}", output_value);
-        } else if self.is_buffered() {
-            let mut first = true;
-            for piece in self.output_buffer.values() {
-                if let OutputFormat::Json = self.query.output_format {
-                    if first {
-                        first = false;
-                    } else {
-                        print!(",");
-                    }
+        progress::reset_interrupted();
+        self.search_queries(on_record);
+
+        // the index of an interrupted search is incomplete
+        if let (Some(mut index), Some(index_path)) = (self.index.take(), self.index_path.clone()) {
+            if !self.indexed_roots.is_empty() && !progress::is_interrupted() {
+                if let Err(err) = index.save(&index_path, &self.indexed_roots) {
+                    self.report_error(&index_path, err);
                 }
-                print!("{}", piece);
             }
         }
 
-        self.print_results_end();
+        if let Some(progress) = self.progress.borrow_mut().take() {
+            progress.finish();
+        }
+    }
 
-        Ok(())
+    /// Files matching the query and the queries of its union. With `summary` they are counted past the limit.
+    pub fn matched(&self) -> u32 {
+        self.matched
     }
 
-    fn visit_dirs(&mut self,
-                  dir: &Path,
-                  need_metadata: bool,
-                  need_dim: bool,
-                  need_mp3: bool,
-                  min_depth: u32,
-                  max_depth: u32,
-                  depth: u32,
-                  search_archives: bool,
-                  follow_symlinks: bool,
-                  apply_gitignore: bool,
-                  t: &mut Box<StdoutTerminal>) -> io::Result<()> {
-        if (min_depth == 0 || (min_depth > 0 && depth >= min_depth)) && (max_depth == 0 || (max_depth > 0 && depth <= max_depth)) {
-            let metadata = match follow_symlinks {
-                true => dir.metadata(),
-                false => symlink_metadata(dir)
-            };
-            match metadata {
-                Ok(metadata) => {
-                    if metadata.is_dir() {
-                        let mut gitignore_filters = None;
-
-                        if apply_gitignore {
-                            let gitignore_file = dir.join(".gitignore");
-                            if gitignore_file.is_file() {
-                                let regexes = parse_gitignore(&gitignore_file, dir);
-                                self.gitignore_map.insert(dir.to_path_buf(), regexes);
-                            }
+    /// Rows of files or groups output, the summary rows of aggregates aside.
+    pub fn shown(&self) -> u32 {
+        self.shown
+    }
 
-                            gitignore_filters = Some(self.get_gitignore_filters(dir));
-                        }
+    /// Total size of the matching regular files, it's only summed up when the query has `summary`.
+    pub fn matched_size(&self) -> u64 {
+        self.matched_size
+    }
 
-                        match fs::read_dir(dir) {
-                            Ok(entry_list) => {
-                                for entry in entry_list {
-                                    if !self.is_buffered() && self.query.limit > 0 && self.query.limit <= self.found {
-                                        break;
-                                    }
+    /// Counts the files matching the query and the queries of its union without printing anything.
+    /// Only the conditions are evaluated, columns, ordering, grouping, `distinct`, `limit` and `into` are ignored.
+    pub fn count(&mut self) -> u32 {
+        self.count_only = true;
+        progress::reset_interrupted();
+        self.search_queries(&mut |_| true);
 
-                                    match entry {
-                                        Ok(entry) => {
-                                            let path = entry.path();
-
-                                            if !apply_gitignore || (apply_gitignore && !matches_gitignore_filter(&gitignore_filters, entry.path().to_string_lossy().as_ref(), path.is_dir())) {
-                                                self.check_file(&entry, &None, need_metadata, need_dim, need_mp3, follow_symlinks, t);
-
-                                                if search_archives && is_zip_archive(&path.to_string_lossy()) {
-                                                    if let Ok(file) = fs::File::open(&path) {
-                                                        if let Ok(mut archive) = zip::ZipArchive::new(file) {
-                                                            for i in 0..archive.len() {
-                                                                if self.query.limit > 0 && self.query.limit <= self.found {
-                                                                    break;
-                                                                }
-
-                                                                if let Ok(afile) = archive.by_index(i) {
-                                                                    let file_info = to_file_info(&afile);
-                                                                    self.check_file(&entry, &Some(file_info), need_metadata, need_dim, need_mp3, false, t);
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
+        self.matched
+    }
 
-                                                if path.is_dir() {
-                                                    let result = self.visit_dirs(
-                                                        &path,
-                                                        need_metadata,
-                                                        need_dim,
-                                                        need_mp3,
-                                                        min_depth,
-                                                        max_depth,
-                                                        depth + 1,
-                                                        search_archives,
-                                                        follow_symlinks,
-                                                        apply_gitignore,
-                                                        t);
-
-                                                    if result.is_err() {
-                                                        path_error_message(&path, result.err().unwrap(), t);
-                                                    }
-                                                }
-                                            }
-                                        },
-                                        Err(err) => {
-                                            path_error_message(dir, err, t);
-                                        }
-                                    }
-                                }
-                            },
-                            Err(err) => {
-                                path_error_message(dir, err, t);
-                            }
+    /// Tells whether any file matches the query or the queries of its union, the search stops at the first one.
+    pub fn exists(&mut self) -> bool {
+        self.max_matches = 1;
+
+        self.count() > 0
+    }
+
+    /// Searches the query and then the queries of its union, the rows are handed over as soon as they are final.
+    fn search_queries(&mut self, on_record: &mut RecordSink) {
+        self.now = Local::now();
+        self.global_ignore_filters = Rc::new(parse_global_ignore());
+        self.stopped.set(false);
+
+        self.union_columns = self.query.fields.iter().map(|column_expr| column_expr.get_column_name()).collect();
+        self.search_query(on_record);
+
+        while !self.union_queries.is_empty() && !self.is_search_stopped() {
+            self.query = self.union_queries.remove(0);
+            self.part += 1;
+            self.found = 0;
+            self.raw_output_buffer.clear();
+            self.visited_paths.clear();
+            self.distinct_rows.clear();
+
+            self.search_query(on_record);
+        }
+
+        if self.is_buffered() {
+            self.emit_buffered_records(on_record);
+        }
+
+        self.emit_summary_records(on_record);
+    }
+
+    /// Searches the roots of the current query.
+    /// Grouped rows are aggregated and buffered as soon as all the files are found.
+    fn search_query(&mut self, on_record: &mut RecordSink) {
+        // ordering criteria are calculated with the same metadata as the columns,
+        // none of them is needed when the files are only counted
+        let mut fields = HashSet::new();
+        if !self.count_only {
+            fields.extend(self.query.get_all_fields());
+            for column_expr in &self.query.ordering_fields {
+                fields.extend(column_expr.get_required_fields());
+            }
+        }
+
+        let need_metadata = fields.iter().any(|f| f.needs_metadata());
+        let need_dim = fields.iter().any(|f| f.is_img_dimension_field());
+        let need_mp3 = fields.iter().any(|f| f.is_mp3_field());
+
+        self.split_archive_names = self.query.get_all_fields().contains(&Field::Archive);
+
+        let mut hashed_fields = fields;
+        if let Some(ref expr) = self.query.expr {
+            hashed_fields.extend(expr.get_required_fields());
+        }
+        self.hash_kinds = HashKinds::from_fields(&hashed_fields);
+
+        if !self.read_markers() {
+            return;
+        }
+
+        let roots = self.expand_subquery_roots();
+        self.query.roots = self.expand_glob_roots(roots);
+        // the listed roots are known only as they are read, any of them could overlap another one
+        let overlapping_roots = self.query.roots.iter().any(|root| root.list) || roots_overlap(&self.query.roots);
+        let use_index = self.can_use_index(overlapping_roots);
+        self.answered_from_index = use_index && self.is_answered_by_index();
+
+        self.dupe_finder = match self.query.dupes {
+            true => Some(DupeFinder::new()),
+            false => None
+        };
+        self.searched_roots.clear();
+
+        for (root_idx, root) in self.query.clone().roots.iter().enumerate() {
+            if progress::is_interrupted() {
+                break;
+            }
+
+            match root.list {
+                true => self.search_listed_roots(root_idx, root, overlapping_roots, use_index, need_metadata, need_dim, need_mp3, on_record),
+                false => self.search_root(root_idx, root, overlapping_roots, use_index, need_metadata, need_dim, need_mp3, on_record)
+            }
+        }
+
+        if let Some(dupe_finder) = self.dupe_finder.take() {
+            self.search_dupes(dupe_finder, need_metadata, need_dim, need_mp3, on_record);
+        }
+
+        if self.has_summary_row() {
+            let summary_record = self.build_summary_record();
+            self.summary_rows.push(summary_record);
+        } else if self.has_aggregate_column() {
+            let mut group_rows = vec![];
+            let mut distinct_groups = HashSet::new();
+
+            for group in self.group_raw_output() {
+                let mut values = vec![];
+                let mut file_map = HashMap::new();
+                let mut criteria = vec![Value::from(String::new()); self.query.ordering_fields.len()];
+
+                for column_expr in &self.query.fields {
+                    let key = column_expr.to_string().to_lowercase();
+                    let value = match column_expr.has_aggregate_function() {
+                        true => self.get_aggregate_function_value(column_expr, &group),
+                        false => match group.first().and_then(|row| row.get(&key)) {
+                            Some(value) => value.clone(),
+                            None => Value::from(String::new())
                         }
+                    };
+                    file_map.insert(key, value.clone());
+                    values.push(Some(value));
+                }
+
+                if self.query.distinct {
+                    if !distinct_groups.insert(values.clone()) {
+                        continue;
                     }
-                },
-                Err(err) => {
-                    path_error_message(dir, err, t);
                 }
+
+                // the groups are ordered by the columns as well as by the expressions which aren't selected
+                for (idx, field) in self.query.ordering_fields.iter().enumerate() {
+                    let key = field.to_string().to_lowercase();
+                    criteria[idx] = match file_map.get(&key) {
+                        Some(value) => value.clone(),
+                        None if field.has_aggregate_function() => self.get_aggregate_function_value(field, &group),
+                        None => match group.first().and_then(|row| row.get(&key)) {
+                            Some(value) => value.clone(),
+                            None => Value::from(String::new())
+                        }
+                    };
+                }
+
+                group_rows.push((criteria, self.to_record(&values, &file_map)));
+            }
+
+            for (criteria, record) in group_rows {
+                self.buffer_row(criteria, record);
             }
         }
+    }
 
-        Ok(())
+    /// The summary row has only the aggregated columns filled, they are calculated over all the found files.
+    fn build_summary_record(&self) -> Record {
+        let all_files: Vec<&HashMap<String, Value>> = self.raw_output_buffer.iter().collect();
+        let mut values = vec![];
+        let mut file_map = HashMap::new();
+
+        for column_expr in &self.query.fields {
+            match column_expr.has_aggregate_function() {
+                true => {
+                    let value = self.get_aggregate_function_value(column_expr, &all_files);
+                    file_map.insert(column_expr.to_string().to_lowercase(), value.clone());
+                    values.push(Some(value));
+                },
+                false => values.push(None)
+            }
+        }
+
+        self.to_record(&values, &file_map)
     }
 
-    fn get_gitignore_filters(&self, dir: &Path) -> Vec<GitignoreFilter> {
-        let mut result = vec![];
+    /// The second pass of the duplicate search: files sharing their size are hashed,
+    /// and those having the same content are checked once again to produce the rows, group by group.
+    fn search_dupes(&mut self,
+                    dupe_finder: DupeFinder,
+                    need_metadata: bool,
+                    need_dim: bool,
+                    need_mp3: bool,
+                    on_record: &mut RecordSink) {
+        let mut errors = vec![];
+        let groups = dupe_finder.find_groups(self.hash_kinds, |path, err| errors.push((path.to_path_buf(), err)));
+        for (path, err) in errors {
+            self.report_error(&path, err);
+        }
 
-        for (dir_path, regexes) in &self.gitignore_map {
-            if dir.to_path_buf() == *dir_path {
-                for ref mut rx in regexes {
-                    result.push(rx.clone());
+        let roots = self.query.roots.clone();
+        self.dedup = false;
+        self.visited_paths.clear();
+
+        // there is no way to get a directory entry by its path, so the parent directories are listed again
+        let mut dir_entries: HashMap<PathBuf, HashMap<PathBuf, DirEntry>> = HashMap::new();
+
+        for group in groups {
+            for (candidate, hashes) in group.files {
+                if self.is_search_stopped() {
+                    return;
                 }
 
-                return result;
+                let parent = candidate.path.parent().map(|parent| parent.to_path_buf()).unwrap_or_default();
+                let entries = dir_entries.entry(parent.clone()).or_insert_with(|| list_dir_entries(&parent));
+
+                if let Some(entry) = entries.remove(&candidate.path) {
+                    let (query_root_idx, ref root_dir) = self.searched_roots[candidate.root_idx];
+                    let root = &roots[query_root_idx];
+                    self.root_dir = root_dir.clone();
+                    self.root_idx = candidate.root_idx;
+                    self.follow_symlinks = root.symlinks;
+                    self.lenient = root.lenient;
+
+                    self.depth = candidate.depth;
+                    self.dupe_hashes.insert(candidate.path, hashes);
+                    self.check_file(&entry, &None, need_metadata, need_dim, need_mp3, root.symlinks, on_record);
+                }
             }
         }
+    }
 
-        let mut path = dir.to_path_buf();
+    /// Rows which don't fit into memory are written to temporary files, a failure to do so is an error of the search.
+    fn buffer_row(&mut self, criteria: Vec<Value>, record: Record) {
+        let key = Criteria::new(Rc::new(self.query.ordering_fields.clone()), criteria, self.query.ordering_asc.clone());
+        if let Err(err) = self.output_buffer.insert(key, record) {
+            self.report_error(&env::temp_dir(), err);
+        }
+    }
 
-        loop {
-            let parent_found = path.pop();
+    /// Sorted rows left after skipping the first `offset` ones.
+    fn get_page<'a>(&self, buffer: &'a SortBuffer) -> Box<Iterator<Item=io::Result<Record>> + 'a> {
+        Box::new(buffer.rows().skip(self.query.offset as usize))
+    }
 
-            if !parent_found {
-                return result;
+    fn emit_buffered_records(&mut self, on_record: &mut RecordSink) {
+        let mut error = None;
+        for record in self.get_page(&self.output_buffer) {
+            if self.stopped.get() {
+                break;
             }
 
-            for (dir_path, regexes) in &self.gitignore_map {
-                if path == *dir_path {
-                    let mut tmp = vec![];
-                    for ref mut rx in regexes {
-                        tmp.push(rx.clone());
-                    }
-                    tmp.append(&mut result);
-                    result.clear();
-                    result.append(&mut tmp);
+            match record {
+                Ok(record) => self.emit(record, on_record),
+                Err(err) => {
+                    error = Some(err);
+                    break;
                 }
             }
+            self.shown += 1;
+        }
+
+        if let Some(err) = error {
+            self.report_error(&env::temp_dir(), err);
         }
     }
 
-    fn get_column_expr_value(&self,
-                             entry: &DirEntry,
-                             file_info: &Option<FileInfo>,
-                             mp3_info: &Option<MP3Metadata>,
-                             attrs: &Option<Box<Metadata>>,
-                             dimensions: Option<(usize, usize)>,
-                             column_expr: &ColumnExpr,
-                             _t: &mut Box<StdoutTerminal>) -> String {
-        if let Some(ref _function) = column_expr.function {
-            return self.get_function_value(entry, file_info, mp3_info, attrs, dimensions, column_expr, _t);
+    fn emit_summary_records(&mut self, on_record: &mut RecordSink) {
+        for record in mem::replace(&mut self.summary_rows, vec![]) {
+            if self.stopped.get() {
+                break;
+            }
+
+            self.emit(record, on_record);
         }
+    }
 
-        if let Some(ref field) = column_expr.field {
-            return self.get_field_value(entry, file_info, mp3_info, attrs, dimensions, field, _t);
+    /// Splits the buffered rows into groups sharing the same values of the `group by` columns.
+    /// Groups keep the order in which they were first encountered.
+    /// Without `group by` all rows form a single (possibly empty) group.
+    fn group_raw_output(&self) -> Vec<Vec<&HashMap<String, Value>>> {
+        if self.query.grouping_fields.is_empty() {
+            return vec![self.raw_output_buffer.iter().collect()];
         }
 
-        if let Some(ref value) = column_expr.val {
-            return value.clone();
+        let grouping_keys: Vec<String> = self.query.grouping_fields.iter()
+            .map(|field| field.to_string().to_lowercase())
+            .collect();
+
+        let mut groups: Vec<Vec<&HashMap<String, Value>>> = vec![];
+        let mut group_index: HashMap<Vec<Option<&Value>>, usize> = HashMap::new();
+
+        for file_map in &self.raw_output_buffer {
+            let group_values: Vec<Option<&Value>> = grouping_keys.iter()
+                .map(|key| file_map.get(key))
+                .collect();
+
+            let idx = *group_index.entry(group_values).or_insert_with(|| {
+                groups.push(vec![]);
+                groups.len() - 1
+            });
+
+            groups[idx].push(file_map);
         }
 
-        String::new()
+        groups
     }
 
-    fn get_function_value(&self,
-                          entry: &DirEntry,
-                          file_info: &Option<FileInfo>,
-                          mp3_info: &Option<MP3Metadata>,
-                          attrs: &Option<Box<Metadata>>,
-                          dimensions: Option<(usize, usize)>,
-                          column_expr: &ColumnExpr,
-                          _t: &mut Box<StdoutTerminal>) -> String {
-        if let Some(ref left_expr) = column_expr.left {
-            let function_arg = self.get_column_expr_value(entry,
-                                                          file_info,
-                                                          mp3_info,
-                                                          attrs,
-                                                          dimensions,
-                                                          left_expr,
-                                                          _t);
+    /// Every file would be compared with a missing marker file, so the query isn't searched at all then.
+    fn read_markers(&mut self) -> bool {
+        let paths = match self.query.expr {
+            Some(ref expr) => expr.get_marker_paths(),
+            None => vec![]
+        };
 
-            match column_expr.function {
-                Some(Function::Lower) => {
-                    return function_arg.to_lowercase();
-                },
-                Some(Function::Upper) => {
-                    return function_arg.to_uppercase();
-                },
-                Some(Function::Length) => {
-                    return format!("{}", function_arg.chars().count());
-                },
-                Some(Function::Year) => {
-                    match parse_datetime(&function_arg) {
-                        Ok(date) => {
-                            return date.0.year().to_string();
-                        },
-                        _ => {
-                            return String::new();
-                        }
-                    }
-                },
-                Some(Function::Month) => {
-                    match parse_datetime(&function_arg) {
-                        Ok(date) => {
-                            return date.0.month().to_string();
-                        },
-                        _ => {
-                            return String::new();
-                        }
-                    }
-                },
-                Some(Function::Day) => {
-                    match parse_datetime(&function_arg) {
-                        Ok(date) => {
-                            return date.0.day().to_string();
-                        },
-                        _ => {
-                            return String::new();
-                        }
-                    }
+        let mut found = true;
+        for path in paths {
+            if self.markers.contains_key(&path) {
+                continue;
+            }
+
+            let marker = fs::metadata(&path).and_then(|metadata| {
+                Ok(Marker {
+                    modified: DateTime::from(metadata.modified()?),
+                    size: metadata.len(),
+                    file_id: mode::get_file_id(&metadata),
+                })
+            });
+
+            match marker {
+                Ok(marker) => {
+                    self.markers.insert(path, marker);
                 },
-                _ => {
-                    return String::new();
+                Err(err) => {
+                    self.report_error(Path::new(&path), err);
+                    found = false;
                 }
             }
         }
 
-        String::new()
+        found
     }
 
-    fn get_aggregate_function_value(&self,
-                                    column_expr: &ColumnExpr) -> String {
-        let mut field_value = String::new();
+    /// Roots given by a subquery are replaced with the paths it finds, the options of the root apply to each of them.
+    fn expand_subquery_roots(&mut self) -> Vec<Root> {
+        let mut roots = vec![];
 
-        if let Some(ref field) = column_expr.field {
-            field_value = field.to_string();
-        } else if let Some(ref left) = column_expr.left  {
-            if let Some(ref field) = left.field {
-                field_value = field.to_string();
+        for root in self.query.roots.clone() {
+            match root.subquery {
+                Some(ref subquery) => {
+                    for path in self.run_subquery(subquery) {
+                        roots.push(Root { path, subquery: None, ..root.clone() });
+                    }
+                },
+                None => roots.push(root.clone())
             }
         }
 
-        let field = field_value.to_lowercase();
-        match column_expr.function {
-            Some(Function::Min) => {
-                let mut min = -1;
-                for value in &self.raw_output_buffer {
-                    if let Some(value) = value.get(&field) {
-                        if let Ok(value) = value.parse::<i64>() {
-                            if value < min || min == -1 {
-                                min = value;
-                            }
-                        }
-                    }
-                }
+        roots
+    }
 
-                return min.to_string();
-            },
-            Some(Function::Max) => {
-                let mut max = 0;
-                for value in &self.raw_output_buffer {
-                    if let Some(value) = value.get(&field) {
-                        if let Ok(value) = value.parse::<usize>() {
-                            if value > max {
-                                max = value;
-                            }
-                        }
-                    }
-                }
+    /// Roots given as `-` or `@FILE` are read from stdin or from the file one at a time,
+    /// each of them is searched before the next one is read. The options of the list apply to each of them.
+    /// The paths are taken literally, they aren't patterns.
+    fn search_listed_roots(&mut self,
+                           query_root_idx: usize,
+                           list: &Root,
+                           overlapping_roots: bool,
+                           use_index: bool,
+                           need_metadata: bool,
+                           need_dim: bool,
+                           need_mp3: bool,
+                           on_record: &mut RecordSink) {
+        let mut root = Root { list: false, list_file: None, ..list.clone() };
+
+        let result = {
+            let mut search_root = |path: String| {
+                root.path = path;
+                self.search_root(query_root_idx, &root, overlapping_roots, use_index, need_metadata, need_dim, need_mp3, on_record);
+
+                !progress::is_interrupted()
+            };
 
-                return max.to_string();
-            },
-            Some(Function::Avg) => {
-                let mut sum = 0;
-                for value in &self.raw_output_buffer {
-                    if let Some(value) = value.get(&field) {
-                        if let Ok(value) = value.parse::<usize>() {
-                            sum += value;
-                        }
-                    }
+            match list.list_file {
+                Some(ref list_file) => read_root_list(&mut list_file.reader(), &mut search_root),
+                None if list.path == "-" => {
+                    let stdin = io::stdin();
+                    let mut reader = stdin.lock();
+                    read_root_list(&mut reader, &mut search_root)
+                },
+                // the root wasn't parsed from a query
+                None => RootList::open(&list.path).and_then(|list_file| read_root_list(&mut list_file.reader(), &mut search_root))
+            }
+        };
+
+        if let Err(err) = result {
+            self.report_error(Path::new(&list.path), err);
+        }
+    }
+
+    /// Searches the root with its own options, the listed roots come one by one with the options of their list.
+    fn search_root(&mut self,
+                   query_root_idx: usize,
+                   root: &Root,
+                   overlapping_roots: bool,
+                   use_index: bool,
+                   need_metadata: bool,
+                   need_dim: bool,
+                   need_mp3: bool,
+                   on_record: &mut RecordSink) {
+        let root_dir = Path::new(&root.path);
+        let min_depth = root.min_depth;
+        let max_depth = root.max_depth;
+        let search_archives = root.archives;
+        let follow_symlinks = root.symlinks;
+        // `.gitignore` files are read either way, but their filters are used separately when inverted
+        let apply_gitignore = root.gitignore || root.gitignored;
+        self.dedup = overlapping_roots && root.dedup;
+        self.root_dir = root_dir.to_path_buf();
+        self.root_idx = self.searched_roots.len();
+        self.searched_roots.push((query_root_idx, root_dir.to_path_buf()));
+        self.follow_symlinks = follow_symlinks;
+        self.apply_gitignore = root.gitignore && !root.gitignored;
+        self.invert_gitignore = root.gitignored;
+        self.ignore_file = root.ignore_file.clone().unwrap_or(String::from(DEFAULT_IGNORE_FILE));
+        self.apply_global_ignore = root.global_ignore;
+        self.lenient = root.lenient;
+        self.max_archive_entries = root.max_archive_entries;
+        self.max_archive_size = root.max_archive_size;
+        self.archive_depth = match root.archive_depth {
+            0 => DEFAULT_ARCHIVE_DEPTH,
+            depth => depth
+        };
+        self.max_nested_size = match root.max_nested_size {
+            0 => DEFAULT_MAX_NESTED_SIZE,
+            size => size
+        };
+        self.ordered = root.ordered;
+        self.max_files = root.max_files;
+        self.prune = root.prune.clone();
+        self.root_device = match root.one_filesystem {
+            true => self.get_root_device(root_dir, follow_symlinks),
+            false => None
+        };
+        self.dir_size_cache.borrow_mut().clear();
+
+        if apply_gitignore {
+            for (dir, regexes) in parse_parent_gitignores(&to_absolute_path(root_dir)) {
+                self.gitignore_map.entry((dir, IgnoreSource::Gitignore)).or_insert(Rc::new(regexes));
+            }
+        }
+
+        // the filters of the directories above the root are looked up once, deeper ones are added on the way down
+        let absolute_root_dir = to_absolute_path(root_dir);
+        let parent_dir = absolute_root_dir.parent().unwrap_or(Path::new(""));
+        let gitignore_filters = self.get_gitignore_filters(parent_dir);
+        let inverted_filters = self.get_inverted_gitignore_filters(parent_dir);
+
+        if use_index {
+            self.indexed_roots.push(absolute_root_dir);
+            self.visit_indexed_dirs(root_dir, min_depth, max_depth, 1, apply_gitignore, &gitignore_filters,
+                                    need_metadata, need_dim, need_mp3, on_record);
+            return;
+        }
+
+        let _result = self.visit_dirs(
+            root_dir,
+            need_metadata,
+            need_dim,
+            need_mp3,
+            min_depth,
+            max_depth,
+            1,
+            search_archives,
+            follow_symlinks,
+            apply_gitignore,
+            &gitignore_filters,
+            &inverted_filters,
+            false,
+            on_record
+        );
+    }
+
+    /// Roots written as patterns are replaced with the paths they match in sorted order,
+    /// a pattern matching nothing is reported as an error.
+    fn expand_glob_roots(&mut self, roots: Vec<Root>) -> Vec<Root> {
+        let mut expanded = vec![];
+
+        for root in roots {
+            if !root.glob {
+                expanded.push(root);
+                continue;
+            }
+
+            let paths = expand_root_pattern(&root.path);
+            if paths.is_empty() {
+                self.report_error(Path::new(&root.path), io::Error::new(io::ErrorKind::NotFound, "no files match the pattern"));
+            }
+
+            for path in paths {
+                expanded.push(Root { path: path.to_string_lossy().to_string(), glob: false, ..root.clone() });
+            }
+        }
+
+        expanded
+    }
+
+    /// Values of the `path` column found by the subquery, its errors count as errors of this search.
+    fn run_subquery(&mut self, subquery: &Query) -> Vec<String> {
+        let path_idx = subquery.fields.iter()
+            .position(|column_expr| column_expr.get_field() == Some(Field::Path))
+            .unwrap_or(0);
+
+        let mut searcher = Searcher::new(subquery.clone());
+        searcher.set_quiet(self.quiet);
+        searcher.error_reporter = self.error_reporter.replace(Box::new(StderrReporter)).into();
+        // the subquery usually walks the same files, so the owners and the probes found are shared
+        searcher.user_cache = mem::replace(&mut self.user_cache, Box::new(SystemOwnerNames::new()));
+        searcher.probe_cache = self.probe_cache.replace(LruCache::new(PROBE_CACHE_SIZE)).into();
+
+        // the subquery has neither a progress of its own nor an index to save
+        let mut paths = vec![];
+        searcher.search_queries(&mut |record| {
+            if let Some(path) = record.values.get(path_idx).filter(|path| !path.is_empty()) {
+                paths.push(path.clone());
+            }
+            true
+        });
+
+        self.errors += searcher.errors;
+        self.error_reporter = searcher.error_reporter;
+        self.user_cache = searcher.user_cache;
+        self.probe_cache = searcher.probe_cache;
+
+        paths
+    }
+
+    fn visit_dirs(&mut self,
+                  dir: &Path,
+                  need_metadata: bool,
+                  need_dim: bool,
+                  need_mp3: bool,
+                  min_depth: u32,
+                  max_depth: u32,
+                  depth: u32,
+                  search_archives: bool,
+                  follow_symlinks: bool,
+                  apply_gitignore: bool,
+                  parent_gitignore_filters: &GitignoreFilters,
+                  parent_inverted_filters: &GitignoreFilters,
+                  inside_gitignored: bool,
+                  on_record: &mut RecordSink) -> io::Result<()> {
+        // entries of the directory being read are at this depth, the root's own entries are at depth 1
+        let in_depth_range = (min_depth == 0 || depth >= min_depth) && (max_depth == 0 || depth <= max_depth);
+
+        match get_metadata(dir, follow_symlinks) {
+            Ok(metadata) => {
+                // a mount point is reported as usual, but its content is on another file system
+                if depth > 1 && crosses_device(self.root_device, mode::get_device(&metadata)) {
+                    return Ok(());
                 }
 
-                return (sum / self.raw_output_buffer.len()).to_string();
-            },
-            Some(Function::Sum) => {
-                let mut sum = 0;
-                for value in &self.raw_output_buffer {
-                    if let Some(value) = value.get(&field) {
-                        if let Ok(value) = value.parse::<usize>() {
-                            sum += value;
+                if metadata.is_dir() {
+                    let (gitignore_filters, inverted_filters) = self.read_ignore_files(&to_absolute_path(dir), apply_gitignore,
+                                                                                       parent_gitignore_filters, parent_inverted_filters);
+
+                    match fs::read_dir(dir) {
+                        Ok(entry_list) => {
+                            if let Some(ref mut progress) = *self.progress.borrow_mut() {
+                                progress.add_dir();
+                            }
+
+                            let entry_list: Box<Iterator<Item=io::Result<DirEntry>>> = match self.ordered {
+                                true => {
+                                    let mut entries: Vec<io::Result<DirEntry>> = entry_list.collect();
+                                    entries.sort_by_key(|entry| entry.as_ref().ok().map(|entry| entry.file_name()));
+                                    Box::new(entries.into_iter())
+                                },
+                                false => Box::new(entry_list)
+                            };
+                            let mut files_left = self.max_files;
+
+                            for entry in entry_list {
+                                if self.is_search_stopped() {
+                                    break;
+                                }
+
+                                match entry {
+                                    Ok(entry) => {
+                                        #[cfg(test)]
+                                        { self.visited_entries += 1; }
+
+                                        let path = entry.path();
+                                        let is_dir = is_dir_entry(&entry, follow_symlinks);
+
+                                        let absolute_path = to_absolute_path(&path);
+                                        let is_ignored = matches_gitignore_filter(&gitignore_filters, absolute_path.to_string_lossy().as_ref(), is_dir);
+
+                                        // when inverted, everything inside a directory git ignores is ignored as well,
+                                        // and the repository itself is never reported
+                                        let (is_skipped, is_found, is_gitignored) = match self.invert_gitignore {
+                                            true => {
+                                                let is_skipped = is_ignored || (is_dir && entry.file_name() == ".git");
+                                                let is_gitignored = inside_gitignored || matches_gitignore_filter(&inverted_filters, absolute_path.to_string_lossy().as_ref(), is_dir);
+                                                (is_skipped, !is_skipped && is_gitignored, is_gitignored)
+                                            },
+                                            false => (is_ignored, !is_ignored, false)
+                                        };
+
+                                        if !is_skipped {
+                                            // ignored files don't use up the quota, subdirectories are not subject to it
+                                            if is_found && self.max_files > 0 && !is_dir {
+                                                if files_left == 0 {
+                                                    continue;
+                                                }
+                                                files_left -= 1;
+                                            }
+
+                                            // subdirectories visited in between change the depth
+                                            self.depth = depth;
+
+                                            if in_depth_range && is_found {
+                                                self.check_file(&entry, &None, need_metadata, need_dim, need_mp3, follow_symlinks, on_record);
+                                            }
+
+                                            if in_depth_range && is_found && search_archives {
+                                                self.search_archive(&entry, &path, need_metadata, need_dim, need_mp3, on_record);
+                                            }
+
+                                            if is_dir && (max_depth == 0 || depth < max_depth) && !self.is_pruned(&entry.file_name().to_string_lossy()) {
+                                                let result = self.visit_dirs(
+                                                    &path,
+                                                    need_metadata,
+                                                    need_dim,
+                                                    need_mp3,
+                                                    min_depth,
+                                                    max_depth,
+                                                    depth + 1,
+                                                    search_archives,
+                                                    follow_symlinks,
+                                                    apply_gitignore,
+                                                    &gitignore_filters,
+                                                    &inverted_filters,
+                                                    is_gitignored,
+                                                    on_record);
+
+                                                if result.is_err() {
+                                                    self.report_error(&path, result.err().unwrap());
+                                                }
+                                            }
+                                        }
+                                    },
+                                    Err(err) => {
+                                        self.report_error(dir, err);
+                                    }
+                                }
+                            }
+                        },
+                        Err(err) => {
+                            self.report_error(dir, err);
                         }
                     }
                 }
-
-                return sum.to_string();
-            },
-            Some(Function::Count) => {
-                return self.raw_output_buffer.len().to_string();
             },
-            _ => {
-                match &column_expr.val {
-                    Some(val) => return val.clone(),
-                    _ => return String::new()
+            Err(err) => {
+                self.report_error(dir, err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pruned directories are found as any other, but what is inside them is never looked at.
+    fn is_pruned(&self, dir_name: &str) -> bool {
+        self.prune.iter().any(|pattern| pattern.is_match(dir_name))
+    }
+
+    /// Reads the ignore files of the directory, if any, and adds their filters to the ones of the directory above.
+    /// Returns the filters applied to its entries and the ones a search with `gitignored` inverts.
+    fn read_ignore_files(&mut self,
+                         absolute_dir: &Path,
+                         apply_gitignore: bool,
+                         parent_gitignore_filters: &GitignoreFilters,
+                         parent_inverted_filters: &GitignoreFilters) -> (GitignoreFilters, GitignoreFilters) {
+        let mut gitignore_filters = parent_gitignore_filters.clone();
+        let mut inverted_filters = parent_inverted_filters.clone();
+
+        if apply_gitignore {
+            let gitignore_file = absolute_dir.join(".gitignore");
+            if gitignore_file.is_file() {
+                let regexes = Rc::new(parse_gitignore(&gitignore_file, absolute_dir));
+                self.gitignore_map.insert((absolute_dir.to_path_buf(), IgnoreSource::Gitignore), regexes.clone());
+                if self.apply_gitignore {
+                    gitignore_filters.push(regexes.clone());
                 }
+                inverted_filters.push(regexes);
             }
         }
+
+        let ignore_file = absolute_dir.join(&self.ignore_file);
+        if ignore_file.is_file() {
+            let regexes = Rc::new(parse_ignore_file(&ignore_file, absolute_dir));
+            self.gitignore_map.insert((absolute_dir.to_path_buf(), IgnoreSource::IgnoreFile(self.ignore_file.clone())), regexes.clone());
+            gitignore_filters.push(regexes);
+        }
+
+        (gitignore_filters, inverted_filters)
     }
 
-    fn get_field_value(&self,
-                       entry: &DirEntry,
-                       file_info: &Option<FileInfo>,
-                       mp3_info: &Option<MP3Metadata>,
-                       attrs: &Option<Box<Metadata>>,
-                       dimensions: Option<(usize, usize)>,
-                       field: &Field,
-                       _t: &mut Box<StdoutTerminal>) -> String {
-        match field {
-            Field::Name => {
-                match file_info {
-                    Some(ref file_info) => {
-                        return format!("[{}] {}", entry.file_name().to_string_lossy(), file_info.name);
-                    },
-                    _ => {
-                        return format!("{}", entry.file_name().to_string_lossy());
-                    }
+    /// The directories are walked with the index only when every file is found on the disk just once.
+    fn can_use_index(&self, overlapping_roots: bool) -> bool {
+        if self.index.is_none() || self.query.dupes {
+            return false;
+        }
+
+        !self.query.roots.iter().any(|root| root.archives || root.symlinks || root.one_filesystem || root.gitignored || (overlapping_roots && root.dedup))
+    }
+
+    /// Only the queries on names, sizes, modification dates and modes are answered from the index alone.
+    fn is_answered_by_index(&self) -> bool {
+        let columns_indexed = self.query.fields.iter()
+            .chain(self.query.ordering_fields.iter())
+            .chain(self.query.grouping_fields.iter())
+            .all(|column_expr| is_indexed_column(column_expr));
+
+        columns_indexed && match self.query.expr {
+            Some(ref expr) => is_indexed_expr(expr),
+            None => true
+        }
+    }
+
+    /// Walks the directories the same way `visit_dirs` does, but the entries come from the index,
+    /// which lists again only the directories changed since the previous search.
+    fn visit_indexed_dirs(&mut self,
+                          dir: &Path,
+                          min_depth: u32,
+                          max_depth: u32,
+                          depth: u32,
+                          apply_gitignore: bool,
+                          parent_gitignore_filters: &GitignoreFilters,
+                          need_metadata: bool,
+                          need_dim: bool,
+                          need_mp3: bool,
+                          on_record: &mut RecordSink) {
+        let in_depth_range = (min_depth == 0 || depth >= min_depth) && (max_depth == 0 || depth <= max_depth);
+        let absolute_dir = to_absolute_path(dir);
+
+        let entries = match self.index.as_mut().unwrap().list_dir(&absolute_dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.report_error(dir, err);
+                return;
+            }
+        };
+
+        if let Some(ref mut progress) = *self.progress.borrow_mut() {
+            progress.add_dir();
+        }
+
+        let (gitignore_filters, _) = self.read_ignore_files(&absolute_dir, apply_gitignore, parent_gitignore_filters, &vec![]);
+        let mut files_left = self.max_files;
+        let mut dir_entries = None;
+
+        for entry in entries {
+            if self.is_search_stopped() {
+                break;
+            }
+
+            let path = dir.join(&entry.name);
+            let is_dir = entry.is_dir();
+
+            if matches_gitignore_filter(&gitignore_filters, absolute_dir.join(&entry.name).to_string_lossy().as_ref(), is_dir) {
+                continue;
+            }
+
+            if self.max_files > 0 && !is_dir {
+                if files_left == 0 {
+                    continue;
                 }
-            },
-            Field::Path => {
-                match file_info {
-                    Some(ref file_info) => {
-                        return format!("[{}] {}", entry.path().to_string_lossy(), file_info.name);
-                    },
-                    _ => {
-                        return format!("{}", entry.path().to_string_lossy());
-                    }
+                files_left -= 1;
+            }
+
+            if in_depth_range {
+                self.depth = depth;
+                match self.answered_from_index {
+                    true => self.check_indexed_file(&path, &entry, on_record),
+                    false => self.check_indexed_file_on_disk(&path, &entry, &mut dir_entries, need_metadata, need_dim, need_mp3, on_record)
                 }
-            },
-            Field::Size => {
-                match file_info {
-                    Some(ref file_info) => {
-                        return format!("{}", file_info.size);
-                    },
-                    _ => {
-                        if let Some(ref attrs) = attrs {
-                            return format!("{}", attrs.len());
+            }
+
+            if is_dir && (max_depth == 0 || depth < max_depth) && !self.is_pruned(&entry.name.to_string_lossy()) {
+                self.visit_indexed_dirs(&path, min_depth, max_depth, depth + 1, apply_gitignore, &gitignore_filters,
+                                        need_metadata, need_dim, need_mp3, on_record);
+            }
+        }
+    }
+
+    /// Files ruled out by the conditions on the indexed fields are not looked at, the rest are checked on the disk as usual.
+    fn check_indexed_file_on_disk(&mut self,
+                                  path: &Path,
+                                  entry: &IndexedEntry,
+                                  dir_entries: &mut Option<HashMap<PathBuf, DirEntry>>,
+                                  need_metadata: bool,
+                                  need_dim: bool,
+                                  need_mp3: bool,
+                                  on_record: &mut RecordSink) {
+        let ruled_out = match self.query.expr.clone() {
+            Some(ref expr) => self.conforms_indexed(path, entry, expr) == Some(false),
+            None => false
+        };
+
+        if ruled_out {
+            if let Some(ref mut progress) = *self.progress.borrow_mut() {
+                progress.add_file();
+            }
+            return;
+        }
+
+        // there is no way to get a directory entry by its path, so the directory is listed when the first of its files is needed
+        let parent = path.parent().unwrap_or(Path::new(""));
+        if let Some(dir_entry) = dir_entries.get_or_insert_with(|| list_dir_entries(parent)).remove(path) {
+            self.check_file(&dir_entry, &None, need_metadata, need_dim, need_mp3, false, on_record);
+        }
+    }
+
+    fn check_indexed_file(&mut self, path: &Path, entry: &IndexedEntry, on_record: &mut RecordSink) {
+        if let Some(ref mut progress) = *self.progress.borrow_mut() {
+            progress.add_file();
+        }
+
+        if let Some(ref expr) = self.query.expr.clone() {
+            if self.conforms_indexed(path, entry, expr) == Some(false) {
+                return
+            }
+        }
+
+        let size = match self.query.summary && entry.kind == EntryKind::File {
+            true => entry.size,
+            false => 0
+        };
+
+        if !self.is_distinct_rows() && !self.count_found(size) {
+            return;
+        }
+
+        let mut file_map = HashMap::new();
+
+        for field in self.query.get_all_fields() {
+            file_map.insert(field.to_string().to_lowercase(), self.get_indexed_field_typed_value(path, entry, &field));
+        }
+
+        let values: Vec<Option<Value>> = self.query.fields.iter()
+            .map(|column_expr| match column_expr.has_aggregate_function() {
+                true => None,
+                false => Some(self.get_indexed_column_typed_value(path, entry, column_expr))
+            })
+            .collect();
+
+        add_column_values(&mut file_map, &self.query.fields, &values);
+
+        if self.has_aggregate_column() && !self.has_summary_row() {
+            for column_expr in &self.query.ordering_fields {
+                if column_expr.has_aggregate_function() {
+                    for field in column_expr.get_required_fields() {
+                        let key = field.to_string().to_lowercase();
+                        if !file_map.contains_key(&key) {
+                            file_map.insert(key, self.get_indexed_field_typed_value(path, entry, &field));
                         }
                     }
+                    continue;
+                }
+
+                let key = column_expr.to_string().to_lowercase();
+                if !file_map.contains_key(&key) {
+                    file_map.insert(key, self.get_indexed_column_typed_value(path, entry, column_expr));
                 }
+            }
+        }
+
+        let criteria = self.query.ordering_fields.iter()
+            .map(|column_expr| match file_map.get(&column_expr.to_string().to_lowercase()) {
+                Some(value) => value.clone(),
+                None => self.get_indexed_column_typed_value(path, entry, column_expr)
+            })
+            .collect();
+
+        self.add_file_row(file_map, values, criteria, size, on_record);
+    }
+
+    /// The modification time keeps its time, like the one read from the file system.
+    fn get_indexed_column_typed_value(&self, path: &Path, entry: &IndexedEntry, column_expr: &ColumnExpr) -> Value {
+        match column_expr.get_field() {
+            Some(ref field) => self.get_indexed_field_typed_value(path, entry, field),
+            None => Value::from(self.get_indexed_column_value(path, entry, column_expr))
+        }
+    }
+
+    fn get_indexed_field_typed_value(&self, path: &Path, entry: &IndexedEntry, field: &Field) -> Value {
+        match field {
+            Field::Modified => Value::from_datetime(&DateTime::from(entry.modified())),
+            _ => Value::from(self.get_indexed_field_value(path, entry, field))
+        }
+    }
+
+    fn get_indexed_column_value(&self, path: &Path, entry: &IndexedEntry, column_expr: &ColumnExpr) -> String {
+        if let Some(ref field) = column_expr.field {
+            return self.get_indexed_field_value(path, entry, field);
+        }
+
+        if let Some(ref value) = column_expr.val {
+            return value.clone();
+        }
+
+        if let Some(ref arithmetic_op) = column_expr.arithmetic_op {
+            let left = match column_expr.left {
+                Some(ref left) => self.get_indexed_column_value(path, entry, left),
+                None => String::new()
+            };
+            let right = match column_expr.right {
+                Some(ref right) => self.get_indexed_column_value(path, entry, right),
+                None => String::new()
+            };
+
+            return calc_arithmetic(arithmetic_op, &left, &right);
+        }
+
+        if let Some(ref left) = column_expr.left {
+            return self.get_indexed_column_value(path, entry, left);
+        }
+
+        String::new()
+    }
+
+    /// Values of the indexed fields are formatted exactly as the values read from the file system.
+    fn get_indexed_field_value(&self, path: &Path, entry: &IndexedEntry, field: &Field) -> String {
+        match field {
+            Field::Name => entry.name.to_string_lossy().to_string(),
+            Field::Extension => get_extension(&entry.name.to_string_lossy()),
+            Field::Path => path.to_string_lossy().to_string(),
+            Field::RelativePath => path.strip_prefix(&self.root_dir).unwrap_or(path).to_string_lossy().to_string(),
+            Field::Depth => format!("{}", self.depth),
+            Field::Size => format!("{}", entry.size),
+            Field::FormattedSize => format!("{}", entry.size.file_size(file_size_opts::BINARY).unwrap()),
+            Field::Modified => {
+                let dt: DateTime<Local> = DateTime::from(entry.modified());
+                format!("{}", dt.format(DEFAULT_DATE_FORMAT))
             },
-            Field::FormattedSize => {
-                match file_info {
-                    Some(ref file_info) => {
-                        return format!("{}", file_info.size.file_size(file_size_opts::BINARY).unwrap());
-                    },
-                    _ => {
-                        if let Some(ref attrs) = attrs {
-                            return format!("{}", attrs.len().file_size(file_size_opts::BINARY).unwrap());
-                        }
-                    }
+            Field::ModifiedUnix => DateTime::<Local>::from(entry.modified()).timestamp().to_string(),
+            Field::ModifiedUtc => format_utc_datetime(&DateTime::from(entry.modified())),
+            Field::Mode => mode::format_mode(entry.mode),
+            Field::ModeOctal if cfg!(unix) => mode::format_mode_octal(entry.mode),
+            Field::IsDir => format!("{}", entry.kind == EntryKind::Dir),
+            Field::IsFile => format!("{}", entry.kind == EntryKind::File),
+            Field::IsSymlink => format!("{}", entry.kind == EntryKind::Symlink),
+            _ => String::new()
+        }
+    }
+
+    /// Checks the conditions on the indexed fields the same way `conforms` checks them on the files.
+    /// Other conditions are unknown, and so is the result unless the known ones decide it.
+    fn conforms_indexed(&self, path: &Path, entry: &IndexedEntry, expr: &Box<Expr>) -> Option<bool> {
+        let result = match expr.logical_op {
+            Some(ref logical_op) => {
+                let first_result = match expr.left {
+                    Some(ref left) => self.conforms_indexed(path, entry, left),
+                    None => None
+                };
+
+                let short_circuit = match logical_op {
+                    LogicalOp::And => first_result == Some(false),
+                    LogicalOp::Or => first_result == Some(true)
+                };
+
+                let second_result = match (short_circuit, &expr.right) {
+                    (false, &Some(ref right)) => self.conforms_indexed(path, entry, right),
+                    _ => None
+                };
+
+                match (logical_op, first_result, second_result) {
+                    (&LogicalOp::And, Some(false), _) | (&LogicalOp::And, _, Some(false)) => Some(false),
+                    (&LogicalOp::And, Some(true), Some(true)) => Some(true),
+                    (&LogicalOp::Or, Some(true), _) | (&LogicalOp::Or, _, Some(true)) => Some(true),
+                    (&LogicalOp::Or, Some(false), Some(false)) => Some(false),
+                    _ => None
                 }
             },
-            Field::IsDir => {
-                match file_info {
-                    Some(ref file_info) => {
-                        return format!("{}", file_info.name.ends_with('/'));
-                    },
-                    _ => {
-                        if let Some(ref attrs) = attrs {
-                            return format!("{}", attrs.is_dir());
-                        }
-                    }
+            None if is_indexed_expr(expr) => Some(self.conforms_indexed_condition(path, entry, expr)),
+            None => None
+        };
+
+        match expr.negate {
+            true => result.map(|result| !result),
+            false => result
+        }
+    }
+
+    fn conforms_indexed_condition(&self, path: &Path, entry: &IndexedEntry, expr: &Box<Expr>) -> bool {
+        let column_expr = match expr.field {
+            Some(ref column_expr) => column_expr,
+            None => return false
+        };
+
+        if let Some(ref val_expr) = expr.val_expr {
+            let value = self.get_indexed_column_value(path, entry, column_expr);
+            let val = self.get_indexed_column_value(path, entry, val_expr);
+            return compare_column_values(&expr.op, &value, &val, column_expr.is_datetime());
+        }
+
+        let val = match expr.val {
+            Some(ref val) => val,
+            None => return false
+        };
+
+        if column_expr.function.is_some() {
+            return self.compare_with_marker(column_expr, expr, DateTime::from(entry.modified()));
+        }
+
+        match column_expr.field {
+            Some(Field::ModifiedUnix) => match parse_float(val) {
+                Some(timestamp) => compare_numbers(&expr.op, DateTime::<Local>::from(entry.modified()).timestamp() as f64, timestamp),
+                None => false
+            },
+            Some(Field::Depth) => match parse_float(val) {
+                Some(depth) => compare_numbers(&expr.op, self.depth as f64, depth),
+                None => false
+            },
+            Some(Field::Size) | Some(Field::FormattedSize) => match parse_float(val) {
+                Some(size) => compare_numbers(&expr.op, entry.size as f64, size),
+                None => false
+            },
+            Some(Field::Modified) => {
+                let dt: DateTime<Local> = DateTime::from(entry.modified());
+                let start = expr.dt_from.unwrap();
+                let finish = expr.dt_to.unwrap();
+
+                match expr.op {
+                    Some(Op::Eeq) => dt == start,
+                    Some(Op::Ene) => dt != start,
+                    Some(Op::Eq) => dt >= start && dt <= finish,
+                    Some(Op::Ne) => dt < start || dt > finish,
+                    Some(Op::Gt) => dt > finish,
+                    Some(Op::Gte) => dt >= start,
+                    Some(Op::Lt) => dt < start,
+                    Some(Op::Lte) => dt <= finish,
+                    _ => false
                 }
             },
-            Field::IsFile => {
-                match file_info {
-                    Some(ref file_info) => {
-                        return format!("{}", !file_info.name.ends_with('/'));
-                    },
-                    _ => {
-                        if let Some(ref attrs) = attrs {
-                            return format!("{}", attrs.is_file());
-                        }
-                    }
-                }
-            },
-            Field::IsSymlink => {
-                match file_info {
-                    Some(_) => {
-                        return format!("{}", false);
-                    },
-                    _ => {
-                        if let Some(ref attrs) = attrs {
-                            return format!("{}", attrs.file_type().is_symlink());
-                        }
-                    }
-                }
-            },
-            Field::IsPipe => {
-                return Self::print_file_mode(&attrs, &mode::is_pipe, &file_info, &mode::mode_is_pipe);
-            },
-            Field::IsCharacterDevice => {
-                return Self::print_file_mode(&attrs, &mode::is_char_device, &file_info, &mode::mode_is_char_device);
-            },
-            Field::IsBlockDevice => {
-                return Self::print_file_mode(&attrs, &mode::is_block_device, &file_info, &mode::mode_is_block_device);
-            },
-            Field::IsSocket => {
-                return Self::print_file_mode(&attrs, &mode::is_socket, &file_info, &mode::mode_is_socket);
-            },
-            Field::Mode => {
-                match file_info {
-                    Some(ref file_info) => {
-                        if let Some(mode) = file_info.mode {
-                            return format!("{}", mode::format_mode(mode));
-                        }
-                    },
-                    _ => {
-                        if let Some(ref attrs) = attrs {
-                            return format!("{}", mode::get_mode(attrs));
-                        }
-                    }
-                }
-            },
-            Field::UserRead => {
-                return Self::print_file_mode(&attrs, &mode::user_read, &file_info, &mode::mode_user_read);
-            },
-            Field::UserWrite => {
-                return Self::print_file_mode(&attrs, &mode::user_write, &file_info, &mode::mode_user_write);
-            },
-            Field::UserExec => {
-                return Self::print_file_mode(&attrs, &mode::user_exec, &file_info, &mode::mode_user_exec);
-            },
-            Field::GroupRead => {
-                return Self::print_file_mode(&attrs, &mode::group_read, &file_info, &mode::mode_group_read);
-            },
-            Field::GroupWrite => {
-                return Self::print_file_mode(&attrs, &mode::group_write, &file_info, &mode::mode_group_write);
-            },
-            Field::GroupExec => {
-                return Self::print_file_mode(&attrs, &mode::group_exec, &file_info, &mode::mode_group_exec);
-            },
-            Field::OtherRead => {
-                return Self::print_file_mode(&attrs, &mode::other_read, &file_info, &mode::mode_other_read);
-            },
-            Field::OtherWrite => {
-                return Self::print_file_mode(&attrs, &mode::other_write, &file_info, &mode::mode_other_write);
-            },
-            Field::OtherExec => {
-                return Self::print_file_mode(&attrs, &mode::other_exec, &file_info, &mode::mode_other_exec);
-            },
-            Field::IsHidden => {
-                match file_info {
-                    Some(ref file_info) => {
-                        return format!("{}", is_hidden(&file_info.name, &None, true));
-                    },
-                    _ => {
-                        return format!("{}", is_hidden(&entry.file_name().to_string_lossy(), &attrs, false));
-                    }
-                }
-            },
-            Field::Uid => {
-                if let Some(ref attrs) = attrs {
-                    if let Some(uid) = mode::get_uid(attrs) {
-                        return format!("{}", uid);
-                    }
-                }
-            },
-            Field::Gid => {
-                if let Some(ref attrs) = attrs {
-                    if let Some(gid) = mode::get_gid(attrs) {
-                        return format!("{}", gid);
-                    }
-                }
-            },
-            Field::User => {
-                if let Some(ref attrs) = attrs {
-                    if let Some(uid) = mode::get_uid(attrs) {
-                        if let Some(user) = self.user_cache.get_user_by_uid(uid) {
-                            return format!("{}", user.name().to_string_lossy());
-                        }
-                    }
-                }
-            },
-            Field::Group => {
-                if let Some(ref attrs) = attrs {
-                    if let Some(gid) = mode::get_gid(attrs) {
-                        if let Some(group) = self.user_cache.get_group_by_gid(gid) {
-                            return format!("{}", group.name().to_string_lossy());
-                        }
-                    }
-                }
-            },
-            Field::Created => {
-                if let Some(ref attrs) = attrs {
-                    if let Ok(sdt) = attrs.created() {
-                        let dt: DateTime<Local> = DateTime::from(sdt);
-                        let format = dt.format("%Y-%m-%d %H:%M:%S");
-                        return format!("{}", format);
-                    }
-                }
-            },
-            Field::Accessed => {
-                if let Some(ref attrs) = attrs {
-                    if let Ok(sdt) = attrs.accessed() {
-                        let dt: DateTime<Local> = DateTime::from(sdt);
-                        let format = dt.format("%Y-%m-%d %H:%M:%S");
-                        return format!("{}", format);
-                    }
-                }
-            },
-            Field::Modified => {
-                match file_info {
-                    Some(ref file_info) => {
-                        let dt: DateTime<Local> = to_local_datetime(&file_info.modified);
-                        let format = dt.format("%Y-%m-%d %H:%M:%S");
-                        return format!("{}", format);
-                    },
-                    _ => {
-                        if let Some(ref attrs) = attrs {
-                            if let Ok(sdt) = attrs.modified() {
-                                let dt: DateTime<Local> = DateTime::from(sdt);
-                                let format = dt.format("%Y-%m-%d %H:%M:%S");
-                                return format!("{}", format);
-                            }
-                        }
-                    }
-                }
-            },
-            Field::HasXattrs => {
-                #[cfg(unix)]
-                    {
-                        if let Ok(file) = File::open(&entry.path()) {
-                            if let Ok(xattrs) = file.list_xattr() {
-                                let has_xattrs = xattrs.count() > 0;
-                                return format!("{}", has_xattrs);
-                            }
-                        }
-                    }
+            Some(Field::IsDir) | Some(Field::IsFile) | Some(Field::IsSymlink) => {
+                let value = self.get_indexed_field_value(path, entry, column_expr.field.as_ref().unwrap()) == "true";
 
-                #[cfg(not(unix))]
-                    {
-                        return format!("{}", false);
-                    }
-            },
-            Field::IsShebang => {
-                return format!("{}", is_shebang(&entry.path()));
-            },
-            Field::Width => {
-                if let Some(ref dimensions) = dimensions {
-                    return format!("{}", dimensions.0);
-                }
-            },
-            Field::Height => {
-                if let Some(ref dimensions) = dimensions {
-                    return format!("{}", dimensions.1);
-                }
-            },
-            Field::Bitrate => {
-                if let Some(ref mp3_info) = mp3_info {
-                    return format!("{}", mp3_info.frames[0].bitrate);
-                }
-            },
-            Field::Freq => {
-                if let Some(ref mp3_info) = mp3_info {
-                    return format!("{}", mp3_info.frames[0].sampling_freq);
-                }
-            },
-            Field::Title => {
-                if let Some(ref mp3_info) = mp3_info {
-                    if let Some(ref mp3_tag) = mp3_info.tag {
-                        return format!("{}", mp3_tag.title);
-                    }
-                }
-            },
-            Field::Artist => {
-                if let Some(ref mp3_info) = mp3_info {
-                    if let Some(ref mp3_tag) = mp3_info.tag {
-                        return format!("{}", mp3_tag.artist);
-                    }
-                }
-            },
-            Field::Album => {
-                if let Some(ref mp3_info) = mp3_info {
-                    if let Some(ref mp3_tag) = mp3_info.tag {
-                        return format!("{}", mp3_tag.album);
-                    }
-                }
-            },
-            Field::Year => {
-                if let Some(ref mp3_info) = mp3_info {
-                    if let Some(ref mp3_tag) = mp3_info.tag {
-                        return format!("{}", mp3_tag.year);
-                    }
-                }
-            },
-            Field::Genre => {
-                if let Some(ref mp3_info) = mp3_info {
-                    if let Some(ref mp3_tag) = mp3_info.tag {
-                        return format!("{:?}", mp3_tag.genre);
-                    }
+                match expr.op {
+                    Some(Op::Eq) | Some(Op::Eeq) => value == str_to_bool(val),
+                    Some(Op::Ne) | Some(Op::Ene) => value != str_to_bool(val),
+                    _ => false
                 }
             },
-            Field::IsArchive => {
-                let is_archive = is_archive(&entry.file_name().to_string_lossy());
-                return format!("{}", is_archive);
-            },
-            Field::IsAudio => {
-                let is_audio = is_audio(&entry.file_name().to_string_lossy());
-                return format!("{}", is_audio);
-            },
-            Field::IsBook => {
-                let is_book = is_book(&entry.file_name().to_string_lossy());
-                return format!("{}", is_book);
-            },
-            Field::IsDoc => {
-                let is_doc = is_doc(&entry.file_name().to_string_lossy());
-                return format!("{}", is_doc);
-            },
-            Field::IsImage => {
-                let is_image = is_image(&entry.file_name().to_string_lossy());
-                return format!("{}", is_image);
-            },
-            Field::IsSource => {
-                let is_source = is_source(&entry.file_name().to_string_lossy());
-                return format!("{}", is_source);
+            Some(Field::Mode) | Some(Field::ModeOctal) => {
+                let bits = match cfg!(unix) {
+                    true => Some(entry.mode),
+                    false => None
+                };
+                let formatted = self.get_indexed_field_value(path, entry, column_expr.field.as_ref().unwrap());
+                compare_mode(&expr.op, val, &expr.regex, bits, &formatted)
             },
-            Field::IsVideo => {
-                let is_video = is_video(&entry.file_name().to_string_lossy());
-                return format!("{}", is_video);
-            }
+            Some(ref field) => compare_str(&expr.op, val, &expr.regex, &self.get_indexed_field_value(path, entry, field)),
+            None => false
+        }
+    }
+
+    /// Files modified at the same moment as the marker file are neither newer nor older.
+    fn compare_with_marker(&self, column_expr: &ColumnExpr, expr: &Expr, modified: DateTime<Local>) -> bool {
+        let marker = match expr.val.as_ref().and_then(|path| self.markers.get(path)) {
+            Some(marker) => marker,
+            None => return false
         };
 
-        return String::new();
+        match column_expr.function {
+            Some(Function::IsNewerThan) => modified > marker.modified,
+            Some(Function::IsOlderThan) => modified < marker.modified,
+            _ => false
+        }
     }
 
-    fn check_file(&mut self,
-                  entry: &DirEntry,
-                  file_info: &Option<FileInfo>,
-                  need_metadata: bool,
-                  need_dim: bool,
-                  need_mp3: bool,
-                  follow_symlinks: bool,
-                  t: &mut Box<StdoutTerminal>) {
-        let mut meta = None;
-        let mut dim = None;
-        let mut mp3 = None;
+    /// Hard links to the marker file share its device and inode. The sizes are compared first, as they are cheaper
+    /// to tell apart. Without the inodes, e.g. on Windows, no file is the same as the marker.
+    fn is_same_file_as_marker(&self, expr: &Expr, metadata: &Metadata) -> bool {
+        let marker = match expr.val.as_ref().and_then(|path| self.markers.get(path)) {
+            Some(marker) => marker,
+            None => return false
+        };
 
-        if let Some(ref expr) = self.query.expr.clone() {
-            let (result, entry_meta, entry_dim, entry_mp3) = self.conforms(entry, file_info, expr, None, None, None, follow_symlinks);
-            if !result {
-                return
-            }
+        if metadata.len() != marker.size {
+            return false;
+        }
 
-            meta = entry_meta;
-            dim = entry_dim;
-            mp3 = entry_mp3;
+        match (marker.file_id, mode::get_file_id(metadata)) {
+            (Some(marker_id), Some(file_id)) => marker_id == file_id,
+            _ => false
         }
+    }
 
-        self.found += 1;
+    fn search_archive(&mut self,
+                      entry: &DirEntry,
+                      path: &Path,
+                      need_metadata: bool,
+                      need_dim: bool,
+                      need_mp3: bool,
+                      on_record: &mut RecordSink) {
+        let file_name = path.to_string_lossy();
+
+        if is_searchable_archive(&file_name) && !is_regular_file(entry) {
+            return;
+        }
 
-        let attrs = match need_metadata {
-            true => update_meta(entry, meta, follow_symlinks),
-            false => None
-        };
+        if self.max_archive_size > 0 && is_searchable_archive(&file_name) {
+            match fs::metadata(path) {
+                Ok(ref metadata) if metadata.len() > self.max_archive_size => return,
+                _ => { }
+            }
+        }
 
-        let dimensions = match need_dim {
-            true => update_img_dimensions(&entry, dim),
-            false => None
+        let result = if is_zip_archive(&file_name) {
+            self.search_zip_archive(entry, path, need_metadata, need_dim, need_mp3, on_record)
+        } else if is_tar_archive(&file_name) {
+            fs::File::open(path).and_then(|file| {
+                self.search_tar_archive(tar::Archive::new(file), entry, need_metadata, need_dim, need_mp3, on_record)
+            })
+        } else if is_tar_gz_archive(&file_name) {
+            fs::File::open(path).and_then(|file| {
+                self.search_tar_archive(tar::Archive::new(GzDecoder::new(file)), entry, need_metadata, need_dim, need_mp3, on_record)
+            })
+        } else if is_tar_bz2_archive(&file_name) {
+            fs::File::open(path).and_then(|file| {
+                self.search_tar_archive(tar::Archive::new(BzDecoder::new(file)), entry, need_metadata, need_dim, need_mp3, on_record)
+            })
+        } else {
+            Ok(())
         };
 
-        let mp3_info = match need_mp3 {
-            true => update_mp3_meta(&entry, mp3),
-            false => None
+        if let Err(err) = result {
+            self.report_error(path, err);
+        }
+    }
+
+    fn search_zip_archive(&mut self,
+                          entry: &DirEntry,
+                          path: &Path,
+                          need_metadata: bool,
+                          need_dim: bool,
+                          need_mp3: bool,
+                          on_record: &mut RecordSink) -> io::Result<()> {
+        let file = fs::File::open(path)?;
+        self.search_zip(file, entry, "", 1, need_metadata, need_dim, need_mp3, on_record)
+    }
+
+    /// Members of an archive found inside another one are named after the chain of the archives holding them,
+    /// e.g. `inner.jar!META-INF/MANIFEST.MF`, `prefix` is that chain.
+    fn search_zip<R: Read + Seek + 'static>(&mut self,
+                                            mut reader: R,
+                                            entry: &DirEntry,
+                                            prefix: &str,
+                                            archive_depth: u32,
+                                            need_metadata: bool,
+                                            need_dim: bool,
+                                            need_mp3: bool,
+                                            on_record: &mut RecordSink) -> io::Result<()> {
+        let extra_fields = read_zip_extra_fields(&mut reader).unwrap_or_default();
+        let archive = zip::ZipArchive::new(reader).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+        let archive_len = archive.len();
+        // entries can't be matched with their extra fields if the directory was read differently
+        let no_extra_field = vec![];
+        let extra_fields = match extra_fields.len() == archive_len {
+            true => extra_fields,
+            false => vec![]
         };
 
-        let mut records = vec![];
-        let mut file_map = HashMap::new();
+        let mut archive: Box<ZipMembers> = Box::new(archive);
 
-        let mut output_value = String::new();
-        let mut criteria = vec!["".to_string(); self.query.ordering_fields.len()];
+        for i in 0..cmp::min(archive_len, self.get_archive_entries_limit()) {
+            if self.is_search_stopped() {
+                break;
+            }
 
-        for field in self.query.get_all_fields() {
-            file_map.insert(field.to_string().to_lowercase(), self.get_field_value(entry, file_info, &mp3_info, &attrs, dimensions, &field, t));
+            let member = match archive.by_index(i) {
+                Ok(mut afile) => {
+                    let mut file_info = to_file_info(&afile, extra_fields.get(i).unwrap_or(&no_extra_field));
+                    file_info.name = format!("{}{}", prefix, file_info.name);
+                    file_info.zip_index = Some(i);
+                    let nested = self.read_nested_archive(entry, &mut afile, &mut file_info, archive_depth);
+                    Some((file_info, nested))
+                },
+                Err(_) => None
+            };
+
+            if let Some((file_info, nested)) = member {
+                // the searcher holds the archive while its member is checked, content conditions read the member from it
+                self.zip_archives.push(archive);
+                self.search_archive_member(entry, file_info, nested, archive_depth, need_metadata, need_dim, need_mp3, on_record);
+                archive = self.zip_archives.pop().expect("archive of the checked member");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Content of the zip archive member being checked, decompressed from the archive held by the searcher.
+    /// Members larger than the limit are skipped with a note.
+    fn read_zip_member(&mut self, entry: &DirEntry, file_info: &FileInfo, max_size: u64) -> Option<Vec<u8>> {
+        if file_info.name.ends_with('/') {
+            return None;
         }
 
-        for field in self.query.fields.iter() {
-            let mut record = self.get_column_expr_value(entry, file_info, &mp3_info, &attrs, dimensions, &field, t);
-            file_map.insert(field.to_string().to_lowercase(), record.clone());
+        let path = entry.path().join(&file_info.name);
 
-            output_value = self.format_results_row(record, output_value, &mut records);
+        if file_info.size > max_size {
+            self.report_member_too_large(&path, max_size);
+            return None;
         }
 
-        for (idx, field) in self.query.ordering_fields.iter().enumerate() {
-            criteria[idx] = match file_map.get(&field.to_string().to_lowercase()) {
-                Some(record) => record.clone(),
-                None => self.get_field_value(entry, file_info, &mp3_info, &attrs, dimensions, &field.clone().field.unwrap(), t)
+        let result = match (file_info.zip_index, self.zip_archives.last_mut()) {
+            (Some(idx), Some(archive)) => {
+                archive.by_index(idx)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+                    .and_then(|afile| {
+                        // the recorded size could be wrong, one byte more tells a member above the limit
+                        let mut content = Vec::with_capacity(file_info.size as usize);
+                        afile.take(max_size.saturating_add(1)).read_to_end(&mut content)?;
+                        Ok(content)
+                    })
+            },
+            _ => return None
+        };
+
+        match result {
+            Ok(ref content) if content.len() as u64 > max_size => {
+                self.report_member_too_large(&path, max_size);
+                None
+            },
+            Ok(content) => Some(content),
+            Err(err) => {
+                self.report_error(&path, err);
+                None
             }
         }
+    }
 
-        output_value = self.format_results_row_end(output_value, &records, &file_map);
+    fn report_member_too_large(&mut self, path: &Path, max_size: u64) {
+        if !self.quiet {
+            if let Some(ref mut progress) = *self.progress.borrow_mut() {
+                progress.clear();
+            }
 
-        if self.is_buffered() {
-            self.output_buffer.insert(Criteria::new(Rc::new(self.query.ordering_fields.clone()), criteria, self.query.ordering_asc.clone()), output_value);
+            let description = format!("larger than {} bytes, the content is not searched", max_size);
+            self.error_reporter.borrow_mut().error(&path.to_string_lossy(), &description);
+        }
+    }
 
-            if self.has_aggregate_column() {
-                self.raw_output_buffer.push(file_map);
+    fn search_tar_archive<R: Read>(&mut self,
+                                   mut archive: tar::Archive<R>,
+                                   entry: &DirEntry,
+                                   need_metadata: bool,
+                                   need_dim: bool,
+                                   need_mp3: bool,
+                                   on_record: &mut RecordSink) -> io::Result<()> {
+        // tar is a stream, so members are visited in order and a broken one stops the whole archive
+        for afile in archive.entries()?.take(self.get_archive_entries_limit()) {
+            if self.is_search_stopped() {
+                break;
             }
-        } else {
-            print!("{}", output_value);
+
+            let mut afile = afile?;
+            let mut file_info = tar_entry_to_file_info(&afile);
+            let nested = self.read_nested_archive(entry, &mut afile, &mut file_info, 1);
+            self.search_archive_member(entry, file_info, nested, 1, need_metadata, need_dim, need_mp3, on_record);
         }
+
+        Ok(())
     }
 
-    fn print_file_mode(attrs: &Option<Box<Metadata>>,
-                       mode_func_boxed: &Fn(&Box<Metadata>) -> bool,
-                       file_info: &Option<FileInfo>,
-                       mode_func_i32: &Fn(u32) -> bool) -> String {
-        match file_info {
-            Some(ref file_info) => {
-                if let Some(mode) = file_info.mode {
-                    return format!("{}", mode_func_i32(mode));
+    /// Archive members can be read only once, so they are hashed right away.
+    /// A zip archive inside is read into memory then, unless it's too large or too deep, and hashed from there.
+    /// An archive longer than its recorded size is read up to the limit only, it's neither hashed nor searched then.
+    fn read_nested_archive<R: Read>(&mut self,
+                                    entry: &DirEntry,
+                                    afile: &mut R,
+                                    file_info: &mut FileInfo,
+                                    archive_depth: u32) -> Option<Vec<u8>> {
+        if file_info.name.ends_with('/') {
+            return None;
+        }
+
+        let nested = match archive_depth < self.archive_depth
+            && is_zip_archive(&file_info.name)
+            && file_info.size <= self.max_nested_size {
+            true => {
+                let mut data = Vec::with_capacity(file_info.size as usize);
+                if afile.take(self.max_nested_size).read_to_end(&mut data).is_err() {
+                    return None;
                 }
-            },
-            _ => {
-                if let Some(ref attrs) = attrs {
-                    return format!("{}", mode_func_boxed(attrs));
+
+                if data.len() as u64 == self.max_nested_size && afile.read(&mut [0]).map(|len| len > 0).unwrap_or(false) {
+                    let message = format!("larger than {} bytes, neither hashed nor searched", self.max_nested_size);
+                    self.report_error(&entry.path().join(&file_info.name), io::Error::new(io::ErrorKind::InvalidData, message));
+                    return None;
                 }
+
+                Some(data)
+            },
+            false => None
+        };
+
+        if self.hash_kinds.any() {
+            file_info.hashes = match nested {
+                Some(ref data) => calc_hashes(&mut &data[..], self.hash_kinds).ok(),
+                None => calc_hashes(afile, self.hash_kinds).ok()
+            };
+        }
+
+        nested
+    }
+
+    fn search_archive_member(&mut self,
+                             entry: &DirEntry,
+                             file_info: FileInfo,
+                             nested: Option<Vec<u8>>,
+                             archive_depth: u32,
+                             need_metadata: bool,
+                             need_dim: bool,
+                             need_mp3: bool,
+                             on_record: &mut RecordSink) {
+        let name = file_info.name.clone();
+        self.check_file(entry, &Some(file_info), need_metadata, need_dim, need_mp3, false, on_record);
+
+        if let Some(data) = nested {
+            let prefix = format!("{}!", name);
+            if let Err(err) = self.search_zip(Cursor::new(data), entry, &prefix, archive_depth + 1, need_metadata, need_dim, need_mp3, on_record) {
+                self.report_error(&entry.path().join(&name), err);
             }
         }
+    }
 
-        String::new()
+    /// Number of entries to examine in every archive of the current root.
+    fn get_archive_entries_limit(&self) -> usize {
+        match self.max_archive_entries {
+            0 => usize::max_value(),
+            max => max as usize
+        }
     }
 
-    fn conforms(&mut self,
-                entry: &DirEntry,
-                file_info: &Option<FileInfo>,
-                expr: &Box<Expr>,
-                entry_meta: Option<Box<fs::Metadata>>,
-                entry_dim: Option<(usize, usize)>,
-                entry_mp3: Option<MP3Metadata>,
-                follow_symlinks: bool) -> (bool, Option<Box<fs::Metadata>>, Option<(usize, usize)>, Option<MP3Metadata>) {
-        let mut result = false;
-        let mut meta = entry_meta;
-        let mut dim = entry_dim;
-        let mut mp3 = entry_mp3;
+    /// Filters of the ignore files from `dir` and the directories above it, along with the global ones.
+    /// The filters of the deeper directories go last, so their rules take precedence.
+    fn get_gitignore_filters(&self, dir: &Path) -> GitignoreFilters {
+        let mut sources: Vec<(&(PathBuf, IgnoreSource), &Rc<Vec<GitignoreFilter>>)> = self.gitignore_map.iter()
+            .filter(|&(&(ref dir_path, ref source), _)| dir.starts_with(dir_path) && self.is_ignore_source_applied(source))
+            .collect();
+        sources.sort_by_key(|&(&(ref dir_path, ref source), _)| (dir_path.components().count(), source.clone()));
 
-        if let Some(ref logical_op) = expr.logical_op {
-            let mut left_result = false;
-            let mut right_result = false;
+        let mut result = vec![];
+        if self.apply_global_ignore && !self.global_ignore_filters.is_empty() {
+            result.push(self.global_ignore_filters.clone());
+        }
+        for (_, regexes) in sources {
+            result.push(regexes.clone());
+        }
 
-            if let Some(ref left) = expr.left {
-                let (left_res, left_meta, left_dim, left_mp3) = self.conforms(entry, file_info, &left, meta, dim, mp3, follow_symlinks);
-                left_result = left_res;
-                meta = left_meta;
-                dim = left_dim;
-                mp3 = left_mp3;
-            }
+        result
+    }
 
-            match logical_op {
-                LogicalOp::And => {
-                    if !left_result {
-                        result = false;
-                    } else {
-                        if let Some(ref right) = expr.right {
-                            let (right_res, right_meta, right_dim, right_mp3) = self.conforms(entry, file_info, &right, meta, dim, mp3, follow_symlinks);
-                            right_result = right_res;
-                            meta = right_meta;
-                            dim = right_dim;
-                            mp3 = right_mp3;
-                        }
+    /// Filters of the `.gitignore` files from `dir` and the directories above it, the ones a search with `gitignored` inverts.
+    fn get_inverted_gitignore_filters(&self, dir: &Path) -> GitignoreFilters {
+        let mut sources: Vec<(&PathBuf, &Rc<Vec<GitignoreFilter>>)> = self.gitignore_map.iter()
+            .filter(|&(&(ref dir_path, ref source), _)| dir.starts_with(dir_path) && *source == IgnoreSource::Gitignore)
+            .map(|(&(ref dir_path, _), filters)| (dir_path, filters))
+            .collect();
+        sources.sort_by_key(|&(dir_path, _)| dir_path.components().count());
 
-                        result = left_result && right_result;
-                    }
-                },
-                LogicalOp::Or => {
-                    if left_result {
-                        result = true;
-                    } else {
-                        if let Some(ref right) = expr.right {
-                            let (right_res, right_meta, right_dim, right_mp3) = self.conforms(entry, file_info, &right, meta, dim, mp3, follow_symlinks);
-                            right_result = right_res;
-                            meta = right_meta;
-                            dim = right_dim;
-                            mp3 = right_mp3;
-                        }
+        sources.into_iter().map(|(_, filters)| filters.clone()).collect()
+    }
 
-                        result = left_result || right_result
-                    }
-                }
+    /// The map keeps the filters of the previous roots, which could have other ignore settings.
+    fn is_ignore_source_applied(&self, source: &IgnoreSource) -> bool {
+        match source {
+            IgnoreSource::Gitignore => self.apply_gitignore,
+            IgnoreSource::IgnoreFile(ref file_name) => *file_name == self.ignore_file
+        }
+    }
+
+    /// Size of a directory is the total size of all the files inside, recursively.
+    /// For other files it's just their size.
+    fn get_dir_size(&self,
+                    entry: &DirEntry,
+                    file_info: &Option<FileInfo>,
+                    attrs: &Option<Box<Metadata>>) -> Option<u64> {
+        if let Some(ref file_info) = file_info {
+            return Some(file_info.size);
+        }
+
+        match attrs {
+            Some(ref attrs) if attrs.is_dir() => {
+                let dir = to_absolute_path(&entry.path());
+                let gitignore_filters = Some(self.get_gitignore_filters(dir.parent().unwrap_or(&dir)));
+
+                Some(self.calc_dir_size(&dir, &gitignore_filters, &mut HashSet::new()))
+            },
+            Some(ref attrs) => Some(attrs.len()),
+            None => None
+        }
+    }
+
+    fn calc_dir_size(&self,
+                     dir: &Path,
+                     parent_gitignore_filters: &Option<GitignoreFilters>,
+                     visited_dirs: &mut HashSet<PathBuf>) -> u64 {
+        if let Some(size) = self.dir_size_cache.borrow().get(dir) {
+            return *size;
+        }
+
+        // following symlinks could lead into a loop
+        if self.follow_symlinks {
+            let canonical_dir = fs::canonicalize(dir).unwrap_or(dir.to_path_buf());
+            if !visited_dirs.insert(canonical_dir) {
+                return 0;
             }
         }
 
-        if let Some(ref field) = expr.field {
-            let field = field.field.clone().unwrap();
-            match field {
-                Field::Name => {
-                    if let Some(ref val) = expr.val {
-                        let file_name = match file_info {
-                            Some(ref file_info) => file_info.name.clone(),
-                            _ => entry.file_name().to_string_lossy().to_string()
-                        };
+        let mut gitignore_filters = parent_gitignore_filters.clone();
+        if let Some(ref mut gitignore_filters) = gitignore_filters {
+            let gitignore_file = dir.join(".gitignore");
+            if self.apply_gitignore && gitignore_file.is_file() {
+                gitignore_filters.push(Rc::new(parse_gitignore(&gitignore_file, dir)));
+            }
 
-                        result = match expr.op {
-                            Some(Op::Eq) => {
-                                match expr.regex {
-                                    Some(ref regex) => regex.is_match(&file_name),
-                                    None => val.eq(&file_name)
-                                }
-                            },
-                            Some(Op::Ne) => {
-                                match expr.regex {
-                                    Some(ref regex) => !regex.is_match(&file_name),
-                                    None => val.ne(&file_name)
-                                }
-                            },
-                            Some(Op::Rx) | Some(Op::Like) => {
-                                match expr.regex {
-                                    Some(ref regex) => regex.is_match(&file_name),
-                                    None => false
-                                }
-                            },
-                            Some(Op::Eeq) => {
-                                val.eq(&file_name)
-                            },
-                            Some(Op::Ene) => {
-                                val.ne(&file_name)
-                            },
-                            _ => false
-                        };
-                    }
-                },
-                Field::Path => {
-                    if let Some(ref val) = expr.val {
-                        let file_path = match file_info {
-                            Some(ref file_info) => file_info.name.clone(),
-                            _ => String::from(entry.path().to_string_lossy())
-                        };
+            let ignore_file = dir.join(&self.ignore_file);
+            if ignore_file.is_file() {
+                gitignore_filters.push(Rc::new(parse_ignore_file(&ignore_file, dir)));
+            }
+        }
 
-                        result = match expr.op {
-                            Some(Op::Eq) => {
-                                match expr.regex {
-                                    Some(ref regex) => regex.is_match(&file_path),
-                                    None => val.eq(&file_path)
-                                }
-                            },
-                            Some(Op::Ne) => {
-                                match expr.regex {
-                                    Some(ref regex) => !regex.is_match(&file_path),
-                                    None => val.ne(&file_path)
-                                }
-                            },
-                            Some(Op::Rx) | Some(Op::Like) => {
-                                match expr.regex {
-                                    Some(ref regex) => regex.is_match(&file_path),
-                                    None => false
-                                }
-                            },
-                            Some(Op::Eeq) => {
-                                val.eq(&file_path)
-                            },
-                            Some(Op::Ene) => {
-                                val.ne(&file_path)
-                            },
-                            _ => false
-                        };
-                    }
-                },
-                Field::Size | Field::FormattedSize => {
-                    if let Some(ref val) = expr.val {
-                        let file_size = match file_info {
-                            Some(ref file_info) => {
-                                Some(file_info.size)
-                            },
-                            _ => {
-                                meta = update_meta(entry, meta, follow_symlinks);
-                                match meta {
-                                    Some(ref metadata) => {
-                                        Some(metadata.len())
-                                    },
-                                    _ => None
-                                }
-                            }
-                        };
+        let mut size = 0;
 
-                        if let Some(file_size) = file_size {
-                            let size = parse_filesize(val);
-                            if let Some(size) = size {
-                                result = match expr.op {
-                                    Some(Op::Eq) | Some(Op::Eeq) => file_size == size,
-                                    Some(Op::Ne) | Some(Op::Ene) => file_size != size,
-                                    Some(Op::Gt) => file_size > size,
-                                    Some(Op::Gte) => file_size >= size,
-                                    Some(Op::Lt) => file_size < size,
-                                    Some(Op::Lte) => file_size <= size,
-                                    _ => false
-                                };
-                            }
+        if let Ok(entry_list) = fs::read_dir(dir) {
+            for entry in entry_list {
+                if let Ok(entry) = entry {
+                    let path = entry.path();
+                    let is_dir = is_dir_entry(&entry, self.follow_symlinks);
+
+                    if let Some(ref gitignore_filters) = gitignore_filters {
+                        if matches_gitignore_filter(gitignore_filters, path.to_string_lossy().as_ref(), is_dir) {
+                            continue;
                         }
                     }
-                },
-                Field::Uid => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
+
+                    if is_dir {
+                        size += self.calc_dir_size(&path, &gitignore_filters, visited_dirs);
+                    } else if let Ok(metadata) = get_metadata(&path, self.follow_symlinks) {
+                        size += metadata.len();
                     }
+                }
+            }
+        }
 
-                    if let Some(ref val) = expr.val {
-                        meta = update_meta(entry, meta, follow_symlinks);
+        self.dir_size_cache.borrow_mut().insert(dir.to_path_buf(), size);
 
-                        if let Some(ref metadata) = meta {
-                            let uid = val.parse::<u32>();
-                            if let Ok(uid) = uid {
-                                let file_uid = mode::get_uid(metadata);
-                                if let Some(file_uid) = file_uid {
-                                    result = match expr.op {
-                                        Some(Op::Eq) | Some(Op::Eeq) => file_uid == uid,
-                                        Some(Op::Ne) | Some(Op::Ene) => file_uid != uid,
-                                        Some(Op::Gt) => file_uid > uid,
-                                        Some(Op::Gte) => file_uid >= uid,
-                                        Some(Op::Lt) => file_uid < uid,
-                                        Some(Op::Lte) => file_uid <= uid,
-                                        _ => false
-                                    };
-                                }
-                            }
-                        }
-                    }
-                },
-                Field::User => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+        size
+    }
 
-                    if let Some(ref val) = expr.val {
-                        meta = update_meta(entry, meta, follow_symlinks);
+    /// Hex encoded digest of the file content, empty for directories and unreadable files.
+    /// All the digests referenced in the query are calculated in one pass and cached until the next file.
+    fn get_hash(&self,
+                entry: &DirEntry,
+                file_info: &Option<FileInfo>,
+                field: &Field) -> String {
+        if let Some(ref file_info) = file_info {
+            return match file_info.hashes {
+                Some(ref hashes) => hashes.get(field).cloned().unwrap_or_default(),
+                None => String::new()
+            };
+        }
 
-                        if let Some(ref metadata) = meta {
-                            let file_uid = mode::get_uid(metadata);
-                            if let Some(file_uid) = file_uid {
-                                if let Some(user) = self.user_cache.get_user_by_uid(file_uid) {
-                                    let user_name = user.name().to_string_lossy().to_string();
-                                    result = match expr.op {
-                                        Some(Op::Eq) => {
-                                            match expr.regex {
-                                                Some(ref regex) => regex.is_match(&user_name),
-                                                None => val.eq(&user_name)
-                                            }
-                                        },
-                                        Some(Op::Ne) => {
-                                            match expr.regex {
-                                                Some(ref regex) => !regex.is_match(&user_name),
-                                                None => val.ne(&user_name)
-                                            }
-                                        },
-                                        Some(Op::Rx) | Some(Op::Like) => {
-                                            match expr.regex {
-                                                Some(ref regex) => regex.is_match(&user_name),
-                                                None => false
-                                            }
-                                        },
-                                        Some(Op::Eeq) => {
-                                            val.eq(&user_name)
-                                        },
-                                        Some(Op::Ene) => {
-                                            val.ne(&user_name)
-                                        },
-                                        _ => false
-                                    };
-                                }
-                            }
-                        }
-                    }
-                },
-                Field::Gid => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+        if !is_regular_file(entry) {
+            return String::new();
+        }
 
-                    if let Some(ref val) = expr.val {
-                        meta = update_meta(entry, meta, follow_symlinks);
+        if self.hash_cache.borrow().is_none() {
+            *self.hash_cache.borrow_mut() = Some(self.probe_hashes(entry));
+        }
 
-                        if let Some(ref metadata) = meta {
-                            let gid = val.parse::<u32>();
-                            if let Ok(gid) = gid {
-                                let file_gid = mode::get_gid(metadata);
-                                if let Some(file_gid) = file_gid {
-                                    result = match expr.op {
-                                        Some(Op::Eq) | Some(Op::Eeq) => file_gid == gid,
-                                        Some(Op::Ne) | Some(Op::Ene) => file_gid != gid,
-                                        Some(Op::Gt) => file_gid > gid,
-                                        Some(Op::Gte) => file_gid >= gid,
-                                        Some(Op::Lt) => file_gid < gid,
-                                        Some(Op::Lte) => file_gid <= gid,
-                                        _ => false
-                                    };
-                                }
-                            }
-                        }
-                    }
-                },
-                Field::Group => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+        match *self.hash_cache.borrow() {
+            Some(ref hashes) => hashes.get(field).cloned().unwrap_or_default(),
+            None => String::new()
+        }
+    }
 
-                    if let Some(ref val) = expr.val {
-                        meta = update_meta(entry, meta, follow_symlinks);
+    /// Whether the file content looks binary, the file is read once no matter how many times it's asked.
+    /// Directories, unreadable files and archived files give no answer.
+    fn get_is_binary(&self, entry: &DirEntry, file_info: &Option<FileInfo>) -> Option<bool> {
+        if file_info.is_some() || !is_regular_file(entry) {
+            return None;
+        }
 
-                        if let Some(ref metadata) = meta {
-                            let file_gid = mode::get_gid(metadata);
-                            if let Some(file_gid) = file_gid {
-                                if let Some(group) = self.user_cache.get_group_by_gid(file_gid) {
-                                    let group_name = group.name().to_string_lossy().to_string();
-                                    result = match expr.op {
-                                        Some(Op::Eq) => {
-                                            match expr.regex {
-                                                Some(ref regex) => regex.is_match(&group_name),
-                                                None => val.eq(&group_name)
-                                            }
-                                        },
-                                        Some(Op::Ne) => {
-                                            match expr.regex {
-                                                Some(ref regex) => !regex.is_match(&group_name),
-                                                None => val.ne(&group_name)
-                                            }
-                                        },
-                                        Some(Op::Rx) | Some(Op::Like) => {
-                                            match expr.regex {
-                                                Some(ref regex) => regex.is_match(&group_name),
-                                                None => false
-                                            }
-                                        },
-                                        Some(Op::Eeq) => {
-                                            val.eq(&group_name)
-                                        },
-                                        Some(Op::Ene) => {
-                                            val.ne(&group_name)
-                                        },
-                                        _ => false
-                                    };
-                                }
-                            }
+        if self.binary_cache.borrow().is_none() {
+            let is_binary = File::open(entry.path()).and_then(|file| {
+                let mut buf = vec![];
+                file.take(BINARY_CHECK_SIZE).read_to_end(&mut buf)?;
+                Ok(is_binary_content(&buf))
+            });
+            *self.binary_cache.borrow_mut() = Some(is_binary.ok());
+        }
+
+        self.binary_cache.borrow().unwrap_or(None)
+    }
+
+    /// Kind of line breaks in the first 64 KB of the file, read once no matter how many times it's asked.
+    /// Directories, unreadable, binary and archived files as well as files without line breaks give no answer.
+    fn get_line_endings(&self, entry: &DirEntry, file_info: &Option<FileInfo>) -> Option<&'static str> {
+        if file_info.is_some() || !is_regular_file(entry) {
+            return None;
+        }
+
+        if self.line_endings_cache.borrow().is_none() {
+            let line_endings = File::open(entry.path()).and_then(|file| {
+                let mut buf = vec![];
+                file.take(LINE_ENDINGS_CHECK_SIZE).read_to_end(&mut buf)?;
+                Ok(buf)
+            }).ok().and_then(|buf| match is_binary_content(&buf) {
+                true => None,
+                false => detect_line_endings(&buf)
+            });
+            *self.line_endings_cache.borrow_mut() = Some(line_endings);
+        }
+
+        self.line_endings_cache.borrow().unwrap_or(None)
+    }
+
+    /// Whether the file starts with a byte order mark, the same entries as for `get_line_endings` give no answer.
+    fn get_has_bom(&self, entry: &DirEntry, file_info: &Option<FileInfo>) -> Option<bool> {
+        if file_info.is_some() || !is_regular_file(entry) {
+            return None;
+        }
+
+        if self.bom_cache.borrow().is_none() {
+            let bom = File::open(entry.path()).and_then(|file| {
+                let mut buf = vec![];
+                file.take(3).read_to_end(&mut buf)?;
+                Ok(has_bom(&buf))
+            });
+            *self.bom_cache.borrow_mut() = Some(bom.ok());
+        }
+
+        self.bom_cache.borrow().unwrap_or(None)
+    }
+
+    /// Interpreter of the script named on its first line, read once no matter how many times it's asked.
+    /// Directories, archived files and files without a shebang give no answer.
+    fn get_shebang(&self, entry: &DirEntry, file_info: &Option<FileInfo>) -> Option<String> {
+        if file_info.is_some() || !is_regular_file(entry) {
+            return None;
+        }
+
+        if self.shebang_cache.borrow().is_none() {
+            let shebang = File::open(entry.path()).and_then(|file| {
+                let mut buf = vec![];
+                file.take(SHEBANG_CHECK_SIZE).read_to_end(&mut buf)?;
+                Ok(buf)
+            }).ok().and_then(|buf| parse_shebang(&buf));
+            *self.shebang_cache.borrow_mut() = Some(shebang);
+        }
+
+        self.shebang_cache.borrow().clone().unwrap_or(None)
+    }
+
+    /// Video track properties read from the headers of MP4 or Matroska file once no matter how many fields ask for them.
+    /// Other files, directories and archived files have none.
+    fn get_video_metadata(&self, entry: &DirEntry, file_info: &Option<FileInfo>) -> Option<VideoMetadata> {
+        if file_info.is_some() || !is_regular_file(entry) {
+            return None;
+        }
+
+        if self.video_cache.borrow().is_none() {
+            *self.video_cache.borrow_mut() = Some(self.probe(entry, |probes| &mut probes.video, read_video_metadata));
+        }
+
+        self.video_cache.borrow().clone().unwrap_or(None)
+    }
+
+    /// Resolves the parent directory of the entry once no matter how many probes or checks need the path.
+    fn get_entry_canonical_path(&self, entry: &DirEntry) -> PathBuf {
+        if let Some(ref path) = *self.canonical_path_cache.borrow() {
+            return path.clone();
+        }
+
+        #[cfg(test)]
+        self.canonicalized_paths.set(self.canonicalized_paths.get() + 1);
+
+        let path = get_canonical_path(entry);
+        *self.canonical_path_cache.borrow_mut() = Some(path.clone());
+
+        path
+    }
+
+    /// Value read from the file content, or the one read before if the file has been met already.
+    /// `select` tells which of the probes of the file it is.
+    fn probe<T, S, R>(&self, entry: &DirEntry, select: S, read: R) -> T
+        where T: Clone, S: Fn(&mut FileProbes) -> &mut Option<T>, R: FnOnce(&Path) -> T {
+        let key = self.get_entry_canonical_path(entry);
+
+        if let Some(probes) = self.probe_cache.borrow_mut().get_mut(&key) {
+            if let Some(ref value) = *select(probes) {
+                return value.clone();
+            }
+        }
+
+        let value = read(&entry.path());
+        *select(self.probe_cache.borrow_mut().get_or_insert_default(key)) = Some(value.clone());
+
+        value
+    }
+
+    /// Digests of the kinds the query needs, unreadable files have none.
+    fn probe_hashes(&self, entry: &DirEntry) -> Hashes {
+        let key = self.get_entry_canonical_path(entry);
+
+        if let Some(&mut FileProbes { hashes: Some((kinds, ref hashes)), .. }) = self.probe_cache.borrow_mut().get_mut(&key) {
+            if kinds == self.hash_kinds {
+                return hashes.clone();
+            }
+        }
+
+        let hashes = File::open(entry.path())
+            .and_then(|mut file| calc_hashes(&mut file, self.hash_kinds))
+            .unwrap_or_default();
+        self.probe_cache.borrow_mut().get_or_insert_default(key).hashes = Some((self.hash_kinds, hashes.clone()));
+
+        hashes
+    }
+
+    fn update_img_dimensions(&self, entry: &DirEntry, dim: Option<(usize, usize)>) -> Option<(usize, usize)> {
+        match dim {
+            None if !is_regular_file(entry) => None,
+            None => self.probe(entry, |probes| &mut probes.dimensions, |path| {
+                #[cfg(test)]
+                self.dimension_probes.set(self.dimension_probes.get() + 1);
+
+                match imagesize::size(path) {
+                    Ok(dimensions) => Some((dimensions.width, dimensions.height)),
+                    _ => None
+                }
+            }),
+            Some(dim_) => Some(dim_)
+        }
+    }
+
+    fn update_mp3_meta(&self, entry: &DirEntry, mp3: Option<Rc<MP3Metadata>>) -> Option<Rc<MP3Metadata>> {
+        match mp3 {
+            None if !is_regular_file(entry) => None,
+            None => self.probe(entry, |probes| &mut probes.mp3, |path| {
+                match mp3_metadata::read_from_file(path) {
+                    Ok(mp3_meta) => Some(Rc::new(mp3_meta)),
+                    _ => None
+                }
+            }),
+            Some(mp3_) => Some(mp3_)
+        }
+    }
+
+    /// Status of the file in the enclosing git repository, files outside of any and archived files have none.
+    fn get_git_status(&self, entry: &DirEntry, file_info: &Option<FileInfo>) -> Option<&'static str> {
+        if file_info.is_some() {
+            return None;
+        }
+
+        let path = to_absolute_path(&entry.path());
+        self.git_status_cache.borrow_mut().get_status(&path, is_dir_entry(entry, self.follow_symlinks))
+    }
+
+    /// Name of the owner of the file. Owners unknown on this system, like the ones of archived files
+    /// or of files on NFS shares and in containers, are shown by their uid.
+    fn get_user_name(&self, uid: u32) -> String {
+        self.user_cache.get_user_name(uid).unwrap_or_else(|| uid.to_string())
+    }
+
+    /// Name of the group of the file, the gid is used when the group is unknown on this system.
+    fn get_group_name(&self, gid: u32) -> String {
+        self.user_cache.get_group_name(gid).unwrap_or_else(|| gid.to_string())
+    }
+
+    /// Files are empty when they have zero size, directories when they have no entries at all.
+    /// Directories inside archives are never considered empty.
+    fn get_is_empty(&self, entry: &DirEntry, file_info: &Option<FileInfo>, attrs: &Option<Box<Metadata>>) -> Option<bool> {
+        if let Some(ref file_info) = file_info {
+            return match file_info.name.ends_with('/') {
+                true => None,
+                false => Some(file_info.size == 0)
+            };
+        }
+
+        match attrs {
+            Some(ref attrs) if attrs.is_dir() => {
+                if self.empty_dir_cache.borrow().is_none() {
+                    let is_empty = fs::read_dir(entry.path()).map(|mut entries| entries.next().is_none());
+                    *self.empty_dir_cache.borrow_mut() = Some(is_empty.ok());
+                }
+
+                self.empty_dir_cache.borrow().unwrap_or(None)
+            },
+            Some(ref attrs) => Some(attrs.len() == 0),
+            None => None
+        }
+    }
+
+    /// Number of the direct children of a directory, whatever the ignore files say.
+    /// Counting stops at the limit, which is enough when the count is only compared with a number.
+    /// Other files and directories inside archives give no answer.
+    fn get_entries(&self,
+                   entry: &DirEntry,
+                   file_info: &Option<FileInfo>,
+                   attrs: &Option<Box<Metadata>>,
+                   limit: Option<u64>) -> Option<u64> {
+        if file_info.is_some() {
+            return None;
+        }
+
+        match attrs {
+            Some(ref attrs) if attrs.is_dir() => {
+                if let Some(entries) = *self.entries_cache.borrow() {
+                    return entries;
+                }
+
+                let limit = limit.unwrap_or(u64::max_value());
+                let entries = fs::read_dir(entry.path()).ok().map(|entries| {
+                    let mut count = 0;
+                    for _ in entries {
+                        count += 1;
+                        if count >= limit {
+                            break;
                         }
                     }
-                },
-                Field::IsDir => {
-                    if let Some(ref val) = expr.val {
-                        let is_dir = match file_info {
-                            Some(ref file_info) => Some(file_info.name.ends_with('/')),
-                            _ => {
-                                meta = update_meta(entry, meta, follow_symlinks);
+                    count
+                });
 
-                                match meta {
-                                    Some(ref metadata) => {
-                                        Some(metadata.is_dir())
-                                    },
-                                    _ => None
-                                }
-                            }
-                        };
+                if entries.map_or(true, |count| count < limit) {
+                    *self.entries_cache.borrow_mut() = Some(entries);
+                }
 
-                        if let Some(is_dir) = is_dir {
-                            let bool_val = str_to_bool(val);
+                entries
+            },
+            _ => None
+        }
+    }
 
-                            result = match expr.op {
-                                Some(Op::Eq) | Some(Op::Eeq) => {
-                                    if bool_val {
-                                        is_dir
-                                    } else {
-                                        !is_dir
-                                    }
-                                },
-                                Some(Op::Ne) | Some(Op::Ene) => {
-                                    if bool_val {
-                                        !is_dir
-                                    } else {
-                                        is_dir
-                                    }
-                                },
-                                _ => false
-                            };
+    /// Device of the root directory, which is unknown on platforms without device ids and then the option is ignored.
+    fn get_root_device(&self, root_dir: &Path, follow_symlinks: bool) -> Option<u64> {
+        let device = get_metadata(root_dir, follow_symlinks).ok().and_then(|metadata| mode::get_device(&metadata));
+
+        if device.is_none() && cfg!(not(unix)) && !self.quiet {
+            self.error_reporter.borrow_mut().error("onefilesystem", "the option is not supported on this platform and is ignored");
+        }
+
+        device
+    }
+
+    /// Birth time isn't supported by every file system, the earliest of the modification
+    /// and the inode change times is taken then, that is reported only once.
+    fn get_created_time(&self, metadata: &Box<Metadata>) -> Option<DateTime<Local>> {
+        if let Ok(sdt) = metadata.created() {
+            return Some(DateTime::from(sdt));
+        }
+
+        if !self.created_fallback_reported.get() {
+            self.created_fallback_reported.set(true);
+
+            if !self.quiet {
+                self.error_reporter.borrow_mut().error("created", "creation time is not available, the earliest of modification and change times is used instead");
+            }
+        }
+
+        metadata.modified().ok().into_iter()
+            .chain(mode::get_ctime(metadata))
+            .min()
+            .map(DateTime::from)
+    }
+
+    /// Time of the modification, the access or the creation of the file the `_unix` and `_utc` fields show.
+    /// Files inside archives have only the modification time.
+    fn get_file_time(&self, field: &Field, attrs: &Option<Box<Metadata>>, file_info: &Option<FileInfo>) -> Option<DateTime<Local>> {
+        match (field, file_info, attrs) {
+            (&Field::ModifiedUnix, &Some(ref file_info), _) | (&Field::ModifiedUtc, &Some(ref file_info), _) => Some(to_local_datetime(&file_info.modified)),
+            (_, &Some(_), _) | (_, _, &None) => None,
+            (&Field::ModifiedUnix, _, &Some(ref attrs)) | (&Field::ModifiedUtc, _, &Some(ref attrs)) => attrs.modified().ok().map(DateTime::from),
+            (&Field::AccessedUnix, _, &Some(ref attrs)) | (&Field::AccessedUtc, _, &Some(ref attrs)) => attrs.accessed().ok().map(DateTime::from),
+            (&Field::CreatedUnix, _, &Some(ref attrs)) | (&Field::CreatedUtc, _, &Some(ref attrs)) => self.get_created_time(attrs),
+            _ => None
+        }
+    }
+
+    /// Days since the modification, the access or the creation of the file.
+    /// Only the modification time is known for archive entries.
+    fn get_age_days(&self, field: &Field, attrs: &Option<Box<Metadata>>, file_info: &Option<FileInfo>) -> Option<u64> {
+        let dt = match (field, file_info, attrs) {
+            (&Field::AgeDays, &Some(ref file_info), _) => Some(to_local_datetime(&file_info.modified)),
+            (_, &Some(_), _) | (_, _, &None) => None,
+            (&Field::AgeDays, _, &Some(ref attrs)) => attrs.modified().ok().map(DateTime::from),
+            (&Field::AccessAgeDays, _, &Some(ref attrs)) => attrs.accessed().ok().map(DateTime::from),
+            (&Field::CreateAgeDays, _, &Some(ref attrs)) => self.get_created_time(attrs),
+            _ => None
+        };
+
+        dt.map(|dt| days_between(&dt, &self.now))
+    }
+
+    /// Dates keep their time, so they are ordered and output in the date format of the query exactly.
+    fn get_column_typed_value(&self,
+                              entry: &DirEntry,
+                              file_info: &Option<FileInfo>,
+                              mp3_info: &Option<Rc<MP3Metadata>>,
+                              attrs: &Option<Box<Metadata>>,
+                              dimensions: Option<(usize, usize)>,
+                              column_expr: &ColumnExpr) -> Value {
+        match column_expr.get_field() {
+            Some(ref field) => self.get_field_typed_value(entry, file_info, mp3_info, attrs, dimensions, field),
+            None => Value::from(self.get_column_expr_value(entry, file_info, mp3_info, attrs, dimensions, column_expr))
+        }
+    }
+
+    fn get_field_typed_value(&self,
+                             entry: &DirEntry,
+                             file_info: &Option<FileInfo>,
+                             mp3_info: &Option<Rc<MP3Metadata>>,
+                             attrs: &Option<Box<Metadata>>,
+                             dimensions: Option<(usize, usize)>,
+                             field: &Field) -> Value {
+        match self.get_datetime_field_value(field, attrs, file_info) {
+            Some(dt) => Value::from_datetime(&dt),
+            None => Value::from(self.get_field_value(entry, file_info, mp3_info, attrs, dimensions, field))
+        }
+    }
+
+    /// Time of the date fields, only the modification time is known for archive entries.
+    fn get_datetime_field_value(&self, field: &Field, attrs: &Option<Box<Metadata>>, file_info: &Option<FileInfo>) -> Option<DateTime<Local>> {
+        match (field, file_info, attrs) {
+            (&Field::Modified, &Some(ref file_info), _) => Some(to_local_datetime(&file_info.modified)),
+            (_, _, &None) => None,
+            (&Field::Created, _, &Some(ref attrs)) => self.get_created_time(attrs),
+            (&Field::Accessed, _, &Some(ref attrs)) => attrs.accessed().ok().map(DateTime::from),
+            (&Field::Changed, _, &Some(ref attrs)) => mode::get_ctime(attrs).map(DateTime::from),
+            (&Field::Modified, _, &Some(ref attrs)) => attrs.modified().ok().map(DateTime::from),
+            _ => None
+        }
+    }
+
+    fn get_column_expr_value(&self,
+                             entry: &DirEntry,
+                             file_info: &Option<FileInfo>,
+                             mp3_info: &Option<Rc<MP3Metadata>>,
+                             attrs: &Option<Box<Metadata>>,
+                             dimensions: Option<(usize, usize)>,
+                             column_expr: &ColumnExpr) -> String {
+        if let Some(ref _function) = column_expr.function {
+            return self.get_function_value(entry, file_info, mp3_info, attrs, dimensions, column_expr);
+        }
+
+        if let Some(ref field) = column_expr.field {
+            return self.get_field_value(entry, file_info, mp3_info, attrs, dimensions, field);
+        }
+
+        if let Some(ref value) = column_expr.val {
+            return value.clone();
+        }
+
+        if let Some(ref arithmetic_op) = column_expr.arithmetic_op {
+            let left = match column_expr.left {
+                Some(ref left) => self.get_column_expr_value(entry, file_info, mp3_info, attrs, dimensions, left),
+                None => String::new()
+            };
+            let right = match column_expr.right {
+                Some(ref right) => self.get_column_expr_value(entry, file_info, mp3_info, attrs, dimensions, right),
+                None => String::new()
+            };
+
+            return calc_arithmetic(arithmetic_op, &left, &right);
+        }
+
+        if let Some(ref left) = column_expr.left {
+            return self.get_column_expr_value(entry, file_info, mp3_info, attrs, dimensions, left);
+        }
+
+        String::new()
+    }
+
+    fn get_function_value(&self,
+                          entry: &DirEntry,
+                          file_info: &Option<FileInfo>,
+                          mp3_info: &Option<Rc<MP3Metadata>>,
+                          attrs: &Option<Box<Metadata>>,
+                          dimensions: Option<(usize, usize)>,
+                          column_expr: &ColumnExpr) -> String {
+        if let Some(ref left_expr) = column_expr.left {
+            let function_arg = self.get_column_expr_value(entry,
+                                                          file_info,
+                                                          mp3_info,
+                                                          attrs,
+                                                          dimensions,
+                                                          left_expr);
+            let args: Vec<String> = column_expr.args.iter()
+                .map(|arg| self.get_column_expr_value(entry, file_info, mp3_info, attrs, dimensions, arg))
+                .collect();
+
+            match column_expr.function {
+                Some(Function::Lower) => {
+                    return function_arg.to_lowercase();
+                },
+                Some(Function::Upper) => {
+                    return function_arg.to_uppercase();
+                },
+                Some(Function::Length) => {
+                    return format!("{}", function_arg.chars().count());
+                },
+                Some(Function::Trim) => {
+                    return function_arg.trim().to_string();
+                },
+                Some(Function::Substr) => {
+                    return substr(&function_arg, &args[0], args.get(1));
+                },
+                Some(Function::Replace) => {
+                    if args[0].is_empty() {
+                        return function_arg;
+                    }
+
+                    return function_arg.replace(&args[0], &args[1]);
+                },
+                Some(Function::Concat) => {
+                    return args.iter().fold(function_arg, |result, arg| result + arg);
+                },
+                Some(Function::Year) => {
+                    match parse_datetime(&function_arg) {
+                        Ok(date) => {
+                            return date.0.year().to_string();
+                        },
+                        _ => {
+                            return String::new();
                         }
                     }
                 },
-                Field::IsFile => {
-                    if let Some(ref val) = expr.val {
-                        let is_file = match file_info {
-                            Some(ref file_info) => Some(!file_info.name.ends_with('/')),
-                            _ => {
-                                meta = update_meta(entry, meta, follow_symlinks);
-
-                                match meta {
-                                    Some(ref metadata) => {
-                                        Some(metadata.is_file())
-                                    },
-                                    _ => None
-                                }
-                            }
-                        };
+                Some(Function::Month) => {
+                    match parse_datetime(&function_arg) {
+                        Ok(date) => {
+                            return date.0.month().to_string();
+                        },
+                        _ => {
+                            return String::new();
+                        }
+                    }
+                },
+                Some(Function::Day) => {
+                    match parse_datetime(&function_arg) {
+                        Ok(date) => {
+                            return date.0.day().to_string();
+                        },
+                        _ => {
+                            return String::new();
+                        }
+                    }
+                },
+                Some(Function::Xattr) => {
+                    if file_info.is_some() {
+                        return String::new();
+                    }
+
+                    return get_xattr(&entry.path(), &function_arg);
+                },
+                Some(Function::Capture) => {
+                    match function_arg.parse::<usize>() {
+                        Ok(group) => {
+                            return self.captures.get(group).cloned().unwrap_or_default();
+                        },
+                        _ => {
+                            return String::new();
+                        }
+                    }
+                },
+                _ => {
+                    return String::new();
+                }
+            }
+        }
+
+        String::new()
+    }
+
+    fn get_aggregate_function_value(&self,
+                                    column_expr: &ColumnExpr,
+                                    rows: &Vec<&HashMap<String, Value>>) -> Value {
+        if let Some(ref arithmetic_op) = column_expr.arithmetic_op {
+            let left = match column_expr.left {
+                Some(ref left) => self.get_aggregate_function_value(left, rows).to_string(),
+                None => String::new()
+            };
+            let right = match column_expr.right {
+                Some(ref right) => self.get_aggregate_function_value(right, rows).to_string(),
+                None => String::new()
+            };
+
+            return Value::from(calc_arithmetic(arithmetic_op, &left, &right));
+        }
+
+        if let Some(ref val) = column_expr.val {
+            return Value::from(val.clone());
+        }
+
+        if column_expr.function.is_none() {
+            if let Some(ref left) = column_expr.left {
+                return self.get_aggregate_function_value(left, rows);
+            }
+        }
+
+        let field = match column_expr.left {
+            Some(ref left) => left.to_string().to_lowercase(),
+            None => String::new()
+        };
+
+        let kind = match column_expr.left {
+            Some(ref left) => AggregateKind::from_column_expr(left),
+            None => AggregateKind::Number
+        };
+
+        let values: Vec<i64> = rows.iter()
+            .filter_map(|row| row.get(&field))
+            .filter_map(|value| match value.to_datetime() {
+                Some(dt) => Some(dt.timestamp()),
+                None => Some(value.to_string()).filter(|value| !value.is_empty()).and_then(|value| kind.parse(&value))
+            })
+            .collect();
+
+        match column_expr.function {
+            Some(Function::Min) => {
+                match values.iter().min() {
+                    Some(min) => return kind.to_value(*min),
+                    None => return Value::from(String::new())
+                }
+            },
+            Some(Function::Max) => {
+                match values.iter().max() {
+                    Some(max) => return kind.to_value(*max),
+                    None => return Value::from(String::new())
+                }
+            },
+            Some(Function::Avg) => {
+                if values.is_empty() {
+                    return Value::from(String::new());
+                }
+
+                let sum = values.iter().fold(0i64, |sum, value| sum.saturating_add(*value));
+
+                return kind.to_value(sum / values.len() as i64);
+            },
+            Some(Function::Sum) => {
+                if kind == AggregateKind::DateTime {
+                    return Value::from(String::new());
+                }
+
+                let sum = values.iter().fold(0i64, |sum, value| sum.saturating_add(*value));
+
+                return kind.to_value(sum);
+            },
+            Some(Function::Count) => {
+                if self.is_count_only() {
+                    return Value::from(self.found.to_string());
+                }
+
+                if column_expr.distinct {
+                    let distinct_values: HashSet<&Value> = rows.iter()
+                        .filter_map(|row| row.get(&field))
+                        .filter(|value| value.to_datetime().is_some() || !value.to_string().is_empty())
+                        .collect();
+
+                    return Value::from(distinct_values.len().to_string());
+                }
+
+                return Value::from(rows.len().to_string());
+            },
+            _ => {
+                match &column_expr.val {
+                    Some(val) => return Value::from(val.clone()),
+                    _ => return Value::from(String::new())
+                }
+            }
+        }
+    }
+
+    fn get_field_value(&self,
+                       entry: &DirEntry,
+                       file_info: &Option<FileInfo>,
+                       mp3_info: &Option<Rc<MP3Metadata>>,
+                       attrs: &Option<Box<Metadata>>,
+                       dimensions: Option<(usize, usize)>,
+                       field: &Field) -> String {
+        match field {
+            Field::Name => {
+                match file_info {
+                    Some(ref file_info) if self.split_archive_names => {
+                        return file_info.name.clone();
+                    },
+                    Some(ref file_info) => {
+                        return format!("[{}] {}", entry.file_name().to_string_lossy(), file_info.name);
+                    },
+                    _ => {
+                        return format!("{}", entry.file_name().to_string_lossy());
+                    }
+                }
+            },
+            Field::Extension => {
+                match file_info {
+                    Some(ref file_info) => {
+                        return get_extension(&file_info.name);
+                    },
+                    _ => {
+                        return get_extension(&entry.file_name().to_string_lossy());
+                    }
+                }
+            },
+            Field::Path => {
+                match file_info {
+                    Some(ref file_info) if self.split_archive_names => {
+                        return file_info.name.clone();
+                    },
+                    Some(ref file_info) => {
+                        return format!("[{}] {}", entry.path().to_string_lossy(), file_info.name);
+                    },
+                    _ => {
+                        return format!("{}", entry.path().to_string_lossy());
+                    }
+                }
+            },
+            Field::RelativePath => {
+                return self.get_relative_path(entry, file_info);
+            },
+            Field::Archive => {
+                if file_info.is_some() {
+                    return format!("{}", entry.path().to_string_lossy());
+                }
+            },
+            Field::Depth => {
+                return format!("{}", self.depth);
+            },
+            Field::Size => {
+                match file_info {
+                    Some(ref file_info) => {
+                        return format!("{}", file_info.size);
+                    },
+                    _ => {
+                        if let Some(ref attrs) = attrs {
+                            return format!("{}", attrs.len());
+                        }
+                    }
+                }
+            },
+            Field::FormattedSize => {
+                match file_info {
+                    Some(ref file_info) => {
+                        return format!("{}", file_info.size.file_size(file_size_opts::BINARY).unwrap());
+                    },
+                    _ => {
+                        if let Some(ref attrs) = attrs {
+                            return format!("{}", attrs.len().file_size(file_size_opts::BINARY).unwrap());
+                        }
+                    }
+                }
+            },
+            Field::DirSize => {
+                if let Some(size) = self.get_dir_size(entry, file_info, attrs) {
+                    return format!("{}", size);
+                }
+            },
+            Field::FormattedDirSize => {
+                if let Some(size) = self.get_dir_size(entry, file_info, attrs) {
+                    return format!("{}", size.file_size(file_size_opts::BINARY).unwrap());
+                }
+            },
+            Field::Entries => {
+                if let Some(entries) = self.get_entries(entry, file_info, attrs, None) {
+                    return format!("{}", entries);
+                }
+            },
+            Field::CompressedSize => {
+                if let Some(ref file_info) = file_info {
+                    if let Some(compressed_size) = file_info.compressed_size {
+                        return format!("{}", compressed_size);
+                    }
+                }
+            },
+            Field::CompressionRatio => {
+                if let Some(ref file_info) = file_info {
+                    if let Some(ratio) = file_info.get_compression_ratio() {
+                        return format!("{:.2}", ratio);
+                    }
+                }
+            },
+            Field::IsDir => {
+                match file_info {
+                    Some(ref file_info) => {
+                        return format!("{}", file_info.name.ends_with('/'));
+                    },
+                    _ => {
+                        if let Some(ref attrs) = attrs {
+                            return format!("{}", attrs.is_dir());
+                        }
+                    }
+                }
+            },
+            Field::IsEmpty => {
+                if let Some(is_empty) = self.get_is_empty(entry, file_info, attrs) {
+                    return format!("{}", is_empty);
+                }
+            },
+            Field::IsFile => {
+                match file_info {
+                    Some(ref file_info) => {
+                        return format!("{}", !file_info.name.ends_with('/'));
+                    },
+                    _ => {
+                        if let Some(ref attrs) = attrs {
+                            return format!("{}", attrs.is_file());
+                        }
+                    }
+                }
+            },
+            Field::IsSymlink => {
+                match file_info {
+                    Some(_) => {
+                        return format!("{}", false);
+                    },
+                    _ => {
+                        if let Some(ref attrs) = attrs {
+                            return format!("{}", attrs.file_type().is_symlink());
+                        }
+                    }
+                }
+            },
+            Field::IsPipe => {
+                return Self::print_file_mode(&attrs, &mode::is_pipe, &file_info, &mode::mode_is_pipe);
+            },
+            Field::IsCharacterDevice => {
+                return Self::print_file_mode(&attrs, &mode::is_char_device, &file_info, &mode::mode_is_char_device);
+            },
+            Field::IsBlockDevice => {
+                return Self::print_file_mode(&attrs, &mode::is_block_device, &file_info, &mode::mode_is_block_device);
+            },
+            Field::IsSocket => {
+                return Self::print_file_mode(&attrs, &mode::is_socket, &file_info, &mode::mode_is_socket);
+            },
+            Field::IsReadonly => {
+                return Self::print_file_mode(&attrs, &mode::is_readonly, &file_info, &mode::mode_is_readonly);
+            },
+            Field::IsSystem => {
+                return Self::print_file_mode(&attrs, &mode::is_system, &file_info, &|_| false);
+            },
+            Field::IsArchiveBit => {
+                return Self::print_file_mode(&attrs, &mode::is_archive_bit, &file_info, &|_| false);
+            },
+            Field::Mode => {
+                match file_info {
+                    Some(ref file_info) => {
+                        if let Some(mode) = file_info.mode {
+                            return format!("{}", mode::format_mode(mode));
+                        }
+                    },
+                    _ => {
+                        if let Some(ref attrs) = attrs {
+                            return format!("{}", mode::get_mode(attrs));
+                        }
+                    }
+                }
+            },
+            Field::ModeOctal => {
+                let mode = match file_info {
+                    Some(ref file_info) => file_info.mode,
+                    _ => attrs.as_ref().and_then(mode::get_mode_from_boxed_unix_int)
+                };
+
+                if let Some(mode) = mode {
+                    return mode::format_mode_octal(mode);
+                }
+            },
+            Field::Flags => {
+                if let (&None, &Some(ref attrs)) = (file_info, attrs) {
+                    if let Some(flags) = mode::get_flags(attrs) {
+                        return mode::format_flags(flags);
+                    }
+                }
+            },
+            Field::UserRead => {
+                return Self::print_file_mode(&attrs, &mode::user_read, &file_info, &mode::mode_user_read);
+            },
+            Field::UserWrite => {
+                return Self::print_file_mode(&attrs, &mode::user_write, &file_info, &mode::mode_user_write);
+            },
+            Field::UserExec => {
+                return Self::print_file_mode(&attrs, &mode::user_exec, &file_info, &mode::mode_user_exec);
+            },
+            Field::GroupRead => {
+                return Self::print_file_mode(&attrs, &mode::group_read, &file_info, &mode::mode_group_read);
+            },
+            Field::GroupWrite => {
+                return Self::print_file_mode(&attrs, &mode::group_write, &file_info, &mode::mode_group_write);
+            },
+            Field::GroupExec => {
+                return Self::print_file_mode(&attrs, &mode::group_exec, &file_info, &mode::mode_group_exec);
+            },
+            Field::OtherRead => {
+                return Self::print_file_mode(&attrs, &mode::other_read, &file_info, &mode::mode_other_read);
+            },
+            Field::OtherWrite => {
+                return Self::print_file_mode(&attrs, &mode::other_write, &file_info, &mode::mode_other_write);
+            },
+            Field::OtherExec => {
+                return Self::print_file_mode(&attrs, &mode::other_exec, &file_info, &mode::mode_other_exec);
+            },
+            Field::IsSuid => {
+                return Self::print_file_mode(&attrs, &mode::is_suid, &file_info, &mode::mode_is_suid);
+            },
+            Field::IsSgid => {
+                return Self::print_file_mode(&attrs, &mode::is_sgid, &file_info, &mode::mode_is_sgid);
+            },
+            Field::IsSticky => {
+                return Self::print_file_mode(&attrs, &mode::is_sticky, &file_info, &mode::mode_is_sticky);
+            },
+            Field::IsMine => {
+                return Self::print_file_mode(&attrs, &|meta| mode::is_mine(meta, &self.current_user), &file_info, &|_| false);
+            },
+            Field::IsReadable => {
+                return Self::print_file_mode(&attrs, &|meta| mode::is_readable(meta, &self.current_user), &file_info, &|_| false);
+            },
+            Field::IsWritable => {
+                return Self::print_file_mode(&attrs, &|meta| mode::is_writable(meta, &self.current_user), &file_info, &|_| false);
+            },
+            Field::IsExecutable => {
+                return Self::print_file_mode(&attrs, &|meta| mode::is_executable(meta, &self.current_user), &file_info, &|_| false);
+            },
+            Field::IsHidden => {
+                match file_info {
+                    Some(ref file_info) => {
+                        return format!("{}", is_hidden(&file_info.name, &None, true));
+                    },
+                    _ => {
+                        return format!("{}", is_hidden(&entry.file_name().to_string_lossy(), &attrs, false));
+                    }
+                }
+            },
+            Field::Uid => {
+                if let Some(uid) = get_file_uid(&attrs, &file_info) {
+                    return format!("{}", uid);
+                }
+            },
+            Field::Gid => {
+                if let Some(gid) = get_file_gid(&attrs, &file_info) {
+                    return format!("{}", gid);
+                }
+            },
+            Field::Inode => {
+                if let Some(ref attrs) = attrs {
+                    if let Some(inode) = mode::get_inode(attrs) {
+                        return format!("{}", inode);
+                    }
+                }
+            },
+            Field::Device => {
+                if let Some(ref attrs) = attrs {
+                    if let Some(device) = mode::get_device(attrs) {
+                        return format!("{}", device);
+                    }
+                }
+            },
+            Field::Nlinks => {
+                if let Some(ref attrs) = attrs {
+                    if let Some(nlinks) = mode::get_nlinks(attrs) {
+                        return format!("{}", nlinks);
+                    }
+                }
+            },
+            Field::User => {
+                if let Some(uid) = get_file_uid(&attrs, &file_info) {
+                    return self.get_user_name(uid);
+                }
+            },
+            Field::Group => {
+                if let Some(gid) = get_file_gid(&attrs, &file_info) {
+                    return self.get_group_name(gid);
+                }
+            },
+            Field::Created | Field::Accessed | Field::Changed | Field::Modified => {
+                if let Some(dt) = self.get_datetime_field_value(field, attrs, file_info) {
+                    let format = dt.format(DEFAULT_DATE_FORMAT);
+                    return format!("{}", format);
+                }
+            },
+            Field::CreatedUnix | Field::AccessedUnix | Field::ModifiedUnix => {
+                if let Some(dt) = self.get_file_time(field, attrs, file_info) {
+                    return dt.timestamp().to_string();
+                }
+            },
+            Field::CreatedUtc | Field::AccessedUtc | Field::ModifiedUtc => {
+                if let Some(dt) = self.get_file_time(field, attrs, file_info) {
+                    return format_utc_datetime(&dt);
+                }
+            },
+            Field::AgeDays | Field::AccessAgeDays | Field::CreateAgeDays => {
+                if let Some(days) = self.get_age_days(field, attrs, file_info) {
+                    return days.to_string();
+                }
+            },
+            Field::HasXattrs => {
+                #[cfg(unix)]
+                    {
+                        if let Ok(xattrs) = xattr::list(&entry.path()) {
+                            let has_xattrs = xattrs.count() > 0;
+                            return format!("{}", has_xattrs);
+                        }
+                    }
+
+                #[cfg(not(unix))]
+                    {
+                        return format!("{}", false);
+                    }
+            },
+            Field::Xattrs => {
+                if file_info.is_none() {
+                    return list_xattrs(&entry.path()).join(",");
+                }
+            },
+            Field::IsShebang => {
+                return format!("{}", is_regular_file(entry) && is_shebang(&entry.path()));
+            },
+            Field::Shebang => {
+                if let Some(shebang) = self.get_shebang(entry, file_info) {
+                    return shebang;
+                }
+            },
+            Field::IsBinary => {
+                if let Some(is_binary) = self.get_is_binary(entry, file_info) {
+                    return format!("{}", is_binary);
+                }
+            },
+            Field::IsText => {
+                if let Some(is_binary) = self.get_is_binary(entry, file_info) {
+                    return format!("{}", !is_binary);
+                }
+            },
+            Field::LineEndings => {
+                if let Some(line_endings) = self.get_line_endings(entry, file_info) {
+                    return String::from(line_endings);
+                }
+            },
+            Field::HasBom => {
+                if let Some(has_bom) = self.get_has_bom(entry, file_info) {
+                    return format!("{}", has_bom);
+                }
+            },
+            Field::GitStatus => {
+                if let Some(git_status) = self.get_git_status(entry, file_info) {
+                    return String::from(git_status);
+                }
+            },
+            Field::MimeType => {
+                if file_info.is_none() {
+                    if let Some(mime_type) = get_mime_type(&entry.path()) {
+                        return mime_type;
+                    }
+                }
+            },
+            Field::Md5 | Field::Sha1 | Field::Sha256 => {
+                return self.get_hash(entry, file_info, field);
+            },
+            Field::Width => {
+                if let Some(ref dimensions) = dimensions {
+                    return format!("{}", dimensions.0);
+                }
+            },
+            Field::Height => {
+                if let Some(ref dimensions) = dimensions {
+                    return format!("{}", dimensions.1);
+                }
+            },
+            Field::AspectRatio => {
+                if let Some(aspect_ratio) = dimensions.and_then(get_aspect_ratio) {
+                    return format!("{:.3}", aspect_ratio);
+                }
+            },
+            Field::Bitrate | Field::MinBitrate | Field::MaxBitrate | Field::Freq => {
+                if let Some(rates) = mp3_info.as_ref().and_then(|mp3_info| get_audio_rates(mp3_info)) {
+                    return format!("{}", get_rate(&rates, field));
+                }
+            },
+            Field::Duration => {
+                if let Some(ref mp3_info) = mp3_info {
+                    return format!("{}", mp3_info.duration.as_secs());
+                }
+            },
+            Field::FormattedDuration => {
+                if let Some(ref mp3_info) = mp3_info {
+                    return format_duration(mp3_info.duration.as_secs());
+                }
+            },
+            Field::Title => {
+                if let Some(ref mp3_info) = mp3_info {
+                    if let Some(ref mp3_tag) = mp3_info.tag {
+                        return format!("{}", mp3_tag.title);
+                    }
+                }
+            },
+            Field::Artist => {
+                if let Some(ref mp3_info) = mp3_info {
+                    if let Some(ref mp3_tag) = mp3_info.tag {
+                        return format!("{}", mp3_tag.artist);
+                    }
+                }
+            },
+            Field::Album => {
+                if let Some(ref mp3_info) = mp3_info {
+                    if let Some(ref mp3_tag) = mp3_info.tag {
+                        return format!("{}", mp3_tag.album);
+                    }
+                }
+            },
+            Field::Year => {
+                if let Some(ref mp3_info) = mp3_info {
+                    if let Some(ref mp3_tag) = mp3_info.tag {
+                        return format!("{}", mp3_tag.year);
+                    }
+                }
+            },
+            Field::Genre => {
+                if let Some(ref mp3_info) = mp3_info {
+                    if let Some(ref mp3_tag) = mp3_info.tag {
+                        return format!("{:?}", mp3_tag.genre);
+                    }
+                }
+            },
+            Field::VideoWidth => {
+                if let Some(video) = self.get_video_metadata(entry, file_info) {
+                    return video.width.to_string();
+                }
+            },
+            Field::VideoHeight => {
+                if let Some(video) = self.get_video_metadata(entry, file_info) {
+                    return video.height.to_string();
+                }
+            },
+            Field::VideoDuration => {
+                if let Some(duration) = self.get_video_metadata(entry, file_info).and_then(|video| video.duration) {
+                    return duration.to_string();
+                }
+            },
+            Field::VideoCodec => {
+                if let Some(video) = self.get_video_metadata(entry, file_info) {
+                    return video.codec;
+                }
+            },
+            Field::IsArchive => {
+                let is_archive = is_archive(&entry.file_name().to_string_lossy());
+                return format!("{}", is_archive);
+            },
+            Field::IsAudio => {
+                let is_audio = is_audio(&entry.file_name().to_string_lossy());
+                return format!("{}", is_audio);
+            },
+            Field::IsBook => {
+                let is_book = is_book(&entry.file_name().to_string_lossy());
+                return format!("{}", is_book);
+            },
+            Field::IsDoc => {
+                let is_doc = is_doc(&entry.file_name().to_string_lossy());
+                return format!("{}", is_doc);
+            },
+            Field::IsImage => {
+                let is_image = is_image(&entry.file_name().to_string_lossy());
+                return format!("{}", is_image);
+            },
+            Field::IsSource => {
+                let is_source = is_source(&entry.file_name().to_string_lossy());
+                return format!("{}", is_source);
+            },
+            Field::IsVideo => {
+                let is_video = is_video(&entry.file_name().to_string_lossy());
+                return format!("{}", is_video);
+            }
+        };
+
+        return String::new();
+    }
+
+    fn check_file(&mut self,
+                  entry: &DirEntry,
+                  file_info: &Option<FileInfo>,
+                  need_metadata: bool,
+                  need_dim: bool,
+                  need_mp3: bool,
+                  follow_symlinks: bool,
+                  on_record: &mut RecordSink) {
+        let mut meta = None;
+        let mut dim = None;
+        let mut mp3 = None;
+
+        // files of a duplicate group have been hashed already
+        *self.hash_cache.borrow_mut() = match file_info {
+            Some(_) => None,
+            None => self.dupe_hashes.remove(&entry.path())
+        };
+        *self.binary_cache.borrow_mut() = None;
+        *self.line_endings_cache.borrow_mut() = None;
+        *self.bom_cache.borrow_mut() = None;
+        *self.shebang_cache.borrow_mut() = None;
+        *self.video_cache.borrow_mut() = None;
+        *self.canonical_path_cache.borrow_mut() = None;
+        *self.empty_dir_cache.borrow_mut() = None;
+        *self.entries_cache.borrow_mut() = None;
+        self.captures.clear();
+
+        if let Some(ref mut progress) = *self.progress.borrow_mut() {
+            progress.add_file();
+        }
+
+        if let Some(ref expr) = self.query.expr.clone() {
+            let (result, entry_meta, entry_dim, entry_mp3) = self.conforms(entry, file_info, expr, None, None, None, follow_symlinks);
+            if result == Some(false) {
+                return
+            }
+
+            meta = entry_meta;
+            dim = entry_dim;
+            mp3 = entry_mp3;
+        }
+
+        if self.dedup {
+            let canonical_path = match file_info {
+                Some(ref file_info) => self.get_entry_canonical_path(entry).join(&file_info.name),
+                None => self.get_entry_canonical_path(entry)
+            };
+
+            if !self.visited_paths.insert(canonical_path) {
+                return
+            }
+        }
+
+        // duplicates are looked for among non-empty files on the disk, their rows are made in the second pass
+        if let Some(ref mut dupe_finder) = self.dupe_finder {
+            if file_info.is_none() {
+                meta = update_meta(entry, meta, follow_symlinks);
+
+                if let Some(ref metadata) = meta {
+                    if metadata.is_file() && metadata.len() > 0 {
+                        dupe_finder.add(entry.path(), metadata.len(), self.root_idx, self.depth);
+                    }
+                }
+            }
+
+            return
+        }
+
+        // the size for the summary comes from the metadata read for the conditions, if there is any,
+        // only regular files are summed up
+        let size = match (self.query.summary, file_info) {
+            (false, _) => 0,
+            (true, &Some(ref file_info)) if file_info.name.ends_with('/') => 0,
+            (true, &Some(ref file_info)) => file_info.size,
+            (true, &None) => {
+                meta = update_meta(entry, meta, follow_symlinks);
+                meta.as_ref().filter(|meta| meta.is_file()).map_or(0, |meta| meta.len())
+            }
+        };
+
+        // distinct rows are counted only after their values are known
+        if !self.is_distinct_rows() && !self.count_found(size) {
+            return;
+        }
+
+        let attrs = match need_metadata {
+            true => update_meta(entry, meta, follow_symlinks),
+            false => None
+        };
+
+        // rows which can't make it into the limited buffer are dropped before the rest of the columns is calculated,
+        // distinct and aggregated rows need all the values, so they are always built
+        let early_criteria = match self.query.limit > 0 && self.has_ordering() && !self.has_aggregate_column() && !self.is_distinct_rows() {
+            true => {
+                let ordering_fields = self.query.ordering_fields.clone();
+                let ordering_required_fields: Vec<Field> = ordering_fields.iter().flat_map(|field| field.get_required_fields()).collect();
+
+                if ordering_required_fields.iter().any(|f| f.is_img_dimension_field()) {
+                    dim = self.update_img_dimensions(entry, dim);
+                }
+
+                if ordering_required_fields.iter().any(|f| f.is_mp3_field()) {
+                    mp3 = self.update_mp3_meta(entry, mp3);
+                }
+
+                let criteria: Vec<Value> = ordering_fields.iter()
+                    .map(|field| self.get_column_typed_value(entry, file_info, &mp3, &attrs, dim, field))
+                    .collect();
+
+                if !self.output_buffer.admits(&Criteria::new(Rc::new(ordering_fields), criteria.clone(), self.query.ordering_asc.clone())) {
+                    return;
+                }
+
+                Some(criteria)
+            },
+            false => None
+        };
+
+        let dimensions = match need_dim {
+            true => self.update_img_dimensions(entry, dim),
+            false => None
+        };
+
+        let mp3_info = match need_mp3 {
+            true => self.update_mp3_meta(entry, mp3),
+            false => None
+        };
+
+        let mut file_map = HashMap::new();
+
+        for field in self.query.get_all_fields() {
+            file_map.insert(field.to_string().to_lowercase(), self.get_field_typed_value(entry, file_info, &mp3_info, &attrs, dimensions, &field));
+        }
+
+        // aggregated values are calculated once all the files are found
+        let values: Vec<Option<Value>> = self.query.fields.iter()
+            .map(|column_expr| match column_expr.has_aggregate_function() {
+                true => None,
+                false => Some(self.get_column_typed_value(entry, file_info, &mp3_info, &attrs, dimensions, column_expr))
+            })
+            .collect();
+
+        add_column_values(&mut file_map, &self.query.fields, &values);
+
+        // aggregated rows are ordered by what the first file of the group has,
+        // or by aggregates of the fields which aren't selected
+        if self.has_aggregate_column() && !self.has_summary_row() {
+            for field in &self.query.ordering_fields {
+                if field.has_aggregate_function() {
+                    for required_field in field.get_required_fields() {
+                        let key = required_field.to_string().to_lowercase();
+                        if !file_map.contains_key(&key) {
+                            file_map.insert(key, self.get_field_typed_value(entry, file_info, &mp3_info, &attrs, dimensions, &required_field));
+                        }
+                    }
+                    continue;
+                }
+
+                let key = field.to_string().to_lowercase();
+                if !file_map.contains_key(&key) {
+                    file_map.insert(key, self.get_column_typed_value(entry, file_info, &mp3_info, &attrs, dimensions, &field));
+                }
+            }
+        }
+
+        let criteria = match early_criteria {
+            Some(criteria) => criteria,
+            None => {
+                self.query.ordering_fields.iter().map(|field| {
+                    match file_map.get(&field.to_string().to_lowercase()) {
+                        Some(value) => value.clone(),
+                        None => self.get_column_typed_value(entry, file_info, &mp3_info, &attrs, dimensions, &field)
+                    }
+                }).collect()
+            }
+        };
+
+        self.add_file_row(file_map, values, criteria, size, on_record);
+    }
+
+    /// Builds the record of a found file from the values of its columns, in the order they are selected,
+    /// then hands it over or keeps it for ordering and aggregation. `file_map` keeps the values by the names
+    /// of the fields and the columns for the aggregation and the `cmd` templates.
+    fn add_file_row(&mut self,
+                    file_map: HashMap<String, Value>,
+                    values: Vec<Option<Value>>,
+                    criteria: Vec<Value>,
+                    size: u64,
+                    on_record: &mut RecordSink) {
+        if self.is_distinct_rows() {
+            if !self.distinct_rows.insert(values.clone()) || !self.count_found(size) {
+                return;
+            }
+        }
+
+        if self.has_aggregate_column() && !self.has_summary_row() {
+            self.raw_output_buffer.push(file_map);
+            return;
+        }
+
+        let record = self.to_record(&values, &file_map);
+
+        if self.has_summary_row() {
+            self.raw_output_buffer.push(file_map);
+            self.buffer_row(criteria, record);
+        } else if self.is_buffered() {
+            self.buffer_row(criteria, record);
+        } else {
+            self.emit(record, on_record);
+            self.shown += 1;
+        }
+    }
+
+    /// Path of the entry relative to the root it was found under,
+    /// archive members are appended to the relative path of their archive.
+    fn get_relative_path(&self, entry: &DirEntry, file_info: &Option<FileInfo>) -> String {
+        let path = entry.path();
+        let relative_path = path.strip_prefix(&self.root_dir).unwrap_or(&path);
+
+        match file_info {
+            Some(ref file_info) => relative_path.join(&file_info.name).to_string_lossy().to_string(),
+            _ => relative_path.to_string_lossy().to_string()
+        }
+    }
+
+    fn print_file_mode(attrs: &Option<Box<Metadata>>,
+                       mode_func_boxed: &Fn(&Box<Metadata>) -> bool,
+                       file_info: &Option<FileInfo>,
+                       mode_func_i32: &Fn(u32) -> bool) -> String {
+        match file_info {
+            Some(ref file_info) => {
+                if let Some(mode) = file_info.mode {
+                    return format!("{}", mode_func_i32(mode));
+                }
+            },
+            _ => {
+                if let Some(ref attrs) = attrs {
+                    return format!("{}", mode_func_boxed(attrs));
+                }
+            }
+        }
+
+        String::new()
+    }
+
+    fn conforms(&mut self,
+                entry: &DirEntry,
+                file_info: &Option<FileInfo>,
+                expr: &Box<Expr>,
+                entry_meta: Option<Box<fs::Metadata>>,
+                entry_dim: Option<(usize, usize)>,
+                entry_mp3: Option<Rc<MP3Metadata>>,
+                follow_symlinks: bool) -> (Option<bool>, Option<Box<fs::Metadata>>, Option<(usize, usize)>, Option<Rc<MP3Metadata>>) {
+        // groups are captured only by the conditions that make the file match
+        let captures = self.captures.clone();
+        let (result, meta, dim, mp3) = self.conforms_expr(entry, file_info, expr, entry_meta, entry_dim, entry_mp3, follow_symlinks);
+
+        let result = match expr.negate {
+            true => result.map(|result| !result),
+            false => result
+        };
+
+        if result != Some(true) {
+            self.captures = captures;
+        }
+
+        (result, meta, dim, mp3)
+    }
+
+    fn conforms_expr(&mut self,
+                     entry: &DirEntry,
+                     file_info: &Option<FileInfo>,
+                     expr: &Box<Expr>,
+                     entry_meta: Option<Box<fs::Metadata>>,
+                     entry_dim: Option<(usize, usize)>,
+                     entry_mp3: Option<Rc<MP3Metadata>>,
+                     follow_symlinks: bool) -> (Option<bool>, Option<Box<fs::Metadata>>, Option<(usize, usize)>, Option<Rc<MP3Metadata>>) {
+        let mut result = false;
+        let mut meta = entry_meta;
+        let mut dim = entry_dim;
+        let mut mp3 = entry_mp3;
+
+        if let Some(ref logical_op) = expr.logical_op {
+            // Both operands are free of side effects, so content searches can be postponed
+            // until cheaper conditions have been checked
+            let (first, second) = match (&expr.left, &expr.right) {
+                (&Some(ref left), &Some(ref right)) if left.has_content_search() && !right.has_content_search() => (&expr.right, &expr.left),
+                _ => (&expr.left, &expr.right)
+            };
+
+            let mut first_result = None;
+            let mut second_result = None;
+
+            if let Some(ref first) = first {
+                let (first_res, first_meta, first_dim, first_mp3) = self.conforms(entry, file_info, &first, meta, dim, mp3, follow_symlinks);
+                first_result = first_res;
+                meta = first_meta;
+                dim = first_dim;
+                mp3 = first_mp3;
+            }
+
+            // An unknown operand is neutral: it neither fails an AND nor satisfies an OR
+            let short_circuit = match logical_op {
+                LogicalOp::And => first_result == Some(false),
+                LogicalOp::Or => first_result == Some(true)
+            };
+
+            if !short_circuit {
+                if let Some(ref second) = second {
+                    let (second_res, second_meta, second_dim, second_mp3) = self.conforms(entry, file_info, &second, meta, dim, mp3, follow_symlinks);
+                    second_result = second_res;
+                    meta = second_meta;
+                    dim = second_dim;
+                    mp3 = second_mp3;
+                }
+            }
+
+            let result = match (first_result, second_result) {
+                (Some(first_result), Some(second_result)) => {
+                    match logical_op {
+                        LogicalOp::And => Some(first_result && second_result),
+                        LogicalOp::Or => Some(first_result || second_result)
+                    }
+                },
+                (Some(result), None) | (None, Some(result)) => Some(result),
+                (None, None) => None
+            };
+
+            return (result, meta, dim, mp3)
+        }
+
+        if let (&Some(ref column_expr), &Some(ref val_expr)) = (&expr.field, &expr.val_expr) {
+            let mut fields = column_expr.get_required_fields();
+            fields.extend(val_expr.get_required_fields());
+
+            if fields.iter().any(|f| f.needs_metadata()) {
+                meta = update_meta(entry, meta, follow_symlinks);
+            }
+            if fields.iter().any(|f| f.is_img_dimension_field()) {
+                dim = self.update_img_dimensions(entry, dim);
+            }
+            if fields.iter().any(|f| f.is_mp3_field()) {
+                mp3 = self.update_mp3_meta(entry, mp3);
+            }
+
+            let value = self.get_column_expr_value(entry, file_info, &mp3, &meta, dim, column_expr);
+            let val = self.get_column_expr_value(entry, file_info, &mp3, &meta, dim, val_expr);
+            result = compare_column_values(&expr.op, &value, &val, column_expr.is_datetime());
+
+            return (Some(result), meta, dim, mp3)
+        }
+
+        if let Some(ref column_expr) = expr.field {
+            if let Some(Function::Xattr) = column_expr.function {
+                if file_info.is_some() {
+                    return (self.unavailable_result(), meta, dim, mp3)
+                }
+
+                if let Some(ref val) = expr.val {
+                    let value = self.get_function_value(entry, file_info, &mp3, &meta, dim, column_expr);
+                    result = compare_str(&expr.op, val, &expr.regex, &value);
+                }
+            } else if let Some(Function::SameFileAs) = column_expr.function {
+                // files inside archives are never the same as the one on the disk
+                if file_info.is_none() {
+                    meta = update_meta(entry, meta, follow_symlinks);
+
+                    if let Some(ref metadata) = meta {
+                        result = self.is_same_file_as_marker(expr, metadata);
+                    }
+                }
+            } else if column_expr.function.as_ref().map_or(false, |function| function.is_marker_function()) {
+                let modified = match file_info {
+                    Some(ref file_info) => Some(to_local_datetime(&file_info.modified)),
+                    None => {
+                        meta = update_meta(entry, meta, follow_symlinks);
+                        meta.as_ref().and_then(|metadata| metadata.modified().ok()).map(DateTime::from)
+                    }
+                };
+
+                if let Some(modified) = modified {
+                    result = self.compare_with_marker(column_expr, expr, modified);
+                }
+            } else if let Some(ref function) = column_expr.function {
+                if let Some(ref val) = expr.val {
+                    let max_size = match column_expr.right {
+                        Some(ref right) => parse_filesize(&right.to_string()).unwrap_or(DEFAULT_CONTENT_SEARCH_LIMIT),
+                        None => DEFAULT_CONTENT_SEARCH_LIMIT
+                    };
+
+                    let content = match *file_info {
+                        // members of tar archives can't be read again
+                        Some(ref file_info) if file_info.zip_index.is_none() => return (self.unavailable_result(), meta, dim, mp3),
+                        Some(ref file_info) => self.read_zip_member(entry, file_info, max_size),
+                        None => {
+                            meta = update_meta(entry, meta, follow_symlinks);
+
+                            let searchable = match meta {
+                                Some(ref metadata) => metadata.is_file() && metadata.len() <= max_size,
+                                _ => false
+                            };
+
+                            match searchable {
+                                true => read_file_bytes(&entry.path()),
+                                false => None
+                            }
+                        }
+                    };
+
+                    if let Some(content) = content {
+                        result = match function {
+                            Function::Contains => contains_bytes(&content, val.as_bytes()),
+                            Function::Matches => {
+                                match expr.regex {
+                                    Some(ref regex) => regex.is_match(&String::from_utf8_lossy(&content)),
+                                    None => false
+                                }
+                            },
+                            _ => false
+                        };
+                    }
+                }
+            }
+
+            if column_expr.arithmetic_op.is_some() {
+                let fields = column_expr.get_required_fields();
+                if fields.iter().any(|f| f.needs_metadata()) {
+                    meta = update_meta(entry, meta, follow_symlinks);
+                }
+                if fields.iter().any(|f| f.is_img_dimension_field()) {
+                    dim = self.update_img_dimensions(entry, dim);
+                }
+                if fields.iter().any(|f| f.is_mp3_field()) {
+                    mp3 = self.update_mp3_meta(entry, mp3);
+                }
+
+                let value = self.get_column_expr_value(entry, file_info, &mp3, &meta, dim, column_expr);
+
+                if let (Some(value), Some(ref val)) = (parse_float(&value), &expr.val) {
+                    if let Some(val) = parse_float(val) {
+                        result = compare_numbers(&expr.op, value, val);
+                    }
+                }
+            }
+
+            let field = match column_expr.field {
+                Some(ref field) => field.clone(),
+                None => return (Some(result), meta, dim, mp3)
+            };
+
+            match field {
+                Field::Name => {
+                    if let Some(ref val) = expr.val {
+                        let file_name = match file_info {
+                            Some(ref file_info) => file_info.name.clone(),
+                            _ => entry.file_name().to_string_lossy().to_string()
+                        };
+
+                        result = match expr.op {
+                            Some(Op::Eq) => {
+                                match expr.regex {
+                                    Some(ref regex) => regex.is_match(&file_name),
+                                    None => val.eq(&file_name)
+                                }
+                            },
+                            Some(Op::Ne) | Some(Op::Nei) => {
+                                match expr.regex {
+                                    Some(ref regex) => !regex.is_match(&file_name),
+                                    None => val.ne(&file_name)
+                                }
+                            },
+                            Some(Op::Rx) | Some(Op::Like) | Some(Op::Glob) | Some(Op::Eqi) => {
+                                match expr.regex {
+                                    Some(ref regex) => regex.is_match(&file_name),
+                                    None => false
+                                }
+                            },
+                            Some(Op::Eeq) => {
+                                val.eq(&file_name)
+                            },
+                            Some(Op::Ene) => {
+                                val.ne(&file_name)
+                            },
+                            _ => false
+                        };
+
+                        if let (true, &Some(ref regex)) = (result, &expr.regex) {
+                            self.save_captures(regex, &file_name);
+                        }
+                    }
+                },
+                Field::Extension => {
+                    if let Some(ref val) = expr.val {
+                        let file_ext = match file_info {
+                            Some(ref file_info) => get_extension(&file_info.name),
+                            _ => get_extension(&entry.file_name().to_string_lossy())
+                        };
+
+                        result = match expr.op {
+                            Some(Op::Eq) => {
+                                match expr.regex {
+                                    Some(ref regex) => regex.is_match(&file_ext),
+                                    None => val.eq(&file_ext)
+                                }
+                            },
+                            Some(Op::Ne) | Some(Op::Nei) => {
+                                match expr.regex {
+                                    Some(ref regex) => !regex.is_match(&file_ext),
+                                    None => val.ne(&file_ext)
+                                }
+                            },
+                            Some(Op::Rx) | Some(Op::Like) | Some(Op::Glob) | Some(Op::Eqi) => {
+                                match expr.regex {
+                                    Some(ref regex) => regex.is_match(&file_ext),
+                                    None => false
+                                }
+                            },
+                            Some(Op::Eeq) => {
+                                val.eq(&file_ext)
+                            },
+                            Some(Op::Ene) => {
+                                val.ne(&file_ext)
+                            },
+                            _ => false
+                        };
+                    }
+                },
+                Field::Path => {
+                    if let Some(ref val) = expr.val {
+                        let file_path = match file_info {
+                            Some(ref file_info) => file_info.name.clone(),
+                            _ => String::from(entry.path().to_string_lossy())
+                        };
+
+                        result = compare_str(&expr.op, val, &expr.regex, &file_path);
+
+                        if let (true, &Some(ref regex)) = (result, &expr.regex) {
+                            self.save_captures(regex, &file_path);
+                        }
+                    }
+                },
+                Field::Archive => {
+                    if let Some(ref val) = expr.val {
+                        let archive = match file_info {
+                            Some(_) => entry.path().to_string_lossy().to_string(),
+                            None => String::new()
+                        };
+
+                        result = compare_str(&expr.op, val, &expr.regex, &archive);
+                    }
+                },
+                Field::RelativePath => {
+                    if let Some(ref val) = expr.val {
+                        let file_path = self.get_relative_path(entry, file_info);
+
+                        result = compare_str(&expr.op, val, &expr.regex, &file_path);
+
+                        if let (true, &Some(ref regex)) = (result, &expr.regex) {
+                            self.save_captures(regex, &file_path);
+                        }
+                    }
+                },
+                Field::Depth => {
+                    if let Some(ref val) = expr.val {
+                        if let Some(depth) = parse_float(val) {
+                            result = compare_numbers(&expr.op, self.depth as f64, depth);
+                        }
+                    }
+                },
+                Field::Size | Field::FormattedSize => {
+                    if let Some(ref val) = expr.val {
+                        let file_size = match file_info {
+                            Some(ref file_info) => {
+                                Some(file_info.size)
+                            },
+                            _ => {
+                                meta = update_meta(entry, meta, follow_symlinks);
+                                match meta {
+                                    Some(ref metadata) => {
+                                        Some(metadata.len())
+                                    },
+                                    _ => None
+                                }
+                            }
+                        };
+
+                        if let (Some(file_size), Some(size)) = (file_size, parse_float(val)) {
+                            result = compare_numbers(&expr.op, file_size as f64, size);
+                        }
+                    }
+                },
+                Field::DirSize | Field::FormattedDirSize => {
+                    if let Some(ref val) = expr.val {
+                        if file_info.is_none() {
+                            meta = update_meta(entry, meta, follow_symlinks);
+                        }
+
+                        if let (Some(dir_size), Some(size)) = (self.get_dir_size(entry, file_info, &meta), parse_float(val)) {
+                            result = compare_numbers(&expr.op, dir_size as f64, size);
+                        }
+                    }
+                },
+                Field::Entries => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        meta = update_meta(entry, meta, follow_symlinks);
+
+                        // once there are more entries than the number the comparison can't change
+                        if let Some(val) = parse_float(val).filter(|&val| val >= 0.0) {
+                            if let Some(entries) = self.get_entries(entry, file_info, &meta, Some((val.floor() as u64).saturating_add(1))) {
+                                result = compare_numbers(&expr.op, entries as f64, val);
+                            }
+                        }
+                    }
+                },
+                Field::CompressedSize => {
+                    if let Some(ref val) = expr.val {
+                        let compressed_size = file_info.as_ref().and_then(|file_info| file_info.compressed_size);
+
+                        if let (Some(compressed_size), Some(size)) = (compressed_size, parse_float(val)) {
+                            result = compare_numbers(&expr.op, compressed_size as f64, size);
+                        }
+                    }
+                },
+                Field::CompressionRatio => {
+                    if let Some(ref val) = expr.val {
+                        let ratio = file_info.as_ref().and_then(|file_info| file_info.get_compression_ratio());
+
+                        if let (Some(ratio), Some(val)) = (ratio, parse_float(val)) {
+                            result = compare_numbers(&expr.op, ratio, val);
+                        }
+                    }
+                },
+                Field::Uid => {
+                    if file_info.is_some() && get_file_uid(&None, file_info).is_none() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        if file_info.is_none() {
+                            meta = update_meta(entry, meta, follow_symlinks);
+                        }
+
+                        if let (Some(uid), Some(file_uid)) = (parse_float(val), get_file_uid(&meta, file_info)) {
+                            result = compare_numbers(&expr.op, file_uid as f64, uid);
+                        }
+                    }
+                },
+                Field::User => {
+                    if file_info.is_some() && get_file_uid(&None, file_info).is_none() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        if file_info.is_none() {
+                            meta = update_meta(entry, meta, follow_symlinks);
+                        }
+
+                        if let Some(file_uid) = get_file_uid(&meta, file_info) {
+                            result = compare_str(&expr.op, val, &expr.regex, &self.get_user_name(file_uid));
+                        }
+                    }
+                },
+                Field::Gid => {
+                    if file_info.is_some() && get_file_gid(&None, file_info).is_none() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        if file_info.is_none() {
+                            meta = update_meta(entry, meta, follow_symlinks);
+                        }
+
+                        if let (Some(gid), Some(file_gid)) = (parse_float(val), get_file_gid(&meta, file_info)) {
+                            result = compare_numbers(&expr.op, file_gid as f64, gid);
+                        }
+                    }
+                },
+                Field::Inode => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        meta = update_meta(entry, meta, follow_symlinks);
+
+                        if let Some(ref metadata) = meta {
+                            if let (Some(inode), Some(file_inode)) = (parse_float(val), mode::get_inode(metadata)) {
+                                result = compare_numbers(&expr.op, file_inode as f64, inode);
+                            }
+                        }
+                    }
+                },
+                Field::Device => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        meta = update_meta(entry, meta, follow_symlinks);
+
+                        if let Some(ref metadata) = meta {
+                            if let (Some(device), Some(file_device)) = (parse_float(val), mode::get_device(metadata)) {
+                                result = compare_numbers(&expr.op, file_device as f64, device);
+                            }
+                        }
+                    }
+                },
+                Field::Nlinks => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        meta = update_meta(entry, meta, follow_symlinks);
+
+                        if let Some(ref metadata) = meta {
+                            if let (Some(nlinks), Some(file_nlinks)) = (parse_float(val), mode::get_nlinks(metadata)) {
+                                result = compare_numbers(&expr.op, file_nlinks as f64, nlinks);
+                            }
+                        }
+                    }
+                },
+                Field::Group => {
+                    if file_info.is_some() && get_file_gid(&None, file_info).is_none() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        if file_info.is_none() {
+                            meta = update_meta(entry, meta, follow_symlinks);
+                        }
+
+                        if let Some(file_gid) = get_file_gid(&meta, file_info) {
+                            result = compare_str(&expr.op, val, &expr.regex, &self.get_group_name(file_gid));
+                        }
+                    }
+                },
+                Field::IsDir => {
+                    if let Some(ref val) = expr.val {
+                        let is_dir = match file_info {
+                            Some(ref file_info) => Some(file_info.name.ends_with('/')),
+                            _ => {
+                                meta = update_meta(entry, meta, follow_symlinks);
+
+                                match meta {
+                                    Some(ref metadata) => {
+                                        Some(metadata.is_dir())
+                                    },
+                                    _ => None
+                                }
+                            }
+                        };
+
+                        if let Some(is_dir) = is_dir {
+                            let bool_val = str_to_bool(val);
+
+                            result = match expr.op {
+                                Some(Op::Eq) | Some(Op::Eeq) => {
+                                    if bool_val {
+                                        is_dir
+                                    } else {
+                                        !is_dir
+                                    }
+                                },
+                                Some(Op::Ne) | Some(Op::Ene) => {
+                                    if bool_val {
+                                        !is_dir
+                                    } else {
+                                        is_dir
+                                    }
+                                },
+                                _ => false
+                            };
+                        }
+                    }
+                },
+                Field::IsEmpty => {
+                    if let Some(ref val) = expr.val {
+                        if file_info.is_none() {
+                            meta = update_meta(entry, meta, follow_symlinks);
+                        }
+
+                        if let Some(is_empty) = self.get_is_empty(entry, file_info, &meta) {
+                            let bool_val = str_to_bool(val);
+
+                            result = match expr.op {
+                                Some(Op::Eq) | Some(Op::Eeq) => is_empty == bool_val,
+                                Some(Op::Ne) | Some(Op::Ene) => is_empty != bool_val,
+                                _ => false
+                            };
+                        }
+                    }
+                },
+                Field::IsFile => {
+                    if let Some(ref val) = expr.val {
+                        let is_file = match file_info {
+                            Some(ref file_info) => Some(!file_info.name.ends_with('/')),
+                            _ => {
+                                meta = update_meta(entry, meta, follow_symlinks);
+
+                                match meta {
+                                    Some(ref metadata) => {
+                                        Some(metadata.is_file())
+                                    },
+                                    _ => None
+                                }
+                            }
+                        };
 
                         if let Some(is_file) = is_file {
                             let bool_val = str_to_bool(val);
 
-                            result = match expr.op {
-                                Some(Op::Eq) | Some(Op::Eeq) => {
-                                    if bool_val {
-                                        is_file
-                                    } else {
-                                        !is_file
-                                    }
-                                },
-                                Some(Op::Ne) | Some(Op::Ene) => {
-                                    if bool_val {
-                                        !is_file
-                                    } else {
-                                        is_file
-                                    }
-                                },
-                                _ => false
-                            };
-                        }
-                    }
-                },
-                Field::IsSymlink => {
-                    if let Some(ref val) = expr.val {
-                        let is_symlink = match file_info {
-                            Some(_) => Some(false),
-                            _ => {
-                                meta = update_meta(entry, meta, follow_symlinks);
+                            result = match expr.op {
+                                Some(Op::Eq) | Some(Op::Eeq) => {
+                                    if bool_val {
+                                        is_file
+                                    } else {
+                                        !is_file
+                                    }
+                                },
+                                Some(Op::Ne) | Some(Op::Ene) => {
+                                    if bool_val {
+                                        !is_file
+                                    } else {
+                                        is_file
+                                    }
+                                },
+                                _ => false
+                            };
+                        }
+                    }
+                },
+                Field::IsSymlink => {
+                    if let Some(ref val) = expr.val {
+                        let is_symlink = match file_info {
+                            Some(_) => Some(false),
+                            _ => {
+                                meta = update_meta(entry, meta, follow_symlinks);
+
+                                match meta {
+                                    Some(ref metadata) => {
+                                        Some(metadata.file_type().is_symlink())
+                                    },
+                                    _ => None
+                                }
+                            }
+                        };
+
+                        if let Some(is_symlink) = is_symlink {
+                            let bool_val = str_to_bool(val);
+
+                            result = match expr.op {
+                                Some(Op::Eq) | Some(Op::Eeq) => {
+                                    if bool_val {
+                                        is_symlink
+                                    } else {
+                                        !is_symlink
+                                    }
+                                },
+                                Some(Op::Ne) | Some(Op::Ene) => {
+                                    if bool_val {
+                                        !is_symlink
+                                    } else {
+                                        is_symlink
+                                    }
+                                },
+                                _ => false
+                            };
+                        }
+                    }
+                },
+                Field::IsPipe => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_is_pipe);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsCharacterDevice => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_is_char_device);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsBlockDevice => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_is_block_device);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsSocket => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_is_socket);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsReadonly => {
+                    let (res_, meta_) = confirm_file_attribute(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::is_readonly, &mode::mode_is_readonly);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsSystem => {
+                    let (res_, meta_) = confirm_file_attribute(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::is_system, &|_| false);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsArchiveBit => {
+                    let (res_, meta_) = confirm_file_attribute(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::is_archive_bit, &|_| false);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::Mode | Field::ModeOctal => {
+                    if let Some(ref val) = expr.val {
+                        let bits = match file_info {
+                            Some(ref file_info) => file_info.mode,
+                            _ => {
+                                meta = update_meta(entry, meta, follow_symlinks);
+                                meta.as_ref().and_then(mode::get_mode_from_boxed_unix_int)
+                            }
+                        };
+
+                        let formatted = match (&field, file_info, &meta) {
+                            (&Field::ModeOctal, _, _) => bits.map(mode::format_mode_octal),
+                            (_, &Some(_), _) => bits.map(mode::format_mode),
+                            (_, &None, &Some(ref metadata)) => Some(mode::get_mode(metadata)),
+                            _ => None
+                        };
+
+                        if let Some(formatted) = formatted {
+                            result = compare_mode(&expr.op, val, &expr.regex, bits, &formatted);
+                        }
+                    }
+                },
+                Field::Flags => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    meta = update_meta(entry, meta, follow_symlinks);
+
+                    if let Some(ref val) = expr.val {
+                        let flags = match meta {
+                            Some(ref metadata) => mode::get_flags(metadata).map(mode::format_flags).unwrap_or_default(),
+                            None => String::new()
+                        };
+
+                        result = compare_str(&expr.op, val, &expr.regex, &flags);
+                    }
+                },
+                Field::UserRead => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_user_read);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::UserWrite => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_user_write);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::UserExec => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_user_exec);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::GroupRead => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_group_read);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::GroupWrite => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_group_write);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::GroupExec => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_group_exec);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::OtherRead => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_other_read);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::OtherWrite => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_other_write);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::OtherExec => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_other_exec);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsSuid => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_is_suid);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsSgid => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_is_sgid);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsSticky => {
+                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_is_sticky);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsMine => {
+                    let (res_, meta_) = confirm_file_attribute(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &|meta| mode::is_mine(meta, &self.current_user), &|_| false);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsReadable => {
+                    let (res_, meta_) = confirm_file_attribute(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &|meta| mode::is_readable(meta, &self.current_user), &|_| false);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsWritable => {
+                    let (res_, meta_) = confirm_file_attribute(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &|meta| mode::is_writable(meta, &self.current_user), &|_| false);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsExecutable => {
+                    let (res_, meta_) = confirm_file_attribute(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &|meta| mode::is_executable(meta, &self.current_user), &|_| false);
+                    meta = meta_;
+                    result = res_;
+                },
+                Field::IsHidden => {
+                    if let Some(ref val) = expr.val {
+                        let is_hidden = match file_info {
+                            Some(ref file_info) => is_hidden(&file_info.name, &None, true),
+                            _ => is_hidden(&entry.file_name().to_string_lossy(), &meta, false)
+                        };
+
+                        let bool_val = str_to_bool(val);
+
+                        result = match expr.op {
+                            Some(Op::Eq) | Some(Op::Eeq) => {
+                                if bool_val {
+                                    is_hidden
+                                } else {
+                                    !is_hidden
+                                }
+                            },
+                            Some(Op::Ne) | Some(Op::Ene) => {
+                                if bool_val {
+                                    !is_hidden
+                                } else {
+                                    is_hidden
+                                }
+                            },
+                            _ => false
+                        };
+                    }
+                },
+                Field::Created => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref _val) = expr.val {
+                        meta = update_meta(entry, meta, follow_symlinks);
+
+                        if let Some(ref metadata) = meta {
+                            if let Some(dt) = self.get_created_time(metadata) {
+                                let start = expr.dt_from.unwrap();
+                                let finish = expr.dt_to.unwrap();
+
+                                result = match expr.op {
+                                    Some(Op::Eeq) => dt == start,
+                                    Some(Op::Ene) => dt != start,
+                                    Some(Op::Eq) => dt >= start && dt <= finish,
+                                    Some(Op::Ne) => dt < start || dt > finish,
+                                    Some(Op::Gt) => dt > finish,
+                                    Some(Op::Gte) => dt >= start,
+                                    Some(Op::Lt) => dt < start,
+                                    Some(Op::Lte) => dt <= finish,
+                                    _ => false
+                                };
+                            }
+                        }
+                    }
+                },
+                Field::Accessed => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref _val) = expr.val {
+                        meta = update_meta(entry, meta, follow_symlinks);
+
+                        if let Some(ref metadata) = meta {
+                            if let Ok(sdt) = metadata.accessed() {
+                                let dt: DateTime<Local> = DateTime::from(sdt);
+                                let start = expr.dt_from.unwrap();
+                                let finish = expr.dt_to.unwrap();
+
+                                result = match expr.op {
+                                    Some(Op::Eeq) => dt == start,
+                                    Some(Op::Ene) => dt != start,
+                                    Some(Op::Eq) => dt >= start && dt <= finish,
+                                    Some(Op::Ne) => dt < start || dt > finish,
+                                    Some(Op::Gt) => dt > finish,
+                                    Some(Op::Gte) => dt >= start,
+                                    Some(Op::Lt) => dt < start,
+                                    Some(Op::Lte) => dt <= finish,
+                                    _ => false
+                                };
+                            }
+                        }
+                    }
+                },
+                Field::Changed => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref _val) = expr.val {
+                        meta = update_meta(entry, meta, follow_symlinks);
+
+                        if let Some(ref metadata) = meta {
+                            if let Some(sdt) = mode::get_ctime(metadata) {
+                                let dt: DateTime<Local> = DateTime::from(sdt);
+                                let start = expr.dt_from.unwrap();
+                                let finish = expr.dt_to.unwrap();
+
+                                result = match expr.op {
+                                    Some(Op::Eeq) => dt == start,
+                                    Some(Op::Ene) => dt != start,
+                                    Some(Op::Eq) => dt >= start && dt <= finish,
+                                    Some(Op::Ne) => dt < start || dt > finish,
+                                    Some(Op::Gt) => dt > finish,
+                                    Some(Op::Gte) => dt >= start,
+                                    Some(Op::Lt) => dt < start,
+                                    Some(Op::Lte) => dt <= finish,
+                                    _ => false
+                                };
+                            }
+                        }
+                    }
+                },
+                Field::Modified => {
+                    if let Some(ref _val) = expr.val {
+                        let dt = match file_info {
+                            Some(ref file_info) => Some(to_local_datetime(&file_info.modified)),
+                            _ => {
+                                meta = update_meta(entry, meta, follow_symlinks);
+                                match meta {
+                                    Some(ref metadata) => {
+                                        match metadata.modified() {
+                                            Ok(sdt) => Some(DateTime::from(sdt)),
+                                            _ => None
+                                        }
+                                    },
+                                    _ => None
+                                }
+                            }
+                        };
+
+                        if let Some(dt) = dt {
+                            let start = expr.dt_from.unwrap();
+                            let finish = expr.dt_to.unwrap();
+
+                            result = match expr.op {
+                                Some(Op::Eeq) => dt == start,
+                                Some(Op::Ene) => dt != start,
+                                Some(Op::Eq) => dt >= start && dt <= finish,
+                                Some(Op::Ne) => dt < start || dt > finish,
+                                Some(Op::Gt) => dt > finish,
+                                Some(Op::Gte) => dt >= start,
+                                Some(Op::Lt) => dt < start,
+                                Some(Op::Lte) => dt <= finish,
+                                _ => false
+                            };
+                        }
+                    }
+                },
+                Field::CreatedUnix | Field::AccessedUnix | Field::ModifiedUnix => {
+                    if file_info.is_some() && field != Field::ModifiedUnix {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        if file_info.is_none() {
+                            meta = update_meta(entry, meta, follow_symlinks);
+                        }
+
+                        if let (Some(val), Some(dt)) = (parse_float(val), self.get_file_time(&field, &meta, file_info)) {
+                            result = compare_numbers(&expr.op, dt.timestamp() as f64, val);
+                        }
+                    }
+                },
+                Field::CreatedUtc | Field::AccessedUtc | Field::ModifiedUtc => {
+                    if file_info.is_some() && field != Field::ModifiedUtc {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        if file_info.is_none() {
+                            meta = update_meta(entry, meta, follow_symlinks);
+                        }
+
+                        if let Some(dt) = self.get_file_time(&field, &meta, file_info) {
+                            result = compare_str(&expr.op, val, &expr.regex, &format_utc_datetime(&dt));
+                        }
+                    }
+                },
+                Field::AgeDays | Field::AccessAgeDays | Field::CreateAgeDays => {
+                    if file_info.is_some() && field != Field::AgeDays {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        if file_info.is_none() {
+                            meta = update_meta(entry, meta, follow_symlinks);
+                        }
+
+                        if let (Some(val), Some(days)) = (parse_float(val), self.get_age_days(&field, &meta, file_info)) {
+                            result = compare_numbers(&expr.op, days as f64, val);
+                        }
+                    }
+                },
+                Field::HasXattrs => {
+                    #[cfg(unix)]
+                        {
+                            if file_info.is_some() {
+                                return (self.unavailable_result(), meta, dim, mp3)
+                            }
+
+                            if let Some(ref val) = expr.val {
+                                if let Ok(xattrs) = xattr::list(&entry.path()) {
+                                    let has_xattrs = xattrs.count() > 0;
+                                    let bool_val = str_to_bool(val);
+
+                                    result = match &expr.op {
+                                        Some(Op::Eq) | Some(Op::Eeq) => {
+                                            if bool_val {
+                                                has_xattrs
+                                            } else {
+                                                !has_xattrs
+                                            }
+                                        },
+                                        Some(Op::Ne) | Some(Op::Ene) => {
+                                            if bool_val {
+                                                !has_xattrs
+                                            } else {
+                                                has_xattrs
+                                            }
+                                        },
+                                        _ => false
+                                    };
+                                }
+                            }
+                        }
+                },
+                Field::Xattrs => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        result = compare_str(&expr.op, val, &expr.regex, &list_xattrs(&entry.path()).join(","));
+                    }
+                },
+                Field::IsShebang => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    result = is_regular_file(entry) && is_shebang(&entry.path())
+                },
+                Field::Shebang => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let (Some(ref val), Some(shebang)) = (&expr.val, self.get_shebang(entry, file_info)) {
+                        result = compare_str(&expr.op, val, &expr.regex, &shebang);
+                    }
+                },
+                Field::IsBinary | Field::IsText => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let (Some(ref val), Some(is_binary)) = (&expr.val, self.get_is_binary(entry, file_info)) {
+                        let field_value = match field {
+                            Field::IsBinary => is_binary,
+                            _ => !is_binary
+                        };
+                        let bool_val = str_to_bool(val);
+
+                        result = match expr.op {
+                            Some(Op::Eq) | Some(Op::Eeq) => {
+                                if bool_val {
+                                    field_value
+                                } else {
+                                    !field_value
+                                }
+                            },
+                            Some(Op::Ne) | Some(Op::Ene) => {
+                                if bool_val {
+                                    !field_value
+                                } else {
+                                    field_value
+                                }
+                            },
+                            _ => false
+                        };
+                    }
+                },
+                Field::LineEndings => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let (Some(ref val), Some(line_endings)) = (&expr.val, self.get_line_endings(entry, file_info)) {
+                        result = compare_str(&expr.op, val, &expr.regex, line_endings);
+                    }
+                },
+                Field::HasBom => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let (Some(ref val), Some(has_bom)) = (&expr.val, self.get_has_bom(entry, file_info)) {
+                        let bool_val = str_to_bool(val);
+
+                        result = match expr.op {
+                            Some(Op::Eq) | Some(Op::Eeq) => has_bom == bool_val,
+                            Some(Op::Ne) | Some(Op::Ene) => has_bom != bool_val,
+                            _ => false
+                        };
+                    }
+                },
+                Field::GitStatus => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let (Some(ref val), Some(git_status)) = (&expr.val, self.get_git_status(entry, file_info)) {
+                        result = compare_str(&expr.op, val, &expr.regex, git_status);
+                    }
+                },
+                Field::MimeType => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        if let Some(mime_type) = get_mime_type(&entry.path()) {
+                            result = match expr.op {
+                                Some(Op::Eq) => {
+                                    match expr.regex {
+                                        Some(ref regex) => regex.is_match(&mime_type),
+                                        None => val.eq(&mime_type)
+                                    }
+                                },
+                                Some(Op::Ne) | Some(Op::Nei) => {
+                                    match expr.regex {
+                                        Some(ref regex) => !regex.is_match(&mime_type),
+                                        None => val.ne(&mime_type)
+                                    }
+                                },
+                                Some(Op::Rx) | Some(Op::Like) | Some(Op::Glob) | Some(Op::Eqi) => {
+                                    match expr.regex {
+                                        Some(ref regex) => regex.is_match(&mime_type),
+                                        None => false
+                                    }
+                                },
+                                Some(Op::Eeq) => {
+                                    val.eq(&mime_type)
+                                },
+                                Some(Op::Ene) => {
+                                    val.ne(&mime_type)
+                                },
+                                _ => false
+                            };
+                        }
+                    }
+                },
+                Field::Md5 | Field::Sha1 | Field::Sha256 => {
+                    if let Some(ref val) = expr.val {
+                        let hash = self.get_hash(entry, file_info, &field);
+
+                        result = match expr.op {
+                            Some(Op::Eq) => {
+                                match expr.regex {
+                                    Some(ref regex) => regex.is_match(&hash),
+                                    None => val.eq_ignore_ascii_case(&hash)
+                                }
+                            },
+                            Some(Op::Ne) | Some(Op::Nei) => {
+                                match expr.regex {
+                                    Some(ref regex) => !regex.is_match(&hash),
+                                    None => !val.eq_ignore_ascii_case(&hash)
+                                }
+                            },
+                            Some(Op::Rx) | Some(Op::Like) | Some(Op::Glob) | Some(Op::Eqi) => {
+                                match expr.regex {
+                                    Some(ref regex) => regex.is_match(&hash),
+                                    None => false
+                                }
+                            },
+                            Some(Op::Eeq) => {
+                                val.eq(&hash)
+                            },
+                            Some(Op::Ene) => {
+                                val.ne(&hash)
+                            },
+                            _ => false
+                        };
+                    }
+                },
+                Field::Width => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if !is_image_dim_readable(&entry.file_name().to_string_lossy()) {
+                        return (Some(false), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        dim = self.update_img_dimensions(entry, dim);
+
+                        if let Some((width, _)) = dim {
+                            if let Some(val) = parse_float(val) {
+                                result = compare_numbers(&expr.op, width as f64, val);
+                            }
+                        }
+                    }
+                },
+                Field::Height => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if !is_image_dim_readable(&entry.file_name().to_string_lossy()) {
+                        return (Some(false), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        dim = self.update_img_dimensions(entry, dim);
+
+                        if let Some((_, height)) = dim {
+                            if let Some(val) = parse_float(val) {
+                                result = compare_numbers(&expr.op, height as f64, val);
+                            }
+                        }
+                    }
+                },
+                Field::AspectRatio => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if !is_image_dim_readable(&entry.file_name().to_string_lossy()) {
+                        return (Some(false), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        dim = self.update_img_dimensions(entry, dim);
+
+                        if let (Some(aspect_ratio), Some(val)) = (dim.and_then(get_aspect_ratio), parse_float(val)) {
+                            result = compare_numbers(&expr.op, aspect_ratio, val);
+                        }
+                    }
+                },
+                Field::Bitrate | Field::MinBitrate | Field::MaxBitrate | Field::Freq => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        mp3 = self.update_mp3_meta(entry, mp3);
+
+                        if let Some(rates) = mp3.as_ref().and_then(|mp3_meta| get_audio_rates(mp3_meta)) {
+                            if let Some(val) = parse_float(val) {
+                                result = compare_numbers(&expr.op, get_rate(&rates, &field) as f64, val);
+                            }
+                        }
+                    }
+                },
+                Field::Duration | Field::FormattedDuration => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        mp3 = self.update_mp3_meta(entry, mp3);
+
+                        if let Some(ref mp3_meta) = mp3 {
+                            if let Some(val) = parse_duration(val) {
+                                let duration = mp3_meta.duration.as_secs();
+                                result = match expr.op {
+                                    Some(Op::Eq) | Some(Op::Eeq) => duration == val,
+                                    Some(Op::Ne) | Some(Op::Ene) => duration != val,
+                                    Some(Op::Gt) => duration > val,
+                                    Some(Op::Gte) => duration >= val,
+                                    Some(Op::Lt) => duration < val,
+                                    Some(Op::Lte) => duration <= val,
+                                    _ => false
+                                };
+                            }
+                        }
+                    }
+                },
+                Field::Title => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        mp3 = self.update_mp3_meta(entry, mp3);
+
+                        if let Some(ref mp3_meta) = mp3 {
+                            if let Some(ref mp3_tag) = mp3_meta.tag {
+                                let title = &mp3_tag.title;
+                                result = match expr.op {
+                                    Some(Op::Eq) | Some(Op::Eeq) => {
+                                        match expr.regex {
+                                            Some(ref regex) => regex.is_match(title),
+                                            None => val.eq(title)
+                                        }
+                                    },
+                                    Some(Op::Ne) | Some(Op::Ene) | Some(Op::Nei) => {
+                                        match expr.regex {
+                                            Some(ref regex) => !regex.is_match(title),
+                                            None => val.ne(title)
+                                        }
+                                    },
+                                    Some(Op::Rx) | Some(Op::Like) | Some(Op::Glob) | Some(Op::Eqi) => {
+                                        match expr.regex {
+                                            Some(ref regex) => regex.is_match(title),
+                                            None => false
+                                        }
+                                    },
+                                    _ => false
+                                };
+                            }
+                        }
+                    }
+                },
+                Field::Artist => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        mp3 = self.update_mp3_meta(entry, mp3);
+
+                        if let Some(ref mp3_meta) = mp3 {
+                            if let Some(ref mp3_tag) = mp3_meta.tag {
+                                let artist = &mp3_tag.artist;
+
+                                result = match expr.op {
+                                    Some(Op::Eq) | Some(Op::Eeq) => {
+                                        match expr.regex {
+                                            Some(ref regex) => regex.is_match(artist),
+                                            None => val.eq(artist)
+                                        }
+                                    },
+                                    Some(Op::Ne) | Some(Op::Ene) | Some(Op::Nei) => {
+                                        match expr.regex {
+                                            Some(ref regex) => !regex.is_match(artist),
+                                            None => val.ne(artist)
+                                        }
+                                    },
+                                    Some(Op::Rx) | Some(Op::Like) | Some(Op::Glob) | Some(Op::Eqi) => {
+                                        match expr.regex {
+                                            Some(ref regex) => regex.is_match(artist),
+                                            None => false
+                                        }
+                                    },
+                                    _ => false
+                                };
+                            }
+                        }
+                    }
+                },
+                Field::Album => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        mp3 = self.update_mp3_meta(entry, mp3);
+
+                        if let Some(ref mp3_meta) = mp3 {
+                            if let Some(ref mp3_tag) = mp3_meta.tag {
+                                let album = &mp3_tag.album;
+
+                                result = match expr.op {
+                                    Some(Op::Eq) | Some(Op::Eeq) => {
+                                        match expr.regex {
+                                            Some(ref regex) => regex.is_match(album),
+                                            None => val.eq(album)
+                                        }
+                                    },
+                                    Some(Op::Ne) | Some(Op::Ene) | Some(Op::Nei) => {
+                                        match expr.regex {
+                                            Some(ref regex) => !regex.is_match(album),
+                                            None => val.ne(album)
+                                        }
+                                    },
+                                    Some(Op::Rx) | Some(Op::Like) | Some(Op::Glob) | Some(Op::Eqi) => {
+                                        match expr.regex {
+                                            Some(ref regex) => regex.is_match(album),
+                                            None => false
+                                        }
+                                    },
+                                    _ => false
+                                };
+                            }
+                        }
+                    }
+                },
+                Field::Year => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        mp3 = self.update_mp3_meta(entry, mp3);
+
+                        if let Some(ref mp3_meta) = mp3 {
+                            if let Some(val) = parse_float(val) {
+                                if let Some(ref mp3_tag) = mp3_meta.tag {
+                                    let year = mp3_tag.year;
+                                    if year > 0 {
+                                        result = compare_numbers(&expr.op, year as f64, val);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                },
+                Field::Genre => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        mp3 = self.update_mp3_meta(entry, mp3);
+
+                        if let Some(ref mp3_meta) = mp3 {
+                            if let Some(ref mp3_tag) = mp3_meta.tag {
+                                let genre = &format!("{:?}", &mp3_tag.genre);
+
+                                result = match expr.op {
+                                    Some(Op::Eq) | Some(Op::Eeq) => {
+                                        match expr.regex {
+                                            Some(ref regex) => regex.is_match(genre),
+                                            None => val.eq(genre)
+                                        }
+                                    },
+                                    Some(Op::Ne) | Some(Op::Ene) | Some(Op::Nei) => {
+                                        match expr.regex {
+                                            Some(ref regex) => !regex.is_match(genre),
+                                            None => val.ne(genre)
+                                        }
+                                    },
+                                    Some(Op::Rx) | Some(Op::Like) | Some(Op::Glob) | Some(Op::Eqi) => {
+                                        match expr.regex {
+                                            Some(ref regex) => regex.is_match(genre),
+                                            None => false
+                                        }
+                                    },
+                                    _ => false
+                                };
+                            }
+                        }
+                    }
+                },
+                Field::VideoWidth | Field::VideoHeight | Field::VideoDuration => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let Some(ref val) = expr.val {
+                        let value = self.get_video_metadata(entry, file_info).and_then(|video| match field {
+                            Field::VideoWidth => Some(video.width as u64),
+                            Field::VideoHeight => Some(video.height as u64),
+                            _ => video.duration
+                        });
+
+                        if let (Some(val), Some(value)) = (parse_float(val), value) {
+                            result = compare_numbers(&expr.op, value as f64, val);
+                        }
+                    }
+                },
+                Field::VideoCodec => {
+                    if file_info.is_some() {
+                        return (self.unavailable_result(), meta, dim, mp3)
+                    }
+
+                    if let (Some(ref val), Some(video)) = (&expr.val, self.get_video_metadata(entry, file_info)) {
+                        result = compare_str(&expr.op, val, &expr.regex, &video.codec);
+                    }
+                },
+                Field::IsArchive => {
+                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_archive);
+                },
+                Field::IsAudio => {
+                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_audio);
+                },
+                Field::IsBook => {
+                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_book);
+                },
+                Field::IsDoc => {
+                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_doc);
+                },
+                Field::IsImage => {
+                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_image);
+                },
+                Field::IsSource => {
+                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_source);
+                },
+                Field::IsVideo => {
+                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_video);
+                }
+            }
+        }
+
+        (Some(result), meta, dim, mp3)
+    }
+
+    /// Capture groups of the last name or path regex that matched the current file.
+    fn save_captures(&mut self, regex: &Regex, value: &str) {
+        if let Some(captures) = regex.captures(value) {
+            self.captures = captures.iter()
+                .map(|group| group.map(|group| group.as_str().to_string()).unwrap_or_default())
+                .collect();
+        }
+    }
+
+    /// Result of a condition on data which archive entries don't carry: unknown conditions
+    /// are skipped in lenient mode, otherwise they don't match.
+    fn unavailable_result(&self) -> Option<bool> {
+        match self.lenient {
+            true => None,
+            false => Some(false)
+        }
+    }
+}
+
+fn confirm_file_mode(expr_op: &Option<Op>,
+                     expr_val: &Option<String>,
+                     entry: &DirEntry,
+                     meta: Option<Box<Metadata>>,
+                     file_info: &Option<FileInfo>,
+                     follow_symlinks: bool,
+                     mode_func: &Fn(u32) -> bool) -> (bool, Option<Box<Metadata>>) {
+    let mut result = false;
+    let mut meta = meta;
+
+    if let Some(ref val) = expr_val {
+        let mode = match file_info {
+            Some(ref file_info) => file_info.mode,
+            _ => {
+                meta = update_meta(entry, meta, follow_symlinks);
+
+                match meta {
+                    Some(ref metadata) => mode::get_mode_from_boxed_unix_int(metadata),
+                    _ => None
+                }
+            }
+        };
+
+        if let Some(mode) = mode {
+            let bool_val = str_to_bool(val);
+
+            result = match expr_op {
+                Some(Op::Eq) => {
+                    if bool_val {
+                        mode_func(mode)
+                    } else {
+                        !mode_func(mode)
+                    }
+                },
+                Some(Op::Ne) => {
+                    if bool_val {
+                        !mode_func(mode)
+                    } else {
+                        mode_func(mode)
+                    }
+                },
+                _ => false
+            };
+        }
+    }
+
+    (result, meta)
+}
+
+fn confirm_file_attribute(expr_op: &Option<Op>,
+                          expr_val: &Option<String>,
+                          entry: &DirEntry,
+                          meta: Option<Box<Metadata>>,
+                          file_info: &Option<FileInfo>,
+                          follow_symlinks: bool,
+                          attribute_func: &Fn(&Box<Metadata>) -> bool,
+                          mode_func: &Fn(u32) -> bool) -> (bool, Option<Box<Metadata>>) {
+    let mut result = false;
+    let mut meta = meta;
+
+    if let Some(ref val) = expr_val {
+        let attribute = match file_info {
+            Some(ref file_info) => file_info.mode.map(|mode| mode_func(mode)),
+            _ => {
+                meta = update_meta(entry, meta, follow_symlinks);
+
+                match meta {
+                    Some(ref metadata) => Some(attribute_func(metadata)),
+                    _ => None
+                }
+            }
+        };
+
+        if let Some(attribute) = attribute {
+            let bool_val = str_to_bool(val);
+
+            result = match expr_op {
+                Some(Op::Eq) => attribute == bool_val,
+                Some(Op::Ne) => attribute != bool_val,
+                _ => false
+            };
+        }
+    }
+
+    (result, meta)
+}
+
+fn confirm_file_ext(expr_op: &Option<Op>,
+                    expr_val: &Option<String>,
+                    entry: &DirEntry,
+                    file_info: &Option<FileInfo>,
+                    file_ext_func: &Fn(&str) -> bool) -> bool {
+    let mut result = false;
+
+    if let Some(ref val) = expr_val {
+        let file_name = match file_info {
+            Some(ref file_info) => file_info.name.clone(),
+            _ => String::from(entry.file_name().to_string_lossy())
+        };
+
+        let bool_val = str_to_bool(val);
+
+        result = match expr_op {
+            Some(Op::Eq) | Some(Op::Eeq) => {
+                if bool_val {
+                    file_ext_func(&file_name)
+                } else {
+                    !file_ext_func(&file_name)
+                }
+            },
+            Some(Op::Ne) | Some(Op::Ene) => {
+                if bool_val {
+                    !file_ext_func(&file_name)
+                } else {
+                    file_ext_func(&file_name)
+                }
+            },
+            _ => false
+        };
+    }
+
+    result
+}
+
+#[cfg(test)]
+thread_local!(static METADATA_CALLS: ::std::cell::Cell<usize> = ::std::cell::Cell::new(0));
+
+/// The only place where file system metadata is requested, so `stat` calls could be counted in tests.
+fn get_metadata(path: &Path, follow_symlinks: bool) -> io::Result<Metadata> {
+    #[cfg(test)]
+    METADATA_CALLS.with(|calls| calls.set(calls.get() + 1));
+
+    match follow_symlinks {
+        false => symlink_metadata(path),
+        true => fs::metadata(path)
+    }
+}
+
+/// Roots overlap when they resolve to the same directory or one of them is nested in another.
+fn roots_overlap(roots: &Vec<Root>) -> bool {
+    let canonical_roots: Vec<PathBuf> = roots.iter()
+        .filter_map(|root| fs::canonicalize(&root.path).ok())
+        .collect();
+
+    for (i, root) in canonical_roots.iter().enumerate() {
+        for other_root in canonical_roots.iter().skip(i + 1) {
+            if root.starts_with(other_root) || other_root.starts_with(root) {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+/// Entries of the directory by their paths, nothing if it can't be read.
+fn list_dir_entries(dir: &Path) -> HashMap<PathBuf, DirEntry> {
+    match fs::read_dir(dir) {
+        Ok(entry_list) => entry_list.filter_map(|entry| entry.ok()).map(|entry| (entry.path(), entry)).collect(),
+        Err(_) => HashMap::new()
+    }
+}
+
+/// Only the parent directory is resolved, so symlinks themselves are not confused with their targets.
+fn get_canonical_path(entry: &DirEntry) -> PathBuf {
+    let path = entry.path();
+    match path.parent().map(|parent| fs::canonicalize(parent)) {
+        Some(Ok(parent)) => parent.join(entry.file_name()),
+        _ => path
+    }
+}
+
+/// Directory entries usually know their type without any extra `stat` call.
+/// Only symlinks to be followed need to be resolved.
+fn is_dir_entry(entry: &DirEntry, follow_symlinks: bool) -> bool {
+    match entry.file_type() {
+        Ok(ref file_type) if file_type.is_symlink() => {
+            follow_symlinks && match get_metadata(&entry.path(), true) {
+                Ok(metadata) => metadata.is_dir(),
+                _ => false
+            }
+        },
+        Ok(file_type) => file_type.is_dir(),
+        _ => false
+    }
+}
+
+/// Only regular files are read for their content, opening a FIFO would block and a device could never end.
+/// Symbolic links are resolved, as opening one opens the file it points to.
+fn is_regular_file(entry: &DirEntry) -> bool {
+    match entry.file_type() {
+        Ok(ref file_type) if file_type.is_symlink() => {
+            match get_metadata(&entry.path(), true) {
+                Ok(metadata) => metadata.is_file(),
+                _ => false
+            }
+        },
+        Ok(file_type) => file_type.is_file(),
+        _ => false
+    }
+}
+
+/// Directory is on another file system when both devices are known and differ.
+fn crosses_device(root_device: Option<u64>, dir_device: Option<u64>) -> bool {
+    match (root_device, dir_device) {
+        (Some(root_device), Some(dir_device)) => root_device != dir_device,
+        _ => false
+    }
+}
+
+/// Files inside archives have an owner only if the archive keeps it, the metadata is of the archive itself.
+fn get_file_uid(meta: &Option<Box<Metadata>>, file_info: &Option<FileInfo>) -> Option<u32> {
+    match file_info {
+        Some(ref file_info) => file_info.uid,
+        None => meta.as_ref().and_then(|meta| mode::get_uid(meta))
+    }
+}
+
+fn get_file_gid(meta: &Option<Box<Metadata>>, file_info: &Option<FileInfo>) -> Option<u32> {
+    match file_info {
+        Some(ref file_info) => file_info.gid,
+        None => meta.as_ref().and_then(|meta| mode::get_gid(meta))
+    }
+}
+
+fn update_meta(entry: &DirEntry, meta: Option<Box<Metadata>>, follow_symlinks: bool) -> Option<Box<Metadata>> {
+    if !meta.is_some() {
+        if let Ok(metadata) = get_metadata(&entry.path(), follow_symlinks) {
+            return Some(Box::new(metadata));
+        }
+    }
+
+    meta
+}
+
+/// Values of the columns are kept by their names as well, unless a field of the same name is already there.
+/// So a literal like `'size'` doesn't hide the size of the file from the ordering or a `cmd` template.
+fn add_column_values(file_map: &mut HashMap<String, Value>, columns: &Vec<ColumnExpr>, values: &Vec<Option<Value>>) {
+    for (column_expr, value) in columns.iter().zip(values.iter()) {
+        if let Some(ref value) = *value {
+            file_map.entry(column_expr.to_string().to_lowercase()).or_insert_with(|| value.clone());
+        }
+    }
+}
+
+/// Integer value of a number or a file size with a unit, e.g. `1g`.
+fn parse_number(s: &str) -> Option<i64> {
+    match s.parse::<i64>() {
+        Ok(number) => Some(number),
+        _ => parse_filesize(s).map(|size| size as i64)
+    }
+}
+
+/// Value of a number which may have a fractional part, or of a file size with a unit.
+fn parse_float(s: &str) -> Option<f64> {
+    match s.parse::<f64>() {
+        Ok(number) if number.is_finite() => Some(number),
+        Ok(_) => None,
+        _ => parse_filesize(s).map(|size| size as f64)
+    }
+}
+
+/// Paths separated with newlines or, if the first chunk read has a NUL symbol in it, with NUL symbols
+/// like `find -print0` gives. Every path is handed over as soon as it's read, until the callback returns false.
+/// Empty lines and lines starting with `#` are skipped, while a NUL-separated path is taken as it is.
+fn read_root_list<R: BufRead, F: FnMut(String) -> bool>(reader: &mut R, add_root: &mut F) -> io::Result<()> {
+    let separator = match reader.fill_buf()?.contains(&0) {
+        true => 0,
+        false => b'\n'
+    };
+
+    let mut entry = vec![];
+
+    loop {
+        entry.clear();
+        if reader.read_until(separator, &mut entry)? == 0 {
+            return Ok(());
+        }
+
+        if entry.last() == Some(&separator) {
+            entry.pop();
+        }
+
+        let path = String::from_utf8_lossy(&entry);
+        let path = match separator {
+            0 => path.as_ref(),
+            _ => path.trim_end_matches('\r')
+        };
+
+        if path.is_empty() || (separator != 0 && (path.trim().is_empty() || path.trim_start().starts_with('#'))) {
+            continue;
+        }
+
+        if !add_root(path.to_string()) {
+            return Ok(());
+        }
+    }
+}
+
+/// Rate of the MP3 file the field stands for, the average bitrate for `bitrate`.
+fn get_rate(rates: &AudioRates, field: &Field) -> u16 {
+    match field {
+        Field::MinBitrate => rates.min_bitrate,
+        Field::MaxBitrate => rates.max_bitrate,
+        Field::Freq => rates.freq,
+        _ => rates.bitrate
+    }
+}
+
+/// Width divided by height, rounded to three decimal places, e.g. 1.778 for 16:9.
+fn get_aspect_ratio(dimensions: (usize, usize)) -> Option<f64> {
+    match dimensions {
+        (_, 0) => None,
+        (width, height) => Some((width as f64 / height as f64 * 1000.0).round() / 1000.0)
+    }
+}
+
+/// Columns made of the indexed fields, aggregates over them and constants.
+fn is_indexed_column(column_expr: &ColumnExpr) -> bool {
+    let function_indexed = match column_expr.function {
+        Some(ref function) => function.is_aggregate_function(),
+        None => true
+    };
+    let field_indexed = match column_expr.field {
+        Some(ref field) => field.is_indexed_field(),
+        None => true
+    };
+
+    function_indexed && field_indexed
+        && column_expr.left.as_ref().map_or(true, |left| is_indexed_column(left))
+        && column_expr.right.as_ref().map_or(true, |right| is_indexed_column(right))
+}
+
+/// Conditions on the indexed fields, either compared with a value or with another column.
+fn is_indexed_expr(expr: &Expr) -> bool {
+    if expr.logical_op.is_some() {
+        return expr.left.as_ref().map_or(true, |left| is_indexed_expr(left))
+            && expr.right.as_ref().map_or(true, |right| is_indexed_expr(right));
+    }
+
+    match (&expr.field, &expr.val_expr) {
+        (&Some(ref column_expr), &Some(ref val_expr)) => is_indexed_column(column_expr) && is_indexed_column(val_expr)
+            && !column_expr.has_aggregate_function() && !val_expr.has_aggregate_function(),
+        (&Some(ref column_expr), &None) => match column_expr.function {
+            // the index keeps no inodes
+            Some(Function::SameFileAs) => false,
+            Some(ref function) => function.is_marker_function(),
+            None => column_expr.field.as_ref().map_or(false, |field| field.is_indexed_field())
+        },
+        _ => false
+    }
+}
+
+/// Compares values of two columns as dates, numbers or strings, whichever both of them are.
+/// Nothing matches when any of the values is unknown.
+fn compare_column_values(op: &Option<Op>, value: &str, val: &str, is_datetime: bool) -> bool {
+    if value.is_empty() || val.is_empty() {
+        return false;
+    }
+
+    match op {
+        Some(Op::Eqi) => return value.to_lowercase() == val.to_lowercase(),
+        Some(Op::Nei) => return value.to_lowercase() != val.to_lowercase(),
+        _ => {}
+    }
+
+    if is_datetime {
+        return match (NaiveDateTime::parse_from_str(value, DEFAULT_DATE_FORMAT), NaiveDateTime::parse_from_str(val, DEFAULT_DATE_FORMAT)) {
+            (Ok(value), Ok(val)) => compare_ordered(op, value, val),
+            _ => false
+        };
+    }
+
+    // fractions would be truncated by the file size parsing
+    if let (Ok(value), Ok(val)) = (value.parse::<f64>(), val.parse::<f64>()) {
+        return compare_numbers(op, value, val);
+    }
+
+    match (parse_number(value), parse_number(val)) {
+        (Some(value), Some(val)) => compare_ordered(op, value, val),
+        _ => compare_ordered(op, value, val)
+    }
+}
+
+/// Numbers closer than this are equal, so a fraction compares equal to the same value computed differently.
+const FLOAT_EPSILON: f64 = 1e-9;
+
+/// Compares numbers which may have a fractional part, e.g. `aspect_ratio != 1.778` or `width > 99.5`.
+fn compare_numbers(op: &Option<Op>, value: f64, val: f64) -> bool {
+    let equal = (value - val).abs() < FLOAT_EPSILON;
+
+    match op {
+        Some(Op::Eq) | Some(Op::Eeq) => equal,
+        Some(Op::Ne) | Some(Op::Ene) => !equal,
+        Some(Op::Gt) => !equal && value > val,
+        Some(Op::Gte) => equal || value > val,
+        Some(Op::Lt) => !equal && value < val,
+        Some(Op::Lte) => equal || value < val,
+        _ => false
+    }
+}
+
+fn compare_ordered<T: PartialOrd>(op: &Option<Op>, value: T, val: T) -> bool {
+    match op {
+        Some(Op::Eq) | Some(Op::Eeq) => value == val,
+        Some(Op::Ne) | Some(Op::Ene) => value != val,
+        Some(Op::Gt) => value > val,
+        Some(Op::Gte) => value >= val,
+        Some(Op::Lt) => value < val,
+        Some(Op::Lte) => value <= val,
+        _ => false
+    }
+}
+
+/// Mode is checked for the permission bits with `has` and compared with octal numbers as a number,
+/// otherwise it's compared as the text shown for it. Only the text is known on Windows.
+fn compare_mode(op: &Option<Op>, val: &str, regex: &Option<Regex>, bits: Option<u32>, formatted: &str) -> bool {
+    if let Some(Op::Has) = op {
+        return bits.map_or(false, |bits| mode::mode_has_bits(bits, val));
+    }
+
+    match (mode::parse_octal_mode(val), bits) {
+        (Some(val), Some(bits)) => compare_ordered(op, bits & 0o7777, val),
+        (Some(_), None) => false,
+        _ => {
+            // the pattern may start with the file type as well, like `ls -l` shows the mode
+            let typed = bits.and_then(mode::get_file_type_char).map(|file_type| format!("{}{}", file_type, formatted));
+
+            match (op, typed) {
+                (&Some(Op::Ne), Some(typed)) | (&Some(Op::Nei), Some(typed)) | (&Some(Op::Ene), Some(typed)) => {
+                    compare_str(op, val, regex, formatted) && compare_str(op, val, regex, &typed)
+                },
+                (_, Some(typed)) => compare_str(op, val, regex, formatted) || compare_str(op, val, regex, &typed),
+                (_, None) => compare_str(op, val, regex, formatted)
+            }
+        }
+    }
+}
+
+/// Compares a string value the same way textual fields are compared in `where`.
+fn compare_str(op: &Option<Op>, val: &str, regex: &Option<Regex>, value: &str) -> bool {
+    match op {
+        Some(Op::Eq) => {
+            match regex {
+                Some(ref regex) => regex.is_match(value),
+                None => val.eq(value)
+            }
+        },
+        Some(Op::Ne) | Some(Op::Nei) => {
+            match regex {
+                Some(ref regex) => !regex.is_match(value),
+                None => val.ne(value)
+            }
+        },
+        Some(Op::Rx) | Some(Op::Like) | Some(Op::Glob) | Some(Op::Eqi) => {
+            match regex {
+                Some(ref regex) => regex.is_match(value),
+                None => false
+            }
+        },
+        Some(Op::Eeq) => val.eq(value),
+        Some(Op::Ene) => val.ne(value),
+        _ => false
+    }
+}
+
+/// Value of the named extended attribute, empty if it's absent or isn't valid UTF-8.
+#[allow(unused)]
+fn get_xattr(path: &Path, name: &str) -> String {
+    #[cfg(unix)]
+    {
+        if let Ok(Some(value)) = xattr::get(path, name) {
+            return String::from_utf8(value).unwrap_or_default();
+        }
+    }
+
+    String::new()
+}
+
+/// Names of all extended attributes of the file.
+#[allow(unused)]
+fn list_xattrs(path: &Path) -> Vec<String> {
+    let mut result = vec![];
+
+    #[cfg(unix)]
+    {
+        if let Ok(xattrs) = xattr::list(path) {
+            result.extend(xattrs.map(|name| name.to_string_lossy().to_string()));
+        }
+    }
+
+    result
+}
+
+/// Domain in which aggregate functions compare and sum the values of a column.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AggregateKind {
+    Number,
+    FileSize,
+    DateTime,
+    Duration,
+}
+
+impl AggregateKind {
+    fn from_column_expr(column_expr: &ColumnExpr) -> AggregateKind {
+        match column_expr.get_field() {
+            Some(Field::FormattedSize) | Some(Field::FormattedDirSize) => AggregateKind::FileSize,
+            Some(Field::Created) | Some(Field::Accessed) | Some(Field::Changed) | Some(Field::Modified) => AggregateKind::DateTime,
+            Some(Field::FormattedDuration) => AggregateKind::Duration,
+            _ => AggregateKind::Number
+        }
+    }
+
+    /// Converts the printed value back to a number: bytes, seconds since the epoch or seconds of duration.
+    fn parse(&self, value: &str) -> Option<i64> {
+        match self {
+            AggregateKind::Number => value.parse::<i64>().ok(),
+            AggregateKind::FileSize => parse_filesize(value).map(|size| size as i64),
+            AggregateKind::DateTime => parse_datetime(value).ok().map(|(start, _)| start.timestamp()),
+            AggregateKind::Duration => parse_duration(value).map(|duration| duration as i64),
+        }
+    }
+
+    /// Dates keep their time, so they are output in the date format of the query.
+    fn to_value(&self, value: i64) -> Value {
+        match self {
+            AggregateKind::DateTime => Value::DateTime(value, 0),
+            _ => Value::from(self.format(value))
+        }
+    }
+
+    /// Prints the number in the same format as the field it came from.
+    fn format(&self, value: i64) -> String {
+        match self {
+            AggregateKind::Number => value.to_string(),
+            AggregateKind::FileSize => (value as u64).file_size(file_size_opts::BINARY).unwrap(),
+            AggregateKind::DateTime => Local.timestamp(value, 0).format(DEFAULT_DATE_FORMAT).to_string(),
+            AggregateKind::Duration => format_duration(value as u64),
+        }
+    }
+}
+
+/// Result of the arithmetic operation, empty when any of the operands isn't a number or on division by zero.
+fn calc_arithmetic(op: &ArithmeticOp, left: &str, right: &str) -> String {
+    match (parse_number(left), parse_number(right)) {
+        (Some(left), Some(right)) => {
+            match op.calc(left, right) {
+                Some(result) => result.to_string(),
+                None => String::new()
+            }
+        },
+        _ => String::new()
+    }
+}
+
+fn read_file_bytes(path: &PathBuf) -> Option<Vec<u8>> {
+    let mut content = vec![];
+    match File::open(path) {
+        Ok(mut file) => {
+            match file.read_to_end(&mut content) {
+                Ok(_) => Some(content),
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn contains_bytes(haystack: &[u8], needle: &[u8]) -> bool {
+    if needle.is_empty() {
+        return true;
+    }
+
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+fn is_shebang(path: &PathBuf) -> bool {
+    if let Ok(file) = File::open(path) {
+        let mut buf_reader = BufReader::new(file);
+        let mut buf = vec![0; 2];
+        if buf_reader.read_exact(&mut buf).is_ok() {
+            return buf[0] == 0x23 && buf[1] == 0x21
+        }
+    }
+
+    false
+}
+
+/// Characters are counted from 1 like in SQL, a negative start counts them from the end, e.g. -3 is the third last one.
+/// The part of the range out of the string is cut off, so start 0 with length 2 gives just the first character.
+fn substr(s: &str, start: &str, len: Option<&String>) -> String {
+    let start = match start.trim().parse::<i64>() {
+        Ok(start) => start,
+        _ => return String::new()
+    };
+
+    let len = match len {
+        Some(len) => match len.trim().parse::<i64>() {
+            Ok(len) if len > 0 => len,
+            _ => return String::new()
+        },
+        None => i64::max_value()
+    };
+
+    // zero-based position of the first character, it could be before the string
+    let first = match start {
+        start if start > 0 => start - 1,
+        0 => -1,
+        start => s.chars().count() as i64 + start
+    };
+    let skip = first.max(0);
+    let take = first.saturating_add(len) - skip;
+
+    match take > 0 {
+        true => s.chars().skip(skip as usize).take(take as usize).collect(),
+        false => String::new()
+    }
+}
+
+#[allow(unused)]
+fn is_hidden(file_name: &str, metadata: &Option<Box<Metadata>>, archive_mode: bool) -> bool {
+    if archive_mode {
+        if !file_name.contains('\\') {
+            return parse_unix_filename(file_name).starts_with('.');
+        } else {
+            return false;
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        if let Some(ref metadata) = metadata {
+            if mode::has_hidden_flag(metadata) {
+                return true;
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        return file_name.starts_with('.');
+    }
+
+    #[cfg(windows)]
+    {
+        if let Some(ref metadata) = metadata {
+            return mode::get_mode(metadata).contains("Hidden");
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+macro_rules! def_extension_queries {
+    ($($name:ident $extensions:expr);*) => {
+        $(
+            fn $name(file_name: &str) -> bool {
+                has_extension(file_name, &$extensions)
+            }
+        )*
+    }
+}
+
+def_extension_queries! {
+    is_zip_archive          [".zip", ".jar", ".war", ".ear"]
+;   is_tar_archive          [".tar"]
+;   is_tar_gz_archive       [".tar.gz", ".tgz"]
+;   is_tar_bz2_archive      [".tar.bz2", ".tbz2"]
+;   is_archive              [".7z", ".bz2", ".bzip2", ".gz", ".gzip", ".rar", ".tar", ".tbz2", ".tgz", ".xz", ".zip"]
+;   is_audio                [".aac", ".aiff", ".amr", ".flac", ".gsm", ".m4a", ".m4b", ".m4p", ".mp3", ".ogg", ".wav", ".wma"]
+;   is_book                 [".azw3", ".chm", ".epub", ".fb2", ".mobi", ".pdf"]
+;   is_doc                  [".accdb", ".doc", ".docm", ".docx", ".dot", ".dotm", ".dotx", ".mdb", ".ods", ".odt", ".pdf", ".potm", ".potx", ".ppt", ".pptm", ".pptx", ".rtf", ".xlm", ".xls", ".xlsm", ".xlsx", ".xlt", ".xltm", ".xltx", ".xps"]
+;   is_image                [".bmp", ".gif", ".jpeg", ".jpg", ".png", ".tiff", ".webp"]
+;   is_image_dim_readable   [".bmp", ".gif", ".jpeg", ".jpg", ".png", ".webp"]
+;   is_source               [".asm", ".c", ".cpp", ".cs", ".go", ".h", ".hpp", ".java", ".js", ".jsp", ".pas", ".php", ".pl", ".pm", ".py", ".rb", ".rs", ".swift"]
+;   is_video                [".3gp", ".avi", ".flv", ".m4p", ".m4v", ".mkv", ".mov", ".mp4", ".mpeg", ".mpg", ".webm", ".wmv"]
+}
+
+/// Archives which content could be searched.
+fn is_searchable_archive(file_name: &str) -> bool {
+    is_zip_archive(file_name) || is_tar_archive(file_name) || is_tar_gz_archive(file_name) || is_tar_bz2_archive(file_name)
+}
+
+fn has_extension(file_name: &str, extensions: &[&str]) -> bool {
+    let s = file_name.to_ascii_lowercase();
+
+    for ext in extensions {
+        if s.ends_with(ext) {
+            return true
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    use parser::Parser;
+    use parser::convert_mode_glob_to_pattern;
+    use time;
+    use util::TempDir;
+
+    fn count_metadata_calls(query: &str) -> usize {
+        let query = Parser::new().parse(query).unwrap();
+        let mut searcher = Searcher::new(query);
+
+        METADATA_CALLS.with(|calls| calls.set(0));
+        searcher.search(&mut |_| true);
+
+        METADATA_CALLS.with(|calls| calls.get())
+    }
+
+    #[test]
+    fn test_lazy_metadata() {
+        let fixture = TempDir::new("metadata");
+        fs::create_dir_all(fixture.join("logs")).unwrap();
+        fs::File::create(fixture.join("a.log")).unwrap();
+        fs::File::create(fixture.join("b.txt")).unwrap();
+        fs::File::create(fixture.join("logs").join("c.log")).unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        // only the two visited directories are looked at
+        assert_eq!(count_metadata_calls(&format!("select path from '{}' where name like '%.log'", root)), 2);
+        assert_eq!(count_metadata_calls(&format!("select name, ext, is_image from '{}' where ext = log", root)), 2);
+
+        // but every found file needs its size
+        assert_eq!(count_metadata_calls(&format!("select path, size from '{}' where name like '%.log'", root)), 4);
+    }
+
+    fn count_found(query: &str) -> u32 {
+        let query = Parser::new().parse(query).unwrap();
+        let mut searcher = Searcher::new(query);
+        searcher.search(&mut |_| true);
+
+        searcher.found
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_overlapping_roots() {
+        let fixture = TempDir::new("roots");
+        let dir = fixture.join("dir");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::File::create(dir.join("a.txt")).unwrap();
+        fs::File::create(dir.join("sub").join("b.txt")).unwrap();
+        fs::File::create(dir.join("sub").join("c.txt")).unwrap();
+        ::std::os::unix::fs::symlink(&dir, fixture.join("link")).unwrap();
+
+        let dir = dir.to_string_lossy();
+        let sub = fixture.join("dir").join("sub");
+        let sub = sub.to_string_lossy();
+        let link = fixture.join("link");
+        let link = link.to_string_lossy();
+
+        assert_eq!(count_found(&format!("select path from '{}', '{}' where is_file = true", dir, sub)), 3);
+        assert_eq!(count_found(&format!("select path from '{}', '{}' symlinks where is_file = true", dir, link)), 3);
+        assert_eq!(count_found(&format!("select path from '{}', '{}' nodedup where is_file = true", dir, sub)), 5);
+        assert_eq!(count_found(&format!("select path from '{}', '{}' where is_file = true limit 3", sub, dir)), 3);
+    }
+
+    #[test]
+    fn test_probe_cache() {
+        let fixture = TempDir::new("probe_cache");
+        let dir = fixture.join("dir");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+
+        for &(name, width, height) in &[("a.png", 40u32, 20u32), ("sub/b.png", 10, 30)] {
+            let mut header = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+            header.extend_from_slice(&width.to_be_bytes());
+            header.extend_from_slice(&height.to_be_bytes());
+            header.extend_from_slice(&[8, 6, 0, 0, 0, 0, 0, 0, 0]);
+            fs::write(dir.join(name), header).unwrap();
+        }
+
+        let sub = dir.join("sub");
+        let query = format!("select name, width, height from '{}', '{}' nodedup where is_file = true \
+                             union select name, width, height from '{}' where width > 0 order by name",
+                            dir.to_string_lossy(), sub.to_string_lossy(), sub.to_string_lossy());
+        let mut searcher = Searcher::new(Parser::new().parse(&query).unwrap());
+
+        let names = search_rows(&mut searcher);
+        assert_eq!(names, vec!["a.png\t40\t20", "b.png\t10\t30", "b.png\t10\t30", "b.png\t10\t30"]);
+
+        // each image is read once, whichever root or query reaches it
+        assert_eq!(searcher.dimension_probes.get(), 2);
+
+        // the probes and the check of the visited paths share the path of the entry
+        let query = format!("select name, width, sha1, bitrate, is_video from '{}' where is_file = true", dir.to_string_lossy());
+        let mut searcher = Searcher::new(Parser::new().parse(&query).unwrap());
+        assert_eq!(search_rows(&mut searcher).len(), 2);
+        assert_eq!(searcher.canonicalized_paths.get(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dir_size() {
+        let fixture = TempDir::new("dirsize");
+        let dir = fixture.join("dir");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::File::create(dir.join("a.txt")).unwrap().write_all(&[0; 10]).unwrap();
+        fs::File::create(dir.join("sub").join("b.txt")).unwrap().write_all(&[0; 20]).unwrap();
+        fs::File::create(dir.join("c.log")).unwrap().write_all(&[0; 100]).unwrap();
+        fs::File::create(dir.join(".gitignore")).unwrap().write_all(b"*.log\n").unwrap();
+        ::std::os::unix::fs::symlink(&dir, dir.join("sub").join("loop")).unwrap();
+
+        // unless followed, symlinks are counted with their own size
+        let link_size = fs::symlink_metadata(dir.join("sub").join("loop")).unwrap().len();
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(count_found(&format!("select path from '{}' where name = sub and dirsize = {}", root, 20 + link_size)), 1);
+        assert_eq!(count_found(&format!("select path from '{}' where name = dir and dirsize = {}", root, 136 + link_size)), 1);
+        assert_eq!(count_found(&format!("select path from '{}' where name = a.txt and dirsize = 10", root)), 1);
+        assert_eq!(count_found(&format!("select path from '{}' gitignore where name = dir and dirsize = {}", root, 36 + link_size)), 1);
+        assert_eq!(count_found(&format!("select path from '{}' symlinks depth 3 where name = dir and dirsize = 136", root)), 1);
+    }
+
+    #[test]
+    fn test_ignore_files() {
+        let fixture = TempDir::new("ignore");
+        fs::create_dir_all(fixture.join("sub")).unwrap();
+        fs::create_dir_all(fixture.join("tmp")).unwrap();
+        fs::File::create(fixture.join(".gitignore")).unwrap().write_all(b"*.log\n").unwrap();
+        fs::File::create(fixture.join(".fselectignore")).unwrap().write_all(b"!keep.log\ntmp/\n").unwrap();
+        fs::File::create(fixture.join(".myignore")).unwrap().write_all(b"b.txt\n").unwrap();
+        fs::File::create(fixture.join("sub").join(".fselectignore")).unwrap().write_all(b"keep.log\n").unwrap();
+        for file in &["a.log", "keep.log", "b.txt", "sub/c.log", "sub/keep.log", "tmp/x.txt"] {
+            fs::File::create(fixture.join(file)).unwrap();
+        }
+
+        let root = fixture.to_string_lossy();
+        let find = |options: &str| find_names(&format!("select name from '{}' {} where is_file = true order by name", root, options));
+
+        // the deeper .fselectignore excludes the file the upper one has re-included
+        assert_eq!(find("gitignore"), vec![".fselectignore", ".fselectignore", ".gitignore", ".myignore", "b.txt", "keep.log"]);
+        assert_eq!(find(""), vec![".fselectignore", ".fselectignore", ".gitignore", ".myignore", "a.log", "b.txt", "c.log", "keep.log"]);
+        assert_eq!(find("gitignore ignorefile .myignore"), vec![".fselectignore", ".fselectignore", ".gitignore", ".myignore", "x.txt"]);
+
+        assert_eq!(count_found(&format!("select path from '{}' where name = sub and dirsize = 9", root)), 1);
+        assert_eq!(count_found(&format!("select path from '{}' gitignore where name = sub and dirsize = 9", root)), 1);
+    }
+
+    #[test]
+    fn test_gitignored() {
+        let fixture = TempDir::new("gitignored");
+        for dir in &[".git", "src", "target", "sub/tmp"] {
+            fs::create_dir_all(fixture.join(dir)).unwrap();
+        }
+        fs::File::create(fixture.join(".gitignore")).unwrap().write_all(b"*.log\n!keep.log\ntarget/\ntmp/\n").unwrap();
+        fs::File::create(fixture.join(".fselectignore")).unwrap().write_all(b"secret.log\n").unwrap();
+        for file in &[".git/HEAD", "a.log", "keep.log", "secret.log", "tmp", "src/main.rs", "src/debug.log",
+                      "target/out.bin", "target/keep.log", "sub/tmp/x.txt"] {
+            fs::File::create(fixture.join(file)).unwrap();
+        }
+
+        let root = fixture.to_string_lossy();
+        let find = |options: &str, cond: &str| find_names(&format!("select name from '{}' {} where {} order by name", root, options, cond));
+
+        // the file re-included with `!` is ignored again inside an ignored directory,
+        // and only the directories match the pattern with a trailing slash
+        assert_eq!(find("gitignored", "is_file = true"), vec!["a.log", "debug.log", "keep.log", "out.bin", "x.txt"]);
+        assert_eq!(find("gitignored", "is_dir = true"), vec!["target", "tmp"]);
+        assert_eq!(find("gitignore", "is_file = true"), vec![".fselectignore", ".gitignore", "keep.log", "main.rs", "tmp"]);
+
+        assert_eq!(find_names(&format!("select name, size from '{}' gitignored where name = out.bin order by size desc", root)), vec!["out.bin\t0"]);
+        assert_eq!(find("gitignored ordered maxfiles 1", "is_file = true"), vec!["a.log", "debug.log", "keep.log", "x.txt"]);
+    }
+
+    fn count_visited_entries(query: &str) -> u32 {
+        let query = Parser::new().parse(query).unwrap();
+        let mut searcher = Searcher::new(query);
+        searcher.search(&mut |_| true);
+
+        searcher.visited_entries
+    }
+
+    #[test]
+    fn test_prune() {
+        let fixture = TempDir::new("prune");
+        for project in &["app", "lib", "tools/cli"] {
+            let modules = fixture.join(project).join("node_modules");
+            for package in 0..20 {
+                fs::create_dir_all(modules.join(format!("package{}", package)).join("node_modules/dependency")).unwrap();
+                fs::File::create(modules.join(format!("package{}", package)).join("index.js")).unwrap();
+                fs::File::create(modules.join(format!("package{}", package)).join("node_modules/dependency/index.js")).unwrap();
+            }
+            fs::create_dir_all(fixture.join(project).join("src")).unwrap();
+            fs::File::create(fixture.join(project).join("src/index.js")).unwrap();
+        }
+        fs::create_dir_all(fixture.join("app/build")).unwrap();
+        fs::File::create(fixture.join("app/build/index.js")).unwrap();
+        fs::File::create(fixture.join(".gitignore")).unwrap().write_all(b"build/\n").unwrap();
+
+        let root = fixture.to_string_lossy();
+        let find = |options: &str, cond: &str| find_names(&format!("select relpath from '{}' {} where {} order by relpath", root, options, cond));
+
+        assert_eq!(find("", "name = 'index.js'").len(), 3 * 20 * 2 + 4);
+        assert_eq!(find("prune node_modules", "name = 'index.js'"), vec!["app/build/index.js", "app/src/index.js", "lib/src/index.js", "tools/cli/src/index.js"]);
+
+        // the pruned directories themselves are still found, only the outermost ones are reached
+        assert_eq!(find("prune 'node_*'", "name = node_modules"), vec!["app/node_modules", "lib/node_modules", "tools/cli/node_modules"]);
+
+        // along with the ignored and the too deep directories
+        assert_eq!(find("prune node_modules gitignore", "name = 'index.js'"), vec!["app/src/index.js", "lib/src/index.js", "tools/cli/src/index.js"]);
+        assert_eq!(find("prune node_modules, 'src' depth 2", "is_dir = true"), vec!["app", "app/build", "app/node_modules", "app/src", "lib", "lib/node_modules", "lib/src", "tools", "tools/cli"]);
+
+        // almost every entry of the tree is inside node_modules
+        let all = count_visited_entries(&format!("select path from '{}'", root));
+        let pruned = count_visited_entries(&format!("select path from '{}' prune node_modules", root));
+        assert_eq!(all, 4 + 1 + 3 * (3 + 20 * 5) + 2);
+        assert_eq!(pruned, 4 + 1 + 3 * 3 + 2);
+    }
+
+    #[test]
+    fn test_is_empty() {
+        let fixture = TempDir::new("empty");
+        fs::create_dir_all(fixture.join("empty_dir")).unwrap();
+        fs::create_dir_all(fixture.join("full_dir")).unwrap();
+        fs::create_dir_all(fixture.join("ignored_dir")).unwrap();
+        fs::File::create(fixture.join("full_dir").join("empty.txt")).unwrap();
+        fs::File::create(fixture.join("full_dir").join("full.txt")).unwrap().write_all(b"data").unwrap();
+        fs::File::create(fixture.join("ignored_dir").join("debug.log")).unwrap();
+        fs::File::create(fixture.join(".fselectignore")).unwrap().write_all(b"*.log\n").unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, is_empty from '{}' where is_empty = true and is_dir = true order by name", root)),
+                   vec!["empty_dir\ttrue"]);
+        assert_eq!(find_names(&format!("select name from '{}' where is_empty = true order by name", root)),
+                   vec!["empty.txt", "empty_dir"]);
+        assert_eq!(find_names(&format!("select name, is_empty from '{}' where is_empty != true and name like '%_dir' order by name", root)),
+                   vec!["full_dir\tfalse", "ignored_dir\tfalse"]);
+    }
+
+    #[test]
+    fn test_glob() {
+        let fixture = TempDir::new("glob");
+        fs::create_dir_all(fixture.join("src")).unwrap();
+        fs::create_dir_all(fixture.join("target").join("debug")).unwrap();
+        fs::File::create(fixture.join("build.rs")).unwrap();
+        fs::File::create(fixture.join("src").join("main.rs")).unwrap();
+        fs::File::create(fixture.join("src").join("lib.rs")).unwrap();
+        fs::File::create(fixture.join("target").join("debug").join("app.d")).unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name from '{}' where name = '*.rs' order by name", root)),
+                   vec!["build.rs", "lib.rs", "main.rs"]);
+        assert_eq!(find_names(&format!("select name from '{}' where path = '**/target/**' order by name", root)),
+                   vec!["app.d", "debug"]);
+        assert_eq!(find_names(&format!("select name from '{}' where path = '{}/*/*.rs' order by name", root, root)),
+                   vec!["lib.rs", "main.rs"]);
+        assert_eq!(find_names(&format!("select name from '{}' where name = '[lm]*.rs' order by name", root)),
+                   vec!["lib.rs", "main.rs"]);
+        assert_eq!(find_names(&format!("select name from '{}' where name != '[!m]*' order by name", root)),
+                   vec!["main.rs"]);
+        assert_eq!(find_names(&format!("select name from '{}' where name glob 'SRC' order by name", root)),
+                   vec!["src"]);
+    }
+
+    #[test]
+    fn test_case_insensitive_comparison() {
+        let fixture = TempDir::new("eqi");
+        fs::create_dir_all(fixture.join("Docs")).unwrap();
+        fs::File::create(fixture.join("README.MD")).unwrap();
+        fs::File::create(fixture.join("readme.txt")).unwrap();
+        fs::File::create(fixture.join("Docs").join("Guide.md")).unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name from '{}' where name eqi 'readme.md' order by name", root)), vec!["README.MD"]);
+        assert_eq!(find_names(&format!("select name from '{}' where name = 'readme.md' order by name", root)), Vec::<String>::new());
+        assert_eq!(find_names(&format!("select name from '{}' where name EQI 'Readme.*' order by name", root)), vec!["README.MD", "readme.txt"]);
+        assert_eq!(find_names(&format!("select name from '{}' where ext eqi md and name nei 'guide.MD' order by name", root)), vec!["README.MD"]);
+        assert_eq!(find_names(&format!("select name from '{}' where path eqi '{}/docs/guide.md' order by name", root, root.to_uppercase())), vec!["Guide.md"]);
+        assert_eq!(find_names(&format!("select name from '{}' where is_file = true and name nei 'readme.md' order by name", root)), vec!["Guide.md", "readme.txt"]);
+    }
+
+    #[test]
+    fn test_subquery_roots() {
+        let fixture = TempDir::new("subquery");
+        fs::create_dir_all(fixture.join("a").join("node_modules").join("pkg")).unwrap();
+        fs::create_dir_all(fixture.join("b").join("node_modules")).unwrap();
+        fs::create_dir_all(fixture.join("c")).unwrap();
+        fs::File::create(fixture.join("a").join("node_modules").join("pkg").join("big.js")).unwrap().write_all(b"12345").unwrap();
+        fs::File::create(fixture.join("b").join("node_modules").join("small.js")).unwrap().write_all(b"1").unwrap();
+        fs::File::create(fixture.join("c").join("other.js")).unwrap().write_all(b"12345").unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name from (select path from '{}' where name = node_modules and is_dir = true) where is_file = true order by name", root)),
+                   vec!["big.js", "small.js"]);
+        assert_eq!(find_names(&format!("select name from (select path from '{}' where name = node_modules) depth 1 order by name", root)),
+                   vec!["pkg", "small.js"]);
+        assert_eq!(find_names(&format!("select name from (select name, path from '{}' where name = node_modules order by path desc limit 1) order by name", root)),
+                   vec!["small.js"]);
+        assert_eq!(find_names(&format!("select name from (select path from '{}' where name = missing) order by name", root)),
+                   Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_root_list_file() {
+        let fixture = TempDir::new("root_list");
+        for dir in &["a/sub", "b/sub", "c"] {
+            fs::create_dir_all(fixture.join(dir)).unwrap();
+        }
+        fs::write(fixture.join("a").join("top.txt"), "").unwrap();
+        fs::write(fixture.join("a").join("sub").join("deep.txt"), "").unwrap();
+        fs::write(fixture.join("b").join("sub").join("deep.txt"), "").unwrap();
+        fs::write(fixture.join("c").join("skipped.txt"), "").unwrap();
+
+        let list = fixture.join("roots.txt");
+        fs::write(&list, format!("# generated\r\n{0}/a\r\n\n  \n#{0}/c\n{0}/b", fixture.to_string_lossy())).unwrap();
+
+        // the options after the list apply to every root in it
+        assert_eq!(find_names(&format!("select name from @{} depth 1 where is_file = true order by name", list.to_string_lossy())),
+                   vec!["top.txt"]);
+        assert_eq!(find_names(&format!("select name from @{} where is_file = true order by name", list.to_string_lossy())),
+                   vec!["deep.txt", "deep.txt", "top.txt"]);
+
+        // the roots listed could overlap, every file is found once anyway
+        let overlapping = fixture.join("overlapping.txt");
+        fs::write(&overlapping, format!("{0}/a/sub
+{0}/a
+", fixture.to_string_lossy())).unwrap();
+        let query = format!("select name from @{} where is_file = true order by name", overlapping.to_string_lossy());
+        assert_eq!(find_names(&query), vec!["deep.txt", "top.txt"]);
+
+        // the list is read from the file opened when the query was parsed
+        #[cfg(unix)]
+        {
+            let mut searcher = Searcher::new(Parser::new().parse(&query).unwrap());
+            fs::rename(&list, &overlapping).unwrap();
+            assert_eq!(search_rows(&mut searcher), vec!["deep.txt", "top.txt"]);
+        }
+    }
+
+    #[test]
+    fn test_read_root_list() {
+        let read = |list: &[u8]| -> Vec<String> {
+            let mut paths = vec![];
+            read_root_list(&mut Cursor::new(list), &mut |path| {
+                paths.push(path);
+                true
+            }).unwrap();
+            paths
+        };
+
+        assert_eq!(read(b"/a\n\n# comment\n /b \r\n/c"), vec!["/a", " /b ", "/c"]);
+        // NUL-separated paths are taken as they are, newlines and all
+        assert_eq!(read(b"/a\0#/b\0\0/c\nd\0"), vec!["/a", "#/b", "/c\nd"]);
+        assert_eq!(read(b""), Vec::<String>::new());
+    }
 
-                                match meta {
-                                    Some(ref metadata) => {
-                                        Some(metadata.file_type().is_symlink())
-                                    },
-                                    _ => None
-                                }
-                            }
-                        };
+    #[cfg(feature = "git")]
+    #[test]
+    fn test_git_status() {
+        use git2::Repository;
+        use git2::Signature;
+
+        let fixture = TempDir::new("git_status");
+        fs::create_dir_all(fixture.join("src").join("generated")).unwrap();
+        fs::write(fixture.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(fixture.join("src").join("clean.rs"), "clean").unwrap();
+        fs::write(fixture.join("src").join("changed.rs"), "changed").unwrap();
+        fs::write(fixture.join("src").join("staged.rs"), "staged").unwrap();
+
+        let repo = Repository::init(&fixture).unwrap();
+        let mut index = repo.index().unwrap();
+        for path in &[".gitignore", "src/clean.rs", "src/changed.rs", "src/staged.rs"] {
+            index.add_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("fselect", "fselect@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[]).unwrap();
+
+        fs::write(fixture.join("src").join("changed.rs"), "changed again").unwrap();
+        fs::write(fixture.join("src").join("staged.rs"), "staged again").unwrap();
+        index.add_path(Path::new("src/staged.rs")).unwrap();
+        index.write().unwrap();
+        fs::write(fixture.join("src").join("new.rs"), "new").unwrap();
+        fs::write(fixture.join("src").join("debug.log"), "log").unwrap();
+        fs::write(fixture.join("src").join("generated").join("out.rs"), "out").unwrap();
+
+        let root = fixture.join("src");
+        let root = root.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, git_status from '{}' order by name", root)),
+                   vec!["changed.rs\tmodified", "clean.rs\tclean", "debug.log\tignored", "generated\tuntracked",
+                        "new.rs\tuntracked", "out.rs\tuntracked", "staged.rs\tstaged"]);
+        assert_eq!(find_names(&format!("select name from '{}' where git_status = untracked and is_file = true order by name", root)),
+                   vec!["new.rs", "out.rs"]);
+    }
 
-                        if let Some(is_symlink) = is_symlink {
-                            let bool_val = str_to_bool(val);
+    #[test]
+    fn test_crosses_device() {
+        assert!(!crosses_device(None, Some(42)));
+        assert!(!crosses_device(Some(42), None));
+        assert!(!crosses_device(Some(42), Some(42)));
+        assert!(crosses_device(Some(42), Some(43)));
+    }
 
-                            result = match expr.op {
-                                Some(Op::Eq) | Some(Op::Eeq) => {
-                                    if bool_val {
-                                        is_symlink
-                                    } else {
-                                        !is_symlink
-                                    }
-                                },
-                                Some(Op::Ne) | Some(Op::Ene) => {
-                                    if bool_val {
-                                        !is_symlink
-                                    } else {
-                                        is_symlink
-                                    }
-                                },
-                                _ => false
-                            };
-                        }
-                    }
-                },
-                Field::IsPipe => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_is_pipe);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::IsCharacterDevice => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_is_char_device);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::IsBlockDevice => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_is_block_device);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::IsSocket => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_is_socket);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::Mode => {
-                    if let Some(ref val) = expr.val {
-                        let mode = match file_info {
-                            Some(ref file_info) => {
-                                match file_info.mode {
-                                    Some(mode) => Some(mode::format_mode(mode)),
-                                    _ => None
-                                }
-                            },
-                            _ => {
-                                meta = update_meta(entry, meta, follow_symlinks);
+    #[test]
+    fn test_one_filesystem() {
+        let fixture = TempDir::new("one_filesystem");
+        fs::create_dir_all(fixture.join("sub").join("deeper")).unwrap();
+        fs::File::create(fixture.join("sub").join("deeper").join("file.txt")).unwrap();
 
-                                match meta {
-                                    Some(ref metadata) => {
-                                        Some(mode::get_mode(metadata))
-                                    },
-                                    _ => None
-                                }
-                            }
-                        };
+        let root = fixture.to_string_lossy();
 
-                        if let Some(mode) = mode {
-                            result = match expr.op {
-                                Some(Op::Eq) => {
-                                    match expr.regex {
-                                        Some(ref regex) => regex.is_match(&mode),
-                                        None => val.eq(&mode)
-                                    }
-                                },
-                                Some(Op::Ne) => {
-                                    match expr.regex {
-                                        Some(ref regex) => !regex.is_match(&mode),
-                                        None => val.ne(&mode)
-                                    }
-                                },
-                                Some(Op::Rx) | Some(Op::Like) => {
-                                    match expr.regex {
-                                        Some(ref regex) => regex.is_match(&mode),
-                                        None => false
-                                    }
-                                },
-                                _ => false
-                            };
-                        }
-                    }
-                },
-                Field::UserRead => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_user_read);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::UserWrite => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_user_write);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::UserExec => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_user_exec);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::GroupRead => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_group_read);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::GroupWrite => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_group_write);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::GroupExec => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_group_exec);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::OtherRead => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_other_read);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::OtherWrite => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_other_write);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::OtherExec => {
-                    let (res_, meta_) = confirm_file_mode(&expr.op, &expr.val, &entry, meta, &file_info, follow_symlinks, &mode::mode_other_exec);
-                    meta = meta_;
-                    result = res_;
-                },
-                Field::IsHidden => {
-                    if let Some(ref val) = expr.val {
-                        let is_hidden = match file_info {
-                            Some(ref file_info) => is_hidden(&file_info.name, &None, true),
-                            _ => is_hidden(&entry.file_name().to_string_lossy(), &meta, false)
-                        };
+        assert_eq!(find_names(&format!("select name from '{}' onefilesystem order by name", root)),
+                   vec!["deeper", "file.txt", "sub"]);
 
-                        let bool_val = str_to_bool(val);
+        if let Some(device) = mode::get_device(&fs::metadata(&fixture).unwrap()) {
+            assert_eq!(count_found(&format!("select name from '{}' where device = {}", root, device)), 3);
+            assert_eq!(count_found(&format!("select name from '{}' where device != {}", root, device)), 0);
+        }
+    }
 
-                        result = match expr.op {
-                            Some(Op::Eq) | Some(Op::Eeq) => {
-                                if bool_val {
-                                    is_hidden
-                                } else {
-                                    !is_hidden
-                                }
-                            },
-                            Some(Op::Ne) | Some(Op::Ene) => {
-                                if bool_val {
-                                    !is_hidden
-                                } else {
-                                    is_hidden
-                                }
-                            },
-                            _ => false
-                        };
-                    }
-                },
-                Field::Created => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+    #[test]
+    fn test_hash_fields() {
+        let fixture = TempDir::new("hash");
+        fs::create_dir_all(fixture.join("sub")).unwrap();
+        fs::File::create(fixture.join("a.txt")).unwrap().write_all(b"abc").unwrap();
+        fs::File::create(fixture.join("sub").join("b.txt")).unwrap().write_all(b"abc").unwrap();
+        fs::File::create(fixture.join("c.txt")).unwrap().write_all(b"abd").unwrap();
 
-                    if let Some(ref _val) = expr.val {
-                        meta = update_meta(entry, meta, follow_symlinks);
+        let root = fixture.to_string_lossy();
 
-                        if let Some(ref metadata) = meta {
-                            if let Ok(sdt) = metadata.created() {
-                                let dt: DateTime<Local> = DateTime::from(sdt);
-                                let start = expr.dt_from.unwrap();
-                                let finish = expr.dt_to.unwrap();
+        assert_eq!(count_found(&format!("select path from '{}' where sha256 = ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad", root)), 2);
+        assert_eq!(count_found(&format!("select path, md5 from '{}' where sha1 = A9993E364706816ABA3E25717850C26C9CD0D89D", root)), 2);
+        assert_eq!(count_found(&format!("select path from '{}' where md5 like '9001%' or name = sub", root)), 3);
+    }
 
-                                result = match expr.op {
-                                    Some(Op::Eeq) => dt == start,
-                                    Some(Op::Ene) => dt != start,
-                                    Some(Op::Eq) => dt >= start && dt <= finish,
-                                    Some(Op::Ne) => dt < start || dt > finish,
-                                    Some(Op::Gt) => dt > finish,
-                                    Some(Op::Gte) => dt >= start,
-                                    Some(Op::Lt) => dt < start,
-                                    Some(Op::Lte) => dt <= finish,
-                                    _ => false
-                                };
-                            }
-                        }
-                    }
-                },
-                Field::Accessed => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+    fn find_names(query: &str) -> Vec<String> {
+        let mut searcher = Searcher::new(Parser::new().parse(query).unwrap());
 
-                    if let Some(ref _val) = expr.val {
-                        meta = update_meta(entry, meta, follow_symlinks);
+        search_rows(&mut searcher)
+    }
 
-                        if let Some(ref metadata) = meta {
-                            if let Ok(sdt) = metadata.accessed() {
-                                let dt: DateTime<Local> = DateTime::from(sdt);
-                                let start = expr.dt_from.unwrap();
-                                let finish = expr.dt_to.unwrap();
+    /// Rows as the utility prints them by default, without the trailing tab.
+    fn search_rows(searcher: &mut Searcher) -> Vec<String> {
+        let mut rows = vec![];
+        searcher.search(&mut |record| {
+            rows.push(record.values.join("\t").trim_end().to_string());
+            true
+        });
 
-                                result = match expr.op {
-                                    Some(Op::Eeq) => dt == start,
-                                    Some(Op::Ene) => dt != start,
-                                    Some(Op::Eq) => dt >= start && dt <= finish,
-                                    Some(Op::Ne) => dt < start || dt > finish,
-                                    Some(Op::Gt) => dt > finish,
-                                    Some(Op::Gte) => dt >= start,
-                                    Some(Op::Lt) => dt < start,
-                                    Some(Op::Lte) => dt <= finish,
-                                    _ => false
-                                };
-                            }
-                        }
-                    }
-                },
-                Field::Modified => {
-                    if let Some(ref _val) = expr.val {
-                        let dt = match file_info {
-                            Some(ref file_info) => Some(to_local_datetime(&file_info.modified)),
-                            _ => {
-                                meta = update_meta(entry, meta, follow_symlinks);
-                                match meta {
-                                    Some(ref metadata) => {
-                                        match metadata.modified() {
-                                            Ok(sdt) => Some(DateTime::from(sdt)),
-                                            _ => None
-                                        }
-                                    },
-                                    _ => None
-                                }
-                            }
-                        };
+        rows
+    }
 
-                        if let Some(dt) = dt {
-                            let start = expr.dt_from.unwrap();
-                            let finish = expr.dt_to.unwrap();
+    #[test]
+    fn test_depth_limits() {
+        let fixture = TempDir::new("depth");
+        let l3 = fixture.join("l1").join("l2").join("l3");
+        fs::create_dir_all(&l3).unwrap();
+        fs::File::create(fixture.join("f1")).unwrap();
+        fs::File::create(fixture.join("l1").join("f2")).unwrap();
+        fs::File::create(fixture.join("l1").join("l2").join("f3")).unwrap();
+        fs::File::create(l3.join("f4")).unwrap();
+
+        let root = fixture.to_string_lossy();
+        let find = |options: &str| find_names(&format!("select name from '{}' {} order by name", root, options));
+
+        assert_eq!(find(""), vec!["f1", "f2", "f3", "f4", "l1", "l2", "l3"]);
+        assert_eq!(find("maxdepth 1"), vec!["f1", "l1"]);
+        assert_eq!(find("depth 2"), vec!["f1", "f2", "l1", "l2"]);
+        assert_eq!(find("mindepth 1"), vec!["f1", "f2", "f3", "f4", "l1", "l2", "l3"]);
+        assert_eq!(find("mindepth 2"), vec!["f2", "f3", "f4", "l2", "l3"]);
+        assert_eq!(find("mindepth 4"), vec!["f4"]);
+        assert_eq!(find("mindepth 5"), Vec::<String>::new());
+        assert_eq!(find("mindepth 2 maxdepth 3"), vec!["f2", "f3", "l2", "l3"]);
+        assert_eq!(find("mindepth 3 maxdepth 3"), vec!["f3", "l3"]);
+        assert_eq!(find("mindepth 3 maxdepth 2"), Vec::<String>::new());
+    }
 
-                            result = match expr.op {
-                                Some(Op::Eeq) => dt == start,
-                                Some(Op::Ene) => dt != start,
-                                Some(Op::Eq) => dt >= start && dt <= finish,
-                                Some(Op::Ne) => dt < start || dt > finish,
-                                Some(Op::Gt) => dt > finish,
-                                Some(Op::Gte) => dt >= start,
-                                Some(Op::Lt) => dt < start,
-                                Some(Op::Lte) => dt <= finish,
-                                _ => false
-                            };
-                        }
-                    }
-                },
-                Field::HasXattrs => {
-                    #[cfg(unix)]
-                        {
-                            if file_info.is_some() {
-                                return (false, meta, dim, mp3)
-                            }
+    #[test]
+    fn test_depth_field() {
+        let fixture = TempDir::new("depth_field");
+        fs::create_dir_all(fixture.join("l1").join("l2")).unwrap();
+        fs::write(fixture.join("f1"), "same").unwrap();
+        fs::write(fixture.join("l1").join("l2").join("f3"), "same").unwrap();
+        fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/nested.zip"), fixture.join("l1").join("nested.zip")).unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, depth from '{}' where is_dir = true or name = f3 order by depth desc", root)),
+                   vec!["f3\t3", "l2\t2", "l1\t1"]);
+        assert_eq!(find_names(&format!("select name from '{}' mindepth 2 where depth = 2 and is_file = true order by name", root)),
+                   vec!["nested.zip"]);
+
+        // archive members are at the depth of the archive however deep inside it they are
+        assert_eq!(find_names(&format!("select name, depth from '{}' archives where name like '%.txt' order by name", root)),
+                   vec!["[nested.zip] readme.txt\t2"]);
+        assert_eq!(find_names(&format!("select name from '{}' archives where depth = 2 and name like '%!%' order by name", root)),
+                   vec!["[nested.zip] libs/inner.jar!META-INF/MANIFEST.MF", "[nested.zip] libs/inner.jar!lib/deep.zip"]);
+
+        // duplicates keep the depth they were found at
+        assert_eq!(find_names(&format!("select name, depth from '{}' where size = 4 dupes order by depth", root)),
+                   vec!["f1\t1", "f3\t3"]);
+    }
 
-                            if let Some(ref val) = expr.val {
-                                if let Ok(file) = File::open(&entry.path()) {
-                                    if let Ok(xattrs) = file.list_xattr() {
-                                        let has_xattrs = xattrs.count() > 0;
-                                        let bool_val = str_to_bool(val);
-
-                                        result = match &expr.op {
-                                            Some(Op::Eq) | Some(Op::Eeq) => {
-                                                if bool_val {
-                                                    has_xattrs
-                                                } else {
-                                                    !has_xattrs
-                                                }
-                                            },
-                                            Some(Op::Ne) | Some(Op::Ene) => {
-                                                if bool_val {
-                                                    !has_xattrs
-                                                } else {
-                                                    has_xattrs
-                                                }
-                                            },
-                                            _ => false
-                                        };
-                                    }
-                                }
-                            }
-                        }
-                },
-                Field::IsShebang => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+    #[test]
+    fn test_offset() {
+        let fixture = TempDir::new("offset");
+        for name in &["a", "b", "c", "d", "e"] {
+            fs::File::create(fixture.join(name)).unwrap();
+        }
 
-                    result = is_shebang(&entry.path())
-                },
-                Field::Width => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+        let root = fixture.to_string_lossy();
 
-                    if !is_image_dim_readable(&entry.file_name().to_string_lossy()) {
-                        return (false, meta, dim, mp3)
-                    }
+        assert_eq!(find_names(&format!("select name from '{}' order by name limit 2 offset 1", root)), vec!["b", "c"]);
+        assert_eq!(find_names(&format!("select name from '{}' order by name desc limit 2 offset 2", root)), vec!["c", "b"]);
+        assert_eq!(find_names(&format!("select name from '{}' order by name offset 3", root)), vec!["d", "e"]);
+        assert_eq!(find_names(&format!("select name from '{}' order by name limit 10 offset 5", root)), Vec::<String>::new());
 
-                    if let Some(ref val) = expr.val {
-                        dim = update_img_dimensions(&entry, dim);
+        // streamed results stop right after the requested page
+        assert_eq!(count_found(&format!("select name from '{}' limit 2 offset 1", root)), 3);
+    }
 
-                        if let Some((width, _)) = dim {
-                            let val = val.parse::<usize>();
-                            if let Ok(val) = val {
-                                result = match expr.op {
-                                    Some(Op::Eq) | Some(Op::Eeq) => width == val,
-                                    Some(Op::Ne) | Some(Op::Ene) => width != val,
-                                    Some(Op::Gt) => width > val,
-                                    Some(Op::Gte) => width >= val,
-                                    Some(Op::Lt) => width < val,
-                                    Some(Op::Lte) => width <= val,
-                                    _ => false
-                                };
-                            }
-                        }
-                    }
-                },
-                Field::Height => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+    #[test]
+    fn test_limited_ordering() {
+        let fixture = TempDir::new("top");
+        for &(name, size) in &[("a", 3), ("b", 1), ("c", 5), ("d", 5), ("e", 4)] {
+            fs::write(fixture.join(name), vec![b'x'; size]).unwrap();
+        }
 
-                    if !is_image_dim_readable(&entry.file_name().to_string_lossy()) {
-                        return (false, meta, dim, mp3)
-                    }
+        let root = fixture.to_string_lossy();
 
-                    if let Some(ref val) = expr.val {
-                        dim = update_img_dimensions(&entry, dim);
+        assert_eq!(find_names(&format!("select name, size from '{}' where is_file = true order by size desc, name limit 3", root)),
+                   vec!["c\t5", "d\t5", "e\t4"]);
+        assert_eq!(find_names(&format!("select name, sha1 from '{}' where is_file = true order by size, name limit 1 offset 1", root)),
+                   vec!["a\tb60d121b438a380c343d5ec3c2037564b82ffef3"]);
+        assert_eq!(find_names(&format!("select name from '{}' where is_file = true order by 1 desc limit 2", root)),
+                   vec!["e", "d"]);
+    }
 
-                        if let Some((_, height)) = dim {
-                            let val = val.parse::<usize>();
-                            if let Ok(val) = val {
-                                result = match expr.op {
-                                    Some(Op::Eq) | Some(Op::Eeq) => height == val,
-                                    Some(Op::Ne) | Some(Op::Ene) => height != val,
-                                    Some(Op::Gt) => height > val,
-                                    Some(Op::Gte) => height >= val,
-                                    Some(Op::Lt) => height < val,
-                                    Some(Op::Lte) => height <= val,
-                                    _ => false
-                                };
-                            }
-                        }
-                    }
-                },
-                Field::Bitrate => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+    #[test]
+    fn test_union() {
+        let fixture = TempDir::new("union");
+        fs::create_dir_all(fixture.join("images")).unwrap();
+        fs::create_dir_all(fixture.join("videos")).unwrap();
+        for &(name, size) in &[("images/a.jpg", 3), ("images/b.png", 6), ("videos/c.mp4", 5), ("videos/d.avi", 1)] {
+            fs::write(fixture.join(name), vec![b'x'; size]).unwrap();
+        }
 
-                    if let Some(ref val) = expr.val {
-                        mp3 = update_mp3_meta(&entry, mp3);
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, size from '{0}/images' where size > 2 \
+                                        union select name, size from '{0}/videos' where size > 2 \
+                                        order by size desc", root)),
+                   vec!["b.png\t6", "c.mp4\t5", "a.jpg\t3"]);
+        assert_eq!(find_names(&format!("select name from '{0}/images' union select path from '{0}/videos' where name = d.avi \
+                                        order by size limit 2", root)),
+                   vec![format!("{}/videos/d.avi", root), String::from("a.jpg")]);
+        assert_eq!(find_names(&format!("select count(*) from '{0}/images' union select count(*) from '{0}/videos' where size > 2 \
+                                        order by 1", root)),
+                   vec!["1", "2"]);
+    }
 
-                        if let Some(ref mp3_meta) = mp3 {
-                            let val = val.parse::<usize>();
-                            if let Ok(val) = val {
-                                let bitrate = mp3_meta.frames[0].bitrate as usize;
-                                result = match expr.op {
-                                    Some(Op::Eq) | Some(Op::Eeq) => bitrate == val,
-                                    Some(Op::Ne) | Some(Op::Ene) => bitrate != val,
-                                    Some(Op::Gt) => bitrate > val,
-                                    Some(Op::Gte) => bitrate >= val,
-                                    Some(Op::Lt) => bitrate < val,
-                                    Some(Op::Lte) => bitrate <= val,
-                                    _ => false
-                                };
-                            }
-                        }
-                    }
-                },
-                Field::Freq => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+    #[test]
+    fn test_change_and_creation_times() {
+        let fixture = TempDir::new("times");
+        fs::write(fixture.join("new.txt"), "new").unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        #[cfg(unix)]
+        assert_eq!(find_names(&format!("select name from '{}' where changed gte '-1h' and ctime lte today order by name", root)),
+                   vec!["new.txt"]);
+        assert_eq!(find_names(&format!("select name from '{}' where created gte '-1h' order by name", root)),
+                   vec!["new.txt"]);
+        assert_eq!(find_names(&format!("select name from '{}' where created lt '-1h' order by name", root)),
+                   Vec::<String>::new());
+
+        let modified = ::std::time::UNIX_EPOCH + ::std::time::Duration::from_secs(1_000_000_000);
+        fs::File::options().write(true).open(fixture.join("new.txt")).unwrap()
+            .set_times(fs::FileTimes::new().set_modified(modified)).unwrap();
+        let metadata = fs::metadata(fixture.join("new.txt")).unwrap();
+        let format = |sdt| DateTime::<Local>::from(sdt).format("%Y-%m-%d %H:%M:%S").to_string();
+
+        let created = metadata.created().unwrap_or(modified);
+        assert_eq!(find_names(&format!("select created, modified from '{}' order by 1", root)),
+                   vec![format!("{}\t{}", format(created), format(modified))]);
+        #[cfg(unix)]
+        assert_eq!(find_names(&format!("select changed from '{}' order by 1", root)),
+                   vec![format(mode::get_ctime(&Box::new(metadata.clone())).unwrap())]);
+    }
 
-                    if let Some(ref val) = expr.val {
-                        mp3 = update_mp3_meta(&entry, mp3);
+    fn write_tar<W: Write>(writer: W) -> W {
+        let mut builder = tar::Builder::new(writer);
 
-                        if let Some(ref mp3_meta) = mp3 {
-                            let val = val.parse::<usize>();
-                            if let Ok(val) = val {
-                                let freq = mp3_meta.frames[0].sampling_freq as usize;
-                                result = match expr.op {
-                                    Some(Op::Eq) | Some(Op::Eeq) => freq == val,
-                                    Some(Op::Ne) | Some(Op::Ene) => freq != val,
-                                    Some(Op::Gt) => freq > val,
-                                    Some(Op::Gte) => freq >= val,
-                                    Some(Op::Lt) => freq < val,
-                                    Some(Op::Lte) => freq <= val,
-                                    _ => false
-                                };
-                            }
-                        }
-                    }
-                },
-                Field::Title => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_size(0);
+        header.set_cksum();
+        builder.append_data(&mut header, "docs", io::empty()).unwrap();
 
-                    if let Some(ref val) = expr.val {
-                        mp3 = update_mp3_meta(&entry, mp3);
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o640);
+        header.set_size(5);
+        header.set_cksum();
+        builder.append_data(&mut header, "docs/readme.txt", "hello".as_bytes()).unwrap();
 
-                        if let Some(ref mp3_meta) = mp3 {
-                            if let Some(ref mp3_tag) = mp3_meta.tag {
-                                let title = &mp3_tag.title;
-                                result = match expr.op {
-                                    Some(Op::Eq) | Some(Op::Eeq) => {
-                                        match expr.regex {
-                                            Some(ref regex) => regex.is_match(title),
-                                            None => val.eq(title)
-                                        }
-                                    },
-                                    Some(Op::Ne) | Some(Op::Ene) => {
-                                        match expr.regex {
-                                            Some(ref regex) => !regex.is_match(title),
-                                            None => val.ne(title)
-                                        }
-                                    },
-                                    Some(Op::Rx) | Some(Op::Like) => {
-                                        match expr.regex {
-                                            Some(ref regex) => regex.is_match(title),
-                                            None => false
-                                        }
-                                    },
-                                    _ => false
-                                };
-                            }
-                        }
-                    }
-                },
-                Field::Artist => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+        builder.into_inner().unwrap()
+    }
 
-                    if let Some(ref val) = expr.val {
-                        mp3 = update_mp3_meta(&entry, mp3);
+    #[test]
+    fn test_tar_archives() {
+        let fixture = TempDir::new("tar");
+        fs::create_dir_all(fixture.join("plain")).unwrap();
+        fs::create_dir_all(fixture.join("packed")).unwrap();
 
-                        if let Some(ref mp3_meta) = mp3 {
-                            if let Some(ref mp3_tag) = mp3_meta.tag {
-                                let artist = &mp3_tag.artist;
+        write_tar(fs::File::create(fixture.join("plain").join("docs.tar")).unwrap());
+        write_tar(GzEncoder::new(fs::File::create(fixture.join("packed").join("docs.tgz")).unwrap(), Compression::default())).finish().unwrap();
 
-                                result = match expr.op {
-                                    Some(Op::Eq) | Some(Op::Eeq) => {
-                                        match expr.regex {
-                                            Some(ref regex) => regex.is_match(artist),
-                                            None => val.eq(artist)
-                                        }
-                                    },
-                                    Some(Op::Ne) | Some(Op::Ene) => {
-                                        match expr.regex {
-                                            Some(ref regex) => !regex.is_match(artist),
-                                            None => val.ne(artist)
-                                        }
-                                    },
-                                    Some(Op::Rx) | Some(Op::Like) => {
-                                        match expr.regex {
-                                            Some(ref regex) => regex.is_match(artist),
-                                            None => false
-                                        }
-                                    },
-                                    _ => false
-                                };
-                            }
-                        }
-                    }
-                },
-                Field::Album => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+        let root = fixture.to_string_lossy();
 
-                    if let Some(ref val) = expr.val {
-                        mp3 = update_mp3_meta(&entry, mp3);
+        for &(dir, archive) in &[("plain", "docs.tar"), ("packed", "docs.tgz")] {
+            let query = format!("select name, size, mode from '{}/{}' archives where name like 'docs/%' order by name", root, dir);
+            let expected = vec![
+                format!("[{}] docs/\t0\trwxr-xr-x", archive),
+                format!("[{}] docs/readme.txt\t5\trw-r-----", archive),
+            ];
+            assert_eq!(find_names(&query), expected);
+        }
 
-                        if let Some(ref mp3_meta) = mp3 {
-                            if let Some(ref mp3_tag) = mp3_meta.tag {
-                                let album = &mp3_tag.album;
+        assert_eq!(find_names(&format!("select name from '{}' archives where is_dir = true and name like 'docs/%' order by name", root)),
+                   vec!["[docs.tar] docs/", "[docs.tgz] docs/"]);
+        assert_eq!(find_names(&format!("select name from '{}' where name like 'docs/%'", root)), Vec::<String>::new());
+    }
 
-                                result = match expr.op {
-                                    Some(Op::Eq) | Some(Op::Eeq) => {
-                                        match expr.regex {
-                                            Some(ref regex) => regex.is_match(album),
-                                            None => val.eq(album)
-                                        }
-                                    },
-                                    Some(Op::Ne) | Some(Op::Ene) => {
-                                        match expr.regex {
-                                            Some(ref regex) => !regex.is_match(album),
-                                            None => val.ne(album)
-                                        }
-                                    },
-                                    Some(Op::Rx) | Some(Op::Like) => {
-                                        match expr.regex {
-                                            Some(ref regex) => regex.is_match(album),
-                                            None => false
-                                        }
-                                    },
-                                    _ => false
-                                };
-                            }
-                        }
-                    }
-                },
-                Field::Year => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+    #[test]
+    fn test_date_format() {
+        let fixture = TempDir::new("date_format");
+
+        let mut builder = tar::Builder::new(fs::File::create(fixture.join("dates.tar")).unwrap());
+        for &(name, mtime) in &[("a.txt", 1009281600), ("b.txt", 1483444800), ("c.txt", 1308139200)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(0o644);
+            header.set_size(0);
+            header.set_mtime(mtime);
+            header.set_cksum();
+            builder.append_data(&mut header, name, io::empty()).unwrap();
+        }
+        builder.finish().unwrap();
 
-                    if let Some(ref val) = expr.val {
-                        mp3 = update_mp3_meta(&entry, mp3);
+        let root = fixture.to_string_lossy();
 
-                        if let Some(ref mp3_meta) = mp3 {
-                            let val = val.parse::<usize>();
-                            if let Ok(val) = val {
-                                if let Some(ref mp3_tag) = mp3_meta.tag {
-                                    let year = mp3_tag.year as usize;
-                                    if year > 0 {
-                                        result = match expr.op {
-                                            Some(Op::Eq) | Some(Op::Eeq) => year == val,
-                                            Some(Op::Ne) | Some(Op::Ene) => year != val,
-                                            Some(Op::Gt) => year > val,
-                                            Some(Op::Gte) => year >= val,
-                                            Some(Op::Lt) => year < val,
-                                            Some(Op::Lte) => year <= val,
-                                            _ => false
-                                        };
-                                    }
-                                }
-                            }
-                        }
-                    }
-                },
-                Field::Genre => {
-                    if file_info.is_some() {
-                        return (false, meta, dim, mp3)
-                    }
+        assert_eq!(find_names(&format!("select name, modified from '{}' archives where name like '%.txt' order by modified desc dateformat '%d.%m.%Y'", root)),
+                   vec!["[dates.tar] b.txt\t03.01.2017", "[dates.tar] c.txt\t15.06.2011", "[dates.tar] a.txt\t25.12.2001"]);
+        assert_eq!(find_names(&format!("select name, modified from '{}' archives where modified lt 2012-01-01 order by 2 into tabs dateformat '%Y'", root)),
+                   vec!["[dates.tar] a.txt\t2001", "[dates.tar] c.txt\t2011"]);
+        assert_eq!(find_names(&format!("select max(modified), min(modified) from '{}' archives where name like '%.txt' dateformat '%m/%Y'", root)),
+                   vec!["01/2017\t12/2001"]);
 
-                    if let Some(ref val) = expr.val {
-                        mp3 = update_mp3_meta(&entry, mp3);
+        let rows = find_names(&format!("select modified from '{}' archives where name = '*b.txt' order by 1 dateformat iso", root));
+        assert_eq!(DateTime::parse_from_rfc3339(&rows[0]).unwrap().timestamp(), 1483444800);
+    }
 
-                        if let Some(ref mp3_meta) = mp3 {
-                            if let Some(ref mp3_tag) = mp3_meta.tag {
-                                let genre = &format!("{:?}", &mp3_tag.genre);
+    fn write_zip<W: Write + io::Seek>(writer: W) -> W {
+        let mut zip = zip::ZipWriter::new(writer);
 
-                                result = match expr.op {
-                                    Some(Op::Eq) | Some(Op::Eeq) => {
-                                        match expr.regex {
-                                            Some(ref regex) => regex.is_match(genre),
-                                            None => val.eq(genre)
-                                        }
-                                    },
-                                    Some(Op::Ne) | Some(Op::Ene) => {
-                                        match expr.regex {
-                                            Some(ref regex) => !regex.is_match(genre),
-                                            None => val.ne(genre)
-                                        }
-                                    },
-                                    Some(Op::Rx) | Some(Op::Like) => {
-                                        match expr.regex {
-                                            Some(ref regex) => regex.is_match(genre),
-                                            None => false
-                                        }
-                                    },
-                                    _ => false
-                                };
-                            }
-                        }
-                    }
-                },
-                Field::IsArchive => {
-                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_archive);
-                },
-                Field::IsAudio => {
-                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_audio);
-                },
-                Field::IsBook => {
-                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_book);
-                },
-                Field::IsDoc => {
-                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_doc);
-                },
-                Field::IsImage => {
-                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_image);
-                },
-                Field::IsSource => {
-                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_source);
-                },
-                Field::IsVideo => {
-                    result = confirm_file_ext(&expr.op, &expr.val, &entry, &file_info, &is_video);
-                }
-            }
+        for &(name, modified, mode) in &[("new.txt", "2020-06-15", 0o644), ("old.sh", "2010-01-15", 0o755)] {
+            let options = zip::write::FileOptions::default()
+                .compression_method(zip::CompressionMethod::Stored)
+                .last_modified_time(time::strptime(&format!("{} 12:00", modified), "%Y-%m-%d %H:%M").unwrap())
+                .unix_permissions(mode);
+            zip.start_file(name, options).unwrap();
+            zip.write_all(b"hello").unwrap();
+        }
+
+        zip.finish().unwrap()
+    }
+
+    #[test]
+    fn test_compression_ratio() {
+        let fixture = TempDir::new("compression");
+        fs::File::create(fixture.join("plain.txt")).unwrap().write_all(b"plain").unwrap();
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(fixture.join("files.zip")).unwrap());
+        for &(name, method, ref content) in &[("big.txt", zip::CompressionMethod::Deflated, vec![b'a'; 10000]),
+                                          ("stored.bin", zip::CompressionMethod::Stored, vec![7; 100]),
+                                          ("empty.txt", zip::CompressionMethod::Stored, vec![])] {
+            zip.start_file(name, zip::write::FileOptions::default().compression_method(method)).unwrap();
+            zip.write_all(&content).unwrap();
         }
+        zip.finish().unwrap();
+
+        let root = fixture.to_string_lossy();
+        let find = |cond: &str| find_names(&format!("select name from '{}' archives where {} order by name", root, cond));
+
+        assert_eq!(find_names(&format!("select name, compressed_size, compression_ratio from '{}' archives where name = stored.bin or name = empty.txt or name = plain.txt order by name", root)),
+                   vec!["[files.zip] empty.txt\t0", "[files.zip] stored.bin\t100\t1.00", "plain.txt"]);
+        assert_eq!(find("compression_ratio < 1.1"), vec!["[files.zip] stored.bin"]);
+        assert_eq!(find("compression_ratio = 1"), vec!["[files.zip] stored.bin"]);
+        assert_eq!(find("compression_ratio > 10.5"), vec!["[files.zip] big.txt"]);
+        assert_eq!(find("compressed_size lt 1k"), vec!["[files.zip] big.txt", "[files.zip] empty.txt", "[files.zip] stored.bin"]);
+        assert_eq!(find("compression_ratio > compressed_size"), vec!["[files.zip] big.txt"]);
+        assert_eq!(find_names(&format!("select name from '{}' archives where compressed_size gt 0 order by compression_ratio desc", root)),
+                   vec!["[files.zip] big.txt", "[files.zip] stored.bin"]);
+    }
 
-        (result, meta, dim, mp3)
+    #[test]
+    fn test_archive_limits() {
+        let fixture = TempDir::new("archive_limits");
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(fixture.join("many.zip")).unwrap());
+        for i in 0..500 {
+            zip.start_file(format!("file{:03}.txt", i), zip::write::FileOptions::default()).unwrap();
+            zip.write_all(b"data").unwrap();
+        }
+        zip.finish().unwrap();
+
+        let mut builder = tar::Builder::new(fs::File::create(fixture.join("many.tar")).unwrap());
+        for i in 0..100 {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(0o644);
+            header.set_size(0);
+            header.set_cksum();
+            builder.append_data(&mut header, format!("entry{:03}.log", i), io::empty()).unwrap();
+        }
+        builder.finish().unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(count_found(&format!("select name from '{}' archives where name like '%.txt'", root)), 500);
+        assert_eq!(count_found(&format!("select name from '{}' archives where name like '%.log'", root)), 100);
+        assert_eq!(count_found(&format!("select name from '{}' archives 20 where name like '%.txt' or name like '%.log'", root)), 40);
+        assert_eq!(find_names(&format!("select name from '{}' archives 3 where name like '%.txt' order by name", root)),
+                   vec!["[many.zip] file000.txt", "[many.zip] file001.txt", "[many.zip] file002.txt"]);
+
+        // the streamed search stops inside the archive once the limit is reached
+        assert_eq!(count_found(&format!("select name from '{}' archives where name like '%.txt' limit 5", root)), 5);
+        assert_eq!(find_names(&format!("select name from '{}' archives where name like '%.txt' order by name desc limit 2", root)),
+                   vec!["[many.zip] file499.txt", "[many.zip] file498.txt"]);
+
+        // so does the buffered one when the rows of the union aren't ordered
+        let union = format!("select name from '{}' archives where name like '%.txt' \
+                             union select name from '{}' archives where name like '%.log' limit 3 offset 1", root, root);
+        assert_eq!(find_names(&union), vec!["[many.zip] file001.txt", "[many.zip] file002.txt", "[many.zip] file003.txt"]);
+        assert_eq!(count_found(&union), 4);
+
+        let tar_size = fs::metadata(fixture.join("many.tar")).unwrap().len();
+        let zip_size = fs::metadata(fixture.join("many.zip")).unwrap().len();
+        assert!(zip_size < tar_size);
+        assert_eq!(count_found(&format!("select name from '{}' archives maxarchivesize {} where name like '%.txt' or name like '%.log'", root, zip_size)), 500);
+        assert_eq!(count_found(&format!("select name from '{}' archives maxarchivesize {} where name like '%.txt' or name like '%.log'", root, tar_size)), 600);
+        assert_eq!(count_found(&format!("select name from '{}' archives maxarchivesize 1k", root)), 2);
     }
-}
 
-fn confirm_file_mode(expr_op: &Option<Op>,
-                     expr_val: &Option<String>,
-                     entry: &DirEntry,
-                     meta: Option<Box<Metadata>>,
-                     file_info: &Option<FileInfo>,
-                     follow_symlinks: bool,
-                     mode_func: &Fn(u32) -> bool) -> (bool, Option<Box<Metadata>>) {
-    let mut result = false;
-    let mut meta = meta;
+    #[test]
+    fn test_video_metadata() {
+        let fixture = TempDir::new("video");
+        fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/video.mp4"), fixture.join("movie.mp4")).unwrap();
+        fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/video.mkv"), fixture.join("clip.mkv")).unwrap();
+        fs::write(fixture.join("notes.txt"), "not a video").unwrap();
+
+        let mut builder = tar::Builder::new(fs::File::create(fixture.join("videos.tar")).unwrap());
+        builder.append_path_with_name(fixture.join("movie.mp4"), "archived.mp4").unwrap();
+        builder.finish().unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, video_width, video_height, video_duration, video_codec from '{}' order by name", root)),
+                   vec!["clip.mkv\t1280\t720\t95\tvp9", "movie.mp4\t3840\t2160\t12\th264", "notes.txt", "videos.tar"]);
+        assert_eq!(find_names(&format!("select name from '{}' where video_height >= 2160 order by name", root)), vec!["movie.mp4"]);
+        assert_eq!(find_names(&format!("select name from '{}' where video_duration > 60 or video_codec = h264 order by name", root)),
+                   vec!["clip.mkv", "movie.mp4"]);
+        assert_eq!(find_names(&format!("select name from '{}' where video_codec like 'vp%' order by name", root)), vec!["clip.mkv"]);
+        assert_eq!(find_names(&format!("select max(video_width), sum(video_duration) from '{}'", root)), vec!["3840\t107"]);
+
+        // archived files are not read
+        assert_eq!(find_names(&format!("select name from '{}' archives where video_width > 0 order by name", root)),
+                   vec!["clip.mkv", "movie.mp4"]);
+    }
 
-    if let Some(ref val) = expr_val {
-        let mode = match file_info {
-            Some(ref file_info) => file_info.mode,
-            _ => {
-                meta = update_meta(entry, meta, follow_symlinks);
+    #[test]
+    #[cfg(unix)]
+    fn test_zip_owners() {
+        let fixture = TempDir::new("zip_owners");
+        fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/owners.zip"), fixture.join("owners.zip")).unwrap();
+
+        let root = fixture.to_string_lossy();
+        let find = |options: &str, cond: &str| find_names(&format!("select name from '{}' archives {} where name like '%.txt' and ({}) order by name", root, options, cond));
+
+        assert_eq!(find_names(&format!("select name, uid, gid, user from '{}' archives where name like '%.txt' order by name", root)),
+                   vec!["[owners.zip] alien.txt\t4242\t4343\t4242", "[owners.zip] plain.txt", "[owners.zip] root.txt\t0\t0\troot"]);
+
+        assert_eq!(find("", "user = root"), vec!["[owners.zip] root.txt"]);
+        assert_eq!(find("", "uid = 4242"), vec!["[owners.zip] alien.txt"]);
+        assert_eq!(find("", "group = 4343 or gid = 0"), vec!["[owners.zip] alien.txt", "[owners.zip] root.txt"]);
+        // the entry without the unix extra field has no owner
+        assert_eq!(find("", "uid gte 0"), vec!["[owners.zip] alien.txt", "[owners.zip] root.txt"]);
+        assert_eq!(find("lenient", "uid gt 100"), vec!["[owners.zip] alien.txt", "[owners.zip] plain.txt"]);
+    }
 
-                match meta {
-                    Some(ref metadata) => mode::get_mode_from_boxed_unix_int(metadata),
-                    _ => None
-                }
-            }
+    #[test]
+    fn test_nested_archives() {
+        let fixture = TempDir::new("nested_zip");
+        fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/nested.zip"), fixture.join("nested.zip")).unwrap();
+
+        let root = fixture.to_string_lossy();
+        let find = |options: &str| find_names(&format!("select name from '{}' archives {} where is_file = true order by name", root, options));
+
+        assert_eq!(find(""), vec![
+            "[nested.zip] libs/inner.jar",
+            "[nested.zip] libs/inner.jar!META-INF/MANIFEST.MF",
+            "[nested.zip] libs/inner.jar!lib/deep.zip",
+            "[nested.zip] readme.txt",
+            "nested.zip",
+        ]);
+        assert_eq!(find("archivedepth 3"), vec![
+            "[nested.zip] libs/inner.jar",
+            "[nested.zip] libs/inner.jar!META-INF/MANIFEST.MF",
+            "[nested.zip] libs/inner.jar!lib/deep.zip",
+            "[nested.zip] libs/inner.jar!lib/deep.zip!secret.txt",
+            "[nested.zip] readme.txt",
+            "nested.zip",
+        ]);
+        assert_eq!(find("archivedepth 1"), vec!["[nested.zip] libs/inner.jar", "[nested.zip] readme.txt", "nested.zip"]);
+        assert_eq!(find("archivedepth 3 maxnestedsize 100"), vec!["[nested.zip] libs/inner.jar", "[nested.zip] readme.txt", "nested.zip"]);
+
+        // the archive read into memory is hashed the same way as the one that isn't
+        let hash = |options: &str| find_names(&format!("select md5 from '{}' archives {} where name like '%.jar' order by name", root, options));
+        assert_eq!(hash("").len(), 1);
+        assert_eq!(hash(""), hash("archivedepth 1"));
+    }
+
+    #[test]
+    fn test_nested_archive_longer_than_recorded() {
+        let fixture = TempDir::new("nested_zip_size");
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("inner.zip", zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated)).unwrap();
+        zip.write_all(&b"not quite an archive ".repeat(20)).unwrap();
+        let mut data = zip.finish().unwrap().into_inner();
+
+        // the headers claim the member is 50 bytes long, while it inflates to 420
+        for &(signature, offset) in &[(b"PK\x03\x04", 22), (b"PK\x01\x02", 24)] {
+            let start = data.windows(4).position(|window| window == signature).unwrap() + offset;
+            data[start..start + 4].copy_from_slice(&50u32.to_le_bytes());
+        }
+        fs::write(fixture.join("outer.zip"), data).unwrap();
+
+        let root = fixture.to_string_lossy();
+        let find = |options: &str| {
+            let query = format!("select name, md5 from '{}' archives {} where name like '%inner.zip'", root, options);
+            let mut searcher = Searcher::new(Parser::new().parse(&query).unwrap());
+            searcher.set_quiet(true);
+            (search_rows(&mut searcher), searcher.has_errors())
         };
 
-        if let Some(mode) = mode {
-            let bool_val = str_to_bool(val);
+        let (rows, has_errors) = find("archivedepth 1");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].split('\t').nth(1).map(|md5| md5.len()), Some(32));
+        assert!(!has_errors);
 
-            result = match expr_op {
-                Some(Op::Eq) => {
-                    if bool_val {
-                        mode_func(mode)
-                    } else {
-                        !mode_func(mode)
-                    }
-                },
-                Some(Op::Ne) => {
-                    if bool_val {
-                        !mode_func(mode)
-                    } else {
-                        mode_func(mode)
-                    }
-                },
-                _ => false
-            };
+        // what is read up to the limit is not the whole member, so it has no hash
+        let (rows, has_errors) = find("archivedepth 2 maxnestedsize 100");
+        assert_eq!(rows, vec!["[outer.zip] inner.zip"]);
+        assert!(has_errors);
+    }
+
+    #[test]
+    fn test_archive_conditions() {
+        let fixture = TempDir::new("zip");
+        fs::create_dir_all(fixture.join("zipped")).unwrap();
+        write_zip(fs::File::create(fixture.join("zipped").join("files.zip")).unwrap());
+
+        let root = fixture.to_string_lossy();
+        let find = |options: &str, cond: &str| find_names(&format!("select name from '{}' archives {} where (name = new.txt or name = old.sh) and {} order by name", root, options, cond));
+
+        // modification dates and mode bits are stored in the archive
+        assert_eq!(find("", "modified gt 2015-01-01"), vec!["[files.zip] new.txt"]);
+        assert_eq!(find("", "user_exec = true"), vec!["[files.zip] old.sh"]);
+
+        // owners are not, such conditions don't match by default
+        assert_eq!(find("", "uid gte 0"), Vec::<String>::new());
+        assert_eq!(find("", "(uid gte 0 or size = 5)"), vec!["[files.zip] new.txt", "[files.zip] old.sh"]);
+
+        // but are skipped in lenient mode
+        assert_eq!(find("lenient", "uid gte 0"), vec!["[files.zip] new.txt", "[files.zip] old.sh"]);
+        assert_eq!(find("lenient", "not uid gte 0 and modified lt 2015-01-01"), vec!["[files.zip] old.sh"]);
+        assert_eq!(find("lenient", "(uid gte 0 or name like '%.txt')"), vec!["[files.zip] new.txt"]);
+    }
+
+    #[test]
+    fn test_archive_column() {
+        let fixture = TempDir::new("archive_column");
+        fs::create_dir_all(fixture.join("zipped")).unwrap();
+        write_zip(fs::File::create(fixture.join("zipped").join("files.zip")).unwrap());
+        fs::write(fixture.join("plain.txt"), "").unwrap();
+
+        let root = fixture.to_string_lossy();
+        let archive = fixture.join("zipped").join("files.zip").to_string_lossy().to_string();
+
+        // members keep the archive in their names unless it's a column of its own
+        assert_eq!(find_names(&format!("select name from '{}' archives where name like '%.txt' order by name", root)),
+                   vec!["[files.zip] new.txt", "plain.txt"]);
+        assert_eq!(find_names(&format!("select archive, name, path from '{}' archives where name like '%.txt' order by name", root)),
+                   vec![format!("{}\tnew.txt\tnew.txt", archive), format!("\tplain.txt\t{}", fixture.join("plain.txt").to_string_lossy())]);
+
+        assert_eq!(find_names(&format!("select name, archive from '{}' archives where archive like '%.zip' order by name", root)),
+                   vec![format!("new.txt\t{}", archive), format!("old.sh\t{}", archive)]);
+        assert_eq!(find_names(&format!("select name from '{}' archives where archive = '' and is_file = true order by name", root)),
+                   vec!["files.zip", "plain.txt"]);
+    }
+
+    #[test]
+    fn test_root_option_defaults() {
+        let fixture = TempDir::new("root_defaults");
+        for dir in &["x/sub", "y/sub"] {
+            fs::create_dir_all(fixture.join(dir)).unwrap();
+            fs::write(fixture.join(dir).join("deep.txt"), "").unwrap();
         }
+        write_zip(fs::File::create(fixture.join("x").join("files.zip")).unwrap());
+        write_zip(fs::File::create(fixture.join("y").join("files.zip")).unwrap());
+
+        let root = fixture.to_string_lossy();
+
+        // every root takes the defaults unless it turns them off or gives another value
+        assert_eq!(find_names(&format!("select path from options archives depth 1 '{0}/x', '{0}/y' no-archives depth 2 where name like '%.txt' order by path", root)),
+                   vec![fixture.join("y").join("sub").join("deep.txt").to_string_lossy().to_string(),
+                        format!("[{}] new.txt", fixture.join("x").join("files.zip").to_string_lossy())]);
+        assert_eq!(find_names(&format!("select name from '{0}/x' depth 1, '{0}/y' search-archives where name like '%.txt' order by name", root)),
+                   vec!["[files.zip] new.txt", "deep.txt"]);
     }
 
-    (result, meta)
-}
+    #[test]
+    fn test_archive_content_search() {
+        let fixture = TempDir::new("zip_content");
+
+        let mut jar = zip::ZipWriter::new(Cursor::new(vec![]));
+        jar.start_file("conf/inner.properties", zip::write::FileOptions::default()).unwrap();
+        jar.write_all(b"password=inner").unwrap();
+        let jar = jar.finish().unwrap().into_inner();
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(fixture.join("dist.zip")).unwrap());
+        for &(name, ref content) in &[("app.properties", b"user=admin\npassword=secret\n".to_vec()),
+                                     ("lib/plugin.jar", jar),
+                                     ("log.properties", b"level=debug\n".to_vec()),
+                                     ("notes.txt", b"password is in the properties".to_vec()),
+                                     ("large.properties", [vec![b'x'; 2000], b"password=large".to_vec()].concat())] {
+            zip.start_file(name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(&content).unwrap();
+        }
+        zip.finish().unwrap();
+        write_tar(fs::File::create(fixture.join("docs.tar")).unwrap());
+
+        let root = fixture.to_string_lossy();
+        let find = |options: &str, cond: &str| find_names(&format!("select name from '{}' archives {} where {} order by name", root, options, cond));
+
+        assert_eq!(find("", "name like '%.properties' and contains('password')"), vec![
+            "[dist.zip] app.properties",
+            "[dist.zip] large.properties",
+            "[dist.zip] lib/plugin.jar!conf/inner.properties",
+        ]);
+        assert_eq!(find("", "name like '%.properties' and matches('password=s[a-z]+t')"), vec!["[dist.zip] app.properties"]);
+        assert_eq!(find("", "name like '%.properties' and not contains('password')"), vec!["[dist.zip] log.properties"]);
+
+        // members above the limit are skipped
+        assert_eq!(find("", "name like '%.properties' and contains('password', 1k)"), vec![
+            "[dist.zip] app.properties",
+            "[dist.zip] lib/plugin.jar!conf/inner.properties",
+        ]);
+
+        // members of tar archives can't be read again, so they never match unless in lenient mode
+        assert_eq!(find("", "name like '%.txt' and contains('e')"), vec!["[dist.zip] notes.txt"]);
+        assert_eq!(find("lenient", "name like '%.txt' and contains('e')"), vec!["[dist.zip] notes.txt", "[docs.tar] docs/readme.txt"]);
+    }
 
-fn confirm_file_ext(expr_op: &Option<Op>,
-                    expr_val: &Option<String>,
-                    entry: &DirEntry,
-                    file_info: &Option<FileInfo>,
-                    file_ext_func: &Fn(&str) -> bool) -> bool {
-    let mut result = false;
+    #[test]
+    fn test_archive_content_longer_than_recorded() {
+        let fixture = TempDir::new("zip_content_size");
 
-    if let Some(ref val) = expr_val {
-        let file_name = match file_info {
-            Some(ref file_info) => file_info.name.clone(),
-            _ => String::from(entry.file_name().to_string_lossy())
-        };
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("app.properties", zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated)).unwrap();
+        zip.write_all(&[vec![b'x'; 2000], b"password=large".to_vec()].concat()).unwrap();
+        let mut data = zip.finish().unwrap().into_inner();
 
-        let bool_val = str_to_bool(val);
+        // the headers claim the member is 50 bytes long, while it inflates to 2014
+        for &(signature, offset) in &[(b"PK\x03\x04", 22), (b"PK\x01\x02", 24)] {
+            let start = data.windows(4).position(|window| window == signature).unwrap() + offset;
+            data[start..start + 4].copy_from_slice(&50u32.to_le_bytes());
+        }
+        fs::write(fixture.join("dist.zip"), data).unwrap();
 
-        result = match expr_op {
-            Some(Op::Eq) | Some(Op::Eeq) => {
-                if bool_val {
-                    file_ext_func(&file_name)
-                } else {
-                    !file_ext_func(&file_name)
-                }
-            },
-            Some(Op::Ne) | Some(Op::Ene) => {
-                if bool_val {
-                    !file_ext_func(&file_name)
-                } else {
-                    file_ext_func(&file_name)
-                }
-            },
-            _ => false
-        };
+        let root = fixture.to_string_lossy();
+        let find = |cond: &str| find_names(&format!("select name from '{}' archives where {}", root, cond));
+
+        assert_eq!(find("contains('password', 3k)"), vec!["[dist.zip] app.properties"]);
+
+        // the part read up to the limit is not searched, the member is skipped as a larger one
+        assert!(find("contains('x', 1k)").is_empty());
     }
 
-    result
-}
+    #[test]
+    fn test_relative_path() {
+        let fixture = TempDir::new("relpath");
+        fs::create_dir_all(fixture.join("one").join("sub")).unwrap();
+        fs::create_dir_all(fixture.join("two")).unwrap();
+        fs::File::create(fixture.join("one").join("sub").join("a")).unwrap();
+        write_tar(fs::File::create(fixture.join("two").join("docs.tar")).unwrap());
 
-fn update_meta(entry: &DirEntry, meta: Option<Box<Metadata>>, follow_symlinks: bool) -> Option<Box<Metadata>> {
-    if !meta.is_some() {
-        let metadata = match follow_symlinks {
-            false => symlink_metadata(entry.path()),
-            true => fs::metadata(entry.path())
+        let root = fixture.to_string_lossy();
+        let query = format!("select relpath from '{}/one', '{}/two' archives where is_file = true order by relpath", root, root);
+
+        assert_eq!(find_names(&query), vec!["docs.tar", "docs.tar/docs/readme.txt", "sub/a"]);
+        assert_eq!(find_names(&format!("select relpath from '{}' where relpath = 'one/sub/a' order by relpath", root)), vec!["one/sub/a"]);
+    }
+
+    #[test]
+    fn test_distinct() {
+        let fixture = TempDir::new("distinct");
+        for name in &["a.txt", "b.txt", "c.rs", "d.rs", "e.md"] {
+            fs::File::create(fixture.join(name)).unwrap();
+        }
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select distinct ext from '{}' order by ext", root)), vec!["md", "rs", "txt"]);
+        assert_eq!(find_names(&format!("select distinct ext from '{}' order by ext desc limit 2", root)), vec!["txt", "rs"]);
+        assert_eq!(find_names(&format!("select distinct ext from '{}' order by ext limit 1 offset 1", root)), vec!["rs"]);
+
+        let query = Parser::new().parse(&format!("select count(distinct ext), count(*) from '{}'", root)).unwrap();
+        let mut searcher = Searcher::new(query.clone());
+        searcher.search(&mut |_| true);
+        let rows: Vec<&HashMap<String, Value>> = searcher.raw_output_buffer.iter().collect();
+        let values: Vec<String> = query.fields.iter().map(|column_expr| searcher.get_aggregate_function_value(column_expr, &rows).to_string()).collect();
+        assert_eq!(values, vec!["3", "5"]);
+
+        // streamed distinct rows stop right after the limit
+        assert_eq!(count_found(&format!("select distinct ext from '{}' limit 2", root)), 2);
+    }
+
+    #[test]
+    fn test_aggregate_kinds() {
+        assert_eq!(AggregateKind::Number.parse("42"), Some(42));
+        assert_eq!(AggregateKind::Number.parse("abc"), None);
+        assert_eq!(AggregateKind::FileSize.parse("1.50 KiB"), Some(1536));
+        assert_eq!(AggregateKind::FileSize.format(1536), "1.50 KiB");
+        assert_eq!(AggregateKind::Duration.parse("01:02:03"), Some(3723));
+        assert_eq!(AggregateKind::Duration.format(3723), "01:02:03");
+
+        let timestamp = AggregateKind::DateTime.parse("2018-05-01 12:30:00").unwrap();
+        assert_eq!(AggregateKind::DateTime.format(timestamp), "2018-05-01 12:30:00");
+    }
+
+    #[test]
+    fn test_aggregate_functions() {
+        let query = Parser::new().parse("select min(size), max(size), avg(size), sum(size), min(fsize), max(fsize), sum(fsize), \
+                                         min(modified), max(modified), avg(modified), sum(modified) from /").unwrap();
+        let searcher = Searcher::new(query.clone());
+
+        let rows: Vec<HashMap<String, Value>> = vec![
+            ("512", "512 B", "2018-05-01 10:00:00"),
+            ("4096", "4 KiB", "2018-05-03 10:00:00"),
+            ("2048", "2 KiB", "2017-12-31 23:59:59"),
+            ("", "", ""),
+        ].into_iter().map(|(size, fsize, modified)| {
+            let mut row = HashMap::new();
+            row.insert(String::from("size"), Value::from(size));
+            row.insert(String::from("formattedsize"), Value::from(fsize));
+            row.insert(String::from("modified"), Value::from(modified));
+            row
+        }).collect();
+
+        let aggregate = |rows: Vec<&HashMap<String, Value>>| -> Vec<String> {
+            query.fields.iter().map(|column_expr| searcher.get_aggregate_function_value(column_expr, &rows).to_string()).collect()
         };
 
-        if let Ok(metadata) = metadata {
-            return Some(Box::new(metadata));
+        assert_eq!(aggregate(rows.iter().collect()),
+                   vec!["512", "4096", "2218", "6656", "512 B", "4 KiB", "6.50 KiB",
+                        "2017-12-31 23:59:59", "2018-05-03 10:00:00", "2018-03-22 22:39:59", ""]);
+
+        assert_eq!(aggregate(vec![]), vec!["", "", "", "0", "", "", "0 B", "", "", "", ""]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_xattr_values() {
+        let fixture = TempDir::new("xattr");
+        fs::File::create(fixture.join("a")).unwrap();
+        fs::File::create(fixture.join("b")).unwrap();
+
+        // the file system might not support extended attributes at all
+        if ::xattr::set(fixture.join("a"), "user.backup", b"yes").is_err() {
+            return;
         }
+        ::xattr::set(fixture.join("a"), "user.comment", b"hello").unwrap();
+        ::xattr::set(fixture.join("b"), "user.backup", b"no").unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, xattr('user.comment') from '{}' order by name", root)), vec!["a\thello", "b"]);
+        assert_eq!(find_names(&format!("select name from '{}' where xattr('user.backup') = 'yes' order by name", root)), vec!["a"]);
+        assert_eq!(find_names(&format!("select name from '{}' where xattr('user.backup') like 'n%' order by name", root)), vec!["b"]);
+        assert_eq!(find_names(&format!("select name from '{}' where xattrs like '%user.comment%' order by name", root)), vec!["a"]);
     }
 
-    meta
-}
+    #[test]
+    fn test_readonly_field() {
+        let fixture = TempDir::new("readonly");
+        fs::File::create(fixture.join("rw")).unwrap();
+        fs::File::create(fixture.join("ro")).unwrap();
 
-fn update_img_dimensions(entry: &DirEntry, dim: Option<(usize, usize)>) -> Option<(usize, usize)> {
-    match dim {
-        None => {
-            match imagesize::size(entry.path()) {
-                Ok(dimensions) => Some((dimensions.width, dimensions.height)),
-                _ => None
-            }
-        },
-        Some(dim_) => Some(dim_)
+        let mut permissions = fs::metadata(fixture.join("ro")).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(fixture.join("ro"), permissions).unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name from '{}' where is_readonly = true order by name", root)), vec!["ro"]);
+        assert_eq!(find_names(&format!("select name from '{}' where is_readonly = false and is_file = true order by name", root)), vec!["rw"]);
+        assert_eq!(find_names(&format!("select is_readonly from '{}' where is_file = true order by name", root)), vec!["true", "false"]);
+
+        let mut permissions = fs::metadata(fixture.join("ro")).unwrap().permissions();
+        permissions.set_readonly(false);
+        fs::set_permissions(fixture.join("ro"), permissions).unwrap();
     }
-}
 
-fn update_mp3_meta(entry: &DirEntry, mp3: Option<MP3Metadata>) -> Option<MP3Metadata> {
-    match mp3 {
-        None => {
-            match mp3_metadata::read_from_file(entry.path()) {
-                Ok(mp3_meta) => Some(mp3_meta),
-                _ => None
-            }
-        },
-        Some(mp3_) => Some(mp3_)
+    #[test]
+    fn test_line_endings_and_bom() {
+        let fixture = TempDir::new("line_endings");
+        fs::create_dir_all(fixture.join("dir")).unwrap();
+        fs::write(fixture.join("lf.rs"), "fn main() {\n}\n").unwrap();
+        fs::write(fixture.join("crlf.rs"), "fn main() {\r\n}\r\n").unwrap();
+        fs::write(fixture.join("mixed.rs"), "fn main() {\r\n}\n").unwrap();
+        fs::write(fixture.join("bom.txt"), b"\xEF\xBB\xBFsingle line").unwrap();
+        fs::write(fixture.join("image.bin"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, line_endings, has_bom from '{}' order by name", root)),
+                   vec!["bom.txt\t\ttrue", "crlf.rs\tcrlf\tfalse", "dir", "image.bin\t\tfalse", "lf.rs\tlf\tfalse", "mixed.rs\tmixed\tfalse"]);
+        assert_eq!(find_names(&format!("select name from '{}' where ext = 'rs' and line_endings = 'crlf' order by name", root)),
+                   vec!["crlf.rs"]);
+        assert_eq!(find_names(&format!("select name from '{}' where line_endings != lf order by name", root)),
+                   vec!["crlf.rs", "mixed.rs"]);
+        assert_eq!(find_names(&format!("select name from '{}' where has_bom = true order by name", root)),
+                   vec!["bom.txt"]);
     }
-}
 
-fn is_shebang(path: &PathBuf) -> bool {
-    if let Ok(file) = File::open(path) {
-        let mut buf_reader = BufReader::new(file);
-        let mut buf = vec![0; 2];
-        if buf_reader.read_exact(&mut buf).is_ok() {
-            return buf[0] == 0x23 && buf[1] == 0x21
-        }
+    #[test]
+    fn test_shebang() {
+        let fixture = TempDir::new("shebang");
+        fs::create_dir_all(fixture.join("dir")).unwrap();
+        fs::write(fixture.join("old.py"), "#!/usr/bin/env python2\nprint 'hi'\n").unwrap();
+        fs::write(fixture.join("new.py"), "#!/usr/bin/env python3\nprint('hi')\n").unwrap();
+        fs::write(fixture.join("run.sh"), "#!/bin/sh -e\necho hi\n").unwrap();
+        fs::write(fixture.join("notes.txt"), "plain text\n").unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, shebang from '{}' order by name", root)),
+                   vec!["dir", "new.py\tpython3", "notes.txt", "old.py\tpython2", "run.sh\t/bin/sh"]);
+        assert_eq!(find_names(&format!("select name from '{}' where shebang like '%python2%' order by name", root)),
+                   vec!["old.py"]);
+        assert_eq!(find_names(&format!("select name from '{}' where shebang =~ '^python' order by name", root)),
+                   vec!["new.py", "old.py"]);
+        assert_eq!(find_names(&format!("select shebang, count(*) from '{}' where is_shebang = true group by shebang order by shebang", root)),
+                   vec!["/bin/sh\t1", "python2\t1", "python3\t1"]);
     }
 
-    false
-}
+    #[cfg(unix)]
+    #[test]
+    fn test_fifo_is_not_read() {
+        let fixture = TempDir::new("fifo");
 
-#[allow(unused)]
-fn is_hidden(file_name: &str, metadata: &Option<Box<Metadata>>, archive_mode: bool) -> bool {
-    if archive_mode {
-        if !file_name.contains('\\') {
-            return parse_unix_filename(file_name).starts_with('.');
-        } else {
-            return false;
+        // nothing ever writes to it, so opening it for reading would wait forever
+        let fifo = ::std::ffi::CString::new(fixture.join("pipe.zip").to_string_lossy().as_bytes()).unwrap();
+        assert_eq!(unsafe { ::libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, is_binary, line_endings, has_bom, shebang, is_shebang, mime, sha256, width, bitrate, video_codec \
+                                        from '{}' archives order by name", root)),
+                   vec!["pipe.zip\t\t\t\t\tfalse"]);
+        assert_eq!(find_names(&format!("select name from '{}' where is_text = true or mime = 'text/plain' or sha1 != '' order by name", root)),
+                   Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_entries() {
+        let fixture = TempDir::new("entries");
+        fs::create_dir_all(fixture.join("big/nested")).unwrap();
+        fs::create_dir_all(fixture.join("empty")).unwrap();
+        for i in 0..5 {
+            fs::write(fixture.join("big").join(format!("{}.txt", i)), "").unwrap();
         }
+        fs::write(fixture.join("big/nested/deep.txt"), "").unwrap();
+        fs::write(fixture.join("big/.gitignore"), "*.txt\n").unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        // not recursive and ignore files don't matter
+        assert_eq!(find_names(&format!("select name, entries from '{}' depth 1 gitignore order by name", root)),
+                   vec!["big\t7", "empty\t0"]);
+        assert_eq!(find_names(&format!("select name from '{}' where entries > 6 order by name", root)),
+                   vec!["big"]);
+        assert_eq!(find_names(&format!("select name from '{}' where entries >= 7 and entries = 7 order by name", root)),
+                   vec!["big"]);
+        assert_eq!(find_names(&format!("select name from '{}' where entries < 1 or entries > 99 order by name", root)),
+                   vec!["empty"]);
+        assert_eq!(find_names(&format!("select name, entries from '{}' where entries >= 1 order by entries desc", root)),
+                   vec!["big\t7", "nested\t1"]);
     }
 
+    /// Knows only the owners it's given, like a system where files come from NFS shares.
     #[cfg(unix)]
-    {
-        return file_name.starts_with('.');
+    struct FakeOwnerNames {
+        users: HashMap<u32, String>,
     }
 
-    #[cfg(windows)]
-    {
-        if let Some(ref metadata) = metadata {
-            return mode::get_mode(metadata).contains("Hidden");
+    #[cfg(unix)]
+    impl OwnerNames for FakeOwnerNames {
+        fn get_user_name(&self, uid: u32) -> Option<String> {
+            self.users.get(&uid).cloned()
+        }
+
+        fn get_group_name(&self, _: u32) -> Option<String> {
+            None
         }
     }
 
-    #[cfg(not(unix))]
-    {
-        false
+    #[cfg(unix)]
+    #[test]
+    fn test_unknown_owners_shown_by_ids() {
+        use std::os::unix::fs::MetadataExt;
+
+        let fixture = TempDir::new("owners");
+        fs::write(fixture.join("data.txt"), "data").unwrap();
+        let metadata = fs::metadata(fixture.join("data.txt")).unwrap();
+        let (uid, gid) = (metadata.uid(), metadata.gid());
+
+        let root = fixture.to_string_lossy();
+        let find_owned = |query: &str, users: HashMap<u32, String>| {
+            let mut searcher = Searcher::new(Parser::new().parse(query).unwrap());
+            searcher.user_cache = Box::new(FakeOwnerNames { users });
+            search_rows(&mut searcher)
+        };
+
+        assert_eq!(find_owned(&format!("select name, user, group from '{}' order by name", root), HashMap::new()),
+                   vec![format!("data.txt\t{}\t{}", uid, gid)]);
+        assert_eq!(find_owned(&format!("select name from '{}' where user = '{}' and group = '{}' order by name", root, uid, gid), HashMap::new()),
+                   vec!["data.txt"]);
+
+        let mut users = HashMap::new();
+        users.insert(uid, String::from("auditor"));
+        assert_eq!(find_owned(&format!("select name, user from '{}' where user = auditor order by name", root), users.clone()),
+                   vec!["data.txt\tauditor"]);
+        assert!(find_owned(&format!("select name from '{}' where user = '{}' order by name", root, uid), users).is_empty());
     }
-}
 
-macro_rules! def_extension_queries {
-    ($($name:ident $extensions:expr);*) => {
-        $(
-            fn $name(file_name: &str) -> bool {
-                has_extension(file_name, &$extensions)
-            }
-        )*
+    #[test]
+    fn test_binary_and_text() {
+        let fixture = TempDir::new("binary");
+        fs::create_dir_all(fixture.join("dir")).unwrap();
+        fs::write(fixture.join("notes.txt"), "plain text\n").unwrap();
+        fs::write(fixture.join("image.bin"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, is_binary, is_text from '{}' order by name", root)),
+                   vec!["dir", "image.bin\ttrue\tfalse", "notes.txt\tfalse\ttrue"]);
+        assert_eq!(find_names(&format!("select name from '{}' where is_text = true order by name", root)), vec!["notes.txt"]);
+        assert_eq!(find_names(&format!("select name from '{}' where is_binary != false order by name", root)), vec!["image.bin"]);
     }
-}
 
-def_extension_queries! {
-    is_zip_archive          [".zip", ".jar", ".war", ".ear"]
-;   is_archive              [".7z", ".bz2", ".bzip2", ".gz", ".gzip", ".rar", ".tar", ".xz", ".zip"]
-;   is_audio                [".aac", ".aiff", ".amr", ".flac", ".gsm", ".m4a", ".m4b", ".m4p", ".mp3", ".ogg", ".wav", ".wma"]
-;   is_book                 [".azw3", ".chm", ".epub", ".fb2", ".mobi", ".pdf"]
-;   is_doc                  [".accdb", ".doc", ".docm", ".docx", ".dot", ".dotm", ".dotx", ".mdb", ".ods", ".odt", ".pdf", ".potm", ".potx", ".ppt", ".pptm", ".pptx", ".rtf", ".xlm", ".xls", ".xlsm", ".xlsx", ".xlt", ".xltm", ".xltx", ".xps"]
-;   is_image                [".bmp", ".gif", ".jpeg", ".jpg", ".png", ".tiff", ".webp"]
-;   is_image_dim_readable   [".bmp", ".gif", ".jpeg", ".jpg", ".png", ".webp"]
-;   is_source               [".asm", ".c", ".cpp", ".cs", ".go", ".h", ".hpp", ".java", ".js", ".jsp", ".pas", ".php", ".pl", ".pm", ".py", ".rb", ".rs", ".swift"]
-;   is_video                [".3gp", ".avi", ".flv", ".m4p", ".m4v", ".mkv", ".mov", ".mp4", ".mpeg", ".mpg", ".webm", ".wmv"]
-}
+    #[test]
+    fn test_capture_groups() {
+        let fixture = TempDir::new("capture");
+        fs::create_dir_all(fixture.join("2023")).unwrap();
+        fs::File::create(fixture.join("2023").join("2023-01-15_app.log")).unwrap();
+        fs::File::create(fixture.join("notes.txt")).unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!(r"select capture(0), capture(4), capture(1), capture(2) from '{}' where name =~ '(\d{{4}})-(\d{{2}})-(\d{{2}})_.*\.log' order by name", root)),
+                   vec!["2023-01-15_app.log\t\t2023\t01"]);
+        assert_eq!(find_names(&format!(r"select capture(1), name from '{}' where name rx '^(\d+)-.*' or name = notes.txt order by name", root)),
+                   vec!["2023\t2023-01-15_app.log", "\tnotes.txt"]);
+        assert_eq!(find_names(&format!(r"select capture(1) from '{}' where relpath rx '^(\d+)/' order by name", root)),
+                   vec!["2023"]);
+
+        // nothing is captured by a condition that didn't make the file match
+        assert_eq!(find_names(&format!(r"select name, capture(1) from '{}' where (relpath rx '^(\d+)/' and is_dir = true) or ext = log order by name", root)),
+                   vec!["2023-01-15_app.log"]);
+        assert_eq!(find_names(&format!(r"select name, capture(0) from '{}' where (name != 'notes*' or ext = txt) and is_file = true order by name", root)),
+                   vec!["2023-01-15_app.log", "notes.txt"]);
+        assert_eq!(find_names(&format!(r"select name, capture(0) from '{}' where not name rx 'app' and is_file = true order by name", root)),
+                   vec!["notes.txt"]);
+    }
 
-fn has_extension(file_name: &str, extensions: &[&str]) -> bool {
-    let s = file_name.to_ascii_lowercase();
+    #[test]
+    fn test_string_functions() {
+        let fixture = TempDir::new("string_functions");
+        fs::File::create(fixture.join("Some File.TXT")).unwrap();
+        fs::File::create(fixture.join("lower.txt")).unwrap();
 
-    for ext in extensions {
-        if s.ends_with(ext) {
-            return true
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!(r#"select concat('mv "', name, '" "', lower(name), '"') from '{}' where name != lower(name) order by name"#, root)),
+                   vec![r#"mv "Some File.TXT" "some file.txt""#]);
+        assert_eq!(find_names(&format!("select substr(name, 6, 4), substr(name, 0, 2), substr(name, 11), substr(name, 50), replace(upper(name), 'TXT', md), concat(trim(concat('  ', ext, ' ')), '!') from '{}' order by name", root)),
+                   vec!["File\tS\tTXT\t\tSOME FILE.md\ttxt!", ".txt\tl\t\t\tLOWER.md\ttxt!"]);
+    }
+
+    #[test]
+    fn test_substr() {
+        assert_eq!(substr("abcdef", "2", Some(&String::from("3"))), "bcd");
+        assert_eq!(substr("abcdef", "4", None), "def");
+        assert_eq!(substr("abcdef", "-5", Some(&String::from("2"))), "bc");
+        assert_eq!(substr("abcdef", "-3", None), "def");
+        assert_eq!(substr("abcdef", "-10", Some(&String::from("2"))), "");
+        assert_eq!(substr("abcdef", "-10", None), "abcdef");
+        assert_eq!(substr("abcdef", "0", Some(&String::from("2"))), "a");
+        assert_eq!(substr("abcdef", "5", Some(&String::from("100"))), "ef");
+        assert_eq!(substr("abcdef", "10", None), "");
+        assert_eq!(substr("abcdef", "1", Some(&String::from("0"))), "");
+        assert_eq!(substr("abcdef", "x", None), "");
+        assert_eq!(substr("ПРИВЕТ", "2", Some(&String::from("2"))), "РИ");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_special_mode_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let fixture = TempDir::new("special_bits");
+        fs::create_dir_all(fixture.join("shared")).unwrap();
+        fs::File::create(fixture.join("tool")).unwrap();
+        fs::File::create(fixture.join("plain")).unwrap();
+
+        fs::set_permissions(fixture.join("shared"), fs::Permissions::from_mode(0o1777)).unwrap();
+        fs::set_permissions(fixture.join("tool"), fs::Permissions::from_mode(0o4755)).unwrap();
+        fs::set_permissions(fixture.join("plain"), fs::Permissions::from_mode(0o644)).unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, mode from '{}' where is_suid = true order by name", root)), vec!["tool\trwsr-xr-x"]);
+        assert_eq!(find_names(&format!("select name, mode from '{}' where is_sticky = true order by name", root)), vec!["shared\trwxrwxrwt"]);
+        assert_eq!(find_names(&format!("select name from '{}' where is_suid = false and is_sticky = false order by name", root)), vec!["plain"]);
+        assert_eq!(find_names(&format!("select is_sgid from '{}' order by name", root)), vec!["false", "false", "false"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_octal_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let fixture = TempDir::new("octal_mode");
+        for &(name, mode) in &[("open", 0o666), ("tool", 0o4755), ("plain", 0o644)] {
+            fs::File::create(fixture.join(name)).unwrap();
+            fs::set_permissions(fixture.join(name), fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        let root = fixture.to_string_lossy();
+        let find = |cond: &str| find_names(&format!("select name from '{}' where {} order by name", root, cond));
+
+        assert_eq!(find_names(&format!("select name, mode_oct from '{}' order by name", root)),
+                   vec!["open\t0666", "plain\t0644", "tool\t4755"]);
+        assert_eq!(find("mode = 0644"), vec!["plain"]);
+        assert_eq!(find("mode = 644 or mode_oct = 0o4755"), vec!["plain", "tool"]);
+        assert_eq!(find("mode != 0644"), vec!["open", "tool"]);
+        assert_eq!(find("mode has 'o+w'"), vec!["open"]);
+        assert_eq!(find("mode has 'ug+x'"), vec!["tool"]);
+        assert_eq!(find("mode has 'u+s'"), vec!["tool"]);
+        assert_eq!(find("mode has 0044 and not mode has 0002"), vec!["plain", "tool"]);
+        // the text is still compared as before
+        assert_eq!(find("mode = 'rw-r--r--'"), vec!["plain"]);
+        assert_eq!(find("mode = '-rw-r--r--'"), vec!["plain"]);
+        assert_eq!(find("mode like '-rw-r?-r??'"), vec!["open", "plain"]);
+        assert_eq!(find("mode glob 'rw[sx]*'"), vec!["tool"]);
+        assert_eq!(find("mode glob '*s*'"), vec!["tool"]);
+    }
+
+    #[test]
+    fn test_mode_patterns() {
+        let table = [
+            // bits, formatted mode, pattern, matches
+            (0o100755, "rwxr-xr-x", "-rwxr-?r-?", true),
+            (0o100755, "rwxr-xr-x", "rwxr-?r-?", true),
+            (0o100745, "rwxr--r-x", "-rwxr-?r-?", true),
+            (0o100775, "rwxrwxr-x", "-rwxr-?r-?", false),
+            (0o40755, "rwxr-xr-x", "-rwxr-xr-x", false),
+            (0o40755, "rwxr-xr-x", "d*", true),
+            (0o100700, "rwx------", "-rwx------", true),
+            (0o100700, "rwx------", "rwx*", true),
+            (0o120777, "rwxrwxrwx", "l?????????", true),
+            (0o104755, "rwsr-xr-x", "-rws*", true),
+            (0o104755, "rwsr-xr-x", "-rwS*", false),
+            (0o104644, "rwSr--r--", "rwS*", true),
+            (0o104644, "rwSr--r--", "rws*", false),
+            (0o102755, "rwxr-sr-x", "???r-s???", true),
+            (0o102745, "rwxr-Sr-x", "*[sS]*", true),
+            (0o100745, "rwxr--r-x", "*[sS]*", false),
+            (0o41777, "rwxrwxrwt", "d????????t", true),
+            (0o41777, "rwxrwxrwt", "*T", false),
+            (0o41776, "rwxrwxrwT", "*T", true),
+            (0o41776, "rwxrwxrwT", "drwxrwxrw_", true),
+            (0o41776, "rwxrwxrwT", "%rw%", true),
+            // no file type known, only the permissions are compared
+            (0o755, "rwxr-xr-x", "-rwxr-xr-x", false),
+            (0o755, "rwxr-xr-x", "rwxr-xr-x", true),
+        ];
+
+        for &(bits, formatted, pattern, matches) in table.iter() {
+            let regex = Some(Regex::new(&convert_mode_glob_to_pattern(pattern)).unwrap());
+
+            for op in &[Op::Like, Op::Glob] {
+                assert_eq!(compare_mode(&Some(op.clone()), pattern, &regex, Some(bits), formatted), matches, "{} {:?} {}", formatted, op, pattern);
+            }
+            assert_eq!(compare_mode(&Some(Op::Ne), pattern, &regex, Some(bits), formatted), !matches, "{} != {}", formatted, pattern);
         }
+
+        // plain values tolerate the file type too
+        assert!(compare_mode(&Some(Op::Eq), "-rwxr-xr-x", &None, Some(0o100755), "rwxr-xr-x"));
+        assert!(compare_mode(&Some(Op::Eq), "rwxr-xr-x", &None, Some(0o100755), "rwxr-xr-x"));
+        assert!(!compare_mode(&Some(Op::Ne), "-rwxr-xr-x", &None, Some(0o100755), "rwxr-xr-x"));
+        assert!(compare_mode(&Some(Op::Ne), "drwxr-xr-x", &None, Some(0o100755), "rwxr-xr-x"));
     }
 
-    false
-}
+    #[cfg(unix)]
+    #[test]
+    fn test_current_user_access() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let fixture = TempDir::new("access");
+        fs::File::create(fixture.join("run.sh")).unwrap();
+        fs::File::create(fixture.join("notes.txt")).unwrap();
+        write_zip(fs::File::create(fixture.join("files.zip")).unwrap());
+
+        fs::set_permissions(fixture.join("run.sh"), fs::Permissions::from_mode(0o700)).unwrap();
+        fs::set_permissions(fixture.join("notes.txt"), fs::Permissions::from_mode(0o600)).unwrap();
+        fs::set_permissions(fixture.join("files.zip"), fs::Permissions::from_mode(0o644)).unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, is_mine, is_readable, is_writable, is_executable from '{}' order by name", root)),
+                   vec!["files.zip\ttrue\ttrue\ttrue\tfalse", "notes.txt\ttrue\ttrue\ttrue\tfalse", "run.sh\ttrue\ttrue\ttrue\ttrue"]);
+        assert_eq!(find_names(&format!("select name from '{}' where is_executable = true order by name", root)), vec!["run.sh"]);
+        assert_eq!(find_names(&format!("select name from '{}' where mine = true and executable = false order by name", root)),
+                   vec!["files.zip", "notes.txt"]);
+        assert_eq!(find_names(&format!("select name, is_mine, is_executable from '{}' archives where name = old.sh order by name", root)),
+                   vec!["[files.zip] old.sh\tfalse\tfalse"]);
+        assert_eq!(find_names(&format!("select name from '{}' archives where is_readable = true order by name", root)),
+                   vec!["files.zip", "notes.txt", "run.sh"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_field_comparison() {
+        let fixture = TempDir::new("field_comparison");
+
+        for &(name, width, height) in &[("landscape.png", 40u32, 20u32), ("portrait.png", 10, 30), ("square.png", 16, 16)] {
+            let mut header = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+            header.extend_from_slice(&width.to_be_bytes());
+            header.extend_from_slice(&height.to_be_bytes());
+            header.extend_from_slice(&[8, 6, 0, 0, 0, 0, 0, 0, 0]);
+            fs::write(fixture.join(name), header).unwrap();
+        }
 
-#[cfg(windows)]
-use std;
-#[cfg(windows)]
-use std::ffi::OsStr;
+        let old = fs::OpenOptions::new().write(true).open(fixture.join("portrait.png")).unwrap();
+        old.set_modified(::std::time::SystemTime::UNIX_EPOCH + ::std::time::Duration::from_secs(1262304000)).unwrap();
 
-#[cfg(windows)]
-struct UsersCache;
+        let root = fixture.to_string_lossy();
 
-#[cfg(windows)]
-impl UsersCache {
-    fn new() -> Self {
-        UsersCache { }
+        assert_eq!(find_names(&format!("select name from '{}' where width > height order by name", root)), vec!["landscape.png"]);
+        assert_eq!(find_names(&format!("select name from '{}' where width >= height order by name", root)), vec!["landscape.png", "square.png"]);
+        assert_eq!(find_names(&format!("select name from '{}' where height > width * 2 order by name", root)), vec!["portrait.png"]);
+        assert_eq!(find_names(&format!("select name from '{}' where width * 2 = height + 60 order by name", root)), vec!["landscape.png"]);
+
+        assert_eq!(find_names(&format!("select name from '{}' where modified < changed order by name", root)), vec!["portrait.png"]);
+        assert_eq!(find_names(&format!("select name from '{}' where modified >= changed order by name", root)), vec!["landscape.png", "square.png"]);
+        assert_eq!(find_names(&format!("select name from '{}' where name != 'name' and name > ext order by name", root)),
+                   vec!["portrait.png", "square.png"]);
     }
 
-    fn get_user_by_uid(&self, _: u32) -> Option< std::sync::Arc<User>> {
-        None
+    #[test]
+    fn test_exists_stops_at_first_match() {
+        let fixture = TempDir::new("exists");
+        for i in 0..20 {
+            fs::write(fixture.join(format!("file{:02}.txt", i)), "").unwrap();
+        }
+
+        let root = fixture.to_string_lossy();
+        let searcher = |query: &str| Searcher::new(Parser::new().parse(query).unwrap());
+
+        // the ordering would need all the files, but it doesn't matter for the existence
+        let mut exists = searcher(&format!("select name from '{}' where name like '%.txt' order by name desc", root));
+        assert!(exists.exists());
+        assert_eq!(exists.matched, 1);
+
+        let mut counted = searcher(&format!("select name, size from '{}' where name like '%.txt' order by name desc limit 5", root));
+        assert_eq!(counted.count(), 20);
     }
 
-    fn get_group_by_gid(&self, _: u32) -> Option< std::sync::Arc<Group>> {
-        None
+    #[test]
+    fn test_summary_goes_past_the_limit() {
+        let fixture = TempDir::new("summary_footer");
+        fs::write(fixture.join("a.txt"), "aaaa").unwrap();
+        fs::write(fixture.join("b.txt"), "bb").unwrap();
+        fs::write(fixture.join("c.log"), "c").unwrap();
+        write_zip(fs::File::create(fixture.join("files.zip")).unwrap());
+
+        let root = fixture.to_string_lossy();
+        let searcher = |query: &str| Searcher::new(Parser::new().parse(query).unwrap());
+
+        // without the summary the search stops at the limit and the sizes aren't read
+        let mut limited = searcher(&format!("select name from '{}' where name like '%.txt' limit 1", root));
+        assert_eq!(search_rows(&mut limited).len(), 1);
+        assert_eq!((limited.matched, limited.shown, limited.matched_size), (1, 1, 0));
+
+        let mut summarized = searcher(&format!("select name from '{}' where name like '%.txt' limit 1 summary", root));
+        assert_eq!(search_rows(&mut summarized).len(), 1);
+        assert_eq!((summarized.matched(), summarized.shown(), summarized.matched_size()), (2, 1, 6));
+
+        // the files skipped by the offset are matched but not shown
+        let mut offset = searcher(&format!("select name from '{}' where size lt 10 order by name limit 5 offset 2 summary", root));
+        assert_eq!(search_rows(&mut offset).len(), 1);
+        assert_eq!((offset.matched, offset.shown, offset.matched_size), (3, 1, 7));
+
+        // members of the archives are counted with their own sizes
+        let mut archives = searcher(&format!("select name from '{}' archives where name like '%.txt' limit 1 summary", root));
+        assert_eq!(search_rows(&mut archives).len(), 1);
+        assert_eq!((archives.matched, archives.shown, archives.matched_size), (3, 1, 11));
+
+        // directories are matched, but only the sizes of the files are summed up
+        fs::create_dir(fixture.join("sub")).unwrap();
+        let zip_size = fs::metadata(fixture.join("files.zip")).unwrap().len();
+        let mut everything = searcher(&format!("select name from '{}' summary", root));
+        assert_eq!(search_rows(&mut everything).len(), 5);
+        assert_eq!((everything.matched, everything.shown, everything.matched_size), (5, 5, 7 + zip_size));
     }
-}
 
-#[cfg(windows)]
-struct User;
+    #[test]
+    fn test_mp3_bitrates() {
+        let fixture = TempDir::new("mp3_bitrates");
+        // a broken frame header stands in front of the frames of the VBR file
+        fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/vbr.mp3"), fixture.join("vbr.mp3")).unwrap();
+        fs::write(fixture.join("notes.txt"), "not a sound").unwrap();
+
+        let root = fixture.to_string_lossy();
+        let find = |cond: &str| find_names(&format!("select name from '{}' where {} order by name", root, cond));
+
+        assert_eq!(find_names(&format!("select name, bitrate, min_bitrate, max_bitrate, freq from '{}' order by max_bitrate desc", root)), vec![
+            "vbr.mp3\t216\t128\t320\t44100",
+            "notes.txt",
+        ]);
+
+        assert_eq!(find("bitrate >= 216"), vec!["vbr.mp3"]);
+        assert!(find("bitrate >= 217").is_empty());
+        assert_eq!(find("min_bitrate = 128 and max_bitrate = 320"), vec!["vbr.mp3"]);
+        assert!(find("max_bitrate < 320").is_empty());
+    }
 
-#[cfg(windows)]
-impl User {
-    fn name(&self) -> &OsStr {
-        "".as_ref()
+    #[test]
+    fn test_aspect_ratio() {
+        let fixture = TempDir::new("aspect_ratio");
+
+        for &(name, width, height) in &[("hd.png", 1920u32, 1080u32), ("sxga.png", 1280, 1024), ("tall.png", 1000, 3000),
+                                       ("ultrawide.png", 2560, 1080), ("photo.png", 3, 2)] {
+            let mut header = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+            header.extend_from_slice(&width.to_be_bytes());
+            header.extend_from_slice(&height.to_be_bytes());
+            header.extend_from_slice(&[8, 6, 0, 0, 0, 0, 0, 0, 0]);
+            fs::write(fixture.join(name), header).unwrap();
+        }
+        fs::write(fixture.join("notes.txt"), "not an image").unwrap();
+
+        let root = fixture.to_string_lossy();
+        let find = |cond: &str| find_names(&format!("select name from '{}' where {} order by name", root, cond));
+
+        // rounded to three decimal places, 2560 / 1080 is 2.37037...
+        assert_eq!(find_names(&format!("select name, aspect_ratio from '{}' order by aspect_ratio desc", root)), vec![
+            "ultrawide.png\t2.370",
+            "hd.png\t1.778",
+            "photo.png\t1.500",
+            "sxga.png\t1.250",
+            "tall.png\t0.333",
+            "notes.txt",
+        ]);
+
+        assert_eq!(find("aspect_ratio = 1.778"), vec!["hd.png"]);
+        assert_eq!(find("aspect_ratio != 1.778"), vec!["photo.png", "sxga.png", "tall.png", "ultrawide.png"]);
+        assert_eq!(find("aspect_ratio = 2.37"), vec!["ultrawide.png"]);
+        assert_eq!(find("aspect_ratio >= 1.5"), vec!["hd.png", "photo.png", "ultrawide.png"]);
+        assert_eq!(find("aspect_ratio < 0.5 or aspect_ratio between 1.2 and 1.3"), vec!["sxga.png", "tall.png"]);
+
+        // integer fields accept fractions too
+        assert_eq!(find("width > 1280.5 and height <= 1080.0"), vec!["hd.png", "ultrawide.png"]);
+        assert_eq!(find("width * 2 < 6.5"), vec!["photo.png"]);
+        assert_eq!(find("size < 12.5 and size > 11.5"), vec!["notes.txt"]);
+        assert_eq!(find("size = 33.0 and size < 0.5k"), vec!["hd.png", "photo.png", "sxga.png", "tall.png", "ultrawide.png"]);
+        #[cfg(unix)]
+        assert_eq!(find("name = notes.txt and uid >= 0.0 and gid >= 0.0 and inode > 0.5 and device >= 0.0"), vec!["notes.txt"]);
     }
-}
 
-#[cfg(windows)]
-struct Group;
+    #[test]
+    fn test_age_days() {
+        let fixture = TempDir::new("age_days");
+
+        let now = ::std::time::SystemTime::now();
+        let day = ::std::time::Duration::from_secs(24 * 60 * 60);
+        for &(name, modified, accessed) in &[("new.txt", now, now), ("old.txt", now - day * 100, now - day * 40), ("future.txt", now + day * 5, now)] {
+            let file = fs::File::create(fixture.join(name)).unwrap();
+            file.set_times(fs::FileTimes::new().set_modified(modified).set_accessed(accessed)).unwrap();
+        }
+
+        let mut builder = tar::Builder::new(fs::File::create(fixture.join("old.tar")).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(0);
+        header.set_mtime((now - day * 10).duration_since(::std::time::UNIX_EPOCH).unwrap().as_secs());
+        header.set_cksum();
+        builder.append_data(&mut header, "entry.log", io::empty()).unwrap();
+        builder.finish().unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select name, age_days from '{}' where name like '%.txt' order by name", root)),
+                   vec!["future.txt\t0", "new.txt\t0", "old.txt\t100"]);
+        assert_eq!(find_names(&format!("select name from '{}' where age_days > 30 order by name", root)), vec!["old.txt"]);
+        assert_eq!(find_names(&format!("select name from '{}' where access_age_days >= 40 order by name", root)), vec!["old.txt"]);
+        assert_eq!(find_names(&format!("select max(age_days), min(access_age_days) from '{}' where name like '%.txt'", root)), vec!["100\t0"]);
+
+        // only the modification time of archive entries is known
+        assert_eq!(find_names(&format!("select name, age_days from '{}' archives where name like '%.log' order by name", root)),
+                   vec!["[old.tar] entry.log\t10"]);
+        assert_eq!(count_found(&format!("select name from '{}' archives where name like '%.log' and access_age_days >= 0", root)), 0);
+    }
+
+    #[test]
+    fn test_unix_and_utc_times() {
+        let fixture = TempDir::new("unix_times");
+
+        let epoch = ::std::time::UNIX_EPOCH;
+        let second = ::std::time::Duration::from_secs(1);
+        // 2024-03-01 09:30:00, 2001-09-09 01:46:40 and 1969-12-31 00:00:00 UTC
+        for &(name, modified) in &[("a.txt", epoch + second * 1709285400), ("b.txt", epoch + second * 1000000000), ("c.txt", epoch - second * 86400)] {
+            let file = fs::File::create(fixture.join(name)).unwrap();
+            file.set_times(fs::FileTimes::new().set_modified(modified).set_accessed(epoch + second * 1000000000)).unwrap();
+        }
+
+        let mut builder = tar::Builder::new(fs::File::create(fixture.join("old.tar")).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(0);
+        header.set_mtime(1500000000);
+        header.set_cksum();
+        builder.append_data(&mut header, "entry.log", io::empty()).unwrap();
+        builder.finish().unwrap();
+
+        let root = fixture.to_string_lossy();
+
+        // ordered as numbers, not as strings
+        assert_eq!(find_names(&format!("select name, modified_unix, modified_utc from '{}' where name like '%.txt' order by modified_unix desc", root)),
+                   vec!["a.txt\t1709285400\t2024-03-01T09:30:00Z", "b.txt\t1000000000\t2001-09-09T01:46:40Z", "c.txt\t-86400\t1969-12-31T00:00:00Z"]);
+        assert_eq!(find_names(&format!("select name from '{}' where modified_unix >= 1000000000 and modified_unix < 1709285400 order by name", root)),
+                   vec!["b.txt"]);
+        assert_eq!(find_names(&format!("select name from '{}' where name like '%.txt' and (modified_utc like '2024-03-01T%' or accessed_unix != 1000000000) order by name", root)),
+                   vec!["a.txt"]);
+        assert_eq!(find_names(&format!("select min(modified_unix), max(accessed_unix) from '{}' where name like '%.txt'", root)),
+                   vec!["-86400\t1000000000"]);
+
+        // only the modification time of archive entries is known
+        assert_eq!(find_names(&format!("select name, modified_unix, modified_utc from '{}' archives where name like '%.log' order by name", root)),
+                   vec!["[old.tar] entry.log\t1500000000\t2017-07-14T02:40:00Z"]);
+        assert_eq!(count_found(&format!("select name from '{}' archives where name like '%.log' and created_unix > 0", root)), 0);
+    }
+
+    #[test]
+    fn test_marker_files() {
+        let fixture = TempDir::new("marker_files");
+
+        let epoch = ::std::time::UNIX_EPOCH;
+        let second = ::std::time::Duration::from_secs(1);
+        for &(name, modified) in &[("old.txt", 1000), ("same.txt", 2000), ("new.txt", 3000), ("last backup.stamp", 2000)] {
+            let file = fs::File::create(fixture.join(name)).unwrap();
+            file.set_times(fs::FileTimes::new().set_modified(epoch + second * modified)).unwrap();
+        }
+
+        let mut builder = tar::Builder::new(fs::File::create(fixture.join("old.tar")).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(0);
+        header.set_mtime(2500);
+        header.set_cksum();
+        builder.append_data(&mut header, "entry.log", io::empty()).unwrap();
+        builder.finish().unwrap();
+
+        let root = fixture.to_string_lossy();
+        let marker = fixture.join("last backup.stamp").to_string_lossy().to_string();
+
+        assert_eq!(find_names(&format!("select name from '{}' where name like '%.txt' and is_newer_than('{}') order by name", root, marker)),
+                   vec!["new.txt"]);
+        assert_eq!(find_names(&format!("select name from '{}' where name like '%.txt' and is_older_than('{}') order by name", root, marker)),
+                   vec!["old.txt"]);
+        // files modified at the same time as the marker are neither newer nor older
+        assert_eq!(find_names(&format!("select name from '{}' where name like '%.txt' and not (is_newer_than('{}') or is_older_than('{}')) order by name", root, marker, marker)),
+                   vec!["same.txt"]);
+        assert_eq!(find_names(&format!("select name from '{}' archives where name like '%.log' and is_newer_than('{}') order by name", root, marker)),
+                   vec!["[old.tar] entry.log"]);
+
+        // a missing marker file fails the query instead of matching nothing
+        let query = Parser::new().parse(&format!("select name from '{}' where is_newer_than('{}/missing.stamp')", root, root)).unwrap();
+        let mut searcher = Searcher::new(query);
+        searcher.set_quiet(true);
+        searcher.search(&mut |_| true);
+        assert!(searcher.has_errors());
+        assert_eq!(searcher.found, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_same_file_as() {
+        let fixture = TempDir::new("same_file");
+        fs::create_dir_all(fixture.join("sub")).unwrap();
+        fs::write(fixture.join("master.bin"), "content").unwrap();
+        fs::hard_link(fixture.join("master.bin"), fixture.join("sub").join("link.bin")).unwrap();
+        fs::write(fixture.join("copy.bin"), "content").unwrap();
+        fs::write(fixture.join("other.bin"), "other").unwrap();
+        ::std::os::unix::fs::symlink(fixture.join("master.bin"), fixture.join("symlink.bin")).unwrap();
+
+        let root = fixture.to_string_lossy();
+        let master = fixture.join("master.bin").to_string_lossy().to_string();
+
+        // a copy with the same content or a symlink isn't the same file, unless the symlinks are followed
+        assert_eq!(find_names(&format!("select name from '{}' where same_file_as('{}') order by name", root, master)),
+                   vec!["link.bin", "master.bin"]);
+        assert_eq!(find_names(&format!("select name from '{}' symlinks where same_file_as('{}') order by name", root, master)),
+                   vec!["link.bin", "master.bin", "symlink.bin"]);
+        assert_eq!(find_names(&format!("select name from '{}' where name like '%.bin' and not same_file_as('{}') order by name", root, master)),
+                   vec!["copy.bin", "other.bin", "symlink.bin"]);
+
+        // a missing reference file fails the query instead of matching nothing
+        let query = Parser::new().parse(&format!("select name from '{}' where same_file_as('{}/missing.bin')", root, root)).unwrap();
+        let mut searcher = Searcher::new(query);
+        searcher.set_quiet(true);
+        searcher.search(&mut |_| true);
+        assert!(searcher.has_errors());
+        assert_eq!(searcher.found, 0);
+    }
+
+    #[test]
+    fn test_dupes() {
+        let fixture = TempDir::new("dupes");
+        fs::create_dir_all(fixture.join("sub")).unwrap();
+        for &(name, content) in &[("a1", "aaaa"), ("a2", "aaaa"), ("sub/a3", "aaaa"), ("b", "b"), ("c1", "cc"), ("c2", "cc"), ("d", "dddd"), ("e1", ""), ("e2", "")] {
+            fs::write(fixture.join(name), content).unwrap();
+        }
 
-#[cfg(windows)]
-impl Group {
-    fn name(&self) -> &OsStr {
-        "".as_ref()
+        let root = fixture.to_string_lossy();
+
+        assert_eq!(find_names(&format!("select relpath, size from '{}' dupes order by size desc", root)),
+                   vec!["a1\t4", "a2\t4", "sub/a3\t4", "c1\t2", "c2\t2"]);
+        assert_eq!(find_names(&format!("select name from '{}' where name != a2 dupes order by name", root)),
+                   vec!["a1", "a3", "c1", "c2"]);
+        assert_eq!(find_names(&format!("select count(*), sum(size) from '{}' dupes group by sha256 order by 1 desc", root)),
+                   vec!["3\t12", "2\t4"]);
+        assert_eq!(find_names(&format!("select name, md5 from '{}' where name like 'c%' dupes order by name", root)),
+                   vec!["c1\te0323a9039add2978bf5b49550572c7c", "c2\te0323a9039add2978bf5b49550572c7c"]);
+        assert_eq!(find_names(&format!("select name from '{}' where name = c1 dupes order by name", root)), Vec::<String>::new());
     }
 }
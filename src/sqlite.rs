@@ -0,0 +1,77 @@
+use std::cell::RefCell;
+
+use rusqlite::Connection;
+
+/// Writes search results into a SQLite table instead of stdout.
+/// All rows are inserted within a single transaction, which is committed by `finish`.
+pub struct SqliteOutput {
+    connection: Connection,
+    insert_sql: String,
+    error: RefCell<Option<String>>,
+}
+
+impl SqliteOutput {
+    pub fn open(path: &str, table: &str, columns: &Vec<String>, append: bool) -> Result<SqliteOutput, String> {
+        let connection = Connection::open(path).map_err(|err| err.to_string())?;
+
+        let table = quote_identifier(table);
+        let columns: Vec<String> = columns.iter().map(|column| quote_identifier(column)).collect();
+        let column_defs: Vec<String> = columns.iter().map(|column| format!("{} TEXT", column)).collect();
+
+        let mut sql = format!("BEGIN; CREATE TABLE IF NOT EXISTS {} ({});", table, column_defs.join(", "));
+        if !append {
+            sql.push_str(&format!(" DELETE FROM {};", table));
+        }
+
+        if let Err(err) = connection.execute_batch(&sql) {
+            let _ = connection.execute_batch("ROLLBACK");
+            return Err(err.to_string());
+        }
+
+        let placeholders: Vec<String> = (1..columns.len() + 1).map(|i| format!("?{}", i)).collect();
+        let insert_sql = format!("INSERT INTO {} ({}) VALUES ({})", table, columns.join(", "), placeholders.join(", "));
+
+        Ok(SqliteOutput { connection, insert_sql, error: RefCell::new(None) })
+    }
+
+    /// Inserts a row. After the first failure the rest of the rows are skipped,
+    /// the error is reported by `finish`.
+    pub fn insert(&self, values: &Vec<String>) {
+        if self.error.borrow().is_some() {
+            return;
+        }
+
+        let result = self.connection.prepare_cached(&self.insert_sql)
+            .and_then(|mut stmt| stmt.execute(values));
+
+        if let Err(err) = result {
+            *self.error.borrow_mut() = Some(err.to_string());
+        }
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        match self.error.into_inner() {
+            Some(err) => {
+                let _ = self.connection.execute_batch("ROLLBACK");
+                Err(err)
+            },
+            None => self.connection.execute_batch("COMMIT").map_err(|err| err.to_string())
+        }
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("name"), "\"name\"");
+        assert_eq!(quote_identifier("count(*)"), "\"count(*)\"");
+        assert_eq!(quote_identifier("a\"b"), "\"a\"\"b\"");
+    }
+}
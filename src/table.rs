@@ -0,0 +1,201 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+
+use atty;
+use term;
+use term::color::Color;
+use term::StdoutTerminal;
+use term_size;
+
+const COLUMN_SEPARATOR: &str = "  ";
+const MIN_COLUMN_WIDTH: usize = 5;
+const ELLIPSIS: char = '…';
+
+/// Collects search results and prints them as an aligned table once the search is over.
+/// Colors and truncation to the terminal width are applied only when stdout is a terminal.
+pub struct TableOutput {
+    columns: Vec<String>,
+    rows: RefCell<Vec<(Option<Color>, Vec<String>)>>,
+    colored: bool,
+    max_width: Option<usize>,
+}
+
+impl TableOutput {
+    pub fn new(columns: &Vec<String>, truncate: bool) -> TableOutput {
+        let is_tty = atty::is(atty::Stream::Stdout);
+
+        let max_width = match truncate && is_tty {
+            true => term_size::dimensions_stdout().map(|(width, _)| width),
+            false => None
+        };
+
+        TableOutput {
+            columns: columns.clone(),
+            rows: RefCell::new(vec![]),
+            colored: is_tty,
+            max_width,
+        }
+    }
+
+    pub fn insert(&self, color: Option<Color>, values: Vec<String>) {
+        self.rows.borrow_mut().push((color, values));
+    }
+
+    pub fn finish(self, t: &mut Box<StdoutTerminal>) -> io::Result<()> {
+        let colored = self.colored;
+
+        for (color, line) in self.format_lines() {
+            match color {
+                Some(color) if colored => {
+                    let _ = t.fg(color);
+                    write!(t, "{}", line)?;
+                    let _ = t.reset();
+                    writeln!(t)?;
+                },
+                _ => {
+                    writeln!(t, "{}", line)?;
+                }
+            }
+        }
+
+        t.flush()
+    }
+
+    /// Writes the table without colors, e.g. into a file.
+    pub fn write_plain<W: Write>(self, w: &mut W) -> io::Result<()> {
+        for (_, line) in self.format_lines() {
+            writeln!(w, "{}", line)?;
+        }
+
+        Ok(())
+    }
+
+    /// Header, separator and the rows padded to the column widths.
+    fn format_lines(&self) -> Vec<(Option<Color>, String)> {
+        let rows = self.rows.borrow();
+
+        let mut widths: Vec<usize> = self.columns.iter().map(|column| column.chars().count()).collect();
+        for &(_, ref values) in rows.iter() {
+            for (idx, value) in values.iter().enumerate().take(widths.len()) {
+                widths[idx] = widths[idx].max(value.chars().count());
+            }
+        }
+
+        if let Some(max_width) = self.max_width {
+            shrink_widths(&mut widths, max_width);
+        }
+
+        let mut lines = vec![];
+        lines.push((None, format_line(&self.columns, &widths)));
+        lines.push((None, format_line(&widths.iter().map(|width| "-".repeat(*width)).collect(), &widths)));
+
+        for &(color, ref values) in rows.iter() {
+            lines.push((color, format_line(values, &widths)));
+        }
+
+        lines
+    }
+}
+
+/// Narrows the widest columns one by one until the table fits, no column gets narrower than `MIN_COLUMN_WIDTH`.
+fn shrink_widths(widths: &mut Vec<usize>, max_width: usize) {
+    let separators = COLUMN_SEPARATOR.len() * widths.len().saturating_sub(1);
+
+    while widths.iter().sum::<usize>() + separators > max_width {
+        let widest = widths.iter().enumerate().max_by_key(|&(_, width)| *width).map(|(idx, _)| idx);
+
+        match widest {
+            Some(idx) if widths[idx] > MIN_COLUMN_WIDTH => widths[idx] -= 1,
+            _ => break
+        }
+    }
+}
+
+fn format_line(values: &Vec<String>, widths: &Vec<usize>) -> String {
+    let cells: Vec<String> = widths.iter().enumerate().map(|(idx, width)| {
+        let value = values.get(idx).map(|value| value.as_str()).unwrap_or("");
+        format!("{:width$}", truncate(value, *width), width = width)
+    }).collect();
+
+    cells.join(COLUMN_SEPARATOR).trim_end().to_string()
+}
+
+fn truncate(value: &str, width: usize) -> String {
+    if value.chars().count() <= width {
+        return value.to_string();
+    }
+
+    let mut result: String = value.chars().take(width.saturating_sub(1)).collect();
+    result.push(ELLIPSIS);
+    result
+}
+
+/// Color of the row decided by the file type columns, if they were selected.
+pub fn get_row_color(is_dir: Option<&String>, is_symlink: Option<&String>, mode: Option<&String>) -> Option<Color> {
+    if is_dir.map(|val| val == "true").unwrap_or(false) {
+        return Some(term::color::BLUE);
+    }
+
+    if is_symlink.map(|val| val == "true").unwrap_or(false) {
+        return Some(term::color::CYAN);
+    }
+
+    if mode.map(|val| val.contains('x')).unwrap_or(false) {
+        return Some(term::color::GREEN);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(max_width: Option<usize>) -> TableOutput {
+        let table = TableOutput {
+            columns: vec![String::from("name"), String::from("size")],
+            rows: RefCell::new(vec![]),
+            colored: false,
+            max_width,
+        };
+
+        table.insert(None, vec![String::from("a.txt"), String::from("5")]);
+        table.insert(Some(term::color::BLUE), vec![String::from("a_long_directory_name"), String::from("4096")]);
+        table
+    }
+
+    #[test]
+    fn test_aligned_table() {
+        let lines: Vec<String> = table(None).format_lines().into_iter().map(|(_, line)| line).collect();
+
+        assert_eq!(lines, vec![
+            "name                   size",
+            "---------------------  ----",
+            "a.txt                  5",
+            "a_long_directory_name  4096",
+        ]);
+    }
+
+    #[test]
+    fn test_truncated_table() {
+        let lines = table(Some(16)).format_lines();
+
+        assert_eq!(lines[3], (Some(term::color::BLUE), String::from("a_long_di…  4096")));
+        assert_eq!(lines[2].1, "a.txt       5");
+    }
+
+    #[test]
+    fn test_row_color() {
+        let yes = String::from("true");
+        let no = String::from("false");
+        let exec = String::from("rwxr-xr-x");
+        let plain = String::from("rw-r--r--");
+
+        assert_eq!(get_row_color(Some(&yes), None, Some(&exec)), Some(term::color::BLUE));
+        assert_eq!(get_row_color(Some(&no), Some(&yes), None), Some(term::color::CYAN));
+        assert_eq!(get_row_color(None, None, Some(&exec)), Some(term::color::GREEN));
+        assert_eq!(get_row_color(Some(&no), Some(&no), Some(&plain)), None);
+        assert_eq!(get_row_color(None, None, None), None);
+    }
+}
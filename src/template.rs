@@ -0,0 +1,169 @@
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+use field::Field;
+
+/// Command line printed for every found file by `into cmd 'TEMPLATE'`.
+/// `{field}` placeholders are replaced with the values of the file quoted for the shell,
+/// `{}` is the first column of the query, `{{` and `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CmdTemplate {
+    template: String,
+    parts: Vec<TemplatePart>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    FirstColumn,
+    Field(Field),
+}
+
+impl CmdTemplate {
+    /// Fails with the placeholder which isn't a field name or isn't closed.
+    pub fn parse(template: &str) -> Result<CmdTemplate, String> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                },
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    while let Some(c) = chars.next() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+
+                    if !closed {
+                        return Err(format!("{{{}", name));
+                    }
+
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(text));
+                        text = String::new();
+                    }
+
+                    let name = name.trim();
+                    match name.is_empty() {
+                        true => parts.push(TemplatePart::FirstColumn),
+                        false => match Field::from_str(name) {
+                            Ok(field) => parts.push(TemplatePart::Field(field)),
+                            Err(_) => return Err(format!("{{{}}}", name))
+                        }
+                    }
+                },
+                _ => text.push(c)
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+
+        Ok(CmdTemplate { template: template.to_string(), parts })
+    }
+
+    /// Fields the placeholders ask for, `{}` needs none of its own.
+    pub fn get_fields(&self) -> Vec<Field> {
+        self.parts.iter()
+            .filter_map(|part| match part {
+                TemplatePart::Field(field) => Some(field.clone()),
+                _ => None
+            })
+            .collect()
+    }
+
+    /// Substitutes the placeholders, `first_column` is the value of `{}`
+    /// and `get_value` gives the values of the fields.
+    pub fn render(&self, first_column: &str, get_value: &Fn(&Field) -> String) -> String {
+        let mut result = String::new();
+
+        for part in &self.parts {
+            match part {
+                TemplatePart::Text(text) => result.push_str(text),
+                TemplatePart::FirstColumn => result.push_str(&shell_quote(first_column)),
+                TemplatePart::Field(field) => result.push_str(&shell_quote(&get_value(field))),
+            }
+        }
+
+        result
+    }
+}
+
+/// The template as it's written after `into cmd`.
+impl Display for CmdTemplate {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "'{}'", self.template)
+    }
+}
+
+/// Single-quotes the value unless every character of it is safe for the shell as is.
+/// A single quote inside is closed, escaped and reopened: `it's` becomes `'it'\''s'`.
+pub fn shell_quote(value: &str) -> String {
+    let is_safe = !value.is_empty() && value.chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-+=.,/:@%".contains(c));
+
+    match is_safe {
+        true => value.to_string(),
+        false => format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/home/user/file.txt"), "/home/user/file.txt");
+        assert_eq!(shell_quote("123"), "123");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("my file.txt"), "'my file.txt'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote("$(rm -rf ~)"), "'$(rm -rf ~)'");
+        assert_eq!(shell_quote("-n\nx"), "'-n\nx'");
+    }
+
+    #[test]
+    fn test_parse() {
+        let template = CmdTemplate::parse("mv {path} {{backup}}/{NAME}.{}").unwrap();
+        assert_eq!(template.parts, vec![
+            TemplatePart::Text(String::from("mv ")),
+            TemplatePart::Field(Field::Path),
+            TemplatePart::Text(String::from(" {backup}/")),
+            TemplatePart::Field(Field::Name),
+            TemplatePart::Text(String::from(".")),
+            TemplatePart::FirstColumn,
+        ]);
+        assert_eq!(template.get_fields(), vec![Field::Path, Field::Name]);
+
+        assert_eq!(CmdTemplate::parse("rm {pth}"), Err(String::from("{pth}")));
+        assert_eq!(CmdTemplate::parse("rm {path"), Err(String::from("{path")));
+    }
+
+    #[test]
+    fn test_render() {
+        let template = CmdTemplate::parse("cp {path} /backup/{size}-{}").unwrap();
+        let get_value = |field: &Field| match field {
+            Field::Path => String::from("/home/user/it's here.txt"),
+            _ => String::from("42")
+        };
+
+        assert_eq!(template.render("it's here.txt", &get_value), "cp '/home/user/it'\\''s here.txt' /backup/42-'it'\\''s here.txt'");
+    }
+}
@@ -0,0 +1,180 @@
+use std::fs;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use parser::convert_glob_to_pattern;
+use parser::is_glob;
+
+/// Paths matching the pattern of a root, sorted. Braces are expanded first, `/srv/{web,api}/logs`
+/// stands for `/srv/web/logs` and `/srv/api/logs`. Then every component with wildcards is matched
+/// against the entries of the directories found so far, while `**` stands for any number of directories.
+/// Like in the shell, wildcards match hidden entries only when the component itself starts with a dot.
+pub fn expand_root_pattern(pattern: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = expand_braces(pattern).iter()
+        .flat_map(|pattern| expand_glob(pattern))
+        .collect();
+
+    paths.sort();
+    paths.dedup();
+
+    paths
+}
+
+/// `a{b,c}d` gives `abd` and `acd`, nested braces are expanded too. Braces without a comma are kept as they are.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+
+    for start in 0..chars.len() {
+        if chars[start] != '{' {
+            continue;
+        }
+
+        let mut level = 0;
+        let mut separators = vec![start];
+        let mut end = None;
+
+        for idx in start..chars.len() {
+            match chars[idx] {
+                '{' => level += 1,
+                '}' => {
+                    level -= 1;
+                    if level == 0 {
+                        end = Some(idx);
+                        break;
+                    }
+                },
+                ',' if level == 1 => separators.push(idx),
+                _ => {}
+            }
+        }
+
+        let end = match end {
+            Some(end) if separators.len() > 1 => end,
+            _ => continue
+        };
+        separators.push(end);
+
+        let prefix: String = chars[..start].iter().collect();
+        let suffix: String = chars[end + 1..].iter().collect();
+
+        return separators.windows(2)
+            .map(|bounds| chars[bounds[0] + 1..bounds[1]].iter().collect::<String>())
+            .flat_map(|alternative| expand_braces(&format!("{}{}{}", prefix, alternative, suffix)))
+            .collect();
+    }
+
+    vec![pattern.to_string()]
+}
+
+fn expand_glob(pattern: &str) -> Vec<PathBuf> {
+    let components: Vec<Component> = Path::new(pattern).components().collect();
+    let mut paths = vec![PathBuf::new()];
+
+    for (idx, component) in components.iter().enumerate() {
+        let is_last = idx == components.len() - 1;
+        let name = component.as_os_str().to_string_lossy().to_string();
+
+        paths = match component {
+            Component::Normal(_) if name == "**" => {
+                paths.iter().flat_map(|path| list_dirs_recursively(path)).collect()
+            },
+            Component::Normal(_) if is_glob(&name) => {
+                let regex = match Regex::new(&convert_glob_to_pattern(&name)) {
+                    Ok(regex) => regex,
+                    Err(_) => return vec![]
+                };
+
+                paths.iter().flat_map(|path| list_matching_entries(path, &regex, name.starts_with('.'), is_last)).collect()
+            },
+            _ => paths.iter().map(|path| path.join(component.as_os_str())).collect()
+        };
+    }
+
+    paths.into_iter()
+        .filter(|path| !path.as_os_str().is_empty() && path.exists())
+        .collect()
+}
+
+/// Entries of the directory whose names match, only directories unless it's the last component.
+fn list_matching_entries(dir: &Path, regex: &Regex, include_hidden: bool, include_files: bool) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(to_dir(dir)) {
+        Ok(entries) => entries,
+        Err(_) => return vec![]
+    };
+
+    entries.filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            regex.is_match(&name) && (include_hidden || !name.starts_with('.'))
+        })
+        .map(|entry| dir.join(entry.file_name()))
+        .filter(|path| include_files || path.is_dir())
+        .collect()
+}
+
+/// The directory itself and all the directories inside it, except hidden ones. Symlinks are not followed.
+fn list_dirs_recursively(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![dir.to_path_buf()];
+
+    if let Ok(entries) = fs::read_dir(to_dir(dir)) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let is_dir = entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false);
+            if is_dir && !entry.file_name().to_string_lossy().starts_with('.') {
+                dirs.extend(list_dirs_recursively(&dir.join(entry.file_name())));
+            }
+        }
+    }
+
+    dirs
+}
+
+/// Relative patterns start from the current directory.
+fn to_dir(path: &Path) -> &Path {
+    match path.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::TempDir;
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(expand_braces("/srv/{web,api}/logs"), vec!["/srv/web/logs", "/srv/api/logs"]);
+        assert_eq!(expand_braces("{a,b}/{c,d}"), vec!["a/c", "a/d", "b/c", "b/d"]);
+        assert_eq!(expand_braces("x{a,b{1,2}}"), vec!["xa", "xb1", "xb2"]);
+        assert_eq!(expand_braces("dir{,.bak}"), vec!["dir", "dir.bak"]);
+        assert_eq!(expand_braces("{single}/{a,b"), vec!["{single}/{a,b"]);
+    }
+
+    #[test]
+    fn test_expand_root_pattern() {
+        let fixture = TempDir::new("root_pattern");
+        for dir in &["web/logs", "api/logs/old", "db/data", ".hidden/logs"] {
+            fs::create_dir_all(fixture.join(dir)).unwrap();
+        }
+        fs::write(fixture.join("web/logs.txt"), "").unwrap();
+        fs::write(fixture.join("api/logs/old/logs"), "").unwrap();
+
+        let root = fixture.to_string_lossy();
+        let expand = |pattern: &str| -> Vec<String> {
+            expand_root_pattern(&format!("{}/{}", root, pattern)).iter()
+                .map(|path| path.strip_prefix(&fixture).unwrap().to_string_lossy().replace('\\', "/"))
+                .collect()
+        };
+
+        assert_eq!(expand("*/logs"), vec!["api/logs", "web/logs"]);
+        assert_eq!(expand("*/logs*"), vec!["api/logs", "web/logs", "web/logs.txt"]);
+        assert_eq!(expand(".*/logs"), vec![".hidden/logs"]);
+        assert_eq!(expand("{db,web}/*"), vec!["db/data", "web/logs", "web/logs.txt"]);
+        assert_eq!(expand("**/logs"), vec!["api/logs", "api/logs/old/logs", "web/logs"]);
+        assert_eq!(expand("w?b"), vec!["web"]);
+        assert_eq!(expand("*/nothing"), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Map of limited size, the least recently used entry goes away when a new one doesn't fit.
+pub struct LruCache<K: Eq + Hash + Clone, V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<K, (u64, V)>,
+    /// Keys by the tick of their last use, the oldest one goes first
+    usage: BTreeMap<u64, K>,
+}
+
+impl<K: Eq + Hash + Clone, V> LruCache<K, V> {
+
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        debug_assert_ne!(capacity, 0);
+        LruCache {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            usage: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the value and marks it as the most recently used.
+    pub fn get_mut(&mut self, k: &K) -> Option<&mut V> {
+        self.tick += 1;
+        let tick = self.tick;
+
+        match self.entries.get_mut(k) {
+            Some(&mut (ref mut last_used, ref mut v)) => {
+                self.usage.remove(last_used);
+                self.usage.insert(tick, k.clone());
+                *last_used = tick;
+                Some(v)
+            },
+            None => None
+        }
+    }
+
+    /// Returns the value of the key, the default one is inserted first if there's none.
+    pub fn get_or_insert_default(&mut self, k: K) -> &mut V where V: Default {
+        if !self.entries.contains_key(&k) {
+            self.insert(k.clone(), V::default());
+        }
+
+        self.get_mut(&k).unwrap()
+    }
+
+    pub fn insert(&mut self, k: K, v: V) {
+        self.tick += 1;
+
+        if let Some((last_used, _)) = self.entries.remove(&k) {
+            self.usage.remove(&last_used);
+        } else if self.entries.len() >= self.capacity {
+            let oldest = self.usage.keys().next().cloned();
+            if let Some(oldest) = oldest {
+                let oldest_key = self.usage.remove(&oldest).unwrap();
+                self.entries.remove(&oldest_key);
+            }
+        }
+
+        self.usage.insert(self.tick, k.clone());
+        self.entries.insert(k, (self.tick, v));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_get() {
+        let mut lru = LruCache::new(2);
+        lru.insert("a", 1);
+        lru.insert("b", 2);
+        assert_eq!(lru.get_mut(&"a"), Some(&mut 1));
+        assert_eq!(lru.get_mut(&"b"), Some(&mut 2));
+        assert_eq!(lru.get_mut(&"c"), None);
+
+        lru.insert("a", 3);
+        assert_eq!(lru.get_mut(&"a"), Some(&mut 3));
+        assert_eq!(lru.entries.len(), 2);
+    }
+
+    #[test]
+    fn test_least_recently_used_evicted() {
+        let mut lru = LruCache::new(2);
+        lru.insert("a", 1);
+        lru.insert("b", 2);
+        lru.get_mut(&"a");
+        lru.insert("c", 3);
+
+        assert_eq!(lru.entries.len(), 2);
+        assert_eq!(lru.get_mut(&"b"), None);
+        assert_eq!(lru.get_mut(&"a"), Some(&mut 1));
+        assert_eq!(lru.get_mut(&"c"), Some(&mut 3));
+    }
+
+    #[test]
+    fn test_get_or_insert_default() {
+        let mut lru: LruCache<&str, Vec<i32>> = LruCache::new(1);
+        lru.get_or_insert_default("a").push(1);
+        lru.get_or_insert_default("a").push(2);
+        assert_eq!(lru.get_mut(&"a"), Some(&mut vec![1, 2]));
+
+        lru.get_or_insert_default("b");
+        assert_eq!(lru.get_mut(&"a"), None);
+        assert_eq!(lru.get_mut(&"b"), Some(&mut vec![]));
+    }
+}
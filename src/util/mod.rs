@@ -1,11 +1,21 @@
+mod glob;
+mod lru;
+mod sort_buffer;
+#[cfg(test)]
+mod temp_dir;
 mod top_n;
 mod wbuf;
 
 use std::cmp::Ordering;
-use std::error::Error;
+use std::fmt;
 use std::fmt::Display;
+use std::fmt::Formatter;
+use std::env;
 use std::io;
+use std::io::Write;
+use std::path::Component;
 use std::path::Path;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::string::ToString;
 
@@ -13,56 +23,118 @@ use chrono::DateTime;
 use chrono::Duration;
 use chrono::Local;
 use chrono::LocalResult;
+use chrono::Months;
+use chrono::SecondsFormat;
 use chrono::TimeZone;
+use chrono::Utc;
 use chrono_english::{parse_date_string,Dialect};
 use regex::Regex;
 use term;
-use term::StdoutTerminal;
 use time::Tm;
 
+pub use self::glob::expand_root_pattern;
+pub use self::lru::LruCache;
+pub use self::sort_buffer::SortBuffer;
+#[cfg(test)]
+pub use self::temp_dir::TempDir;
 pub use self::top_n::TopN;
 pub use self::wbuf::WritableBuffer;
 use parser::ColumnExpr;
+use parser::DEFAULT_DATE_FORMAT;
+
+/// Value of a column or of an ordering criterion as it's found. Dates keep their time,
+/// so they are ordered and output in any format without parsing their text back.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Value {
+    Text(String),
+    /// Seconds and nanoseconds since the epoch
+    DateTime(i64, u32),
+}
+
+impl Value {
+    pub fn from_datetime(dt: &DateTime<Local>) -> Value {
+        Value::DateTime(dt.timestamp(), dt.timestamp_subsec_nanos())
+    }
+
+    pub fn to_datetime(&self) -> Option<DateTime<Local>> {
+        match self {
+            Value::DateTime(secs, nanos) => Local.timestamp_opt(*secs, *nanos).single(),
+            Value::Text(_) => None
+        }
+    }
+}
+
+/// Dates are written in the default format, the same as the text values of the dates.
+impl Display for Value {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::Text(ref s) => fmt.write_str(s),
+            Value::DateTime(..) => match self.to_datetime() {
+                Some(dt) => write!(fmt, "{}", dt.format(DEFAULT_DATE_FORMAT)),
+                None => Ok(())
+            }
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::Text(s)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd)]
-pub struct Criteria<T> where T: Display + ToString {
+pub struct Criteria {
     fields: Rc<Vec<ColumnExpr>>,
     /// Values of current row to sort with, placed in order of significance.
-    values: Vec<T>,
+    values: Vec<Value>,
     /// Shared smart reference to Vector of boolean where each index corresponds to whether the
     /// field at that index should be ordered in ascending order `true` or descending order `false`.
     orderings: Rc<Vec<bool>>,
 }
 
-impl<T> Criteria<T> where T: Display {
-    pub fn new(fields: Rc<Vec<ColumnExpr>>, values: Vec<T>, orderings: Rc<Vec<bool>>) -> Criteria<T> {
+impl Criteria {
+    pub fn new(fields: Rc<Vec<ColumnExpr>>, values: Vec<Value>, orderings: Rc<Vec<bool>>) -> Criteria {
         debug_assert_eq!(fields.len(), values.len());
         debug_assert_eq!(values.len(), orderings.len());
 
         Criteria { fields, values, orderings }
     }
 
+    pub fn values(&self) -> &Vec<Value> {
+        &self.values
+    }
+
+    /// Criteria of the same columns and directions with other values.
+    pub fn with_values(&self, values: Vec<Value>) -> Criteria {
+        Criteria::new(self.fields.clone(), values, self.orderings.clone())
+    }
+
+    /// Queries of a union sort by their own columns.
+    pub fn has_same_ordering(&self, other: &Self) -> bool {
+        (Rc::ptr_eq(&self.fields, &other.fields) || self.fields == other.fields)
+            && (Rc::ptr_eq(&self.orderings, &other.orderings) || self.orderings == other.orderings)
+    }
+
     #[inline]
-    fn cmp_at(&self, other: &Self, i: usize) -> Ordering where T: Ord {
-        let field = &self.fields[i];
-        let comparison = match &field.field {
-            Some(field) => {
-                if field.is_numeric_field() {
-                    self.cmp_at_numbers(other, i)
-                } else if field.is_datetime_field() {
-                    self.cmp_at_datetimes(other, i)
-                } else {
-                    self.cmp_at_direct(other, i)
-                }
-            },
-            _ => self.cmp_at_direct(other, i)
+    fn cmp_at(&self, other: &Self, i: usize) -> Ordering {
+        let comparison = match self.fields[i].get_field() {
+            Some(ref field) if field.is_numeric_field() => self.cmp_at_numbers(other, i),
+            Some(ref field) if field.is_datetime_field() => self.cmp_at_datetimes(other, i),
+            _ => self.cmp_at_values(other, i)
         };
 
         if self.orderings[i] { comparison } else { comparison.reverse() }
     }
 
     #[inline]
-    fn cmp_at_direct(&self, other: &Self, i: usize) -> Ordering where T: Ord {
+    fn cmp_at_direct(&self, other: &Self, i: usize) -> Ordering {
         if self.values[i] < other.values[i] {
             Ordering::Less
         } else if self.values[i] > other.values[i] {
@@ -72,10 +144,47 @@ impl<T> Criteria<T> where T: Display {
         }
     }
 
+    /// Compares values as numbers or file sizes when both of them could be parsed as such,
+    /// falls back to the direct comparison otherwise.
     #[inline]
-    fn cmp_at_numbers(&self, other: &Self, i: usize) -> Ordering where T: Ord {
-        let a = parse_filesize(&self.values[i].to_string()).unwrap_or(0);
-        let b = parse_filesize(&other.values[i].to_string()).unwrap_or(0);
+    fn cmp_at_values(&self, other: &Self, i: usize) -> Ordering {
+        let a = self.values[i].to_string();
+        let b = other.values[i].to_string();
+
+        if let (Ok(a), Ok(b)) = (a.parse::<i64>(), b.parse::<i64>()) {
+            return a.cmp(&b);
+        }
+
+        if let (Some(a), Some(b)) = (parse_filesize(&a), parse_filesize(&b)) {
+            return a.cmp(&b);
+        }
+
+        self.cmp_at_direct(other, i)
+    }
+
+    #[inline]
+    fn cmp_at_numbers(&self, other: &Self, i: usize) -> Ordering {
+        let a = self.values[i].to_string();
+        let b = other.values[i].to_string();
+
+        // timestamps before 1970 are negative
+        if a.starts_with('-') || b.starts_with('-') {
+            if let (Ok(a), Ok(b)) = (a.parse::<i64>(), b.parse::<i64>()) {
+                return a.cmp(&b);
+            }
+        }
+
+        // fractions like compression ratios would be truncated by the file size parsing,
+        // empty values are zero as below
+        if a.contains('.') || b.contains('.') {
+            let parse_fraction = |s: &str| if s.is_empty() { Ok(0.0) } else { s.parse::<f64>() };
+            if let (Ok(a), Ok(b)) = (parse_fraction(&a), parse_fraction(&b)) {
+                return a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+            }
+        }
+
+        let a = parse_filesize(&a).unwrap_or(0);
+        let b = parse_filesize(&b).unwrap_or(0);
 
         if a < b {
             Ordering::Less
@@ -86,8 +195,13 @@ impl<T> Criteria<T> where T: Display {
         }
     }
 
+    /// Dates of the files are compared by their time, other values like the dates of the indexed files by their text.
     #[inline]
-    fn cmp_at_datetimes(&self, other: &Self, i: usize) -> Ordering where T: Ord {
+    fn cmp_at_datetimes(&self, other: &Self, i: usize) -> Ordering {
+        if let (&Value::DateTime(..), &Value::DateTime(..)) = (&self.values[i], &other.values[i]) {
+            return self.values[i].cmp(&other.values[i]);
+        }
+
         let default = Local.ymd(1970, 1, 1).and_hms(0, 0, 0);
         let a = parse_datetime(&self.values[i].to_string()).unwrap_or((default, default)).0;
         let b = parse_datetime(&other.values[i].to_string()).unwrap_or((default, default)).0;
@@ -102,7 +216,7 @@ impl<T> Criteria<T> where T: Display {
     }
 }
 
-impl<T: Display + Ord> Ord for Criteria<T> {
+impl Ord for Criteria {
     fn cmp(&self, other: &Self) -> Ordering {
         for i in 0..(self.values.len().min(other.values.len())) {
             let ord = self.cmp_at(other, i);
@@ -115,92 +229,92 @@ impl<T: Display + Ord> Ord for Criteria<T> {
     }
 }
 
-pub fn path_error_message(p: &Path, e: io::Error, t: &mut Box<StdoutTerminal>) {
-    error_message(&p.to_string_lossy(), e.description(), t);
-}
-
-pub fn error_message(source: &str, description: &str, t: &mut Box<StdoutTerminal>) {
-    t.fg(term::color::YELLOW).unwrap();
-    eprint!("{}", source);
-    t.reset().unwrap();
-
-    eprint!(": ");
-
-    t.fg(term::color::RED).unwrap();
-    eprintln!("{}", description);
-    t.reset().unwrap();
+pub fn path_error_message(p: &Path, e: &io::Error) {
+    error_message(&p.to_string_lossy(), &e.to_string());
 }
 
-pub fn parse_filesize(s: &str) -> Option<u64> {
-    let string = s.to_string().to_ascii_lowercase();
+/// Errors go to stderr, so they don't get mixed with the results piped somewhere.
+pub fn error_message(source: &str, description: &str) {
+    match term::stderr() {
+        Some(mut t) => {
+            let _ = t.fg(term::color::YELLOW);
+            let _ = write!(t, "{}", source);
+            let _ = t.reset();
 
-    if string.ends_with("k") {
-        match &string[..(s.len() - 1)].parse::<u64>() {
-            Ok(size) => return Some(size * 1024),
-            _ => return None
-        }
-    }
+            let _ = write!(t, ": ");
 
-    if string.ends_with("kb") {
-        match &string[..(s.len() - 2)].parse::<u64>() {
-            Ok(size) => return Some(size * 1024),
-            _ => return None
+            let _ = t.fg(term::color::RED);
+            let _ = writeln!(t, "{}", description);
+            let _ = t.reset();
+        },
+        None => {
+            eprintln!("{}: {}", source, description);
         }
     }
+}
 
-    if string.ends_with("kib") {
-        match &string[..(s.len() - 3)].parse::<u64>() {
-            Ok(size) => return Some(size * 1024),
-            _ => return None
-        }
-    }
+lazy_static! {
+    static ref FILE_SIZE_REGEX: Regex = Regex::new("(?i)^(\\d+(?:\\.\\d+)?) ?(b|k|kb|kib|m|mb|mib|g|gb|gib|t|tb|tib)?$").unwrap();
+}
 
-    if string.ends_with("m") {
-        match &string[..(s.len() - 1)].parse::<u64>() {
-            Ok(size) => return Some(size * 1024 * 1024),
-            _ => return None
-        }
+/// Parses file size with an optional unit, e.g. `512`, `5k`, `10 MiB` or `1.50 KB`.
+pub fn parse_filesize(s: &str) -> Option<u64> {
+    match FILE_SIZE_REGEX.captures(s.trim()) {
+        Some(cap) => {
+            let multiplier: u64 = match cap.get(2) {
+                Some(unit) => {
+                    match unit.as_str().to_ascii_lowercase().chars().next() {
+                        Some('k') => 1024,
+                        Some('m') => 1024 * 1024,
+                        Some('g') => 1024 * 1024 * 1024,
+                        Some('t') => 1024 * 1024 * 1024 * 1024,
+                        _ => 1
+                    }
+                },
+                None => 1
+            };
+
+            let number = &cap[1];
+            match number.parse::<u64>() {
+                Ok(size) => Some(size * multiplier),
+                _ => {
+                    match number.parse::<f64>() {
+                        Ok(size) => Some((size * multiplier as f64) as u64),
+                        _ => None
+                    }
+                }
+            }
+        },
+        None => None
     }
+}
 
-    if string.ends_with("mb") {
-        match &string[..(s.len() - 2)].parse::<u64>() {
-            Ok(size) => return Some(size * 1024 * 1024),
-            _ => return None
-        }
-    }
+lazy_static! {
+    static ref DURATION_REGEX: Regex = Regex::new("^(\\d+):([0-5]?\\d):([0-5]?\\d)$").unwrap();
+}
 
-    if string.ends_with("mib") {
-        match &string[..(s.len() - 3)].parse::<u64>() {
-            Ok(size) => return Some(size * 1024 * 1024),
-            _ => return None
-        }
-    }
+/// Parses duration given either in seconds or as `HH:MM:SS`.
+pub fn parse_duration(s: &str) -> Option<u64> {
+    let s = s.trim();
 
-    if string.ends_with("g") {
-        match &string[..(s.len() - 1)].parse::<u64>() {
-            Ok(size) => return Some(size * 1024 * 1024 * 1024),
-            _ => return None
-        }
+    if let Ok(seconds) = s.parse::<u64>() {
+        return Some(seconds);
     }
 
-    if string.ends_with("gb") {
-        match &string[..(s.len() - 2)].parse::<u64>() {
-            Ok(size) => return Some(size * 1024 * 1024 * 1024),
-            _ => return None
-        }
-    }
+    match DURATION_REGEX.captures(s) {
+        Some(cap) => {
+            let hours = cap[1].parse::<u64>().ok()?;
+            let minutes = cap[2].parse::<u64>().ok()?;
+            let seconds = cap[3].parse::<u64>().ok()?;
 
-    if string.ends_with("gib") {
-        match &string[..(s.len() - 3)].parse::<u64>() {
-            Ok(size) => return Some(size * 1024 * 1024 * 1024),
-            _ => return None
-        }
+            Some(hours * 3600 + minutes * 60 + seconds)
+        },
+        None => None
     }
+}
 
-    match string.parse::<u64>() {
-        Ok(size) => return Some(size),
-        _ => return None
-    }
+pub fn format_duration(seconds: u64) -> String {
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60)
 }
 
 lazy_static! {
@@ -208,22 +322,34 @@ lazy_static! {
 }
 
 pub fn parse_datetime(s: &str) -> Result<(DateTime<Local>, DateTime<Local>), String> {
-    if s == "today" {
-        let date = Local::now().date();
+    parse_datetime_at(s, Local::now())
+}
+
+/// Parses date/time relative to the given moment. Returns the first and the last moment of the
+/// period meant by the value, e.g. the whole day for `2018-05-01` or `today`.
+fn parse_datetime_at(s: &str, now: DateTime<Local>) -> Result<(DateTime<Local>, DateTime<Local>), String> {
+    let s = s.trim();
+
+    if s.eq_ignore_ascii_case("today") {
+        let date = now.date();
         let start = date.and_hms(0, 0, 0);
         let finish = date.and_hms(23, 59, 59);
 
         return Ok((start, finish));
     }
 
-    if s == "yesterday" {
-        let date = Local::now().date() - Duration::days(1);
+    if s.eq_ignore_ascii_case("yesterday") {
+        let date = now.date() - Duration::days(1);
         let start = date.and_hms(0, 0, 0);
         let finish = date.and_hms(23, 59, 59);
 
         return Ok((start, finish));
     }
 
+    if let Some(date_time) = parse_relative_datetime(s, now) {
+        return Ok((date_time, date_time));
+    }
+
     match DATE_REGEX.captures(s) {
         Some(cap) => {
             let year: i32 = cap[1].parse().unwrap();
@@ -252,7 +378,7 @@ pub fn parse_datetime(s: &str) -> Result<(DateTime<Local>, DateTime<Local>), Str
                 },
                 None => {
                     min_start = 0;
-                    min_finish = 23;
+                    min_finish = 59;
                 }
             }
 
@@ -261,11 +387,11 @@ pub fn parse_datetime(s: &str) -> Result<(DateTime<Local>, DateTime<Local>), Str
             match cap.get(6) {
                 Some(val) => {
                     sec_start = val.as_str().parse().unwrap();
-                    sec_finish = min_start;
+                    sec_finish = sec_start;
                 },
                 None => {
                     sec_start = 0;
-                    sec_finish = 23;
+                    sec_finish = 59;
                 }
             }
 
@@ -280,7 +406,7 @@ pub fn parse_datetime(s: &str) -> Result<(DateTime<Local>, DateTime<Local>), Str
             }
         },
         None => {
-            match parse_date_string(s, Local::now(), Dialect::Uk) {
+            match parse_date_string(s, now, Dialect::Uk) {
                 Ok(date_time) => Ok((date_time, date_time)),
                 _ => Err("Error parsing date/time value: ".to_string() + s)
             }
@@ -288,16 +414,195 @@ pub fn parse_datetime(s: &str) -> Result<(DateTime<Local>, DateTime<Local>), Str
     }
 }
 
+lazy_static! {
+    static ref RELATIVE_DATE_REGEX: Regex = Regex::new("(?i)^(-)? ?(\\d+) ?(seconds?|secs?|s|minutes?|mins?|m|hours?|h|days?|d|weeks?|w|months?|years?|y)( ago)?$").unwrap();
+}
+
+/// Parses moments in the past like `-3 days`, `-1h` or `2 hours ago`.
+fn parse_relative_datetime(s: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let cap = RELATIVE_DATE_REGEX.captures(s)?;
+
+    if cap.get(1).is_none() && cap.get(4).is_none() {
+        return None;
+    }
+
+    let amount: i64 = cap[2].parse().ok()?;
+    let unit = cap[3].to_ascii_lowercase();
+
+    match unit.chars().next() {
+        Some('s') => now.checked_sub_signed(Duration::seconds(amount)),
+        Some('m') if unit.starts_with("mo") => now.checked_sub_months(Months::new(amount as u32)),
+        Some('m') => now.checked_sub_signed(Duration::minutes(amount)),
+        Some('h') => now.checked_sub_signed(Duration::hours(amount)),
+        Some('d') => now.checked_sub_signed(Duration::days(amount)),
+        Some('w') => now.checked_sub_signed(Duration::weeks(amount)),
+        Some('y') => now.checked_sub_months(Months::new(amount as u32 * 12)),
+        _ => None
+    }
+}
+
 pub fn to_local_datetime(tm: &Tm) -> DateTime<Local> {
     Local.ymd(tm.tm_year + 1900, (tm.tm_mon + 1) as u32, tm.tm_mday as u32)
         .and_hms(tm.tm_hour as u32, tm.tm_min as u32, tm.tm_sec as u32)
 }
 
+/// RFC 3339 in Zulu time, e.g. `2024-03-01T09:30:00Z`, which sorts the same way as the dates do.
+pub fn format_utc_datetime(dt: &DateTime<Local>) -> String {
+    dt.with_timezone(&Utc).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Whole days passed from the date till now, dates in the future give 0.
+pub fn days_between(dt: &DateTime<Local>, now: &DateTime<Local>) -> u64 {
+    match now.signed_duration_since(*dt).num_days() {
+        days if days > 0 => days as u64,
+        _ => 0
+    }
+}
+
 pub fn str_to_bool(val: &str) -> bool {
     let str_val = val.to_ascii_lowercase();
     str_val.eq("true") || str_val.eq("1")
 }
 
+/// Makes path absolute and resolves `.` and `..` components without touching the filesystem,
+/// so symlinks are not followed.
+pub fn to_absolute_path(path: &Path) -> PathBuf {
+    let path = match path.is_absolute() {
+        true => path.to_path_buf(),
+        false => match env::current_dir() {
+            Ok(current_dir) => current_dir.join(path),
+            _ => path.to_path_buf()
+        }
+    };
+
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                result.pop();
+            },
+            _ => result.push(component.as_os_str())
+        }
+    }
+
+    result
+}
+
+pub fn get_extension(file_name: &str) -> String {
+    match Path::new(file_name).extension() {
+        Some(ext) => ext.to_string_lossy().to_lowercase(),
+        None => String::new()
+    }
+}
+
+/// Tells binary content from text by the leading bytes of a file: any NUL byte or
+/// more than 10% of bytes not being valid UTF-8 make it binary. A multibyte character
+/// cut at the end of the sample is not counted as invalid.
+pub fn is_binary_content(buf: &[u8]) -> bool {
+    if buf.contains(&0) {
+        return true;
+    }
+
+    let mut invalid = 0;
+    let mut rest = buf;
+
+    loop {
+        match ::std::str::from_utf8(rest) {
+            Ok(_) => break,
+            Err(err) => {
+                match err.error_len() {
+                    Some(len) => {
+                        invalid += len;
+                        rest = &rest[err.valid_up_to() + len..];
+                    },
+                    None => break
+                }
+            }
+        }
+    }
+
+    invalid * 10 > buf.len()
+}
+
+/// Kind of line breaks found in the text: `lf`, `crlf` or `mixed` if there are both.
+/// Text without any line breaks has none of them.
+pub fn detect_line_endings(buf: &[u8]) -> Option<&'static str> {
+    let mut lf = false;
+    let mut crlf = false;
+
+    for (idx, &b) in buf.iter().enumerate() {
+        if b == b'\n' {
+            match idx > 0 && buf[idx - 1] == b'\r' {
+                true => crlf = true,
+                false => lf = true
+            }
+        }
+    }
+
+    match (lf, crlf) {
+        (true, true) => Some("mixed"),
+        (true, false) => Some("lf"),
+        (false, true) => Some("crlf"),
+        (false, false) => None
+    }
+}
+
+/// Tells whether the content starts with UTF-8 or UTF-16 byte order mark.
+pub fn has_bom(buf: &[u8]) -> bool {
+    buf.starts_with(&[0xEF, 0xBB, 0xBF]) || buf.starts_with(&[0xFE, 0xFF]) || buf.starts_with(&[0xFF, 0xFE])
+}
+
+/// Command of the interpreter named on the shebang line, without its arguments.
+/// Scripts started through `env` get the command `env` runs, e.g. `python3` for `#!/usr/bin/env python3`.
+pub fn parse_shebang(buf: &[u8]) -> Option<String> {
+    if !buf.starts_with(b"#!") {
+        return None;
+    }
+
+    let line = &buf[2..];
+    let line = match line.iter().position(|&b| b == b'\n') {
+        Some(pos) => &line[..pos],
+        None => line
+    };
+    let line = String::from_utf8_lossy(line);
+
+    let mut words = line.split_whitespace();
+    let command = words.next()?;
+
+    if command != "env" && !command.ends_with("/env") {
+        return Some(command.to_string());
+    }
+
+    // options and variable assignments of `env` go before the command
+    words.map(|word| word.trim_start_matches("-S"))
+        .find(|word| !word.is_empty() && !word.starts_with('-') && !word.contains('='))
+        .map(|word| word.to_string())
+}
+
+/// Levenshtein distance: the number of characters to insert, delete or replace to turn one string into another.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev_row: Vec<usize> = (0..b.len() + 1).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+
+        for (j, cb) in b.iter().enumerate() {
+            let cost = match ca == *cb {
+                true => 0,
+                false => 1
+            };
+            let distance = (prev_row[j] + cost).min(prev_row[j + 1] + 1).min(row[j] + 1);
+            row.push(distance);
+        }
+
+        prev_row = row;
+    }
+
+    prev_row[b.len()]
+}
+
 pub fn parse_unix_filename(s: &str) -> &str {
     let last_slash = s.rfind('/');
     match last_slash {
@@ -311,11 +616,15 @@ mod tests {
     use super::*;
     use field::Field;
 
-    fn basic_criteria<T: Ord + Clone + Display>(vals: &[T]) -> Criteria<T> {
+    fn values(vals: &[i32]) -> Vec<Value> {
+        vals.iter().map(|val| Value::from(val.to_string())).collect()
+    }
+
+    fn basic_criteria(vals: &[i32]) -> Criteria {
         let fields = Rc::new(vec![ColumnExpr::field(Field::Size); vals.len()]);
         let orderings = Rc::new(vec![true; vals.len()]);
 
-        Criteria::new(fields, vals.to_vec(), orderings)
+        Criteria::new(fields, values(vals), orderings)
     }
 
     #[test]
@@ -355,8 +664,8 @@ mod tests {
         let fields = Rc::new(vec![ColumnExpr::field(Field::Size); 3]);
         let orderings = Rc::new(vec![false, false, false]);
 
-        let c1 = Criteria::new(fields.clone(), vec![1, 2, 3], orderings.clone());
-        let c2 = Criteria::new(fields.clone(), vec![1, 3, 1], orderings.clone());
+        let c1 = Criteria::new(fields.clone(), values(&[1, 2, 3]), orderings.clone());
+        let c2 = Criteria::new(fields.clone(), values(&[1, 3, 1]), orderings.clone());
 
         assert_eq!(c1.cmp(&c2), Ordering::Greater);
     }
@@ -366,9 +675,206 @@ mod tests {
         let fields = Rc::new(vec![ColumnExpr::field(Field::Size); 3]);
         let orderings = Rc::new(vec![true, false, true]);
 
-        let c1 = Criteria::new(fields.clone(), vec![1, 2, 3], orderings.clone());
-        let c2 = Criteria::new(fields.clone(), vec![1, 3, 1], orderings.clone());
+        let c1 = Criteria::new(fields.clone(), values(&[1, 2, 3]), orderings.clone());
+        let c2 = Criteria::new(fields.clone(), values(&[1, 3, 1]), orderings.clone());
+
+        assert_eq!(c1.cmp(&c2), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_compare_numbers_of_different_length() {
+        let fields = Rc::new(vec![ColumnExpr::left(ColumnExpr::field(Field::Size))]);
+        let orderings = Rc::new(vec![true]);
+
+        let c1 = Criteria::new(fields.clone(), vec![Value::from("9")], orderings.clone());
+        let c2 = Criteria::new(fields.clone(), vec![Value::from("100")], orderings.clone());
+
+        assert_eq!(c1.cmp(&c2), Ordering::Less);
+    }
+
+    #[test]
+    fn test_compare_formatted_sizes() {
+        let fields = Rc::new(vec![ColumnExpr::field(Field::FormattedSize)]);
+        let orderings = Rc::new(vec![false]);
+
+        let c1 = Criteria::new(fields.clone(), vec![Value::from("2.50 MB")], orderings.clone());
+        let c2 = Criteria::new(fields.clone(), vec![Value::from("900 KB")], orderings.clone());
 
+        assert_eq!(c1.cmp(&c2), Ordering::Less);
+    }
+
+    #[test]
+    fn test_compare_datetimes_by_time() {
+        let fields = Rc::new(vec![ColumnExpr::field(Field::Modified)]);
+        let orderings = Rc::new(vec![true]);
+
+        // both are output as the same second
+        let c1 = Criteria::new(fields.clone(), vec![Value::DateTime(1525170600, 500)], orderings.clone());
+        let c2 = Criteria::new(fields.clone(), vec![Value::DateTime(1525170600, 0)], orderings.clone());
+        assert_eq!(c1.values()[0].to_string(), c2.values()[0].to_string());
         assert_eq!(c1.cmp(&c2), Ordering::Greater);
     }
+
+    #[test]
+    fn test_compare_strings() {
+        let fields = Rc::new(vec![ColumnExpr::field(Field::Name)]);
+        let orderings = Rc::new(vec![true]);
+
+        let c1 = Criteria::new(fields.clone(), vec![Value::from("9.txt")], orderings.clone());
+        let c2 = Criteria::new(fields.clone(), vec![Value::from("100.txt")], orderings.clone());
+
+        assert_eq!(c1.cmp(&c2), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_parse_filesize() {
+        assert_eq!(parse_filesize("512"), Some(512));
+        assert_eq!(parse_filesize("5k"), Some(5 * 1024));
+        assert_eq!(parse_filesize("5KiB"), Some(5 * 1024));
+        assert_eq!(parse_filesize("2gb"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_filesize("1.50 KB"), Some(1536));
+        assert_eq!(parse_filesize("100 B"), Some(100));
+        assert_eq!(parse_filesize("1.5.1"), None);
+        assert_eq!(parse_filesize("kb"), None);
+        assert_eq!(parse_filesize("name"), None);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("3600"), Some(3600));
+        assert_eq!(parse_duration("01:00:00"), Some(3600));
+        assert_eq!(parse_duration("1:02:03"), Some(3723));
+        assert_eq!(parse_duration("100:00:01"), Some(360001));
+        assert_eq!(parse_duration("00:60:00"), None);
+        assert_eq!(parse_duration("1:00"), None);
+        assert_eq!(parse_duration("long"), None);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "00:00:00");
+        assert_eq!(format_duration(3723), "01:02:03");
+        assert_eq!(format_duration(360001), "100:00:01");
+    }
+
+    #[test]
+    fn test_parse_datetime() {
+        let (start, finish) = parse_datetime("2018-05-01").unwrap();
+        assert_eq!(start, Local.ymd(2018, 5, 1).and_hms(0, 0, 0));
+        assert_eq!(finish, Local.ymd(2018, 5, 1).and_hms(23, 59, 59));
+
+        let (start, finish) = parse_datetime("2018-05-01 10:30").unwrap();
+        assert_eq!(start, Local.ymd(2018, 5, 1).and_hms(10, 30, 0));
+        assert_eq!(finish, Local.ymd(2018, 5, 1).and_hms(10, 30, 59));
+    }
+
+    #[test]
+    fn test_parse_relative_datetime() {
+        // right after midnight, so that day boundaries are crossed
+        let now = Local.ymd(2019, 3, 10).and_hms(0, 0, 30);
+
+        let today = (Local.ymd(2019, 3, 10).and_hms(0, 0, 0), Local.ymd(2019, 3, 10).and_hms(23, 59, 59));
+        assert_eq!(parse_datetime_at("today", now), Ok(today));
+        assert_eq!(parse_datetime_at("Today", now), Ok(today));
+
+        let yesterday = (Local.ymd(2019, 3, 9).and_hms(0, 0, 0), Local.ymd(2019, 3, 9).and_hms(23, 59, 59));
+        assert_eq!(parse_datetime_at("yesterday", now), Ok(yesterday));
+
+        let moment = |s: &str| parse_datetime_at(s, now).map(|(start, _)| start);
+        assert_eq!(moment("-3 days"), Ok(Local.ymd(2019, 3, 7).and_hms(0, 0, 30)));
+        assert_eq!(moment("3 days ago"), Ok(Local.ymd(2019, 3, 7).and_hms(0, 0, 30)));
+        assert_eq!(moment("2 hours ago"), Ok(Local.ymd(2019, 3, 9).and_hms(22, 0, 30)));
+        assert_eq!(moment("-1h"), Ok(Local.ymd(2019, 3, 9).and_hms(23, 0, 30)));
+        assert_eq!(moment("-45 seconds"), Ok(Local.ymd(2019, 3, 9).and_hms(23, 59, 45)));
+        assert_eq!(moment("-10min"), Ok(Local.ymd(2019, 3, 9).and_hms(23, 50, 30)));
+        assert_eq!(moment("1 week ago"), Ok(Local.ymd(2019, 3, 3).and_hms(0, 0, 30)));
+        assert_eq!(moment("-1 month"), Ok(Local.ymd(2019, 2, 10).and_hms(0, 0, 30)));
+        assert_eq!(moment("2 years ago"), Ok(Local.ymd(2017, 3, 10).and_hms(0, 0, 30)));
+
+        assert!(parse_relative_datetime("3 days", now).is_none());
+        assert!(parse_relative_datetime("-3 fortnights", now).is_none());
+    }
+
+    #[test]
+    fn test_to_absolute_path() {
+        assert_eq!(to_absolute_path(Path::new("/home/user/repo/src/../target/./debug")), PathBuf::from("/home/user/repo/target/debug"));
+        assert_eq!(to_absolute_path(Path::new("src")), env::current_dir().unwrap().join("src"));
+        assert_eq!(to_absolute_path(Path::new(".")), env::current_dir().unwrap());
+    }
+
+    #[test]
+    fn test_get_extension() {
+        assert_eq!(get_extension("main.rs"), "rs");
+        assert_eq!(get_extension("Photo.JPG"), "jpg");
+        assert_eq!(get_extension("archive.tar.gz"), "gz");
+        assert_eq!(get_extension("dir/file.txt"), "txt");
+        assert_eq!(get_extension("Makefile"), "");
+        assert_eq!(get_extension(".gitignore"), "");
+    }
+
+    #[test]
+    fn test_is_binary_content() {
+        assert!(!is_binary_content(b""));
+        assert!(!is_binary_content(b"fn main() {}\n"));
+        assert!(!is_binary_content("Grüße, ünïcödé".as_bytes()));
+        assert!(!is_binary_content(&"é".as_bytes()[..1]));
+        assert!(!is_binary_content(b"mostly text with a single \xff byte inside"));
+        assert!(is_binary_content(b"text\0with a NUL"));
+        assert!(is_binary_content(b"\x89PNG\xff\xfe\xfa\xfb"));
+    }
+
+    #[test]
+    fn test_detect_line_endings() {
+        assert_eq!(detect_line_endings(b"one\ntwo\n"), Some("lf"));
+        assert_eq!(detect_line_endings(b"one\r\ntwo\r\n"), Some("crlf"));
+        assert_eq!(detect_line_endings(b"one\r\ntwo\nthree"), Some("mixed"));
+        assert_eq!(detect_line_endings(b"\none"), Some("lf"));
+        assert_eq!(detect_line_endings(b"no line breaks"), None);
+        assert_eq!(detect_line_endings(b""), None);
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("modified", "modified"), 0);
+        assert_eq!(edit_distance("modfied", "modified"), 1);
+        assert_eq!(edit_distance("sise", "size"), 1);
+        assert_eq!(edit_distance("isdir", "is_dir"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "name"), 4);
+        assert_eq!(edit_distance("name", ""), 4);
+    }
+
+    #[test]
+    fn test_has_bom() {
+        assert!(has_bom(b"\xEF\xBB\xBFtext"));
+        assert!(has_bom(b"\xFF\xFEt\0"));
+        assert!(has_bom(b"\xFE\xFF\0t"));
+        assert!(!has_bom(b"\xEF\xBBtext"));
+        assert!(!has_bom(b"text"));
+        assert!(!has_bom(b""));
+    }
+
+    #[test]
+    fn test_parse_shebang() {
+        assert_eq!(parse_shebang(b"#!/bin/sh\necho"), Some(String::from("/bin/sh")));
+        assert_eq!(parse_shebang(b"#! /usr/bin/perl -w\n"), Some(String::from("/usr/bin/perl")));
+        assert_eq!(parse_shebang(b"#!/usr/bin/env python3\r\nprint()"), Some(String::from("python3")));
+        assert_eq!(parse_shebang(b"#!/usr/bin/env -S LANG=C python2 -u"), Some(String::from("python2")));
+        assert_eq!(parse_shebang(b"#!/usr/bin/env -Snode"), Some(String::from("node")));
+        assert_eq!(parse_shebang(b"#!/usr/bin/env"), None);
+        assert_eq!(parse_shebang(b"#!\n"), None);
+        assert_eq!(parse_shebang(b"# comment"), None);
+        assert_eq!(parse_shebang(b""), None);
+    }
+
+    #[test]
+    fn test_days_between() {
+        let now = Local.ymd(2020, 3, 10).and_hms(12, 0, 0);
+
+        assert_eq!(days_between(&Local.ymd(2020, 3, 10).and_hms(1, 0, 0), &now), 0);
+        assert_eq!(days_between(&Local.ymd(2020, 3, 9).and_hms(12, 0, 0), &now), 1);
+        assert_eq!(days_between(&Local.ymd(2020, 3, 9).and_hms(13, 0, 0), &now), 0);
+        assert_eq!(days_between(&Local.ymd(2019, 3, 10).and_hms(12, 0, 0), &now), 366);
+        assert_eq!(days_between(&Local.ymd(2021, 1, 1).and_hms(0, 0, 0), &now), 0);
+    }
 }
@@ -0,0 +1,369 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::env;
+use std::fs;
+use std::fs::DirBuilder;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use std::iter;
+#[cfg(unix)]
+use std::os::unix::fs::DirBuilderExt;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering as AtomicOrdering;
+
+use serde_json;
+
+use record::Record;
+use util::Criteria;
+use util::TopN;
+use util::Value;
+
+/// Rows kept in memory before they are written to a temporary file.
+const DEFAULT_MAX_ROWS: usize = 1_000_000;
+/// Estimated size of the rows kept in memory before they are written to a temporary file.
+const DEFAULT_MAX_BYTES: u64 = 512 * 1024 * 1024;
+/// Strings, vectors and tree nodes holding a row take some memory besides the text of its values.
+const ROW_OVERHEAD: u64 = 64;
+/// Names of the temporary directories taken by someone else are skipped, up to this many.
+const MAX_SPILL_DIR_ATTEMPTS: usize = 100;
+
+/// Sorted rows with their criteria.
+type Source<'a> = Box<Iterator<Item=io::Result<(Criteria, Record)>> + 'a>;
+
+/// Temporary directories of all the buffers of the process get different names.
+static SPILL_DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorted rows of an ordered query. With a limit only that many rows are kept anyway.
+/// Without it, whenever there are too many rows they are written to a temporary file as a sorted run,
+/// and the runs are merged while the rows are read. Rows with equal criteria keep the order they came in,
+/// so the output is the same as if all of them were sorted in memory.
+pub struct SortBuffer {
+    rows: TopN<Criteria, Record>,
+    limitless: bool,
+    bytes: u64,
+    max_rows: usize,
+    max_bytes: u64,
+    /// Declared before the directory, so the runs are dropped before it's removed
+    runs: Vec<SortRun>,
+    spill_dir: Option<SpillDir>,
+    /// Criteria of every ordering met, the rows written to the runs refer to them by index
+    orderings: Vec<Criteria>,
+    spilled: usize,
+}
+
+impl SortBuffer {
+    /// No limit means the buffer may spill.
+    pub fn new(limit: u32) -> SortBuffer {
+        SortBuffer {
+            rows: if limit == 0 { TopN::limitless() } else { TopN::new(limit) },
+            limitless: limit == 0,
+            bytes: 0,
+            max_rows: DEFAULT_MAX_ROWS,
+            max_bytes: DEFAULT_MAX_BYTES,
+            runs: vec![],
+            spill_dir: None,
+            orderings: vec![],
+            spilled: 0,
+        }
+    }
+
+    pub fn set_max_rows(&mut self, max_rows: usize) {
+        self.max_rows = max_rows;
+    }
+
+    pub fn set_max_bytes(&mut self, max_bytes: u64) {
+        self.max_bytes = max_bytes;
+    }
+
+    /// The row is buffered even if it fails to spill the rows, they stay in memory from then on.
+    pub fn insert(&mut self, key: Criteria, row: Record) -> io::Result<()> {
+        if self.limitless {
+            self.bytes += row.values.iter().map(|value| value.len() as u64).sum::<u64>() + key.values().iter().map(|value| value.to_string().len() as u64).sum::<u64>() + ROW_OVERHEAD;
+        }
+
+        self.rows.insert(key, row);
+
+        if self.limitless && (self.rows.len() >= self.max_rows || self.bytes >= self.max_bytes) {
+            if let Err(err) = self.spill() {
+                self.max_rows = usize::max_value();
+                self.max_bytes = u64::max_value();
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn admits(&self, key: &Criteria) -> bool {
+        self.rows.admits(key)
+    }
+
+    /// Only a limited buffer gets full, the rows equal to its last one are discarded then.
+    pub fn is_full(&self) -> bool {
+        self.rows.is_full()
+    }
+
+    /// All the rows in order, the runs are read as the rows are taken.
+    pub fn rows<'a>(&'a self) -> Box<Iterator<Item=io::Result<Record>> + 'a> {
+        if self.runs.is_empty() {
+            return Box::new(self.rows.iter().map(|(_, row)| Ok(row.clone())));
+        }
+
+        let mut sources: Vec<Source<'a>> = vec![];
+        for run in &self.runs {
+            match run.read(&self.orderings) {
+                Ok(source) => sources.push(source),
+                Err(err) => return Box::new(iter::once(Err(err)))
+            }
+        }
+        // the rows in memory came after all the spilled ones
+        sources.push(Box::new(self.rows.iter().map(|(key, row)| Ok((key.clone(), row.clone())))));
+
+        Box::new(MergedRows::new(sources))
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        if self.spill_dir.is_none() {
+            self.spill_dir = Some(SpillDir::create()?);
+        }
+
+        let path = match self.spill_dir {
+            Some(ref spill_dir) => spill_dir.path.join(format!("run_{}.tmp", self.runs.len())),
+            None => unreachable!()
+        };
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        // the file is removed when the run is dropped, whether it's written or not
+        let run = SortRun { path };
+
+        let mut writer = BufWriter::new(file);
+        let orderings = &mut self.orderings;
+        for (key, row) in self.rows.iter() {
+            let ordering_idx = match orderings.iter().position(|ordering| ordering.has_same_ordering(key)) {
+                Some(idx) => idx,
+                None => {
+                    orderings.push(key.clone());
+                    orderings.len() - 1
+                }
+            };
+
+            let line = serde_json::to_string(&(ordering_idx, key.values(), row))
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            writeln!(writer, "{}", line)?;
+        }
+        writer.flush()?;
+
+        self.runs.push(run);
+        self.spilled += self.rows.len();
+        self.rows.clear();
+        self.bytes = 0;
+
+        Ok(())
+    }
+}
+
+/// Directory of the runs of a buffer, only its owner may look into it.
+struct SpillDir {
+    path: PathBuf,
+}
+
+impl SpillDir {
+    fn create() -> io::Result<SpillDir> {
+        let mut builder = DirBuilder::new();
+        #[cfg(unix)]
+        builder.mode(0o700);
+
+        for _ in 0..MAX_SPILL_DIR_ATTEMPTS {
+            let path = env::temp_dir().join(format!("fselect_sort_{}_{}", process::id(), SPILL_DIR_COUNTER.fetch_add(1, AtomicOrdering::SeqCst)));
+            match builder.create(&path) {
+                Ok(()) => return Ok(SpillDir { path }),
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err)
+            }
+        }
+
+        Err(io::Error::new(io::ErrorKind::AlreadyExists, "no free name for the temporary directory"))
+    }
+}
+
+impl Drop for SpillDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Temporary file with sorted rows, a JSON array with the ordering index, the criteria values and the row per line.
+struct SortRun {
+    path: PathBuf,
+}
+
+impl SortRun {
+    fn read<'a>(&self, orderings: &'a Vec<Criteria>) -> io::Result<Source<'a>> {
+        let reader = BufReader::new(File::open(&self.path)?);
+
+        Ok(Box::new(reader.lines().map(move |line| {
+            let (ordering_idx, values, row): (usize, Vec<Value>, Record) = serde_json::from_str(&line?)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+            match orderings.get(ordering_idx) {
+                Some(ordering) => Ok((ordering.with_values(values), row)),
+                None => Err(io::Error::new(io::ErrorKind::InvalidData, "unknown ordering in the sorted run"))
+            }
+        })))
+    }
+}
+
+impl Drop for SortRun {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Next rows of the sorted sources, the first one of them wins a tie.
+struct MergedRows<'a> {
+    sources: Vec<Source<'a>>,
+    heads: BinaryHeap<Head>,
+    error: Option<io::Error>,
+}
+
+impl<'a> MergedRows<'a> {
+    fn new(sources: Vec<Source<'a>>) -> MergedRows<'a> {
+        let mut merged_rows = MergedRows { sources, heads: BinaryHeap::new(), error: None };
+        for source in 0..merged_rows.sources.len() {
+            if merged_rows.error.is_some() {
+                break;
+            }
+            merged_rows.pull(source);
+        }
+
+        merged_rows
+    }
+
+    fn pull(&mut self, source: usize) {
+        match self.sources[source].next() {
+            Some(Ok((key, row))) => self.heads.push(Head { key, row, source }),
+            Some(Err(err)) => {
+                // the order of the rest of the rows is unknown
+                self.heads.clear();
+                self.error = Some(err);
+            },
+            None => {}
+        }
+    }
+}
+
+impl<'a> Iterator for MergedRows<'a> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<io::Result<Record>> {
+        if let Some(err) = self.error.take() {
+            return Some(Err(err));
+        }
+
+        let head = self.heads.pop()?;
+        self.pull(head.source);
+
+        Some(Ok(head.row))
+    }
+}
+
+struct Head {
+    key: Criteria,
+    row: Record,
+    source: usize,
+}
+
+/// Reversed, so the heap gives the smallest key first.
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key.cmp(&self.key).then_with(|| other.source.cmp(&self.source))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    use field::Field;
+    use parser::ColumnExpr;
+
+    fn fill(buffer: &mut SortBuffer) {
+        let fields = Rc::new(vec![ColumnExpr::field(Field::Name), ColumnExpr::field(Field::Path)]);
+        let orderings = Rc::new(vec![false, true]);
+
+        // plenty of ties, which have to stay in the order they came in
+        for idx in 0..50_000 {
+            let values = vec![Value::from((idx * 7919 % 97).to_string()), Value::from(format!("file{}", idx % 13))];
+            buffer.insert(Criteria::new(fields.clone(), values, orderings.clone()), Record::new(vec![], vec![format!("row {}", idx)])).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_spilled_rows_keep_the_order() {
+        let mut in_memory = SortBuffer::new(0);
+        in_memory.set_max_rows(usize::max_value());
+        fill(&mut in_memory);
+        assert!(in_memory.runs.is_empty());
+
+        let mut spilled = SortBuffer::new(0);
+        spilled.set_max_rows(1000);
+        fill(&mut spilled);
+        assert_eq!(spilled.runs.len(), 50);
+        assert_eq!(spilled.spilled, 50_000);
+
+        let expected: Vec<Record> = in_memory.rows().map(|row| row.unwrap()).collect();
+        let rows: Vec<Record> = spilled.rows().map(|row| row.unwrap()).collect();
+        assert_eq!(expected.len(), 50_000);
+        assert!(rows == expected);
+
+        let spill_dir = spilled.spill_dir.as_ref().unwrap().path.clone();
+        assert!(spilled.runs.iter().all(|run| run.path.starts_with(&spill_dir) && run.path.exists()));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&spill_dir).unwrap().permissions().mode() & 0o777, 0o700);
+        }
+        drop(spilled);
+        assert!(!spill_dir.exists());
+    }
+
+    #[test]
+    fn test_spill_by_size() {
+        let mut buffer = SortBuffer::new(0);
+        buffer.set_max_bytes(1024 * 1024);
+        fill(&mut buffer);
+        assert!(!buffer.runs.is_empty());
+        assert_eq!(buffer.rows().count(), 50_000);
+    }
+
+    #[test]
+    fn test_limited_buffer_doesnt_spill() {
+        let mut buffer = SortBuffer::new(10);
+        buffer.set_max_rows(5);
+        buffer.set_max_bytes(1);
+        fill(&mut buffer);
+        assert!(buffer.runs.is_empty());
+        assert_eq!(buffer.rows().map(|row| row.unwrap()).collect::<Vec<Record>>().len(), 10);
+    }
+}
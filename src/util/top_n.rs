@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::collections::BTreeMap;
 
 pub struct TopN<K: Ord, V> {
@@ -46,6 +47,42 @@ impl<K: Ord, V> TopN<K, V> {
         None
     }
 
+    /// Tells whether the key would stay in the buffer if it was inserted now.
+    /// When the buffer is full, keys equal to the last one are discarded like the bigger ones.
+    pub fn admits(&self, k: &K) -> bool {
+        match self.limit {
+            Some(limit) if self.count >= limit => {
+                match self.echelons.keys().next_back() {
+                    Some(last_key) => k.cmp(last_key) == Ordering::Less,
+                    None => true
+                }
+            },
+            _ => true
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    /// A full buffer takes only the keys less than its last one.
+    pub fn is_full(&self) -> bool {
+        match self.limit {
+            Some(limit) => self.count >= limit,
+            None => false
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.count = 0;
+        self.echelons.clear();
+    }
+
+    /// Values in order along with the keys of their echelons, i.e. the first keys inserted among the equal ones.
+    pub fn iter<'a>(&'a self) -> Box<Iterator<Item=(&'a K, &'a V)> + 'a> {
+        Box::new(self.echelons.iter().flat_map(|(k, v)| v.iter().map(move |v| (k, v))))
+    }
+
     // see: https://github.com/rust-lang/rfcs/blob/master/text/1522-conservative-impl-trait.md
 //    pub fn values(&self) -> impl Iterator<Item=&V> {
 //        self.echelons.values().flat_map(|v| v)
@@ -118,6 +155,35 @@ mod tests {
         assert_eq!(top_n.values(), vec![1, 3, 3, 2, -1]);
     }
 
+    #[test]
+    fn test_admits() {
+        let mut top_n = TopN::new(2);
+        assert!(top_n.admits(&"z"));
+        top_n.insert("a", 1);
+        top_n.insert("c", 2);
+        assert!(top_n.admits(&"b"));
+        assert!(!top_n.admits(&"c"));
+        assert!(!top_n.admits(&"z"));
+
+        let limitless: TopN<&str, i32> = TopN::limitless();
+        assert!(limitless.admits(&"z"));
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut top_n = TopN::new(3);
+        top_n.insert("b", 2);
+        top_n.insert("a", 1);
+        top_n.insert("b", 3);
+        top_n.insert("c", 4);
+        assert_eq!(top_n.len(), 3);
+        assert_eq!(top_n.iter().collect::<Vec<(&&str, &i32)>>(), vec![(&"a", &1), (&"b", &2), (&"b", &3)]);
+
+        top_n.clear();
+        assert_eq!(top_n.len(), 0);
+        assert!(top_n.iter().next().is_none());
+    }
+
     #[test]
     fn test_limitless() {
         let mut top_n = TopN::limitless();
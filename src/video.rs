@@ -0,0 +1,456 @@
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+
+/// Properties of the first video track of MP4 (QuickTime) or Matroska (WebM) file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoMetadata {
+    pub width: u32,
+    pub height: u32,
+    /// Whole seconds, live streams have no duration
+    pub duration: Option<u64>,
+    /// Common name like `h264` or `hevc`, otherwise the identifier the container gives
+    pub codec: String,
+}
+
+/// Headers bigger than that are considered broken rather than read into memory.
+const MAX_HEADER_SIZE: u64 = 32 * 1024 * 1024;
+
+const MATROSKA_MAGIC: [u8; 4] = [0x1a, 0x45, 0xdf, 0xa3];
+
+const EBML_SEGMENT: u64 = 0x18538067;
+const EBML_INFO: u64 = 0x1549a966;
+const EBML_TIMECODE_SCALE: u64 = 0x2ad7b1;
+const EBML_DURATION: u64 = 0x4489;
+const EBML_TRACKS: u64 = 0x1654ae6b;
+const EBML_TRACK_ENTRY: u64 = 0xae;
+const EBML_TRACK_TYPE: u64 = 0x83;
+const EBML_CODEC_ID: u64 = 0x86;
+const EBML_VIDEO: u64 = 0xe0;
+const EBML_PIXEL_WIDTH: u64 = 0xb0;
+const EBML_PIXEL_HEIGHT: u64 = 0xba;
+
+const MATROSKA_VIDEO_TRACK: u64 = 1;
+const MATROSKA_DEFAULT_TIMECODE_SCALE: u64 = 1_000_000;
+
+/// Only the headers are read, the media data is skipped.
+/// Files of other formats and files without a video track give nothing.
+pub fn read_video_metadata(path: &Path) -> Option<VideoMetadata> {
+    let mut file = File::open(path).ok()?;
+    read_from(&mut file)
+}
+
+fn read_from<R: Read + Seek>(reader: &mut R) -> Option<VideoMetadata> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic).ok()?;
+    reader.seek(SeekFrom::Start(0)).ok()?;
+
+    if magic[..4] == MATROSKA_MAGIC {
+        read_matroska(reader)
+    } else if &magic[4..] == b"ftyp" {
+        read_mp4(reader)
+    } else {
+        None
+    }
+}
+
+/// The top level boxes are skipped until `moov`, which often goes after the media data.
+fn read_mp4<R: Read + Seek>(reader: &mut R) -> Option<VideoMetadata> {
+    let file_len = reader.seek(SeekFrom::End(0)).ok()?;
+    let mut pos = 0;
+
+    while pos + 8 <= file_len {
+        reader.seek(SeekFrom::Start(pos)).ok()?;
+
+        let mut header = [0; 8];
+        reader.read_exact(&mut header).ok()?;
+        let (header_len, box_len) = match read_be(&header[..4]) {
+            0 => (8, file_len - pos),
+            1 => {
+                let mut large_size = [0; 8];
+                reader.read_exact(&mut large_size).ok()?;
+                (16, read_be(&large_size))
+            },
+            box_len => (8, box_len)
+        };
+
+        if box_len < header_len || box_len > file_len - pos {
+            return None;
+        }
+
+        if &header[4..] == b"moov" {
+            if box_len - header_len > MAX_HEADER_SIZE {
+                return None;
+            }
+
+            let mut moov = vec![0; (box_len - header_len) as usize];
+            reader.read_exact(&mut moov).ok()?;
+            return parse_mp4_moov(&moov);
+        }
+
+        pos += box_len;
+    }
+
+    None
+}
+
+fn parse_mp4_moov(moov: &[u8]) -> Option<VideoMetadata> {
+    let duration = find_mp4_box(moov, &[b"mvhd"]).and_then(parse_mp4_duration);
+
+    for (box_type, trak) in mp4_boxes(moov) {
+        if box_type != b"trak" || find_mp4_box(trak, &[b"mdia", b"hdlr"]).and_then(|hdlr| hdlr.get(8..12)) != Some(b"vide") {
+            continue;
+        }
+
+        let (codec, coded_size) = match find_mp4_box(trak, &[b"mdia", b"minf", b"stbl", b"stsd"]).and_then(|stsd| stsd.get(8..)) {
+            Some(entries) => match mp4_boxes(entries).first() {
+                Some(&(fourcc, entry)) => (mp4_codec_name(fourcc), parse_mp4_coded_size(entry)),
+                None => continue
+            },
+            None => continue
+        };
+
+        // the display size goes first, the size of the coded picture is the fallback
+        let (width, height) = match find_mp4_box(trak, &[b"tkhd"]).and_then(parse_mp4_display_size) {
+            Some(size) => size,
+            None => coded_size.unwrap_or((0, 0))
+        };
+
+        return Some(VideoMetadata { width, height, duration, codec });
+    }
+
+    None
+}
+
+/// Types and contents of the boxes following each other.
+fn mp4_boxes(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut result = vec![];
+    let mut pos = 0;
+
+    while pos + 8 <= data.len() {
+        let (header_len, box_len) = match read_be(&data[pos..pos + 4]) {
+            0 => (8, data.len() - pos),
+            1 if pos + 16 <= data.len() => (16, read_be(&data[pos + 8..pos + 16]) as usize),
+            1 => break,
+            box_len => (8, box_len as usize)
+        };
+
+        if box_len < header_len || box_len > data.len() - pos {
+            break;
+        }
+
+        result.push((&data[pos + 4..pos + 8], &data[pos + header_len..pos + box_len]));
+        pos += box_len;
+    }
+
+    result
+}
+
+/// Content of the box found by the types of the nested boxes leading to it.
+fn find_mp4_box<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    let mut result = data;
+    for box_type in path {
+        result = mp4_boxes(result).into_iter()
+            .find(|&(found_type, _)| found_type == &box_type[..])
+            .map(|(_, content)| content)?;
+    }
+
+    Some(result)
+}
+
+/// `mvhd` holds the time scale and the duration, 32-bit in version 0 and 64-bit in version 1.
+fn parse_mp4_duration(mvhd: &[u8]) -> Option<u64> {
+    let (timescale, duration) = match mvhd.first() {
+        Some(&0) => (read_be(mvhd.get(12..16)?), read_be(mvhd.get(16..20)?)),
+        Some(&1) => (read_be(mvhd.get(20..24)?), read_be(mvhd.get(24..32)?)),
+        _ => return None
+    };
+
+    match timescale {
+        0 => None,
+        timescale => Some(duration / timescale)
+    }
+}
+
+/// `tkhd` ends with the width and the height as 16.16 fixed point numbers, they are zero when not set.
+fn parse_mp4_display_size(tkhd: &[u8]) -> Option<(u32, u32)> {
+    let offset = match tkhd.first() {
+        Some(&0) => 76,
+        Some(&1) => 88,
+        _ => return None
+    };
+
+    let width = (read_be(tkhd.get(offset..offset + 4)?) >> 16) as u32;
+    let height = (read_be(tkhd.get(offset + 4..offset + 8)?) >> 16) as u32;
+
+    match width > 0 && height > 0 {
+        true => Some((width, height)),
+        false => None
+    }
+}
+
+/// Visual sample entry has the width and the height after 24 bytes of other fields.
+fn parse_mp4_coded_size(entry: &[u8]) -> Option<(u32, u32)> {
+    Some((read_be(entry.get(24..26)?) as u32, read_be(entry.get(26..28)?) as u32))
+}
+
+fn mp4_codec_name(fourcc: &[u8]) -> String {
+    let name = match fourcc {
+        b"avc1" | b"avc3" => "h264",
+        b"hvc1" | b"hev1" => "hevc",
+        b"vp08" => "vp8",
+        b"vp09" => "vp9",
+        b"av01" => "av1",
+        b"mp4v" => "mpeg4",
+        _ => return String::from_utf8_lossy(fourcc).trim().to_string()
+    };
+
+    name.to_string()
+}
+
+/// The segment elements are skipped until both `Info` and `Tracks` are met.
+fn read_matroska<R: Read + Seek>(reader: &mut R) -> Option<VideoMetadata> {
+    let file_len = reader.seek(SeekFrom::End(0)).ok()?;
+    reader.seek(SeekFrom::Start(0)).ok()?;
+
+    let (_, ebml_header_size) = read_ebml_header(reader)?;
+    reader.seek(SeekFrom::Current(ebml_header_size? as i64)).ok()?;
+
+    let (id, segment_size) = read_ebml_header(reader)?;
+    if id != EBML_SEGMENT {
+        return None;
+    }
+    let segment_start = reader.seek(SeekFrom::Current(0)).ok()?;
+    let segment_end = match segment_size {
+        Some(segment_size) => segment_start.saturating_add(segment_size),
+        None => file_len
+    };
+
+    let mut info = None;
+    let mut tracks = None;
+
+    while info.is_none() || tracks.is_none() {
+        if reader.seek(SeekFrom::Current(0)).ok()? >= segment_end {
+            break;
+        }
+
+        // clusters of a live stream have no size, so nothing after them could be reached
+        let (id, size) = match read_ebml_header(reader) {
+            Some((id, Some(size))) => (id, size),
+            _ => break
+        };
+
+        if (id == EBML_INFO || id == EBML_TRACKS) && size <= MAX_HEADER_SIZE {
+            let mut data = vec![0; size as usize];
+            reader.read_exact(&mut data).ok()?;
+            match id {
+                EBML_INFO => info = Some(data),
+                _ => tracks = Some(data)
+            }
+        } else {
+            reader.seek(SeekFrom::Current(size as i64)).ok()?;
+        }
+    }
+
+    let duration = info.and_then(|info| parse_matroska_duration(&info));
+
+    for (id, track) in ebml_elements(&tracks?) {
+        if id != EBML_TRACK_ENTRY {
+            continue;
+        }
+
+        let elements = ebml_elements(track);
+        if find_ebml_element(&elements, EBML_TRACK_TYPE).map(read_be) != Some(MATROSKA_VIDEO_TRACK) {
+            continue;
+        }
+
+        let codec = find_ebml_element(&elements, EBML_CODEC_ID)
+            .map(|codec_id| matroska_codec_name(&String::from_utf8_lossy(codec_id)))
+            .unwrap_or_default();
+        let video = find_ebml_element(&elements, EBML_VIDEO).map(ebml_elements).unwrap_or_default();
+        let width = find_ebml_element(&video, EBML_PIXEL_WIDTH).map(read_be).unwrap_or(0) as u32;
+        let height = find_ebml_element(&video, EBML_PIXEL_HEIGHT).map(read_be).unwrap_or(0) as u32;
+
+        return Some(VideoMetadata { width, height, duration, codec });
+    }
+
+    None
+}
+
+/// Duration is a float in the units of the timecode scale, which are nanoseconds.
+fn parse_matroska_duration(info: &[u8]) -> Option<u64> {
+    let elements = ebml_elements(info);
+    let timecode_scale = find_ebml_element(&elements, EBML_TIMECODE_SCALE).map(read_be).unwrap_or(MATROSKA_DEFAULT_TIMECODE_SCALE);
+    let duration = match find_ebml_element(&elements, EBML_DURATION)? {
+        bytes if bytes.len() == 4 => f32::from_bits(read_be(bytes) as u32) as f64,
+        bytes if bytes.len() == 8 => f64::from_bits(read_be(bytes)),
+        _ => return None
+    };
+
+    match duration >= 0.0 {
+        true => Some((duration * timecode_scale as f64 / 1_000_000_000.0) as u64),
+        false => None
+    }
+}
+
+fn matroska_codec_name(codec_id: &str) -> String {
+    let codec_id = codec_id.trim_end_matches('\0');
+    let name = match codec_id {
+        "V_MPEG4/ISO/AVC" => "h264",
+        "V_MPEGH/ISO/HEVC" => "hevc",
+        "V_VP8" => "vp8",
+        "V_VP9" => "vp9",
+        "V_AV1" => "av1",
+        "V_THEORA" => "theora",
+        "V_MPEG4/ISO/SP" | "V_MPEG4/ISO/ASP" | "V_MPEG4/ISO/AP" => "mpeg4",
+        _ => return codec_id.trim_start_matches("V_").to_lowercase()
+    };
+
+    name.to_string()
+}
+
+/// Id and size of the element at the current position, the size is unknown for some streamed elements.
+fn read_ebml_header<R: Read>(reader: &mut R) -> Option<(u64, Option<u64>)> {
+    let mut buf = [0; 16];
+
+    let id_len = read_ebml_vint_bytes(reader, &mut buf[..8])?;
+    let size_len = read_ebml_vint_bytes(reader, &mut buf[8..])?;
+
+    let (id, _) = read_ebml_vint(&buf[..id_len], true)?;
+    let (size, _) = read_ebml_vint(&buf[8..8 + size_len], false)?;
+
+    Some((id, to_ebml_size(size, size_len)))
+}
+
+fn read_ebml_vint_bytes<R: Read>(reader: &mut R, buf: &mut [u8]) -> Option<usize> {
+    reader.read_exact(&mut buf[..1]).ok()?;
+    if buf[0] == 0 {
+        return None;
+    }
+
+    let len = buf[0].leading_zeros() as usize + 1;
+    reader.read_exact(&mut buf[1..len]).ok()?;
+
+    Some(len)
+}
+
+/// EBML variable length integer: the number of leading zero bits of the first byte tells its length.
+/// Element ids are used with the length marker, sizes without it.
+fn read_ebml_vint(data: &[u8], keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    if first == 0 {
+        return None;
+    }
+
+    let len = first.leading_zeros() as usize + 1;
+    let first = match keep_marker {
+        true => first,
+        false => first & (0xffu16 >> len) as u8
+    };
+    let value = data.get(1..len)?.iter().fold(first as u64, |value, &byte| value << 8 | byte as u64);
+
+    Some((value, len))
+}
+
+/// All the bits set mean the size is unknown.
+fn to_ebml_size(size: u64, len: usize) -> Option<u64> {
+    match size == (1 << (7 * len)) - 1 {
+        true => None,
+        false => Some(size)
+    }
+}
+
+/// Ids and contents of the elements following each other, an element of unknown size takes the rest.
+fn ebml_elements(data: &[u8]) -> Vec<(u64, &[u8])> {
+    let mut result = vec![];
+    let mut pos = 0;
+
+    while let Some((id, id_len)) = read_ebml_vint(&data[pos..], true) {
+        let (size, size_len) = match read_ebml_vint(&data[pos + id_len..], false) {
+            Some(size) => size,
+            None => break
+        };
+
+        let start = pos + id_len + size_len;
+        let end = match to_ebml_size(size, size_len) {
+            Some(size) if size <= (data.len() - start) as u64 => start + size as usize,
+            Some(_) => break,
+            None => data.len()
+        };
+
+        result.push((id, &data[start..end]));
+        pos = end;
+    }
+
+    result
+}
+
+fn find_ebml_element<'a>(elements: &[(u64, &'a [u8])], id: u64) -> Option<&'a [u8]> {
+    elements.iter()
+        .find(|&&(element_id, _)| element_id == id)
+        .map(|&(_, content)| content)
+}
+
+fn read_be(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |result, &byte| result << 8 | byte as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const MP4: &'static [u8] = include_bytes!("../tests/fixtures/video.mp4");
+    const MATROSKA: &'static [u8] = include_bytes!("../tests/fixtures/video.mkv");
+
+    #[test]
+    fn test_read_mp4() {
+        assert_eq!(read_from(&mut Cursor::new(MP4)), Some(VideoMetadata {
+            width: 3840,
+            height: 2160,
+            duration: Some(12),
+            codec: String::from("h264"),
+        }));
+
+        // the header is cut
+        assert_eq!(read_from(&mut Cursor::new(&MP4[..MP4.len() - 100])), None);
+    }
+
+    #[test]
+    fn test_read_matroska() {
+        assert_eq!(read_from(&mut Cursor::new(MATROSKA)), Some(VideoMetadata {
+            width: 1280,
+            height: 720,
+            duration: Some(95),
+            codec: String::from("vp9"),
+        }));
+    }
+
+    #[test]
+    fn test_not_a_video() {
+        assert_eq!(read_from(&mut Cursor::new(&b"plain text, not a video"[..])), None);
+        assert_eq!(read_from(&mut Cursor::new(&b"ftyp"[..])), None);
+        assert_eq!(read_from(&mut Cursor::new(&[][..])), None);
+    }
+
+    #[test]
+    fn test_ebml_vint() {
+        assert_eq!(read_ebml_vint(&[0x1a, 0x45, 0xdf, 0xa3], true), Some((0x1a45dfa3, 4)));
+        assert_eq!(read_ebml_vint(&[0x81], false), Some((1, 1)));
+        assert_eq!(read_ebml_vint(&[0x40, 0x02], false), Some((2, 2)));
+        assert_eq!(read_ebml_vint(&[0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff], false), Some(((1 << 56) - 1, 8)));
+        assert_eq!(to_ebml_size((1 << 56) - 1, 8), None);
+        assert_eq!(read_ebml_vint(&[0x40], false), None);
+        assert_eq!(read_ebml_vint(&[0x00, 0x01], false), None);
+    }
+
+    #[test]
+    fn test_codec_names() {
+        assert_eq!(mp4_codec_name(b"hvc1"), "hevc");
+        assert_eq!(mp4_codec_name(b"apch"), "apch");
+        assert_eq!(matroska_codec_name("V_MPEG4/ISO/AVC"), "h264");
+        assert_eq!(matroska_codec_name("V_MS/VFW/FOURCC"), "ms/vfw/fourcc");
+    }
+}
@@ -0,0 +1,335 @@
+use std::io;
+use std::io::Write;
+
+use csv;
+use serde::ser::SerializeMap;
+use serde::Serialize;
+use serde::Serializer;
+use serde_json;
+use term::StdoutTerminal;
+
+use field::Field;
+use output::Output;
+use parser::ColumnExpr;
+use parser::OutputFormat;
+use parser::Query;
+use record::Record;
+use sqlite::SqliteOutput;
+use table::get_row_color;
+use table::TableOutput;
+use util::WritableBuffer;
+
+/// Prints the records found by `Searcher::search` in the format the query asks for,
+/// to stdout or into the file the query names, the way the `fselect` utility does.
+pub struct RecordWriter {
+    format: OutputFormat,
+    /// Columns of every query of the union, their formats and fields tell how the values are output
+    columns: Vec<Vec<ColumnExpr>>,
+    output: Output,
+    sqlite_output: Option<SqliteOutput>,
+    table_output: Option<TableOutput>,
+    /// Rows found one by one are flushed right away, so a reader of the pipe gets every row as soon as it's found.
+    /// Ordered and aggregated rows come all at once anyway.
+    flush_rows: bool,
+    written: u32,
+}
+
+impl RecordWriter {
+    /// Opens the file, the database or the table the query outputs into, and writes the header of the format.
+    pub fn new(query: &Query) -> io::Result<RecordWriter> {
+        let output = match query.output_file {
+            Some(ref path) => match Output::file(path) {
+                Ok(output) => output,
+                Err(err) => return Err(io::Error::new(err.kind(), format!("{}: {}", path, err)))
+            },
+            None => Output::stdout()
+        };
+
+        let header: Vec<String> = query.fields.iter().map(|column_expr| column_expr.get_column_name()).collect();
+
+        let sqlite_output = match query.output_format {
+            OutputFormat::Sqlite { ref path, ref table, append } => {
+                match SqliteOutput::open(path, table, &header, append) {
+                    Ok(sqlite_output) => Some(sqlite_output),
+                    Err(err) => return Err(io::Error::new(io::ErrorKind::Other, format!("{}: {}", path, err)))
+                }
+            },
+            _ => None
+        };
+
+        let table_output = match query.output_format {
+            OutputFormat::Table { truncate } => Some(TableOutput::new(&header, truncate && output.is_stdout())),
+            _ => None
+        };
+
+        let mut columns = vec![query.fields.clone()];
+        columns.extend(query.unions.iter().map(|query| query.fields.clone()));
+
+        let mut writer = RecordWriter {
+            format: query.output_format.clone(),
+            columns,
+            output,
+            sqlite_output,
+            table_output,
+            flush_rows: query.unions.is_empty()
+                && query.ordering_fields.is_empty()
+                && !query.fields.iter().any(|column_expr| column_expr.has_aggregate_function()),
+            written: 0,
+        };
+        writer.write_header(&header);
+
+        Ok(writer)
+    }
+
+    fn write_header(&mut self, header: &Vec<String>) {
+        match self.format {
+            OutputFormat::Json => {
+                self.output.print("[");
+            },
+            OutputFormat::Csv => {
+                self.output.print(&format_csv_record(header));
+            },
+            OutputFormat::Html => {
+                self.output.print("<html><body><table><tr>");
+                for column in header {
+                    self.output.print(&format!("<th>{}</th>", escape_html(column)));
+                }
+                self.output.print("</tr>\n");
+            },
+            OutputFormat::Markdown => {
+                for column in header {
+                    self.output.print(&format!("| {} ", escape_markdown(column)));
+                }
+                self.output.print("|\n");
+                for _ in header {
+                    self.output.print("| --- ");
+                }
+                self.output.print("|\n");
+            },
+            _ => {}
+        }
+    }
+
+    /// Tells whether the output goes on, it doesn't once a write has failed, e.g. the reader of the pipe has gone.
+    pub fn write(&mut self, record: &Record) -> bool {
+        if let Some(ref sqlite_output) = self.sqlite_output {
+            sqlite_output.insert(&record.values);
+        } else if let Some(ref table_output) = self.table_output {
+            let color = get_row_color(record.fields.get("isdir"), record.fields.get("issymlink"), record.fields.get("mode"));
+            table_output.insert(color, record.values.clone());
+        } else {
+            let row = self.format_row(record);
+            self.output.print(&row);
+            if self.flush_rows {
+                self.output.end_row();
+            }
+        }
+
+        self.written += 1;
+
+        !self.output.has_failed()
+    }
+
+    fn format_row(&self, record: &Record) -> String {
+        let columns = self.columns.get(record.part).unwrap_or(&self.columns[0]);
+        let mut output_value = String::new();
+
+        match self.format {
+            OutputFormat::Lines { escape } => {
+                for (column_expr, value) in columns.iter().zip(record.values.iter()) {
+                    output_value.push_str(&format_column(column_expr, value, escape));
+                    output_value.push('\n');
+                }
+            },
+            OutputFormat::Tabs { escape } => {
+                for (column_expr, value) in columns.iter().zip(record.values.iter()) {
+                    output_value.push_str(&format_column(column_expr, value, escape));
+                    output_value.push('\t');
+                }
+                output_value.push('\n');
+            },
+            OutputFormat::List => {
+                for value in &record.values {
+                    output_value.push_str(value);
+                    output_value.push('\0');
+                }
+            },
+            OutputFormat::Csv => {
+                output_value.push_str(&format_csv_record(&record.values));
+            },
+            OutputFormat::Json => {
+                if self.written > 0 {
+                    output_value.push(',');
+                }
+                output_value.push_str(&format_json_row(columns, record));
+            },
+            OutputFormat::JsonLines => {
+                output_value.push_str(&format_json_row(columns, record));
+                output_value.push('\n');
+            },
+            OutputFormat::Cmd { ref template } => {
+                // placeholders get the values of the fields as they are output, `{}` gets the first column
+                let first_column = match record.has_value.first() {
+                    Some(&true) => record.values[0].as_str(),
+                    _ => ""
+                };
+                let get_value = |field: &Field| record.fields.get(&field.to_string().to_lowercase()).cloned().unwrap_or_default();
+                output_value.push_str(&template.render(first_column, &get_value));
+                output_value.push('\n');
+            },
+            OutputFormat::Html => {
+                output_value.push_str("<tr>");
+                for value in &record.values {
+                    output_value.push_str("<td>");
+                    output_value.push_str(&escape_html(value));
+                    output_value.push_str("</td>");
+                }
+                output_value.push_str("</tr>\n");
+            },
+            OutputFormat::Markdown => {
+                for value in &record.values {
+                    output_value.push_str("| ");
+                    output_value.push_str(&escape_markdown(value));
+                    output_value.push(' ');
+                }
+                output_value.push_str("|\n");
+            },
+            OutputFormat::Sqlite { .. } | OutputFormat::Table { .. } => {}
+        }
+
+        output_value
+    }
+
+    /// Writes the footer of the format and finishes the output,
+    /// the file appears at its place only if everything was written successfully.
+    /// The table goes to the terminal, where its rows are colored by the file types.
+    pub fn finish(mut self, t: &mut Box<StdoutTerminal>) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Json => {
+                self.output.print("]");
+            },
+            OutputFormat::Html => {
+                self.output.print("</table></body></html>\n");
+            },
+            _ => {}
+        }
+
+        if let Some(sqlite_output) = self.sqlite_output.take() {
+            if let Err(err) = sqlite_output.finish() {
+                return Err(io::Error::new(io::ErrorKind::Other, err));
+            }
+        }
+
+        if let Some(table_output) = self.table_output.take() {
+            match self.output.is_stdout() {
+                true => {
+                    self.output.flush()?;
+                    table_output.finish(t)?
+                },
+                false => table_output.write_plain(&mut self.output)?
+            }
+        }
+
+        self.output.finish()
+    }
+}
+
+fn format_column(column_expr: &ColumnExpr, value: &str, escape: bool) -> String {
+    let value = match escape {
+        true => escape_control_chars(value),
+        false => value.to_string()
+    };
+
+    match column_expr.format {
+        Some(ref format) => format.apply(&value),
+        None => value
+    }
+}
+
+/// Keys follow the order of the columns, a column selected twice is there twice.
+/// Values of the aliased columns are keyed by their aliases, rows of a union are named after the columns of its first query.
+/// Columns without a value in the row are left out.
+fn format_json_row(columns: &Vec<ColumnExpr>, record: &Record) -> String {
+    let row: Vec<(String, serde_json::Value)> = columns.iter()
+        .zip(record.values.iter())
+        .enumerate()
+        .filter(|&(idx, _)| record.has_value.get(idx).cloned().unwrap_or(true))
+        .map(|(idx, (column_expr, value))| {
+            let name = match record.columns.get(idx) {
+                Some(column) => column.clone(),
+                None => column_expr.get_column_name()
+            };
+
+            (name, to_json_value(&column_expr.get_field(), value.clone()))
+        })
+        .collect();
+
+    serde_json::to_string(&JsonRow(&row)).unwrap()
+}
+
+struct JsonRow<'a>(&'a Vec<(String, serde_json::Value)>);
+
+impl<'a> Serialize for JsonRow<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for &(ref key, ref value) in self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+/// Numeric and boolean fields are output as JSON numbers and booleans, and as `null` when there's no value.
+/// Everything else, including formatted sizes and dates, stays a string.
+fn to_json_value(field: &Option<Field>, value: String) -> serde_json::Value {
+    match field {
+        Some(ref field) if field.is_boolean_field() => {
+            match value.as_str() {
+                "true" => serde_json::Value::Bool(true),
+                "false" => serde_json::Value::Bool(false),
+                "" => serde_json::Value::Null,
+                _ => serde_json::Value::String(value)
+            }
+        },
+        Some(ref field) if field.is_numeric_field() && !field.is_formatted_field() => {
+            if value.is_empty() {
+                return serde_json::Value::Null;
+            }
+
+            if let Ok(number) = value.parse::<i64>() {
+                return serde_json::Value::from(number);
+            }
+
+            match value.parse::<f64>() {
+                Ok(number) => serde_json::Value::from(number),
+                _ => serde_json::Value::String(value)
+            }
+        },
+        _ => serde_json::Value::String(value)
+    }
+}
+
+fn format_csv_record(records: &Vec<String>) -> String {
+    let mut csv_output = WritableBuffer::new();
+    {
+        let mut csv_writer = csv::Writer::from_writer(&mut csv_output);
+        let _ = csv_writer.write_record(records);
+    }
+
+    csv_output.into()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn escape_markdown(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', "<br>")
+}
+
+/// Keeps a value on its line and inside its column, the backslash is escaped too so the value could be restored.
+fn escape_control_chars(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n").replace('\t', "\\t")
+}
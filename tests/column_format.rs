@@ -0,0 +1,28 @@
+mod common;
+
+use std::fs;
+
+use common::run_cli;
+use common::Fixture;
+
+#[test]
+fn test_column_width() {
+    let fixture = Fixture::new("column_format");
+    fs::write(fixture.join("a.txt"), "hello").unwrap();
+    fs::write(fixture.join("очень_длинное_имя.txt"), "hello, world").unwrap();
+
+    let root = fixture.to_string_lossy();
+
+    let output = run_cli(&format!("select name:10, size:>4, size from '{}' order by name", root));
+    assert_eq!(output, "a.txt     \t   5\t5\t\nочень_дли…\t  12\t12\t\n");
+
+    let output = run_cli(&format!("select name:<8, size:>3 from '{}' order by name into lines", root));
+    assert_eq!(output, "a.txt   \n  5\nочень_д…\n 12\n");
+
+    // structured formats keep the values as they are
+    let output = run_cli(&format!("select name:3, size:>6 from '{}' order by name into csv", root));
+    assert_eq!(output, "name,size\na.txt,5\nочень_длинное_имя.txt,12\n");
+
+    let output = run_cli(&format!("select name:3 from '{}' where size = 5 into json", root));
+    assert_eq!(output, "[{\"name\":\"a.txt\"}]");
+}
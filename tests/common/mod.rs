@@ -0,0 +1,70 @@
+//! Helpers shared by the integration tests, every test file takes what it needs.
+#![allow(dead_code)]
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::ops::Deref;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::process::Command;
+use std::process::Output;
+
+/// Directory of a test, removed with everything in it when the test ends, even if it fails.
+pub struct Fixture {
+    path: PathBuf,
+}
+
+impl Fixture {
+    /// Empty directory named after the test, whatever was left there by a previous run is gone.
+    pub fn new(name: &str) -> Fixture {
+        let path = env::temp_dir().join(format!("fselect_{}_{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        Fixture { path }
+    }
+}
+
+impl Deref for Fixture {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for Fixture {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Runs the utility whatever the outcome.
+pub fn run<S: AsRef<OsStr>>(args: &[S]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fselect"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Output of a query that must succeed.
+pub fn run_cli_bytes(query: &str) -> Vec<u8> {
+    let output = run(&[query]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    output.stdout
+}
+
+/// Output of a query that must succeed, as text.
+pub fn run_cli(query: &str) -> String {
+    String::from_utf8(run_cli_bytes(query)).unwrap()
+}
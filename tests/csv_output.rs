@@ -0,0 +1,32 @@
+extern crate csv;
+
+mod common;
+
+use std::fs;
+
+use common::run_cli_bytes;
+use common::Fixture;
+
+#[test]
+fn test_csv_output_round_trip() {
+    let fixture = Fixture::new("csv");
+    fs::File::create(fixture.join("weird,\"name\".txt")).unwrap();
+    fs::File::create(fixture.join("plain.txt")).unwrap();
+
+    let query = format!("name, size from '{}' order by name into csv", fixture.to_string_lossy());
+    let output = run_cli_bytes(&query);
+
+    let mut reader = csv::Reader::from_reader(&output[..]);
+
+    let headers = reader.headers().unwrap().clone();
+    assert_eq!(headers.iter().collect::<Vec<&str>>(), vec!["name", "size"]);
+
+    let rows: Vec<Vec<String>> = reader.records()
+        .map(|record| record.unwrap().iter().map(|value| value.to_string()).collect())
+        .collect();
+
+    assert_eq!(rows, vec![
+        vec![String::from("plain.txt"), String::from("0")],
+        vec![String::from("weird,\"name\".txt"), String::from("0")],
+    ]);
+}
@@ -0,0 +1,115 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+
+use common::run_cli;
+use common::run_cli_bytes;
+use common::Fixture;
+
+const HOSTILE_NAMES: &'static [&'static str] = &["back\\slash.txt", "new\nline.txt", "plain.txt", "tab\there.txt"];
+
+fn create_fixture(name: &str) -> Fixture {
+    let fixture = Fixture::new(&format!("escaped_{}", name));
+    for name in HOSTILE_NAMES {
+        fs::write(fixture.join(name), "data").unwrap();
+    }
+
+    fixture
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some(c) => result.push(c),
+                None => result.push('\\')
+            },
+            c => result.push(c)
+        }
+    }
+
+    result
+}
+
+#[test]
+fn test_tabs_and_lines_round_trip() {
+    let fixture = create_fixture("tabs");
+    let root = fixture.to_string_lossy();
+
+    let output = run_cli(&format!("name, size from '{}' order by name", root));
+    assert_eq!(output, "back\\\\slash.txt\t4\t\nnew\\nline.txt\t4\t\nplain.txt\t4\t\ntab\\there.txt\t4\t\n");
+
+    let rows: Vec<Vec<String>> = output.lines()
+        .map(|line| line.trim_end_matches('\t').split('\t').map(unescape).collect())
+        .collect();
+    assert_eq!(rows.iter().map(|row| row[0].as_str()).collect::<Vec<&str>>(), HOSTILE_NAMES);
+    assert!(rows.iter().all(|row| row[1] == "4"));
+
+    let output = run_cli(&format!("name from '{}' order by name into tabs raw", root));
+    assert_eq!(output, "back\\slash.txt\t\nnew\nline.txt\t\nplain.txt\t\ntab\there.txt\t\n");
+
+    let output = run_cli(&format!("name from '{}' order by name into lines", root));
+    assert_eq!(output, "back\\slash.txt\nnew\nline.txt\nplain.txt\ntab\there.txt\n");
+
+    let output = run_cli(&format!("name from '{}' order by name into lines esc", root));
+    assert_eq!(output.lines().map(unescape).collect::<Vec<String>>(), HOSTILE_NAMES);
+}
+
+#[test]
+fn test_list_through_xargs() {
+    let fixture = create_fixture("list");
+    let root = fixture.to_string_lossy();
+
+    // streamed and buffered rows are separated the same way
+    for query in &[format!("path from '{}' where is_file = true into list", root),
+                   format!("path from '{}' where is_file = true order by name desc into list", root)] {
+        let output = run_cli_bytes(query);
+        assert_eq!(output.last(), Some(&0));
+
+        let mut paths: Vec<String> = output[..output.len() - 1].split(|&b| b == 0)
+            .map(|path| String::from_utf8(path.to_vec()).unwrap())
+            .collect();
+        paths.sort();
+        let expected: Vec<String> = HOSTILE_NAMES.iter().map(|name| fixture.join(name).to_string_lossy().to_string()).collect();
+        assert_eq!(paths, expected);
+    }
+
+    let mut xargs = Command::new("xargs")
+        .args(&["-0", "rm", "--"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    xargs.stdin.take().unwrap().write_all(&run_cli_bytes(&format!("path from '{}' where is_file = true order by path into list", root))).unwrap();
+    assert!(xargs.wait().unwrap().success());
+
+    assert_eq!(fs::read_dir(&fixture).unwrap().count(), 0);
+}
+
+#[test]
+fn test_cmd_through_shell() {
+    let fixture = create_fixture("cmd");
+    let root = fixture.to_string_lossy();
+    fs::write(fixture.join("it's.txt"), "data").unwrap();
+
+    // commands follow the order of the rows
+    let output = run_cli(&format!("name from '{}' where name like 'back%' or name like 'it%' order by name desc into cmd 'echo {{}} {{size}}'", root));
+    assert_eq!(output, "echo 'it'\\''s.txt' 4\necho 'back\\slash.txt' 4\n");
+
+    let mut sh = Command::new("sh")
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    sh.stdin.take().unwrap().write_all(&run_cli_bytes(&format!("name from '{}' into cmd 'rm -- {{path}}'", root))).unwrap();
+    assert!(sh.wait().unwrap().success());
+
+    assert_eq!(fs::read_dir(&fixture).unwrap().count(), 0);
+}
@@ -0,0 +1,36 @@
+extern crate serde_json;
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+
+use common::run;
+use common::Fixture;
+
+#[test]
+fn test_file_output() {
+    let fixture = Fixture::new("file_output");
+    let dir = fixture.join("dir");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "a").unwrap();
+    fs::write(dir.join("b.txt"), "bb").unwrap();
+
+    let report = fixture.join("report.json");
+    let query = format!("name, size from '{}' order by name into json '{}'", dir.to_string_lossy(), report.to_string_lossy());
+    let output = run(&[query]);
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let rows: Vec<HashMap<String, serde_json::Value>> = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0]["name"], "a.txt");
+    assert_eq!(rows[1]["size"], 2);
+
+    let query = format!("name from '{}' into csv '{}'", dir.to_string_lossy(), fixture.join("missing").join("report.csv").to_string_lossy());
+    let output = run(&[query]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("report.csv"));
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use common::Fixture;
+
+fn create_fixture(name: &str) -> Fixture {
+    let fixture = Fixture::new(&format!("index_{}", name));
+    fs::create_dir_all(fixture.join("data").join("sub")).unwrap();
+    fs::write(fixture.join("data").join("a.txt"), "aaaa").unwrap();
+    fs::write(fixture.join("data").join("b.log"), "bb").unwrap();
+    fs::write(fixture.join("data").join("sub").join("c.txt"), "c").unwrap();
+
+    fixture
+}
+
+fn run_cli(index: &Path, query: &str) -> String {
+    let output = common::run(&[Path::new("--index"), index, Path::new(query)]);
+
+    assert!(output.status.success());
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Modification time of a directory may have a coarse resolution.
+fn wait_for_clock() {
+    thread::sleep(Duration::from_millis(50));
+}
+
+#[test]
+fn test_index_follows_changed_dirs() {
+    let fixture = create_fixture("changes");
+    let index = fixture.join("data.idx");
+    let query = format!("select relpath, size from '{}' where is_file = true order by relpath into csv", fixture.join("data").to_string_lossy());
+
+    let output = run_cli(&index, &query);
+    assert_eq!(output, "relativepath,size\na.txt,4\nb.log,2\nsub/c.txt,1\n");
+    assert!(index.is_file());
+
+    wait_for_clock();
+    assert_eq!(run_cli(&index, &query), output);
+
+    fs::write(fixture.join("data").join("sub").join("d.txt"), "dd").unwrap();
+    fs::remove_file(fixture.join("data").join("b.log")).unwrap();
+    assert_eq!(run_cli(&index, &query), "relativepath,size\na.txt,4\nsub/c.txt,1\nsub/d.txt,2\n");
+
+    wait_for_clock();
+    fs::remove_dir_all(fixture.join("data").join("sub")).unwrap();
+    assert_eq!(run_cli(&index, &query), "relativepath,size\na.txt,4\n");
+}
+
+#[test]
+fn test_index_answers_as_file_system() {
+    let fixture = create_fixture("answers");
+    let index = fixture.join("data.idx");
+    let root = fixture.join("data").to_string_lossy().to_string();
+
+    let queries = [
+        format!("select name, size, fsize, modified, mode, is_dir from '{}' where name like '%.txt' or is_dir = true order by name into csv", root),
+        format!("select ext, count(*), sum(size) from '{}' depth 1 where size gte 2 group by ext order by ext", root),
+        format!("select path from '{}' where modified gt 2000-01-01 and not ext = txt order by path", root),
+        format!("select relpath, depth, modified_unix, modified_utc from '{}' where depth > 1 and modified_unix > 0 order by depth desc, relpath", root),
+        format!("select relpath from '{}' where is_newer_than('{}/a.txt') or is_older_than('{}/sub/c.txt') order by relpath", root, root, root),
+        format!("select relpath from '{}' where size > 1.5 or depth >= 1.5 or modified_unix < 0.5 order by relpath", root),
+    ];
+
+    for query in &queries {
+        let expected = common::run_cli_bytes(query);
+        assert_eq!(run_cli(&index, query).into_bytes(), expected);
+        // the second time the answers come from the index
+        assert_eq!(run_cli(&index, query).into_bytes(), expected);
+    }
+
+    // a damaged index is built anew
+    fs::write(&index, "not an index").unwrap();
+    assert_eq!(run_cli(&index, &format!("select name from '{}' where size = 1 into lines", root)), "c.txt\n");
+}
+
+#[test]
+fn test_index_rules_out_files_for_other_conditions() {
+    let fixture = create_fixture("mixed");
+    let index = fixture.join("data.idx");
+    let root = fixture.join("data").to_string_lossy().to_string();
+
+    let queries = [
+        format!("select name, size from '{}' where size > 1 and contains('a') order by name", root),
+        format!("select name from '{}' where size = 1 or contains('bb') order by name", root),
+        format!("select name from '{}' where not (ext = txt and contains('c')) order by name", root),
+        format!("select name, is_binary from '{}' where ext = txt order by name", root),
+    ];
+
+    for query in &queries {
+        let expected = common::run_cli_bytes(query);
+        assert_eq!(run_cli(&index, query).into_bytes(), expected);
+        assert_eq!(run_cli(&index, query).into_bytes(), expected);
+    }
+
+    // the directories were walked with the index
+    assert!(index.is_file());
+}
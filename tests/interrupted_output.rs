@@ -0,0 +1,51 @@
+#![cfg(unix)]
+
+extern crate libc;
+extern crate serde_json;
+
+mod common;
+
+use std::fs;
+use std::io::Read;
+use std::process::Command;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+
+use common::Fixture;
+
+#[test]
+fn test_interrupted_search_keeps_partial_output() {
+    let fixture = Fixture::new("interrupted");
+
+    // far more rows than a pipe buffer holds, so the search waits for the reader until it's interrupted
+    for idx in 0..3000 {
+        fs::write(fixture.join(format!("file_with_a_rather_long_name_{:05}.txt", idx)), "").unwrap();
+    }
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fselect"))
+        .arg(format!("select path from '{}' into json", fixture.to_string_lossy()))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(500));
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGINT);
+    }
+
+    let mut stdout = String::new();
+    child.stdout.take().unwrap().read_to_string(&mut stdout).unwrap();
+    let mut stderr = String::new();
+    child.stderr.take().unwrap().read_to_string(&mut stderr).unwrap();
+    let status = child.wait().unwrap();
+
+    assert_eq!(status.code(), Some(130));
+    assert!(stderr.contains("the search was stopped, results are partial"), "{}", stderr);
+
+    // the rows found so far are output as a complete document
+    let rows: Vec<serde_json::Value> = serde_json::from_str(&stdout).unwrap();
+    assert!(!rows.is_empty());
+    assert!(rows.len() < 3000);
+}
@@ -0,0 +1,72 @@
+extern crate serde_json;
+
+mod common;
+
+use std::fs;
+
+use common::run_cli_bytes;
+use common::Fixture;
+
+#[test]
+fn test_json_value_types() {
+    let fixture = Fixture::new("json_output");
+    fs::create_dir(fixture.join("dir")).unwrap();
+    fs::write(fixture.join("a.txt"), "abc").unwrap();
+
+    let query = format!("name, size, fsize, uid, is_dir, is_empty, modified, width from '{}' order by name into json", fixture.to_string_lossy());
+    let output = run_cli_bytes(&query);
+
+    let rows: Vec<serde_json::Value> = serde_json::from_slice(&output).unwrap();
+    assert_eq!(rows.len(), 2);
+
+    let file = &rows[0];
+    assert_eq!(file["name"], "a.txt");
+    assert_eq!(file["size"], 3);
+    assert!(file["formattedsize"].is_string());
+    assert!(file["uid"].is_u64());
+    assert_eq!(file["isdir"], false);
+    assert_eq!(file["isempty"], false);
+    assert!(file["modified"].is_string());
+    assert!(file["width"].is_null());
+
+    let dir = &rows[1];
+    assert_eq!(dir["name"], "dir");
+    assert_eq!(dir["isdir"], true);
+    assert_eq!(dir["isempty"], true);
+}
+
+#[test]
+fn test_json_aliases() {
+    let fixture = Fixture::new("json_aliases");
+    fs::write(fixture.join("A.txt"), "abc").unwrap();
+
+    let query = format!("size as bytes, lower(name) as fname from '{}' order by fname into json", fixture.to_string_lossy());
+    let output = run_cli_bytes(&query);
+
+    let rows: Vec<serde_json::Value> = serde_json::from_slice(&output).unwrap();
+    assert_eq!(rows.len(), 1);
+
+    let file = &rows[0];
+    assert_eq!(file["bytes"], 3);
+    assert_eq!(file["fname"], "a.txt");
+    assert!(file.get("size").is_none());
+    assert!(file.get("lower(name)").is_none());
+}
+
+#[test]
+fn test_json_keys_follow_the_select_list() {
+    let fixture = Fixture::new("json_keys");
+    fs::write(fixture.join("a.txt"), "abc").unwrap();
+
+    let run = |columns: &str| {
+        let query = format!("{} from '{}' into json", columns, fixture.to_string_lossy());
+        common::run_cli(&query)
+    };
+
+    assert_eq!(run("size, name, size as bytes"), r#"[{"size":3,"name":"a.txt","bytes":3}]"#);
+
+    // a column selected twice keeps both of its values
+    assert_eq!(run("name, name"), r#"[{"name":"a.txt","name":"a.txt"}]"#);
+    assert_eq!(run("'size', size"), r#"[{"size":3,"size":3}]"#);
+    assert_eq!(run("'abc' as size, size"), r#"[{"size":"abc","size":3}]"#);
+}
@@ -0,0 +1,166 @@
+extern crate fselect;
+
+mod common;
+
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use fselect::ErrorReporter;
+use fselect::Parser;
+use fselect::Record;
+use fselect::Searcher;
+
+use common::run;
+use common::run_cli;
+use common::Fixture;
+
+fn create_fixture(name: &str) -> Fixture {
+    let fixture = Fixture::new(&format!("library_{}", name));
+    fs::create_dir_all(fixture.join("sub")).unwrap();
+    fs::write(fixture.join("a.txt"), "hello").unwrap();
+    fs::write(fixture.join("b.log"), "").unwrap();
+    fs::write(fixture.join("sub").join("c.txt"), "world!").unwrap();
+
+    fixture
+}
+
+fn searcher(query: &str) -> Searcher {
+    Searcher::new(Parser::new().parse(query).unwrap())
+}
+
+fn collect_records(searcher: &mut Searcher) -> Vec<Record> {
+    let mut records = vec![];
+    searcher.search(&mut |record| {
+        records.push(record);
+        true
+    });
+
+    records
+}
+
+#[test]
+fn test_search_records() {
+    let fixture = create_fixture("records");
+    let root = fixture.to_string_lossy();
+
+    let records: Vec<Record> = collect_records(&mut searcher(&format!("select name, size from '{}' where is_file = true order by name", root)));
+    assert_eq!(records, vec![
+        Record::new(vec![String::from("name"), String::from("size")], vec![String::from("a.txt"), String::from("5")]),
+        Record::new(vec![String::from("name"), String::from("size")], vec![String::from("b.log"), String::from("0")]),
+        Record::new(vec![String::from("name"), String::from("size")], vec![String::from("c.txt"), String::from("6")]),
+    ]);
+
+    let records: Vec<Record> = collect_records(&mut searcher(&format!("select count(*), sum(size) from '{}' where name like '%.txt' into json", root)));
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].get("count(*)"), Some("2"));
+    assert_eq!(records[0].get("SUM(size)"), Some("11"));
+
+    let names: Vec<String> = collect_records(&mut searcher(&format!("select name from '{}' where name = a.txt union select name from '{}/sub' order by name", root, root)))
+        .into_iter()
+        .map(|record| record.get("name").unwrap().to_string())
+        .collect();
+    assert_eq!(names, vec!["a.txt", "c.txt"]);
+
+    // the rows of files come as soon as they are found, the search stops when no more of them are wanted
+    let mut names = vec![];
+    searcher(&format!("select name from '{}'", root)).search(&mut |record| {
+        names.push(record.values[0].clone());
+        false
+    });
+    assert_eq!(names.len(), 1);
+}
+
+#[test]
+fn test_cli_output_is_unchanged() {
+    let fixture = create_fixture("cli");
+    let root = fixture.to_string_lossy();
+
+    let cases = vec![
+        (format!("name, size from '{}' where is_file = true order by name", root), "a.txt\t5\t\nb.log\t0\t\nc.txt\t6\t\n"),
+        (format!("name from '{}' order by name desc limit 2 into lines", root), "sub\nc.txt\n"),
+        (format!("name, size from '{}' where is_file = true and size gt 0 order by size into csv", root), "name,size\na.txt,5\nc.txt,6\n"),
+        (format!("count(*), max(size) from '{}' where is_file = true into html", root),
+         "<html><body><table><tr><th>count(*)</th><th>max(size)</th></tr>\n<tr><td>3</td><td>6</td></tr>\n</table></body></html>\n"),
+        (format!("name from '{}' where name = a.txt union name from '{}' where name = b.log order by name into list", root, root), "a.txt\0b.log\0"),
+    ];
+
+    for (query, expected) in cases {
+        assert_eq!(run_cli(&query), expected, "{}", query);
+    }
+
+    // the records are the same the utility prints
+    let query = format!("select name, size, is_dir from '{}' order by name", root);
+    let printed = run_cli(&query);
+    let found: String = collect_records(&mut searcher(&query)).into_iter()
+        .map(|record| format!("{}\t\n", record.values.join("\t")))
+        .collect();
+    assert_eq!(printed, found);
+}
+
+struct CollectingReporter {
+    errors: Rc<RefCell<Vec<String>>>,
+}
+
+impl ErrorReporter for CollectingReporter {
+    fn path_error(&mut self, path: &Path, _: &io::Error) {
+        self.errors.borrow_mut().push(path.to_string_lossy().to_string());
+    }
+
+    fn error(&mut self, source: &str, description: &str) {
+        self.errors.borrow_mut().push(format!("{}: {}", source, description));
+    }
+}
+
+#[test]
+fn test_error_reporter() {
+    let fixture = create_fixture("errors");
+    let missing = fixture.join("missing");
+
+    let errors = Rc::new(RefCell::new(vec![]));
+    let mut searcher = searcher(&format!("select name from '{}', '{}' where name = a.txt", missing.to_string_lossy(), fixture.to_string_lossy()));
+    searcher.set_error_reporter(Box::new(CollectingReporter { errors: errors.clone() }));
+
+    assert_eq!(collect_records(&mut searcher).into_iter().map(|record| record.values).collect::<Vec<Vec<String>>>(), vec![vec![String::from("a.txt")]]);
+    assert!(searcher.has_errors());
+    assert_eq!(*errors.borrow(), vec![missing.to_string_lossy().to_string()]);
+}
+
+#[test]
+fn test_count_and_exists() {
+    let fixture = create_fixture("count");
+    let root = fixture.to_string_lossy();
+
+    // columns, ordering and limit don't change the number of matching files
+    assert_eq!(searcher(&format!("select name, size from '{}' where is_file = true order by size desc limit 1", root)).count(), 3);
+    assert_eq!(searcher(&format!("select ext, count(*) from '{}' where is_file = true group by ext", root)).count(), 3);
+    assert_eq!(searcher(&format!("select name from '{}' where name like '%.txt' union select name from '{}/sub'", root, root)).count(), 3);
+    assert_eq!(searcher(&format!("select name from '{}' where is_file = true and size gt 1k", root)).count(), 0);
+
+    assert!(searcher(&format!("select name from '{}' where name = c.txt order by name", root)).exists());
+    assert!(!searcher(&format!("select name from '{}' where name = d.txt", root)).exists());
+    assert!(searcher(&format!("select name from '{}' where name = d.txt union select name from '{}' where size = 6", root, root)).exists());
+}
+
+#[test]
+fn test_cli_exists_and_count_only() {
+    let fixture = create_fixture("exit_codes");
+    let root = fixture.to_string_lossy();
+
+    let output = run(&["--exists", &format!("name from '{}' where size > 5 order by size", root)]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    let output = run(&["--exists", &format!("name from '{}' where size > 1g", root)]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+
+    let output = run(&["--count-only", &format!("name, size from '{}' where is_file = true order by name limit 1 into json", root)]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+
+    let output = run(&["--count-only", &format!("name from '{}' where size > 1g", root)]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "0\n");
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use common::Fixture;
+
+fn create_fixture(name: &str) -> Fixture {
+    let fixture = Fixture::new(&format!("ordering_{}", name));
+
+    // the sizes tie in pairs, so the second key decides the order within them
+    for &(name, size) in &[("b.txt", 3), ("A.txt", 1), ("c.txt", 3), ("d.log", 1), ("E.log", 2), ("f.log", 2)] {
+        fs::write(fixture.join(name), "x".repeat(size)).unwrap();
+    }
+
+    fixture
+}
+
+fn run_cli(query: &str) -> String {
+    run_cli_with_sort_rows(query, "")
+}
+
+fn run_cli_with_sort_rows(query: &str, sort_rows: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_fselect"))
+        .arg(query)
+        .env("FSELECT_SORT_ROWS", sort_rows)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_mixed_directions() {
+    let fixture = create_fixture("mixed");
+    let root = fixture.to_string_lossy();
+
+    let output = run_cli(&format!("select name, size from '{}' order by 2 desc, 1 asc into csv", root));
+    assert_eq!(output, "name,size\nb.txt,3\nc.txt,3\nE.log,2\nf.log,2\nA.txt,1\nd.log,1\n");
+
+    let output = run_cli(&format!("select name, size from '{}' order by size, name desc into csv", root));
+    assert_eq!(output, "name,size\nd.log,1\nA.txt,1\nf.log,2\nE.log,2\nc.txt,3\nb.txt,3\n");
+
+    // the limit keeps the rows the whole ordering puts first
+    let output = run_cli(&format!("select name from '{}' order by size desc, name desc limit 3 into lines", root));
+    assert_eq!(output, "c.txt\nb.txt\nf.log\n");
+}
+
+#[test]
+fn test_ordering_by_expressions() {
+    let fixture = create_fixture("expressions");
+    let root = fixture.to_string_lossy();
+
+    // the expression doesn't have to be selected
+    let output = run_cli(&format!("select name from '{}' order by size desc, lower(name) desc into lines", root));
+    assert_eq!(output, "c.txt\nb.txt\nf.log\nE.log\nd.log\nA.txt\n");
+
+    let output = run_cli(&format!("select upper(name), size from '{}' order by size * 2, upper(name) desc into csv", root));
+    assert_eq!(output, "upper(name),size\nD.LOG,1\nA.TXT,1\nF.LOG,2\nE.LOG,2\nC.TXT,3\nB.TXT,3\n");
+
+    // groups are ordered by an aggregate which isn't selected, then by the grouping column
+    let output = run_cli(&format!("select ext, sum(size) from '{}' group by ext order by min(size) desc, ext desc into csv", root));
+    assert_eq!(output, "extension,sum(size)\ntxt,7\nlog,5\n");
+
+    let output = run_cli(&format!("select ext, count(*) from '{}' group by ext order by count(*), max(size) desc into csv", root));
+    assert_eq!(output, "extension,count(*)\ntxt,3\nlog,3\n");
+}
+
+#[test]
+fn test_ordering_by_aliases() {
+    let fixture = create_fixture("aliases");
+    let root = fixture.to_string_lossy();
+
+    let output = run_cli(&format!("select size as bytes, lower(name) as fname from '{}' order by bytes desc, FNAME into csv", root));
+    assert_eq!(output, "bytes,fname\n3,b.txt\n3,c.txt\n2,e.log\n2,f.log\n1,a.txt\n1,d.log\n");
+
+    let output = run_cli(&format!("select ext as kind, count(*) as files, sum(size) as total from '{}' group by kind order by total desc into csv", root));
+    assert_eq!(output, "kind,files,total\ntxt,3,7\nlog,3,5\n");
+
+    // the columns of a union are named after the first query
+    let output = run_cli(&format!("select name as n from '{}' where size = 1 union select path from '{}' where name = b.txt order by n desc into csv", root, root));
+    assert_eq!(output, format!("n\nd.log\nA.txt\n{}\n", fixture.join("b.txt").to_string_lossy()));
+}
+
+#[test]
+fn test_spilled_ordering() {
+    let fixture = create_fixture("spilled");
+    for idx in 0..500 {
+        fs::write(fixture.join(format!("file{}.dat", idx)), "x".repeat(idx * 37 % 23)).unwrap();
+    }
+    let root = fixture.to_string_lossy();
+
+    // the rows written to temporary files every 7 rows are merged into the same output as the ones sorted in memory
+    for query in &[
+        format!("select name, size from '{}' order by size desc into csv", root),
+        format!("select name from '{}' order by size, ext desc offset 20 into json", root),
+        format!("select path, size from '{}' where size > 5 union select name, size from '{}' where size < 3 order by size into lines", root, root),
+    ] {
+        let output = run_cli(query);
+        assert!(output.matches("file").count() > 400);
+        assert_eq!(run_cli_with_sort_rows(query, "7"), output);
+    }
+}
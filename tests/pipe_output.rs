@@ -0,0 +1,70 @@
+mod common;
+
+use std::fs;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::process::Command;
+use std::process::Stdio;
+
+use common::Fixture;
+
+fn create_fixture(name: &str) -> Fixture {
+    let fixture = Fixture::new(&format!("pipe_output_{}", name));
+
+    // far more rows than a pipe buffer holds
+    for idx in 0..3000 {
+        fs::write(fixture.join(format!("file_with_a_rather_long_name_{:05}.txt", idx)), "").unwrap();
+    }
+
+    fixture
+}
+
+/// Reads the first line only and closes the pipe, like `head -n 1` does.
+fn read_first_line(query: &str) -> (String, bool, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fselect"))
+        .arg(query)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut line = String::new();
+    {
+        let mut reader = BufReader::new(child.stdout.take().unwrap());
+        reader.read_line(&mut line).unwrap();
+    }
+
+    let mut stderr = String::new();
+    child.stderr.take().unwrap().read_to_string(&mut stderr).unwrap();
+    let status = child.wait().unwrap();
+
+    (line, status.success(), stderr)
+}
+
+#[test]
+fn test_closed_pipe_stops_the_search() {
+    let fixture = create_fixture("streamed");
+    let root = fixture.to_string_lossy();
+
+    let (line, success, stderr) = read_first_line(&format!("select path from '{}' into lines", root));
+    assert!(line.contains("file_with_a_rather_long_name_"));
+    assert!(success);
+    assert_eq!(stderr, "");
+
+    let (line, success, stderr) = read_first_line(&format!("select name, path, size from '{}' into csv", root));
+    assert_eq!(line, "name,path,size\n");
+    assert!(success);
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn test_closed_pipe_while_buffered_rows_are_written() {
+    let fixture = create_fixture("ordered");
+    let root = fixture.to_string_lossy();
+
+    let (line, success, stderr) = read_first_line(&format!("select path from '{}' order by name desc into lines", root));
+    assert!(line.ends_with("file_with_a_rather_long_name_02999.txt\n"));
+    assert!(success);
+    assert_eq!(stderr, "");
+}
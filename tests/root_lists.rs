@@ -0,0 +1,69 @@
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::process::Command;
+use std::process::Output;
+use std::process::Stdio;
+
+use common::Fixture;
+
+fn create_fixture(name: &str) -> Fixture {
+    let fixture = Fixture::new(&format!("root_lists_{}", name));
+
+    for project in &["alpha", "beta", "gamma"] {
+        fs::create_dir_all(fixture.join(project).join("logs")).unwrap();
+        fs::write(fixture.join(project).join("big.bin"), vec![0; 2048]).unwrap();
+        fs::write(fixture.join(project).join("logs/app.log"), project).unwrap();
+    }
+
+    fixture
+}
+
+fn run_with_stdin(query: &str, stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fselect"))
+        .arg(query)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_roots_from_stdin() {
+    let fixture = create_fixture("stdin");
+    let roots = format!("{0}/gamma\n# not this one\n\n{0}/alpha\n", fixture.to_string_lossy());
+
+    let output = run_with_stdin("select size, name from - where size > 1k and is_file = true into csv", roots.as_bytes());
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "size,name\n2048,big.bin\n2048,big.bin\n");
+
+    // the options after the placeholder apply to every root read
+    let output = run_with_stdin("select relpath from - depth 1 where is_file = true into lines", roots.as_bytes());
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "big.bin\nbig.bin\n");
+}
+
+#[test]
+fn test_nul_separated_roots_from_stdin() {
+    let fixture = create_fixture("nul");
+    let roots = format!("{0}/beta/logs\0{0}/alpha/logs\0", fixture.to_string_lossy());
+
+    let output = run_with_stdin("select name, size from - into csv", roots.as_bytes());
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "name,size\napp.log,4\napp.log,5\n");
+}
+
+#[test]
+fn test_missing_root_list() {
+    let output = run_with_stdin("select name from @/no/such/roots.txt", b"");
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot read the list of roots"));
+}
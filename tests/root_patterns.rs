@@ -0,0 +1,68 @@
+mod common;
+
+use std::fs;
+
+use common::run;
+use common::run_cli;
+use common::Fixture;
+
+fn create_fixture(name: &str) -> Fixture {
+    let fixture = Fixture::new(&format!("root_patterns_{}", name));
+
+    // created out of order, so the expanded roots are less likely to come sorted by chance
+    for project in &["gamma", "alpha", "beta", ".cache"] {
+        fs::create_dir_all(fixture.join("projects").join(project).join("logs/old")).unwrap();
+        fs::write(fixture.join("projects").join(project).join("logs/app.log"), project).unwrap();
+        fs::write(fixture.join("projects").join(project).join("logs/old/app.log"), project).unwrap();
+    }
+    fs::create_dir_all(fixture.join("weird*dir")).unwrap();
+    fs::write(fixture.join("weird*dir/literal.txt"), "").unwrap();
+
+    fixture
+}
+
+#[test]
+fn test_expanded_roots() {
+    let fixture = create_fixture("expanded");
+    let root = fixture.to_string_lossy();
+
+    // every match is a root of its own, searched in sorted order with the same options
+    let output = run_cli(&format!("select relpath, size from {}/projects/*/logs depth 1 where is_file = true into csv", root));
+    assert_eq!(output, "relativepath,size\napp.log,5\napp.log,4\napp.log,5\n");
+
+    let output = run_cli(&format!("select path from {}/projects/*/logs depth 1 where is_file = true into lines", root));
+    let expected: Vec<String> = ["alpha", "beta", "gamma"].iter()
+        .map(|project| fixture.join("projects").join(project).join("logs/app.log").to_string_lossy().to_string())
+        .collect();
+    assert_eq!(output.lines().collect::<Vec<&str>>(), expected);
+
+    let output = run_cli(&format!("select count(*) from {}/projects/{{gamma,alpha}}/logs where is_file = true", root));
+    assert_eq!(output.trim(), "4");
+
+    let output = run_cli(&format!("select count(*) from {}/**/old where is_file = true", root));
+    assert_eq!(output.trim(), "3");
+
+    // hidden directories are matched only by a pattern starting with a dot
+    let output = run_cli(&format!("select count(*) from {}/projects/.c*/logs depth 1 where is_file = true", root));
+    assert_eq!(output.trim(), "1");
+}
+
+#[test]
+fn test_literal_roots() {
+    let fixture = create_fixture("literal");
+    let root = fixture.to_string_lossy();
+
+    // quoted paths aren't expanded
+    let output = run_cli(&format!("select name from '{}/weird*dir' into lines", root));
+    assert_eq!(output, "literal.txt\n");
+
+    let output = run(&[format!("select name from '{}/projects/*/logs' into lines", root)]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+
+    // a pattern matching nothing is an error, not an empty search
+    let output = run(&[format!("select name from {}/projects/*/nothing into lines", root)]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no files match the pattern"));
+}
@@ -0,0 +1,45 @@
+extern crate rusqlite;
+
+mod common;
+
+use std::fs;
+
+use rusqlite::Connection;
+use rusqlite::NO_PARAMS;
+
+use common::Fixture;
+
+fn select_names(db: &Connection) -> Vec<(String, String)> {
+    let mut stmt = db.prepare("select name, size from found order by name").unwrap();
+    let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+
+    rows.map(|row| row.unwrap()).collect()
+}
+
+#[test]
+fn test_sqlite_output() {
+    let fixture = Fixture::new("sqlite");
+    let dir = fixture.join("dir");
+    fs::create_dir_all(&dir).unwrap();
+    fs::File::create(dir.join("a.txt")).unwrap();
+    fs::File::create(dir.join("b,'c'.txt")).unwrap();
+
+    let db_path = fixture.join("files.db");
+    let run = |target: &str| {
+        let query = format!("name, size from '{}' order by name into 'sqlite:{}:found{}'", dir.to_string_lossy(), db_path.to_string_lossy(), target);
+        assert!(common::run_cli(&query).is_empty());
+    };
+
+    run("");
+    run("");
+
+    let db = Connection::open(&db_path).unwrap();
+    assert_eq!(select_names(&db), vec![
+        (String::from("a.txt"), String::from("0")),
+        (String::from("b,'c'.txt"), String::from("0")),
+    ]);
+
+    run(":append");
+
+    assert_eq!(select_names(&db).len(), 4);
+}
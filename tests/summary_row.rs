@@ -0,0 +1,74 @@
+extern crate serde_json;
+
+mod common;
+
+use std::fs;
+
+use common::run;
+use common::run_cli;
+use common::Fixture;
+
+fn create_fixture(name: &str) -> Fixture {
+    let fixture = Fixture::new(&format!("summary_{}", name));
+    fs::write(fixture.join("a.txt"), "aaaa").unwrap();
+    fs::write(fixture.join("b.txt"), "bb").unwrap();
+    fs::write(fixture.join("c.log"), "c").unwrap();
+
+    fixture
+}
+
+#[test]
+fn test_summary_row_follows_file_rows() {
+    let fixture = create_fixture("csv");
+    let root = fixture.to_string_lossy();
+
+    let output = run_cli(&format!("select name, size, sum(size), count(*) from '{}' order by name into csv", root));
+    assert_eq!(output, "name,size,sum(size),count(*)\na.txt,4,,\nb.txt,2,,\nc.log,1,,\n,,7,3\n");
+
+    // the summary covers the files beyond the limit as well
+    let output = run_cli(&format!("select name, max(size) from '{}' order by name limit 1 offset 1 into csv", root));
+    assert_eq!(output, "name,max(size)\nb.txt,\n,4\n");
+
+    // grouped and aggregate-only queries have no rows of files
+    let output = run_cli(&format!("select ext, count(*) from '{}' group by ext order by ext into csv", root));
+    assert_eq!(output, "extension,count(*)\nlog,1\ntxt,2\n");
+
+    let output = run_cli(&format!("select count(*) from '{}' into csv", root));
+    assert_eq!(output, "count(*)\n3\n");
+}
+
+#[test]
+fn test_summary_row_in_json() {
+    let fixture = create_fixture("json");
+    let root = fixture.to_string_lossy();
+
+    let output = run_cli(&format!("select name, sum(size) from '{}' where ext = 'txt' order by name into json", root));
+    let rows: Vec<serde_json::Value> = serde_json::from_str(&output).unwrap();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0]["name"], "a.txt");
+    assert!(rows[0].get("sum(size)").is_none());
+    assert_eq!(rows[1]["name"], "b.txt");
+    assert_eq!(rows[2]["sum(size)"], "6");
+    assert!(rows[2].get("name").is_none());
+
+    // nothing found still gives the summary
+    let output = run_cli(&format!("select name, count(*) from '{}' where size gt 1k into json", root));
+    let rows: Vec<serde_json::Value> = serde_json::from_str(&output).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["count(*)"], "0");
+}
+
+#[test]
+fn test_summary_footer_goes_to_stderr() {
+    let fixture = create_fixture("footer");
+    let root = fixture.to_string_lossy();
+
+    let output = run(&[format!("select name, size from '{}' order by name limit 2 into csv summary", root)]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "name,size\na.txt,4\nb.txt,2\n");
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("matched: 3, shown: 2, total size: 7 B (7 bytes), elapsed: "), "{}", stderr);
+    assert_eq!(stderr.lines().count(), 1);
+}
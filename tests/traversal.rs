@@ -0,0 +1,82 @@
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use common::run_cli;
+use common::Fixture;
+
+fn create_fixture(name: &str) -> Fixture {
+    let fixture = Fixture::new(&format!("traversal_{}", name));
+    fs::create_dir_all(fixture.join("sub")).unwrap();
+
+    // created out of order, so the file system is less likely to list them sorted by chance
+    for name in &["m.txt", "sub/z.txt", "b.log", "k.txt", "sub/a.txt", "a.txt", "sub/c.log", "z.txt", "sub/m.txt"] {
+        fs::write(fixture.join(name), name).unwrap();
+    }
+    fs::write(fixture.join(".gitignore"), "*.log\n").unwrap();
+
+    fixture
+}
+
+#[test]
+fn test_ordered_output_is_reproducible() {
+    let fixture = create_fixture("ordered");
+    let root = fixture.to_string_lossy();
+
+    let query = format!("select path from '{}' ordered where is_file = true limit 4 into lines", root);
+    let first = run_cli(&query);
+    for _ in 0..5 {
+        assert_eq!(run_cli(&query), first);
+    }
+
+    let expected: Vec<String> = [".gitignore", "a.txt", "b.log", "k.txt"].iter()
+        .map(|name| fixture.join(name).to_string_lossy().to_string())
+        .collect();
+    assert_eq!(first.lines().collect::<Vec<&str>>(), expected);
+
+    // subdirectories are visited where they are met
+    let output = run_cli(&format!("select name from '{}' ordered gitignore into lines", root));
+    assert_eq!(output, ".gitignore\na.txt\nk.txt\nm.txt\nsub\na.txt\nm.txt\nz.txt\nz.txt\n");
+}
+
+#[test]
+fn test_max_files_per_directory() {
+    let fixture = create_fixture("maxfiles");
+    let root = fixture.to_string_lossy();
+
+    let output = run_cli(&format!("select name from '{}' ordered maxfiles 2 into lines", root));
+    assert_eq!(output, ".gitignore\na.txt\nsub\na.txt\nc.log\n");
+
+    // ignored files don't use up the quota
+    let output = run_cli(&format!("select name from '{}' ordered maxfiles 2 gitignore into lines", root));
+    assert_eq!(output, ".gitignore\na.txt\nsub\na.txt\nm.txt\n");
+
+    // without the order some of the files are still examined in every directory
+    let output = run_cli(&format!("select count(*) from '{}' maxfiles 3 where is_file = true", root));
+    assert_eq!(output.trim(), "6");
+}
+
+#[test]
+fn test_global_ignore_file() {
+    let fixture = create_fixture("globalignore");
+    let root = fixture.to_string_lossy();
+
+    let config_dir = fixture.join("config");
+    fs::create_dir_all(config_dir.join("fselect")).unwrap();
+    fs::write(config_dir.join("fselect").join("ignore"), "k.txt\nconfig/\n").unwrap();
+
+    let run = |options: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_fselect"))
+            .arg(format!("select name from '{}' {} depth 1 where is_file = true order by name into lines", root, options))
+            .env("XDG_CONFIG_HOME", &config_dir)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(run(""), ".gitignore\na.txt\nb.log\nm.txt\nz.txt\n");
+    assert_eq!(run("noglobalignore"), ".gitignore\na.txt\nb.log\nk.txt\nm.txt\nz.txt\n");
+}